@@ -0,0 +1,499 @@
+//! Best effort scans for the activity of an address, since nodes do not index transactions
+//! by the addresses they touch
+use crate::client::Web3;
+use crate::event_utils::{address_to_event, normalize_logs};
+use crate::hash::TxHash;
+use crate::jsonrpc::error::Web3Error;
+use crate::log_watcher::MAX_LOG_RANGE;
+use crate::types::{BlockId, Log, Trace, TransactionResponse};
+use crate::units::{format_units, ETH_DECIMALS};
+use clarity::abi::derive_signature;
+use clarity::{Address, Uint256};
+use std::cmp::min;
+
+/// The transactions and token transfers involving an address over a block range, returned
+/// by `Web3::get_transactions_for_address`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressActivity {
+    /// transactions sent from or to the address, in block and transaction order
+    pub transactions: Vec<TransactionResponse>,
+    /// ERC-20 Transfer logs from or to the address from any token contract, sorted by
+    /// `LogPosition`
+    pub token_transfers: Vec<Log>,
+}
+
+/// The gas paid for one transaction, part of a `GasReport`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionGas {
+    pub transaction_hash: TxHash,
+    pub block_number: Uint256,
+    pub gas_used: Uint256,
+    /// the price per gas actually paid, the gas price of the transaction on nodes that do
+    /// not return the effective gas price
+    pub effective_gas_price: Uint256,
+    /// the total fee in wei, including the L1 data fee on OP stack chains
+    pub fee: Uint256,
+    /// `fee` formatted in ETH
+    pub fee_eth: String,
+}
+
+/// The gas spent by an address over a block range, returned by `Web3::gas_spent_by_address`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GasReport {
+    /// every transaction sent by the address in the blocks that could be fetched, in block
+    /// and transaction order
+    pub transactions: Vec<TransactionGas>,
+    pub total_gas_used: Uint256,
+    /// the sum of the fees in wei
+    pub total_fees: Uint256,
+    /// `total_fees` formatted in ETH
+    pub total_fees_eth: String,
+    /// blocks whose transactions or receipts could not be fetched, the totals are missing
+    /// anything spent in them
+    pub failed_blocks: Vec<Uint256>,
+}
+
+impl Web3 {
+    /// Finds the transactions sent from or to `address` from `start_block` to `end_block`
+    /// inclusive by downloading every block in the range, `concurrency` at once, along with
+    /// the ERC-20 Transfer events that move tokens from or to `address`. This takes one
+    /// request per block so it is only practical for short ranges or local nodes.
+    ///
+    /// ETH sent to `address` by a contract, such as a withdrawal from a multisig or a DEX
+    /// paying out ETH, happens in an internal call and is not part of any transaction or
+    /// log. Use `get_traces_for_address` on nodes with the trace namespace to find those.
+    pub async fn get_transactions_for_address(
+        &self,
+        address: Address,
+        start_block: Uint256,
+        end_block: Uint256,
+        concurrency: usize,
+    ) -> Result<AddressActivity, Web3Error> {
+        let (blocks, token_transfers) = tokio::join!(
+            self.get_block_range(start_block, end_block, concurrency),
+            self.token_transfers_for_address(address, start_block, end_block)
+        );
+        let mut transactions = Vec::new();
+        for block in blocks {
+            for tx in block?.transactions {
+                if tx.from == address || tx.to == Some(address) {
+                    transactions.push(tx);
+                }
+            }
+        }
+        Ok(AddressActivity {
+            transactions,
+            token_transfers: token_transfers?,
+        })
+    }
+
+    /// Adds up the gas paid by `address` for the transactions it sent from `start_block` to
+    /// `end_block` inclusive, scanning blocks as `get_transactions_for_address` does. Receipts
+    /// are only fetched for blocks with a transaction from `address`, a whole block at a time
+    /// with eth_getBlockReceipts where the node supports it. Blocks that fail are listed in
+    /// `failed_blocks` instead of failing the report.
+    pub async fn gas_spent_by_address(
+        &self,
+        address: Address,
+        start_block: Uint256,
+        end_block: Uint256,
+        concurrency: usize,
+    ) -> Result<GasReport, Web3Error> {
+        let overflow = || Web3Error::BadResponse("Gas spent overflows a Uint256".to_string());
+        let mut report = GasReport::default();
+        let mut stream = self.block_range_stream(start_block, end_block, concurrency);
+        while let Some((number, block)) = stream.next().await {
+            let sent: Vec<TransactionResponse> = match block {
+                Ok(block) => block
+                    .transactions
+                    .into_iter()
+                    .filter(|tx| tx.from == address)
+                    .collect(),
+                Err(e) => {
+                    warn!("Could not fetch block {} for gas report: {:?}", number, e);
+                    report.failed_blocks.push(number);
+                    continue;
+                }
+            };
+            if sent.is_empty() {
+                continue;
+            }
+            let receipts = match self.eth_get_block_receipts(BlockId::Number(number)).await {
+                Ok(Some(receipts)) => receipts,
+                Ok(None) => {
+                    warn!("Block {} does not exist", number);
+                    report.failed_blocks.push(number);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Could not fetch receipts of block {}: {:?}", number, e);
+                    report.failed_blocks.push(number);
+                    continue;
+                }
+            };
+            let paid: Option<Vec<_>> = sent
+                .into_iter()
+                .map(|tx| {
+                    let receipt = receipts.iter().find(|r| r.transaction_hash == tx.hash)?;
+                    Some((tx, receipt))
+                })
+                .collect();
+            let paid = match paid {
+                Some(paid) => paid,
+                None => {
+                    warn!("Receipts of block {} are incomplete", number);
+                    report.failed_blocks.push(number);
+                    continue;
+                }
+            };
+            for (tx, receipt) in paid {
+                let effective_gas_price = receipt.effective_gas_price.unwrap_or(tx.gas_price);
+                let fee = receipt
+                    .gas_used
+                    .checked_mul(effective_gas_price)
+                    .and_then(|fee| fee.checked_add(receipt.l1_fee.unwrap_or_default()))
+                    .ok_or_else(overflow)?;
+                report.total_gas_used = report
+                    .total_gas_used
+                    .checked_add(receipt.gas_used)
+                    .ok_or_else(overflow)?;
+                report.total_fees = report.total_fees.checked_add(fee).ok_or_else(overflow)?;
+                report.transactions.push(TransactionGas {
+                    transaction_hash: tx.hash,
+                    block_number: number,
+                    gas_used: receipt.gas_used,
+                    effective_gas_price,
+                    fee,
+                    fee_eth: format_units(fee, ETH_DECIMALS),
+                });
+            }
+        }
+        report.total_fees_eth = format_units(report.total_fees, ETH_DECIMALS);
+        Ok(report)
+    }
+
+    /// The ERC-20 Transfer logs with `address` as the sender or recipient, ERC-721 transfers
+    /// share the signature but index the token id as well and are left out
+    async fn token_transfers_for_address(
+        &self,
+        address: Address,
+        start_block: Uint256,
+        end_block: Uint256,
+    ) -> Result<Vec<Log>, Web3Error> {
+        let transfer = derive_signature("Transfer(address,address,uint256)")?;
+        let topic = address_to_event(address);
+        let mut logs = Vec::new();
+        let mut start = start_block;
+        while start <= end_block {
+            let end = min(
+                end_block,
+                start
+                    .checked_add(Uint256::from_u64(MAX_LOG_RANGE - 1))
+                    .unwrap_or(end_block),
+            );
+            let (outgoing, incoming) = tokio::join!(
+                self.check_for_arbitrary_events(
+                    start,
+                    Some(end),
+                    Vec::new(),
+                    vec![vec![transfer], vec![topic]],
+                ),
+                self.check_for_arbitrary_events(
+                    start,
+                    Some(end),
+                    Vec::new(),
+                    vec![vec![transfer], vec![], vec![topic]],
+                )
+            );
+            logs.extend(outgoing?);
+            logs.extend(incoming?);
+            start = match end.checked_add(Uint256::from_u64(1)) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        logs.retain(|log| log.topics.len() == 3);
+        // transfers to self match both queries
+        Ok(normalize_logs(logs))
+    }
+
+    /// Finds every call from or to `address` from `start_block` to `end_block` inclusive with
+    /// trace_filter, including the internal calls that `get_transactions_for_address` can not
+    /// see. Only nodes with the trace namespace, such as erigon, nethermind and reth, support
+    /// this, others return `UnsupportedMethod`. The traces are in block, transaction and call
+    /// order.
+    pub async fn get_traces_for_address(
+        &self,
+        address: Address,
+        start_block: Uint256,
+        end_block: Uint256,
+    ) -> Result<Vec<Trace>, Web3Error> {
+        // some clients only match traces both from and to the address if both are given
+        let (from, to) = tokio::join!(
+            self.trace_filter(
+                start_block,
+                end_block,
+                vec![address],
+                Vec::new(),
+                None,
+                None
+            ),
+            self.trace_filter(
+                start_block,
+                end_block,
+                Vec::new(),
+                vec![address],
+                None,
+                None
+            )
+        );
+        let mut traces = from?;
+        for trace in to? {
+            if !traces.contains(&trace) {
+                traces.push(trace);
+            }
+        }
+        traces.sort_by(|a, b| {
+            (a.block_number, a.transaction_position, &a.trace_address).cmp(&(
+                b.block_number,
+                b.transaction_position,
+                &b.trace_address,
+            ))
+        });
+        Ok(traces)
+    }
+}
+
+#[tokio::test]
+async fn test_get_transactions_for_address() {
+    use crate::jsonrpc::mock::{geth_block, MockNode};
+    use clarity::utils::bytes_to_hex_str;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    let usdt: Address = "0xdac17f958d2ee523a2206206994597c13d831ec7"
+        .parse()
+        .unwrap();
+    let signature = derive_signature("Transfer(address,address,uint256)").unwrap();
+    let transfer = json!(format!("0x{}", bytes_to_hex_str(&signature)));
+    let usdt_topic = json!(format!("0x{}", bytes_to_hex_str(&address_to_event(usdt))));
+    let other_topic = json!(format!("0x{:064x}", 0x1234));
+    let transfer_log = |block: u64, index: u64, from: &Value, to: &Value| {
+        json!({
+            "address": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "blockNumber": format!("{:#x}", block),
+            "blockHash": format!("{:#066x}", block),
+            "transactionIndex": "0x0",
+            "logIndex": format!("{:#x}", index),
+            "transactionHash": format!("{:#066x}", index + 100),
+            "data": format!("0x{:064x}", 5),
+            "topics": [transfer, from, to],
+        })
+    };
+    let outgoing = vec![transfer_log(0xb086d7, 1, &usdt_topic, &other_topic)];
+    let incoming = vec![
+        transfer_log(0xb086d7, 0, &other_topic, &usdt_topic),
+        // an ERC-721 transfer has a fourth topic
+        {
+            let mut log = transfer_log(0xb086d6, 2, &other_topic, &usdt_topic);
+            log["topics"]
+                .as_array_mut()
+                .unwrap()
+                .push(json!(format!("0x{:064x}", 7)));
+            log
+        },
+    ];
+
+    let block = geth_block();
+    let node = MockNode::start(move |method, params| match method {
+        "eth_getBlockByNumber" => {
+            let mut block = block.clone();
+            block["number"] = params[0].clone();
+            Ok(block)
+        }
+        "eth_getLogs" if params[0]["topics"][1].is_null() => Ok(json!(incoming)),
+        "eth_getLogs" => Ok(json!(outgoing)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let activity = web3
+        .get_transactions_for_address(
+            usdt,
+            Uint256::from_u64(0xb086d6),
+            Uint256::from_u64(0xb086d7),
+            2,
+        )
+        .await
+        .unwrap();
+    // USDT is the recipient of 61 transactions in each of the two blocks
+    assert_eq!(activity.transactions.len(), 122);
+    assert!(activity
+        .transactions
+        .iter()
+        .all(|tx| tx.to == Some(usdt) || tx.from == usdt));
+    assert_eq!(activity.token_transfers.len(), 2);
+    assert_eq!(
+        activity.token_transfers[0].log_index,
+        Some(Uint256::from_u64(0))
+    );
+    assert_eq!(node.calls("eth_getLogs"), 2);
+}
+
+#[tokio::test]
+async fn test_gas_spent_by_address() {
+    use crate::jsonrpc::mock::{geth_block, MockNode};
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    let block = geth_block();
+    let receipts: Vec<Value> = block["transactions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tx| {
+            json!({
+                "blockHash": block["hash"],
+                "blockNumber": block["number"],
+                "contractAddress": null,
+                "cumulativeGasUsed": "0xa12515",
+                "from": tx["from"],
+                "gasUsed": "0x5208",
+                "logs": [],
+                "logsBloom": "0x00",
+                "status": "0x1",
+                "to": tx["to"],
+                "transactionHash": tx["hash"],
+                "transactionIndex": tx["transactionIndex"]
+            })
+        })
+        .collect();
+    let node = MockNode::start(move |method, params| match (method, params[0].as_str()) {
+        ("eth_getBlockByNumber", Some("0xb086d7")) => Err((-32000, "header not found".into())),
+        ("eth_getBlockByNumber", _) => {
+            let mut block = block.clone();
+            block["number"] = params[0].clone();
+            Ok(block)
+        }
+        ("eth_getBlockReceipts", Some("0xb086d6")) => Ok(json!(receipts)),
+        ("eth_getBlockReceipts", _) => Err((-32000, "missing trie node".into())),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    // the Binance hot wallet sent 17 transactions in the block at 120 gwei before london
+    let report = web3
+        .gas_spent_by_address(
+            "0x3f5ce5fbfe3e9af3971dd833d26ba9b5c936f0be"
+                .parse()
+                .unwrap(),
+            Uint256::from_u64(0xb086d6),
+            Uint256::from_u64(0xb086d8),
+            2,
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.transactions.len(), 17);
+    assert_eq!(report.total_gas_used, Uint256::from_u64(17 * 21_000));
+    assert_eq!(
+        report.transactions[0].effective_gas_price,
+        Uint256::from_u64(120_000_000_000)
+    );
+    assert_eq!(report.transactions[0].fee_eth, "0.00252");
+    assert_eq!(
+        report.total_fees,
+        Uint256::from_u64(17 * 21_000 * 120_000_000_000)
+    );
+    assert_eq!(report.total_fees_eth, "0.04284");
+    assert_eq!(
+        report.failed_blocks,
+        vec![Uint256::from_u64(0xb086d7), Uint256::from_u64(0xb086d8)]
+    );
+    // only blocks with a transaction from the address need receipts
+    let report = web3
+        .gas_spent_by_address(
+            "0x1111111111111111111111111111111111111111"
+                .parse()
+                .unwrap(),
+            Uint256::from_u64(0xb086d6),
+            Uint256::from_u64(0xb086d6),
+            2,
+        )
+        .await
+        .unwrap();
+    assert!(report.transactions.is_empty());
+    assert_eq!(report.total_fees_eth, "0");
+    assert_eq!(node.calls("eth_getBlockReceipts"), 2);
+}
+
+#[tokio::test]
+async fn test_get_traces_for_address() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let trace = |block: u64, position: u64, from: &str, to: &str| {
+        json!({
+            "action": {
+                "callType": "call",
+                "from": from,
+                "to": to,
+                "gas": "0x0",
+                "input": "0x",
+                "value": "0xde0b6b3a7640000"
+            },
+            "blockHash": format!("{:#066x}", block),
+            "blockNumber": block,
+            "result": {"gasUsed": "0x0", "output": "0x"},
+            "subtraces": 0,
+            "traceAddress": [0],
+            "transactionHash": format!("{:#066x}", position + 100),
+            "transactionPosition": position,
+            "type": "call"
+        })
+    };
+    let address = "0x1111111111111111111111111111111111111111";
+    let other = "0x2222222222222222222222222222222222222222";
+    let node = MockNode::start(move |method, params| match method {
+        "trace_filter" if params[0]["fromAddress"].is_null() => Ok(json!([
+            trace(12, 3, other, address),
+            // a call to self is returned by both filters
+            trace(10, 0, address, address),
+        ])),
+        "trace_filter" => Ok(json!([
+            trace(10, 0, address, address),
+            trace(11, 1, address, other),
+        ])),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let traces = web3
+        .get_traces_for_address(
+            address.parse().unwrap(),
+            Uint256::from_u64(10),
+            Uint256::from_u64(12),
+        )
+        .await
+        .unwrap();
+    let blocks: Vec<u64> = traces.iter().map(|t| t.block_number).collect();
+    assert_eq!(blocks, vec![10, 11, 12]);
+    assert_eq!(
+        traces[2].value(),
+        Uint256::from_u64(1_000_000_000_000_000_000)
+    );
+    assert_eq!(node.params("trace_filter")[0][0]["fromBlock"], json!("0xa"));
+
+    // nodes without the trace namespace
+    let node = MockNode::start(|_, _| Err((-32601, "Method not found".to_string())));
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    assert!(matches!(
+        web3.get_traces_for_address(
+            address.parse().unwrap(),
+            Uint256::from_u64(10),
+            Uint256::from_u64(12),
+        )
+        .await,
+        Err(Web3Error::UnsupportedMethod(_))
+    ));
+}
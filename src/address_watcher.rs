@@ -0,0 +1,262 @@
+//! A polling watcher for the balance and nonce of a single address, intended for wallet
+//! backends that need to react promptly to incoming funds and outgoing transactions
+use crate::event_utils::address_to_event;
+use crate::hex::quantity_to_hex;
+use crate::time::sleep as delay_for;
+use crate::types::Log;
+use crate::{client::Web3, jsonrpc::error::Web3Error};
+use clarity::{u256, Address, Uint256};
+use std::time::Duration;
+
+/// The event matched by `AddressWatcher::with_erc20_tokens`
+const TRANSFER_EVENT: &str = "Transfer(address,address,uint256)";
+
+/// A change to a watched address detected between two polls, created by `AddressWatcher`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressChange {
+    pub address: Address,
+    /// the block the new balance and nonce were read at
+    pub block_number: Uint256,
+    pub old_balance: Uint256,
+    pub new_balance: Uint256,
+    pub old_nonce: Uint256,
+    pub new_nonce: Uint256,
+    /// incoming ERC-20 Transfer logs of the watched tokens in the blocks since the last poll
+    pub erc20_transfers: Vec<Log>,
+}
+
+impl AddressChange {
+    pub fn balance_changed(&self) -> bool {
+        self.old_balance != self.new_balance
+    }
+
+    /// True when the address sent a transaction, or several, since the last poll
+    pub fn nonce_changed(&self) -> bool {
+        self.old_nonce != self.new_nonce
+    }
+}
+
+/// The values seen by the previous poll
+#[derive(Debug, Clone, Copy)]
+struct AddressState {
+    block_number: Uint256,
+    balance: Uint256,
+    nonce: Uint256,
+}
+
+/// Yields an `AddressChange` whenever the balance or nonce of an address changes, or it
+/// receives a transfer of a watched ERC-20 token, created with `Web3::watch_address`. The
+/// values when the watcher is first polled are the baseline and are not emitted. The stream
+/// never terminates, errors from the node are logged and retried on the next poll.
+pub struct AddressWatcher {
+    web3: Web3,
+    address: Address,
+    poll_interval: Duration,
+    tokens: Vec<Address>,
+    state: Option<AddressState>,
+}
+
+impl AddressWatcher {
+    /// Also looks for incoming Transfer events of any of `tokens` in each poll, which are
+    /// reported in `AddressChange::erc20_transfers`
+    pub fn with_erc20_tokens(mut self, tokens: Vec<Address>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Waits for and returns the next change
+    pub async fn next(&mut self) -> AddressChange {
+        loop {
+            match self.poll().await {
+                Ok(Some(change)) => return change,
+                Ok(None) => {}
+                Err(e) => warn!("Address watcher failed to poll {} {:?}", self.address, e),
+            }
+            delay_for(self.poll_interval).await;
+        }
+    }
+
+    /// Reads the balance and nonce at the latest block, and the transfers in blocks since the
+    /// last poll, returning a change if anything differs from the last poll
+    async fn poll(&mut self) -> Result<Option<AddressChange>, Web3Error> {
+        let block_number = self.web3.eth_block_number().await?;
+        // nothing can have changed, or the node is behind the one used for the last poll
+        if let Some(state) = self.state {
+            if block_number <= state.block_number {
+                return Ok(None);
+            }
+        }
+
+        let transfers = async {
+            match self.state {
+                Some(state) if !self.tokens.is_empty() => {
+                    self.web3
+                        .check_for_events_filtered(
+                            state.block_number.checked_add(u256!(1)).unwrap(),
+                            Some(block_number),
+                            self.tokens.clone(),
+                            vec![TRANSFER_EVENT],
+                            [None, Some(vec![address_to_event(self.address)]), None],
+                        )
+                        .await
+                }
+                _ => Ok(Vec::new()),
+            }
+        };
+        let (balance, nonce, erc20_transfers) = tokio::try_join!(
+            self.balance_at(block_number),
+            self.nonce_at(block_number),
+            transfers
+        )?;
+
+        let old = self.state.replace(AddressState {
+            block_number,
+            balance,
+            nonce,
+        });
+        let old = match old {
+            Some(old) => old,
+            None => return Ok(None),
+        };
+        if old.balance == balance && old.nonce == nonce && erc20_transfers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(AddressChange {
+            address: self.address,
+            block_number,
+            old_balance: old.balance,
+            new_balance: balance,
+            old_nonce: old.nonce,
+            new_nonce: nonce,
+            erc20_transfers,
+        }))
+    }
+
+    async fn balance_at(&self, block_number: Uint256) -> Result<Uint256, Web3Error> {
+        self.web3
+            .jsonrpc_client
+            .request_method(
+                "eth_getBalance",
+                vec![self.address.to_string(), quantity_to_hex(block_number)],
+                self.web3.timeout,
+            )
+            .await
+    }
+
+    async fn nonce_at(&self, block_number: Uint256) -> Result<Uint256, Web3Error> {
+        self.web3
+            .jsonrpc_client
+            .request_method(
+                "eth_getTransactionCount",
+                vec![self.address.to_string(), quantity_to_hex(block_number)],
+                self.web3.timeout,
+            )
+            .await
+    }
+}
+
+impl Web3 {
+    /// Returns a watcher that polls the balance and nonce of `address` every `poll_interval`,
+    /// see `AddressWatcher`
+    pub fn watch_address(&self, address: Address, poll_interval: Duration) -> AddressWatcher {
+        AddressWatcher {
+            web3: self.clone(),
+            address,
+            poll_interval,
+            tokens: Vec::new(),
+            state: None,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_watch_address() {
+    use crate::jsonrpc::mock::MockNode;
+    use clarity::utils::bytes_to_hex_str;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let address: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let token: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let head = AtomicU64::new(0);
+    let block = |params: &serde_json::Value| {
+        u64::from_str_radix(params[1].as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+    };
+    let topic = |bytes: &[u8]| format!("0x{}", bytes_to_hex_str(bytes));
+    let recipient = topic(&address_to_event(address));
+    let transfer = json!({
+        "address": token.to_string(),
+        "topics": [
+            topic(&clarity::abi::derive_signature(TRANSFER_EVENT).unwrap()),
+            topic(&[0; 32]),
+            recipient,
+        ],
+        "data": format!("0x{:064x}", 5),
+        "blockNumber": "0x6",
+    });
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        // every poll sees a new block
+        "eth_blockNumber" => Ok(json!(format!(
+            "{:#x}",
+            head.fetch_add(1, Ordering::SeqCst) + 1
+        ))),
+        "eth_getBalance" => match block(params) {
+            2 => Err((-32000, "header not found".to_string())),
+            1 | 3 => Ok(json!("0x64")),
+            _ => Ok(json!("0x96")),
+        },
+        "eth_getTransactionCount" => match block(params) {
+            n if n >= 5 => Ok(json!("0x1")),
+            _ => Ok(json!("0x0")),
+        },
+        "eth_getLogs" if params[0]["toBlock"] == json!("0x6") => Ok(json!([transfer.clone()])),
+        "eth_getLogs" => Ok(json!([])),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let mut watcher = web3
+        .watch_address(address, Duration::from_millis(10))
+        .with_erc20_tokens(vec![token]);
+
+    // block 1 is the baseline, block 2 fails and block 3 is unchanged
+    let change = watcher.next().await;
+    assert_eq!(change.block_number, u256!(4));
+    assert_eq!(
+        (change.old_balance, change.new_balance),
+        (u256!(100), u256!(150))
+    );
+    assert!(change.balance_changed());
+    assert!(!change.nonce_changed());
+    assert!(change.erc20_transfers.is_empty());
+    // the failed poll did not skip the transfer query over its block
+    let ranges: Vec<_> = node
+        .params("eth_getLogs")
+        .iter()
+        .map(|p| (p[0]["fromBlock"].clone(), p[0]["toBlock"].clone()))
+        .collect();
+    assert!(ranges.contains(&(json!("0x2"), json!("0x3"))));
+    assert_eq!(ranges.last(), Some(&(json!("0x4"), json!("0x4"))));
+
+    let change = watcher.next().await;
+    assert_eq!(change.block_number, u256!(5));
+    assert!(!change.balance_changed());
+    assert_eq!((change.old_nonce, change.new_nonce), (u256!(0), u256!(1)));
+
+    let change = watcher.next().await;
+    assert_eq!(change.block_number, u256!(6));
+    assert!(!change.balance_changed() && !change.nonce_changed());
+    assert_eq!(change.erc20_transfers.len(), 1);
+    assert_eq!(change.erc20_transfers[0].address, token);
+    let filter = &node.params("eth_getLogs").last().unwrap()[0];
+    assert_eq!(
+        filter["address"][0].as_str().unwrap().to_lowercase(),
+        token.to_string().to_lowercase()
+    );
+    assert_eq!(filter["topics"][2], json!([recipient]));
+    assert_eq!(filter["topics"][1], json!(null));
+}
@@ -1,16 +1,15 @@
 use std::time::Duration;
 
 // Performs interactions with AMMs (Automated Market Makers) on ethereum
-use crate::{client::Web3, jsonrpc::error::Web3Error, types::SendTxOption};
+use crate::time::timeout as future_timeout;
+use crate::{client::Web3, hash::TxHash, jsonrpc::error::Web3Error, types::SendTxOption};
 use clarity::u256;
-use clarity::utils::display_uint256_as_address;
 use clarity::{
     abi::{encode_call, Token},
     constants::{TT160M1, TT24M1},
     Address, PrivateKey, Uint256,
 };
 use num::BigUint;
-use tokio::time::timeout as future_timeout;
 
 /// Default padding multiplied to uniswap exchange gas limit values due to variablity of gas limit values
 /// between iterations
@@ -174,7 +173,7 @@ impl Web3 {
         uniswap_router: Option<Address>, // The default router will be used if None is provided
         options: Option<Vec<SendTxOption>>, // Options for send_transaction
         wait_timeout: Option<Duration>,
-    ) -> Result<Uint256, Web3Error> {
+    ) -> Result<TxHash, Web3Error> {
         let fee_uint24 = fee_uint24.unwrap_or_else(|| u256!(3000));
         if bad_fee(fee_uint24) {
             return Err(Web3Error::BadInput(
@@ -273,10 +272,7 @@ impl Web3 {
                 options,
             )
             .await?;
-        debug!(
-            "txid for uniswap swap is {}",
-            display_uint256_as_address(txid)
-        );
+        debug!("txid for uniswap swap is {}", txid);
         if let Some(timeout) = wait_timeout {
             future_timeout(timeout, self.wait_for_transaction(txid, timeout, None)).await??;
         }
@@ -339,7 +335,7 @@ impl Web3 {
         uniswap_router: Option<Address>, // the default router will be used if none is provided
         options: Option<Vec<SendTxOption>>, // options for send_transaction
         wait_timeout: Option<Duration>,
-    ) -> Result<Uint256, Web3Error> {
+    ) -> Result<TxHash, Web3Error> {
         let token_in = *WETH_CONTRACT_ADDRESS; // Uniswap requires WETH to be one of the swap tokens for ETH swaps
         let fee_uint24 = fee_uint24.unwrap_or_else(|| u256!(3000));
         if bad_fee(fee_uint24) {
@@ -417,10 +413,7 @@ impl Web3 {
                 options,
             )
             .await?;
-        debug!(
-            "txid for uniswap swap is {}",
-            display_uint256_as_address(txid)
-        );
+        debug!("txid for uniswap swap is {}", txid);
         if let Some(timeout) = wait_timeout {
             future_timeout(timeout, self.wait_for_transaction(txid, timeout, None)).await??;
         }
@@ -555,7 +548,7 @@ async fn swap_hardhat_test() {
         .checked_add(Uint256::from_u64(10u64 * 60u64 * 100000u64))
         .unwrap();
 
-    let success = web3.wrap_eth(amount, miner_private_key, None, None).await;
+    let success = web3.wrap_eth(amount, &miner_private_key, None, None).await;
     if let Ok(b) = success {
         info!("Wrapped eth: {}", b);
     } else {
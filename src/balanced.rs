@@ -0,0 +1,307 @@
+//! Spreading requests over several equally good endpoints, see `BalancedClient`
+use crate::client::Web3;
+use crate::jsonrpc::client::HttpClient;
+use crate::jsonrpc::error::Web3Error;
+use crate::time::Instant;
+use hyper::body::Bytes;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// After this many consecutive failed requests a backend is taken out of rotation
+pub const MAX_CONSECUTIVE_FAILURES: u64 = 3;
+
+/// How long an unhealthy backend is left out of rotation before it is tried again
+pub const UNHEALTHY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Methods that create server side state, mapped to the prefix of the pin for their result
+const PINNING_METHODS: &[(&str, &str)] = &[
+    ("eth_newFilter", "filter"),
+    ("eth_newBlockFilter", "filter"),
+    ("eth_newPendingTransactionFilter", "filter"),
+    ("eth_sendRawTransaction", "tx"),
+];
+
+/// Methods that refer to state created by a pinning method through their first param
+const PINNED_METHODS: &[(&str, &str)] = &[
+    ("eth_getFilterChanges", "filter"),
+    ("eth_getFilterLogs", "filter"),
+    ("eth_uninstallFilter", "filter"),
+    ("eth_getTransactionReceipt", "tx"),
+    ("eth_getTransactionByHash", "tx"),
+];
+
+#[derive(Debug)]
+struct Backend {
+    url: String,
+    requests: AtomicU64,
+    failures: AtomicU64,
+    consecutive_failures: AtomicU64,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Backend {
+    fn is_healthy(&self) -> bool {
+        match *self.unhealthy_until.lock().expect("backend poisoned") {
+            Some(until) => Instant::now() >= until,
+            None => true,
+        }
+    }
+}
+
+/// The request counts of one backend of a `BalancedClient`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendStats {
+    pub url: String,
+    pub requests: u64,
+    /// requests that timed out or failed at the network level
+    pub failures: u64,
+    /// false while the backend is out of rotation after consecutive failures
+    pub healthy: bool,
+}
+
+/// Picks the backend for each request of a balanced `HttpClient`
+#[derive(Debug)]
+pub(crate) struct Balancer {
+    backends: Vec<Backend>,
+    next: AtomicUsize,
+    /// filter ids and transaction hashes mapped to the backend that knows about them
+    pins: Mutex<HashMap<String, usize>>,
+}
+
+/// Normalizes a hex quantity or hash so that "0x02" and "0x2" pin to the same backend
+fn pin_key(prefix: &str, value: &Value) -> Option<String> {
+    let hex = value
+        .as_str()?
+        .trim_start_matches("0x")
+        .trim_start_matches('0');
+    Some(format!("{}:{}", prefix, hex.to_lowercase()))
+}
+
+impl Balancer {
+    pub(crate) fn new(urls: &[&str]) -> Balancer {
+        assert!(!urls.is_empty(), "a balanced client needs at least one url");
+        Balancer {
+            backends: urls
+                .iter()
+                .map(|url| Backend {
+                    url: url.to_string(),
+                    requests: AtomicU64::new(0),
+                    failures: AtomicU64::new(0),
+                    consecutive_failures: AtomicU64::new(0),
+                    unhealthy_until: Mutex::new(None),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            pins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn url(&self, backend: usize) -> &str {
+        &self.backends[backend].url
+    }
+
+    /// Picks the backend a request should be sent to, the pinned backend for requests about
+    /// a filter or transaction created through this client and otherwise the next healthy
+    /// backend in round robin order
+    pub(crate) fn select(&self, method: &str, params: &Value) -> usize {
+        let pinned = PINNED_METHODS
+            .iter()
+            .find(|(m, _)| *m == method)
+            .and_then(|(_, prefix)| pin_key(prefix, &params[0]))
+            .and_then(|key| self.pins.lock().expect("pins poisoned").get(&key).copied());
+        let backend = match pinned {
+            Some(backend) => backend,
+            None => {
+                let n = self.backends.len();
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                // if every backend is unhealthy the round robin choice is as good as any
+                (0..n)
+                    .map(|i| (start + i) % n)
+                    .find(|i| self.backends[*i].is_healthy())
+                    .unwrap_or(start % n)
+            }
+        };
+        self.backends[backend]
+            .requests
+            .fetch_add(1, Ordering::Relaxed);
+        backend
+    }
+
+    /// Updates the health of `backend` and pins any state the request created to it
+    pub(crate) fn record(
+        &self,
+        backend: usize,
+        method: &str,
+        params: &Value,
+        result: &Result<Bytes, Web3Error>,
+    ) {
+        let b = &self.backends[backend];
+        let body = match result {
+            Err(Web3Error::TransportError(_)) | Err(Web3Error::Timeout) => {
+                b.failures.fetch_add(1, Ordering::Relaxed);
+                let failures = b.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+                if failures >= MAX_CONSECUTIVE_FAILURES {
+                    warn!(
+                        "Taking {} out of rotation after {} failures",
+                        b.url, failures
+                    );
+                    *b.unhealthy_until.lock().expect("backend poisoned") =
+                        Some(Instant::now() + UNHEALTHY_BACKOFF);
+                }
+                return;
+            }
+            Err(_) => return,
+            Ok(body) => body,
+        };
+        b.consecutive_failures.store(0, Ordering::Relaxed);
+        *b.unhealthy_until.lock().expect("backend poisoned") = None;
+
+        let pinning = PINNING_METHODS.iter().find(|(m, _)| *m == method);
+        // the pin is no longer needed once the filter is gone or the transaction is mined
+        let unpinning = matches!(method, "eth_uninstallFilter" | "eth_getTransactionReceipt");
+        if pinning.is_none() && !unpinning {
+            return;
+        }
+        let result = match serde_json::from_slice::<Value>(body) {
+            Ok(response) => response["result"].clone(),
+            Err(_) => return,
+        };
+        let mut pins = self.pins.lock().expect("pins poisoned");
+        if let Some(key) = pinning.and_then(|(_, prefix)| pin_key(prefix, &result)) {
+            pins.insert(key, backend);
+        }
+        if unpinning && !result.is_null() {
+            let prefix = if method == "eth_uninstallFilter" {
+                "filter"
+            } else {
+                "tx"
+            };
+            if let Some(key) = pin_key(prefix, &params[0]) {
+                pins.remove(&key);
+            }
+        }
+    }
+
+    fn stats(&self) -> Vec<BackendStats> {
+        self.backends
+            .iter()
+            .map(|b| BackendStats {
+                url: b.url.clone(),
+                requests: b.requests.load(Ordering::Relaxed),
+                failures: b.failures.load(Ordering::Relaxed),
+                healthy: b.is_healthy(),
+            })
+            .collect()
+    }
+}
+
+/// A `Web3` that spreads its requests round robin over several endpoints. Requests about
+/// filters or transactions created through the client go to the backend that created them,
+/// since other nodes would not know about the filter or might not have the transaction yet.
+/// Backends that fail repeatedly are left out of rotation for `UNHEALTHY_BACKOFF`. This
+/// derefs to `Web3` so every method is available directly.
+#[derive(Clone)]
+pub struct BalancedClient {
+    web3: Web3,
+    balancer: Arc<Balancer>,
+}
+
+impl BalancedClient {
+    /// Panics if `urls` is empty
+    pub fn new(urls: &[&str], timeout: Duration) -> BalancedClient {
+        let balancer = Arc::new(Balancer::new(urls));
+        let client = HttpClient::new(urls[0]).with_balancer(balancer.clone());
+        BalancedClient {
+            web3: Web3::with_client(urls[0], client, timeout),
+            balancer,
+        }
+    }
+
+    pub fn web3(&self) -> &Web3 {
+        &self.web3
+    }
+
+    /// The request counts and health of each backend, in the order of the urls
+    pub fn backend_stats(&self) -> Vec<BackendStats> {
+        self.balancer.stats()
+    }
+}
+
+impl Deref for BalancedClient {
+    type Target = Web3;
+
+    fn deref(&self) -> &Web3 {
+        &self.web3
+    }
+}
+
+#[tokio::test]
+async fn test_balanced_client() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let handler = |method: &str, _: &Value| match method {
+        "eth_blockNumber" => Ok(json!("0x10")),
+        "eth_newBlockFilter" => Ok(json!("0x02")),
+        "eth_getFilterChanges" => Ok(json!([])),
+        _ => Err((-32601, "Method not found".to_string())),
+    };
+    let a = MockNode::start(handler);
+    let b = MockNode::start(handler);
+    let client = BalancedClient::new(&[&a.url, &b.url], Duration::from_secs(5));
+
+    for _ in 0..10 {
+        client.eth_synced_block_number().await.unwrap();
+    }
+    assert_eq!(a.calls("eth_blockNumber"), 5);
+    assert_eq!(b.calls("eth_blockNumber"), 5);
+
+    // the filter changes always go to the backend that created the filter
+    let filter = client.eth_new_block_filter().await.unwrap();
+    for _ in 0..4 {
+        client.eth_get_block_filter_changes(filter).await.unwrap();
+    }
+    let (creator, other) = if a.calls("eth_newBlockFilter") == 1 {
+        (&a, &b)
+    } else {
+        (&b, &a)
+    };
+    assert_eq!(creator.calls("eth_getFilterChanges"), 4);
+    assert_eq!(other.calls("eth_getFilterChanges"), 0);
+
+    let stats = client.backend_stats();
+    assert_eq!(stats.iter().map(|s| s.requests).sum::<u64>(), 15);
+    assert!(stats.iter().all(|s| s.healthy && s.failures == 0));
+}
+
+#[tokio::test]
+async fn test_balanced_client_unhealthy() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!("0x10")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    // nothing listens on port 1 so every request to it fails at the network level
+    let client = BalancedClient::new(&["http://127.0.0.1:1", &node.url], Duration::from_secs(5));
+
+    let mut failures = 0;
+    for _ in 0..20 {
+        if client.eth_synced_block_number().await.is_err() {
+            failures += 1;
+        }
+    }
+    assert_eq!(failures, MAX_CONSECUTIVE_FAILURES);
+    assert_eq!(
+        node.calls("eth_blockNumber"),
+        20 - MAX_CONSECUTIVE_FAILURES as usize
+    );
+    let stats = client.backend_stats();
+    assert!(!stats[0].healthy);
+    assert_eq!(stats[0].failures, MAX_CONSECUTIVE_FAILURES);
+}
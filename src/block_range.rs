@@ -0,0 +1,160 @@
+//! Downloading ranges of blocks with bounded concurrency, for backfilling indexers
+use crate::client::Web3;
+use crate::jsonrpc::error::Web3Error;
+use crate::time::sleep as delay_for;
+use crate::types::{saturating_u64, Block, BlockId};
+use clarity::Uint256;
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// How many times fetching a block is retried before it is reported as an error
+pub const BLOCK_FETCH_RETRIES: u32 = 3;
+
+/// Yields the blocks of a range in ascending order, created with `Web3::block_range_stream`.
+/// At most `concurrency` blocks are requested or buffered at any time.
+pub struct BlockRangeStream {
+    web3: Web3,
+    concurrency: usize,
+    remaining: RangeInclusive<u64>,
+    in_flight: VecDeque<(u64, JoinHandle<Result<Block, Web3Error>>)>,
+}
+
+impl BlockRangeStream {
+    /// Returns the next block number along with the block or the error that prevented
+    /// fetching it, or `None` once the whole range has been returned
+    pub async fn next(&mut self) -> Option<(Uint256, Result<Block, Web3Error>)> {
+        self.fill();
+        let (number, handle) = self.in_flight.pop_front()?;
+        let res = match handle.await {
+            Ok(res) => res,
+            Err(e) => Err(Web3Error::BadResponse(format!("{}", e))),
+        };
+        self.fill();
+        Some((Uint256::from_u64(number), res))
+    }
+
+    fn fill(&mut self) {
+        while self.in_flight.len() < self.concurrency {
+            let number = match self.remaining.next() {
+                Some(number) => number,
+                None => return,
+            };
+            let web3 = self.web3.clone();
+            self.in_flight.push_back((
+                number,
+                tokio::spawn(async move { web3.get_block_with_retries(number).await }),
+            ));
+        }
+    }
+}
+
+impl Drop for BlockRangeStream {
+    fn drop(&mut self) {
+        for (_, handle) in &self.in_flight {
+            handle.abort();
+        }
+    }
+}
+
+impl Web3 {
+    async fn get_block_with_retries(&self, number: u64) -> Result<Block, Web3Error> {
+        let mut attempt = 0;
+        loop {
+            let res = match self
+                .eth_get_block_by_id(BlockId::Number(Uint256::from_u64(number)))
+                .await
+            {
+                Ok(Some(block)) => return Ok(block),
+                // the node may not have caught up to the block yet
+                Ok(None) => Err(Web3Error::BadResponse(format!(
+                    "Block {} does not exist",
+                    number
+                ))),
+                Err(e) if e.is_retriable() => Err(e),
+                Err(e) => return Err(e),
+            };
+            if attempt >= BLOCK_FETCH_RETRIES {
+                return res;
+            }
+            attempt += 1;
+            delay_for(Duration::from_millis(100 << attempt)).await;
+        }
+    }
+
+    /// Returns a stream of the blocks from `start` to `end` inclusive, fetching up to
+    /// `concurrency` blocks at once, see `BlockRangeStream`. The fetches are spawned as tokio
+    /// tasks, so this needs a tokio runtime and is not available in the browser.
+    pub fn block_range_stream(
+        &self,
+        start: Uint256,
+        end: Uint256,
+        concurrency: usize,
+    ) -> BlockRangeStream {
+        BlockRangeStream {
+            web3: self.clone(),
+            concurrency: concurrency.max(1),
+            remaining: saturating_u64(start)..=saturating_u64(end),
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    /// Fetches the blocks from `start` to `end` inclusive, up to `concurrency` at once. The
+    /// results are in block order and a block that could not be fetched after
+    /// `BLOCK_FETCH_RETRIES` retries is reported as an error at its index instead of failing
+    /// the whole range.
+    pub async fn get_block_range(
+        &self,
+        start: Uint256,
+        end: Uint256,
+        concurrency: usize,
+    ) -> Vec<Result<Block, Web3Error>> {
+        let mut stream = self.block_range_stream(start, end, concurrency);
+        let mut res = Vec::new();
+        while let Some((_, block)) = stream.next().await {
+            res.push(block);
+        }
+        res
+    }
+}
+
+#[tokio::test]
+async fn test_get_block_range() {
+    use crate::jsonrpc::mock::{empty_block, MockNode};
+    use serde_json::{json, Value};
+
+    let block = empty_block(0);
+    let node = MockNode::start(move |method, params| match method {
+        // block 13 is permanently missing
+        "eth_getBlockByNumber" if params[0] == json!("0xd") => Ok(Value::Null),
+        "eth_getBlockByNumber" => {
+            let mut block = block.clone();
+            block["number"] = params[0].clone();
+            Ok(block)
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let blocks = web3
+        .get_block_range(Uint256::from_u64(10), Uint256::from_u64(29), 4)
+        .await;
+    assert_eq!(blocks.len(), 20);
+    for (i, block) in blocks.iter().enumerate() {
+        match block {
+            Ok(block) => assert_eq!(block.number, Uint256::from_u64(10 + i as u64)),
+            Err(_) => assert_eq!(i, 3),
+        }
+    }
+    assert!(blocks[3].is_err());
+    assert_eq!(
+        node.calls("eth_getBlockByNumber"),
+        20 + BLOCK_FETCH_RETRIES as usize
+    );
+
+    let mut stream = web3.block_range_stream(Uint256::from_u64(5), Uint256::from_u64(6), 8);
+    assert_eq!(stream.next().await.unwrap().0, Uint256::from_u64(5));
+    assert_eq!(stream.next().await.unwrap().0, Uint256::from_u64(6));
+    assert!(stream.next().await.is_none());
+}
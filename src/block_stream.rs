@@ -0,0 +1,166 @@
+//! A polling stream of new blocks that detects chain reorganizations by checking that every
+//! block builds on the previously emitted one
+use crate::time::sleep as delay_for;
+use crate::{client::Web3, jsonrpc::error::Web3Error, types::Block};
+use clarity::{u256, Uint256};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// How many emitted blocks are remembered for finding the common ancestor of a reorg,
+/// reorgs deeper than this are reported starting from the oldest remembered block
+pub const MAX_REORG_DEPTH: usize = 128;
+
+/// An item produced by a `BlockStream`
+#[derive(Debug, Clone)]
+pub enum BlockStreamItem {
+    /// A new block that builds on the previously emitted block
+    Block(Block),
+    /// The chain was reorganized, `reverted` are previously emitted blocks that are no longer
+    /// part of the canonical chain and `new` are their replacements, both in ascending order
+    Reorg {
+        reverted: Vec<Block>,
+        new: Vec<Block>,
+    },
+}
+
+/// Yields each new block exactly once and in order, created with `Web3::block_stream`.
+/// The stream starts at the current head when it is first polled and never terminates,
+/// errors from the node are logged and retried on the next poll.
+pub struct BlockStream {
+    web3: Web3,
+    poll_interval: Duration,
+    /// recently emitted blocks in ascending order, the last entry is the current tip
+    history: VecDeque<Block>,
+    queue: VecDeque<BlockStreamItem>,
+}
+
+impl BlockStream {
+    /// Waits for and returns the next item
+    pub async fn next(&mut self) -> BlockStreamItem {
+        loop {
+            if let Some(item) = self.queue.pop_front() {
+                return item;
+            }
+            if let Err(e) = self.poll().await {
+                warn!("Block stream failed to poll for new blocks {:?}", e);
+            }
+            if self.queue.is_empty() {
+                delay_for(self.poll_interval).await;
+            }
+        }
+    }
+
+    /// The most recently emitted block, if any
+    pub fn tip(&self) -> Option<&Block> {
+        self.history.back()
+    }
+
+    fn remember(&mut self, block: Block) {
+        self.history.push_back(block);
+        while self.history.len() > MAX_REORG_DEPTH {
+            self.history.pop_front();
+        }
+    }
+
+    async fn get_block(&self, number: Uint256) -> Result<Block, Web3Error> {
+        match self.web3.eth_get_block_by_number(number).await? {
+            Some(block) => Ok(block),
+            // the head was already fetched, so a node behind a load balancer is lagging
+            None => Err(Web3Error::BadResponse(format!(
+                "Block {} below the head does not exist",
+                number
+            ))),
+        }
+    }
+
+    /// Fetches any blocks past the current tip and queues them, the state is only
+    /// updated after each block has been fetched so an error can be retried safely
+    async fn poll(&mut self) -> Result<(), Web3Error> {
+        let head = self.web3.eth_block_number().await?;
+        let mut next = match self.history.back() {
+            Some(tip) => tip.number.checked_add(u256!(1)).unwrap(),
+            None => {
+                let block = self.get_block(head).await?;
+                self.remember(block.clone());
+                self.queue.push_back(BlockStreamItem::Block(block));
+                return Ok(());
+            }
+        };
+
+        while next <= head {
+            let block = self.get_block(next).await?;
+            next = block.number.checked_add(u256!(1)).unwrap();
+            let tip = self.history.back().unwrap();
+            if block.parent_hash == tip.hash {
+                self.remember(block.clone());
+                self.queue.push_back(BlockStreamItem::Block(block));
+                continue;
+            }
+
+            // walk back until the new chain connects to a block we have emitted
+            let mut depth = 0;
+            let mut new = vec![block];
+            loop {
+                let index = self.history.len() - depth;
+                if index == 0 {
+                    warn!(
+                        "Reorg deeper than the {} blocks remembered by the block stream",
+                        MAX_REORG_DEPTH
+                    );
+                    break;
+                }
+                if self.history[index - 1].hash == new[0].parent_hash {
+                    break;
+                }
+                depth += 1;
+                // the chains must share the genesis block, a node that disagrees is broken
+                let parent_number =
+                    match new[0].number.checked_sub(u256!(1)) {
+                        Some(number) => number,
+                        None => return Err(Web3Error::BadResponse(
+                            "Reorg reached the genesis block without joining the stream's chain"
+                                .to_string(),
+                        )),
+                    };
+                let parent = self.get_block(parent_number).await?;
+                new.insert(0, parent);
+            }
+
+            let reverted: Vec<Block> = self.history.split_off(self.history.len() - depth).into();
+            for block in new.iter() {
+                self.remember(block.clone());
+            }
+            self.queue
+                .push_back(BlockStreamItem::Reorg { reverted, new });
+        }
+        Ok(())
+    }
+}
+
+impl Web3 {
+    /// Returns a stream that polls for new blocks every `poll_interval`, see `BlockStream`
+    pub fn block_stream(&self, poll_interval: Duration) -> BlockStream {
+        BlockStream {
+            web3: self.clone(),
+            poll_interval,
+            history: VecDeque::new(),
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_block_stream() {
+    let web3 = Web3::new("https://eth.althea.net", Duration::from_secs(5));
+    let mut stream = web3.block_stream(Duration::from_secs(1));
+
+    let first = match stream.next().await {
+        BlockStreamItem::Block(block) => block,
+        BlockStreamItem::Reorg { .. } => panic!("First item can not be a reorg"),
+    };
+    match stream.next().await {
+        BlockStreamItem::Block(block) => assert_eq!(block.parent_hash, first.hash),
+        BlockStreamItem::Reorg { new, .. } => assert!(new[0].number <= first.number),
+    }
+}
@@ -0,0 +1,268 @@
+//! Converting between wall clock time and block numbers
+use crate::client::Web3;
+use crate::jsonrpc::error::Web3Error;
+use crate::time::Instant;
+use crate::types::{saturating_u64, Block, BlockId, ConciseBlock};
+use clarity::Uint256;
+use std::time::Duration;
+
+/// The number of known block timestamps kept between searches before the cache is cleared
+const MAX_CACHED_TIMESTAMPS: usize = 4096;
+
+/// The number of blocks `blocks_until` and `duration_for_blocks` average the block time over
+pub const BLOCK_TIME_SAMPLE_BLOCKS: u64 = 1000;
+
+/// How long the block time estimate used by `blocks_until` and `duration_for_blocks` is
+/// reused before it is estimated again
+pub const BLOCK_TIME_CACHE_TTL: Duration = Duration::from_secs(600);
+
+impl Web3 {
+    async fn concise_block(&self, block: BlockId) -> Result<ConciseBlock, Web3Error> {
+        self.eth_get_concise_block_by_id(block)
+            .await?
+            .ok_or_else(|| Web3Error::BadResponse(format!("Block {:?} does not exist", block)))
+    }
+
+    /// Returns the timestamp of block `number`, recording it for later searches
+    async fn block_timestamp(&self, number: u64) -> Result<u64, Web3Error> {
+        if let Some(timestamp) = self.cached_timestamp(number) {
+            return Ok(timestamp);
+        }
+        let block = self
+            .concise_block(BlockId::Number(Uint256::from_u64(number)))
+            .await?;
+        let timestamp = block.timestamp_secs();
+        let mut cache = self.block_timestamps.lock().expect("timestamps poisoned");
+        if cache.len() >= MAX_CACHED_TIMESTAMPS {
+            cache.clear();
+        }
+        cache.insert(number, timestamp);
+        Ok(timestamp)
+    }
+
+    fn cached_timestamp(&self, number: u64) -> Option<u64> {
+        self.block_timestamps
+            .lock()
+            .expect("timestamps poisoned")
+            .get(&number)
+            .copied()
+    }
+
+    /// Returns the latest block with a timestamp at or before `target`, in seconds since the
+    /// unix epoch, by binary searching block numbers. Timestamps seen by earlier searches are
+    /// reused to narrow the search, so repeated searches take only a few requests.
+    pub async fn get_block_by_timestamp(&self, target: u64) -> Result<Block, Web3Error> {
+        let head = self.concise_block(BlockId::Latest).await?;
+        let head_number = saturating_u64(head.number);
+        let (mut lo, mut hi) = if head.timestamp_secs() <= target {
+            (head_number, head_number)
+        } else {
+            // start from the tightest bounds known from earlier searches
+            let (mut lo, mut hi) = (None, head_number);
+            for (number, timestamp) in self
+                .block_timestamps
+                .lock()
+                .expect("timestamps poisoned")
+                .range(..head_number)
+            {
+                if *timestamp <= target {
+                    lo = Some(*number);
+                } else {
+                    hi = hi.min(*number);
+                    break;
+                }
+            }
+            let lo = match lo {
+                Some(lo) => lo,
+                None if self.block_timestamp(0).await? <= target => 0,
+                None => {
+                    return Err(Web3Error::BadInput(format!(
+                        "Timestamp {} is before the genesis block",
+                        target
+                    )))
+                }
+            };
+            (lo, hi)
+        };
+        // invariant: timestamp(lo) <= target < timestamp(hi) unless lo == hi == head
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if self.block_timestamp(mid).await? <= target {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        self.eth_get_block_by_id(BlockId::Number(Uint256::from_u64(lo)))
+            .await?
+            .ok_or_else(|| Web3Error::BadResponse(format!("Block {} does not exist", lo)))
+    }
+
+    /// Estimates the average time between blocks over the last `sample_blocks` blocks, or
+    /// all blocks if the chain is shorter than that. Timestamps only have a resolution of a
+    /// second, so chains with sub-second blocks need a sample spanning many seconds. Estimates
+    /// over `BLOCK_TIME_SAMPLE_BLOCKS` are reused by `blocks_until` and `duration_for_blocks`.
+    pub async fn estimate_block_time(&self, sample_blocks: u64) -> Result<Duration, Web3Error> {
+        let head = self.concise_block(BlockId::Latest).await?;
+        let head_number = saturating_u64(head.number);
+        let is_default_sample = sample_blocks == BLOCK_TIME_SAMPLE_BLOCKS;
+        let sample_blocks = sample_blocks.min(head_number);
+        if sample_blocks == 0 {
+            return Err(Web3Error::BadInput(
+                "Can not estimate the block time without at least two blocks".to_string(),
+            ));
+        }
+        let start = self
+            .concise_block(BlockId::Number(Uint256::from_u64(
+                head_number - sample_blocks,
+            )))
+            .await?;
+        // timestamps are not guaranteed to strictly increase, especially near genesis
+        let elapsed = head.timestamp_secs().saturating_sub(start.timestamp_secs());
+        if elapsed == 0 {
+            return Err(Web3Error::BadResponse(format!(
+                "Block timestamps did not advance over the last {} blocks",
+                sample_blocks
+            )));
+        }
+        let block_time = Duration::from_secs_f64(elapsed as f64 / sample_blocks as f64);
+        // estimates over other samples would change the results of the cached helpers
+        if is_default_sample {
+            *self.block_time_estimate.lock().expect("estimate poisoned") =
+                Some((Instant::now(), block_time));
+        }
+        Ok(block_time)
+    }
+
+    /// The block time estimated over `BLOCK_TIME_SAMPLE_BLOCKS`, reusing the last estimate
+    /// for up to `BLOCK_TIME_CACHE_TTL`
+    async fn cached_block_time(&self) -> Result<Duration, Web3Error> {
+        let cached = *self.block_time_estimate.lock().expect("estimate poisoned");
+        match cached {
+            Some((at, block_time)) if at.elapsed() < BLOCK_TIME_CACHE_TTL => Ok(block_time),
+            _ => self.estimate_block_time(BLOCK_TIME_SAMPLE_BLOCKS).await,
+        }
+    }
+
+    /// Estimates how many blocks will be produced in `duration`, rounding up
+    pub async fn blocks_until(&self, duration: Duration) -> Result<u64, Web3Error> {
+        let block_time = self.cached_block_time().await?;
+        Ok((duration.as_secs_f64() / block_time.as_secs_f64()).ceil() as u64)
+    }
+
+    /// Estimates how long it will take for `blocks` blocks to be produced, saturating at
+    /// `Duration::MAX`
+    pub async fn duration_for_blocks(&self, blocks: u64) -> Result<Duration, Web3Error> {
+        let block_time = self.cached_block_time().await?;
+        Ok(
+            Duration::try_from_secs_f64(block_time.as_secs_f64() * blocks as f64)
+                .unwrap_or(Duration::MAX),
+        )
+    }
+}
+
+#[cfg(test)]
+fn mock_block(number: u64, timestamp: u64) -> serde_json::Value {
+    use serde_json::json;
+
+    let mut block = crate::jsonrpc::mock::empty_block(number);
+    block["timestamp"] = json!(format!("{:#x}", timestamp));
+    block
+}
+
+#[tokio::test]
+async fn test_get_block_by_timestamp() {
+    use crate::jsonrpc::mock::MockNode;
+    use std::time::Duration;
+
+    // blocks every 12 seconds starting at 1_000_000 with block 10_000 as the head
+    let node = MockNode::start(|method, params| match method {
+        "eth_getBlockByNumber" => {
+            let number = match params[0].as_str().unwrap() {
+                "latest" => 10_000,
+                n => u64::from_str_radix(n.trim_start_matches("0x"), 16).unwrap(),
+            };
+            Ok(mock_block(number, 1_000_000 + 12 * number))
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let block = web3
+        .get_block_by_timestamp(1_000_000 + 12 * 1234 + 5)
+        .await
+        .unwrap();
+    assert_eq!(block.number, Uint256::from_u64(1234));
+    assert_eq!(block.timestamp_secs(), 1_000_000 + 12 * 1234);
+    let first_search = node.calls("eth_getBlockByNumber");
+    assert!(first_search <= 20, "{} requests", first_search);
+
+    // a nearby search reuses the known timestamps
+    let block = web3
+        .get_block_by_timestamp(1_000_000 + 12 * 1240)
+        .await
+        .unwrap();
+    assert_eq!(block.number, Uint256::from_u64(1240));
+    assert!(node.calls("eth_getBlockByNumber") - first_search < first_search);
+
+    assert_eq!(
+        web3.get_block_by_timestamp(u64::MAX).await.unwrap().number,
+        Uint256::from_u64(10_000)
+    );
+    assert!(matches!(
+        web3.get_block_by_timestamp(999_999).await,
+        Err(Web3Error::BadInput(_))
+    ));
+
+    // 12 second blocks overflow a Duration long before u64::MAX blocks
+    assert_eq!(
+        web3.duration_for_blocks(u64::MAX).await.unwrap(),
+        Duration::MAX
+    );
+}
+
+#[tokio::test]
+async fn test_estimate_block_time() {
+    use crate::jsonrpc::mock::MockNode;
+
+    // four blocks per second as on arbitrum, with the head at block 10_003
+    let node = MockNode::start(|method, params| match method {
+        "eth_getBlockByNumber" => {
+            let number = match params[0].as_str().unwrap() {
+                "latest" => 10_003,
+                n => u64::from_str_radix(n.trim_start_matches("0x"), 16).unwrap(),
+            };
+            Ok(mock_block(number, 1_000_000 + number / 4))
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    // timestamps do not advance over the last 2 blocks
+    assert!(web3.estimate_block_time(2).await.is_err());
+    // longer than the chain
+    assert!(web3.estimate_block_time(50_000).await.is_ok());
+    assert_eq!(
+        web3.estimate_block_time(1000).await.unwrap(),
+        Duration::from_millis(250)
+    );
+
+    let calls = node.calls("eth_getBlockByNumber");
+    assert_eq!(
+        web3.blocks_until(Duration::from_secs(600)).await.unwrap(),
+        2400
+    );
+    assert_eq!(
+        web3.duration_for_blocks(40).await.unwrap(),
+        Duration::from_secs(10)
+    );
+    // the estimate was cached by estimate_block_time
+    assert_eq!(node.calls("eth_getBlockByNumber"), calls);
+
+    // estimates over other samples are not cached, this one is slightly under 250ms
+    assert!(web3.estimate_block_time(50_000).await.unwrap() < Duration::from_millis(250));
+    assert_eq!(
+        web3.duration_for_blocks(40).await.unwrap(),
+        Duration::from_secs(10)
+    );
+}
@@ -0,0 +1,144 @@
+//! A synchronous wrapper around `Web3` for code that is not async, such as CLI tools and
+//! build scripts. `Web3Blocking` owns its own single threaded tokio runtime and blocks on
+//! the async implementations, so it must not be used from inside of an async context.
+use crate::{
+    client::Web3,
+    hash::TxHash,
+    jsonrpc::error::Web3Error,
+    types::{Data, Log, SendTxOption, TransactionRequest, TransactionResponse},
+};
+use clarity::{Address, PrivateKey, Uint256};
+use std::future::Future;
+use std::time::Duration;
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// Returns an error if called from inside of a tokio runtime, where blocking
+/// on a future would either panic or deadlock the calling runtime
+fn check_not_async() -> Result<(), Web3Error> {
+    if Handle::try_current().is_ok() {
+        return Err(Web3Error::BadInput(
+            "Web3Blocking can not be used inside of an async runtime, use Web3 instead".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// A blocking version of `Web3`, see the module level docs
+pub struct Web3Blocking {
+    web3: Web3,
+    runtime: Runtime,
+}
+
+impl Web3Blocking {
+    /// Creates a new blocking client, returning an error if called inside of an async runtime
+    pub fn new(url: &str, timeout: Duration) -> Result<Self, Web3Error> {
+        check_not_async()?;
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| Web3Error::BadInput(format!("Failed to start tokio runtime {}", e)))?;
+        Ok(Web3Blocking {
+            web3: Web3::new(url, timeout),
+            runtime,
+        })
+    }
+
+    /// Returns the underlying async client
+    pub fn inner(&self) -> &Web3 {
+        &self.web3
+    }
+
+    /// Runs a future on the owned runtime, this is the building block for all the other
+    /// methods and can be used to call any async `Web3` method without a dedicated wrapper
+    pub fn block_on<F: Future>(&self, future: F) -> Result<F::Output, Web3Error> {
+        check_not_async()?;
+        Ok(self.runtime.block_on(future))
+    }
+
+    pub fn eth_block_number(&self) -> Result<Uint256, Web3Error> {
+        self.block_on(self.web3.eth_block_number())?
+    }
+
+    pub fn eth_chainid(&self) -> Result<Option<Uint256>, Web3Error> {
+        self.block_on(self.web3.eth_chainid())?
+    }
+
+    pub fn eth_get_balance(&self, address: Address) -> Result<Uint256, Web3Error> {
+        self.block_on(self.web3.eth_get_balance(address))?
+    }
+
+    pub fn eth_get_transaction_count(&self, address: Address) -> Result<Uint256, Web3Error> {
+        self.block_on(self.web3.eth_get_transaction_count(address))?
+    }
+
+    pub fn get_erc20_balance(
+        &self,
+        erc20: Address,
+        target_address: Address,
+    ) -> Result<Uint256, Web3Error> {
+        self.block_on(self.web3.get_erc20_balance(erc20, target_address))?
+    }
+
+    pub fn eth_call(&self, transaction: TransactionRequest) -> Result<Data, Web3Error> {
+        self.block_on(self.web3.eth_call(transaction))?
+    }
+
+    /// See `Web3::send_transaction`
+    pub fn send_transaction(
+        &self,
+        to_address: Address,
+        data: impl Into<Data>,
+        value: Uint256,
+        own_address: Address,
+        secret: &PrivateKey,
+        options: Vec<SendTxOption>,
+    ) -> Result<TxHash, Web3Error> {
+        self.block_on(self.web3.send_transaction(
+            to_address,
+            data,
+            value,
+            own_address,
+            secret,
+            options,
+        ))?
+    }
+
+    /// See `Web3::wait_for_transaction`
+    pub fn wait_for_transaction(
+        &self,
+        tx_hash: impl Into<TxHash>,
+        timeout: Duration,
+        blocks_to_wait: Option<Uint256>,
+    ) -> Result<TransactionResponse, Web3Error> {
+        self.block_on(
+            self.web3
+                .wait_for_transaction(tx_hash, timeout, blocks_to_wait),
+        )?
+    }
+
+    /// See `Web3::check_for_events`
+    pub fn check_for_events(
+        &self,
+        start_block: Uint256,
+        end_block: Option<Uint256>,
+        contract_address: Vec<Address>,
+        events: Vec<&str>,
+    ) -> Result<Vec<Log>, Web3Error> {
+        self.block_on(
+            self.web3
+                .check_for_events(start_block, end_block, contract_address, events),
+        )?
+    }
+}
+
+#[tokio::test]
+async fn test_blocking_rejects_async_context() {
+    assert!(Web3Blocking::new("http://localhost:8545", Duration::from_secs(5)).is_err());
+}
+
+#[ignore]
+#[test]
+fn test_blocking_block_number() {
+    let web3 = Web3Blocking::new("https://eth.althea.net", Duration::from_secs(5)).unwrap();
+    assert!(web3.eth_block_number().unwrap() > clarity::u256!(10));
+}
@@ -0,0 +1,141 @@
+//! The logs bloom filter in block headers and receipts, which tells whether a block can
+//! contain logs from an address or with a topic without requesting the logs
+use crate::event_utils::address_to_event;
+use crate::keccak::keccak256;
+use crate::types::Data;
+use clarity::Address;
+
+/// The size of a logs bloom in bytes
+pub const BLOOM_BYTES: usize = 256;
+
+/// A 2048 bit logs bloom as defined in the yellow paper. Every log sets 3 bits for its
+/// address and each of its topics, so a bloom can have false positives but a cleared bit
+/// proves that no log in the block has that address or topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bloom(pub [u8; BLOOM_BYTES]);
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom([0; BLOOM_BYTES])
+    }
+}
+
+/// The byte index and mask of the 3 bits set for `item`, bit 0 is the lowest bit of the
+/// last byte since the bloom is a big endian number
+fn bloom_bits(item: &[u8]) -> [(usize, u8); 3] {
+    let hash = keccak256(item);
+    let mut res = [(0, 0); 3];
+    for (i, bit) in res.iter_mut().enumerate() {
+        let index = (((hash[2 * i] as usize) << 8) | hash[2 * i + 1] as usize) & 2047;
+        *bit = (BLOOM_BYTES - 1 - index / 8, 1 << (index % 8));
+    }
+    res
+}
+
+impl Bloom {
+    /// Returns None unless `bytes` is exactly `BLOOM_BYTES` long
+    pub fn from_bytes(bytes: &[u8]) -> Option<Bloom> {
+        Some(Bloom(bytes.try_into().ok()?))
+    }
+
+    /// Sets the bits for `item`, the raw address bytes or a topic
+    pub fn accrue(&mut self, item: &[u8]) {
+        for (byte, mask) in bloom_bits(item) {
+            self.0[byte] |= mask;
+        }
+    }
+
+    /// Returns false if no log that went into this bloom can have `item` as its address
+    /// or one of its topics
+    pub fn contains_input(&self, item: &[u8]) -> bool {
+        bloom_bits(item)
+            .iter()
+            .all(|(byte, mask)| self.0[*byte] & mask != 0)
+    }
+
+    pub fn contains_address(&self, address: &Address) -> bool {
+        self.contains_input(&address_to_event(*address)[12..])
+    }
+
+    pub fn contains_topic(&self, topic: &[u8; 32]) -> bool {
+        self.contains_input(topic)
+    }
+
+    /// Returns false if no log can be emitted by one of `addresses` with one of `topics`,
+    /// an empty list matches anything like in an eth_getLogs filter
+    pub fn may_contain(&self, addresses: &[Address], topics: &[[u8; 32]]) -> bool {
+        (addresses.is_empty() || addresses.iter().any(|a| self.contains_address(a)))
+            && (topics.is_empty() || topics.iter().any(|t| self.contains_topic(t)))
+    }
+}
+
+impl TryFrom<&Data> for Bloom {
+    type Error = ();
+
+    fn try_from(data: &Data) -> Result<Self, Self::Error> {
+        Bloom::from_bytes(data).ok_or(())
+    }
+}
+
+#[test]
+fn test_bloom_bits() {
+    use clarity::abi::derive_signature;
+
+    let usdt: Address = "0xdac17f958d2ee523a2206206994597c13d831ec7"
+        .parse()
+        .unwrap();
+    let transfer = derive_signature("Transfer(address,address,uint256)").unwrap();
+    let mut bloom = Bloom::default();
+    bloom.accrue(&address_to_event(usdt)[12..]);
+    // the bit indices are the low 11 bits of the first three pairs of hash bytes
+    for index in [788, 1672, 679] {
+        assert_ne!(bloom.0[255 - index / 8] & (1 << (index % 8)), 0);
+    }
+    assert_eq!(bloom.0.iter().map(|b| b.count_ones()).sum::<u32>(), 3);
+    assert!(bloom.contains_address(&usdt));
+    assert!(!bloom.contains_topic(&transfer));
+
+    bloom.accrue(&transfer);
+    for index in [1443, 481, 1060] {
+        assert_ne!(bloom.0[255 - index / 8] & (1 << (index % 8)), 0);
+    }
+    assert!(bloom.contains_topic(&transfer));
+    assert!(bloom.may_contain(&[usdt], &[transfer]));
+    assert!(!Bloom::default().may_contain(&[usdt], &[]));
+    assert!(Bloom::default().may_contain(&[], &[]));
+    assert!(Bloom::from_bytes(&[0; 2]).is_none());
+}
+
+#[test]
+fn test_mainnet_block_bloom() {
+    use crate::jsonrpc::mock::geth_block;
+    use crate::types::Block;
+    use clarity::abi::derive_signature;
+
+    // mainnet block 11568854 has USDT, DAI, LINK and USDC transfers
+    let block: Block = serde_json::from_value(geth_block()).unwrap();
+    let bloom = block.bloom().unwrap();
+    for token in [
+        "0xdac17f958d2ee523a2206206994597c13d831ec7",
+        "0x6b175474e89094c44da98b954eedeac495271d0f",
+        "0x514910771af9ca656af840dff83e8264ecf986ca",
+        "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+    ] {
+        assert!(bloom.contains_address(&token.parse().unwrap()), "{}", token);
+    }
+    for event in [
+        "Transfer(address,address,uint256)",
+        "Approval(address,address,uint256)",
+    ] {
+        assert!(bloom.contains_topic(&derive_signature(event).unwrap()));
+    }
+
+    let unused: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let foo = derive_signature("Foo()").unwrap();
+    assert!(!bloom.contains_address(&unused));
+    assert!(!bloom.contains_topic(&foo));
+    assert!(!bloom.may_contain(&[unused], &[]));
+    assert!(!bloom.may_contain(&[], &[foo]));
+}
@@ -0,0 +1,131 @@
+//! An opt-in cache for the values that hot loops request over and over but which rarely
+//! change, namely the chain id, latest block number and gas price.
+use crate::jsonrpc::error::Web3Error;
+use crate::time::Instant;
+use clarity::Uint256;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// How long `Web3` caches the responses of frequently polled methods, `None` disables the
+/// cache for that method. The default disables all caching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheConfig {
+    /// the chain id can never change for a given endpoint so it is cached until invalidated
+    pub chain_id: bool,
+    pub block_number_ttl: Option<Duration>,
+    pub gas_price_ttl: Option<Duration>,
+}
+
+impl CacheConfig {
+    /// Caches the chain id forever and the block number and gas price for `ttl`
+    pub fn with_ttl(ttl: Duration) -> CacheConfig {
+        CacheConfig {
+            chain_id: true,
+            block_number_ttl: Some(ttl),
+            gas_price_ttl: Some(ttl),
+        }
+    }
+}
+
+/// A single cached value. The lock is held across the refresh so that concurrent callers
+/// wait for the one request in flight instead of all sending their own.
+#[derive(Debug)]
+pub(crate) struct CachedValue {
+    ttl: Option<Duration>,
+    value: Mutex<Option<(Instant, u64, Uint256)>>,
+}
+
+impl CachedValue {
+    fn new(ttl: Option<Duration>) -> CachedValue {
+        CachedValue {
+            ttl,
+            value: Mutex::new(None),
+        }
+    }
+
+    /// Returns the cached value if it is younger than the ttl and from the current
+    /// `generation`, otherwise stores and returns the result of `refresh`
+    async fn get_or_refresh<F, Fut>(
+        &self,
+        generation: u64,
+        refresh: F,
+    ) -> Result<Uint256, Web3Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Uint256, Web3Error>>,
+    {
+        let ttl = match self.ttl {
+            Some(ttl) => ttl,
+            None => return refresh().await,
+        };
+        let mut value = self.value.lock().await;
+        if let Some((fetched_at, value_generation, value)) = *value {
+            if value_generation == generation && fetched_at.elapsed() < ttl {
+                return Ok(value);
+            }
+        }
+        let new_value = refresh().await?;
+        *value = Some((Instant::now(), generation, new_value));
+        Ok(new_value)
+    }
+}
+
+/// The caches shared between clones of a `Web3`
+#[derive(Debug)]
+pub(crate) struct ResponseCache {
+    config: CacheConfig,
+    /// bumped by `invalidate` so that invalidation never has to wait on a refresh in flight
+    generation: AtomicU64,
+    chain_id: CachedValue,
+    block_number: CachedValue,
+    gas_price: CachedValue,
+}
+
+impl ResponseCache {
+    pub(crate) fn new(config: CacheConfig) -> ResponseCache {
+        ResponseCache {
+            config,
+            generation: AtomicU64::new(0),
+            chain_id: CachedValue::new(config.chain_id.then_some(Duration::MAX)),
+            block_number: CachedValue::new(config.block_number_ttl),
+            gas_price: CachedValue::new(config.gas_price_ttl),
+        }
+    }
+
+    pub(crate) fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    pub(crate) fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) async fn chain_id<F, Fut>(&self, refresh: F) -> Result<Uint256, Web3Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Uint256, Web3Error>>,
+    {
+        let generation = self.generation.load(Ordering::SeqCst);
+        self.chain_id.get_or_refresh(generation, refresh).await
+    }
+
+    pub(crate) async fn block_number<F, Fut>(&self, refresh: F) -> Result<Uint256, Web3Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Uint256, Web3Error>>,
+    {
+        let generation = self.generation.load(Ordering::SeqCst);
+        self.block_number.get_or_refresh(generation, refresh).await
+    }
+
+    pub(crate) async fn gas_price<F, Fut>(&self, refresh: F) -> Result<Uint256, Web3Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Uint256, Web3Error>>,
+    {
+        let generation = self.generation.load(Ordering::SeqCst);
+        self.gas_price.get_or_refresh(generation, refresh).await
+    }
+}
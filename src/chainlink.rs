@@ -0,0 +1,217 @@
+//! Reading Chainlink AggregatorV3 price feeds, mostly to convert fees into USD
+//!
+//! See more: https://docs.chain.link/data-feeds/api-reference
+use crate::jsonrpc::error::Web3Error;
+use crate::{
+    client::Web3,
+    types::{Data, TransactionRequest},
+};
+use clarity::abi::encode_call;
+use clarity::{Address, Uint256};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    /// The ETH / USD feed on Ethereum mainnet
+    pub static ref ETH_USD_FEED_ADDRESS: Address =
+        Address::parse_and_validate("0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419").unwrap();
+}
+
+/// Reads the 32 byte word at `index` of abi encoded return data
+fn return_word(data: &[u8], index: usize) -> Option<Uint256> {
+    data.get(index * 32..(index + 1) * 32)
+        .and_then(Uint256::from_bytes_be)
+}
+
+/// Decodes the answer and updatedAt values out of the return data of
+/// `latestRoundData() returns (uint80 roundId, int256 answer, uint256 startedAt,
+/// uint256 updatedAt, uint80 answeredInRound)`, a negative answer is an error since
+/// no price this is used for can be below zero
+fn decode_latest_round_data(data: &[u8]) -> Result<(Uint256, i64), Web3Error> {
+    let (answer, updated_at) = match (return_word(data, 1), return_word(data, 3)) {
+        (Some(answer), Some(updated_at)) if data.len() >= 5 * 32 => (answer, updated_at),
+        _ => {
+            return Err(Web3Error::ContractCallError(
+                "Bad response from latestRoundData".to_string(),
+            ))
+        }
+    };
+    if answer.sig_bits() == 256 {
+        return Err(Web3Error::ContractCallError(
+            "Price feed answer is negative".to_string(),
+        ));
+    }
+    if updated_at.sig_bits() > 63 {
+        return Err(Web3Error::ContractCallError(
+            "Price feed updatedAt does not fit in an i64".to_string(),
+        ));
+    }
+    Ok((answer, updated_at.resize_to_u128() as i64))
+}
+
+/// Returns the number of seconds since `updated_at` if it is older than `max_age`
+fn staleness(updated_at: i64, max_age: Duration) -> Option<u64> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let age = now.saturating_sub(updated_at).max(0) as u64;
+    if age > max_age.as_secs() {
+        Some(age)
+    } else {
+        None
+    }
+}
+
+impl Web3 {
+    /// Gets the latest answer of a Chainlink AggregatorV3 price feed, the number of decimals
+    /// the answer has, and the unix timestamp in seconds that the answer was updated at. See
+    /// `get_chainlink_price_checked` to reject stale answers.
+    pub async fn get_chainlink_price(
+        &self,
+        feed_address: Address,
+    ) -> Result<(Uint256, u8, i64), Web3Error> {
+        let data = self.feed_call(feed_address, "latestRoundData()").await?;
+        let (answer, updated_at) = decode_latest_round_data(&data)?;
+
+        let data = self.feed_call(feed_address, "decimals()").await?;
+        let decimals = match return_word(&data, 0) {
+            Some(decimals) if decimals <= Uint256::from_u64(u8::MAX as u64) => {
+                decimals.resize_to_u128() as u8
+            }
+            _ => {
+                return Err(Web3Error::ContractCallError(
+                    "Bad response from decimals".to_string(),
+                ))
+            }
+        };
+        Ok((answer, decimals, updated_at))
+    }
+
+    /// The same as `get_chainlink_price` but returns `Web3Error::StalePrice` if the answer
+    /// was last updated more than `max_age` ago. Feeds update on a heartbeat that depends on
+    /// the feed, usually an hour or a day, so `max_age` should be a little longer than that.
+    pub async fn get_chainlink_price_checked(
+        &self,
+        feed_address: Address,
+        max_age: Duration,
+    ) -> Result<(Uint256, u8, i64), Web3Error> {
+        let (answer, decimals, updated_at) = self.get_chainlink_price(feed_address).await?;
+        if let Some(age) = staleness(updated_at, max_age) {
+            return Err(Web3Error::StalePrice {
+                feed: feed_address,
+                age: Duration::from_secs(age),
+            });
+        }
+        Ok((answer, decimals, updated_at))
+    }
+
+    /// Converts an amount of wei into USD using the given ETH / USD feed, erroring if the
+    /// answer is older than `max_age`. The result has the decimals of the feed, which are
+    /// also returned, so `format_units(usd, decimals as u32)` gives a human readable value.
+    pub async fn wei_to_usd(
+        &self,
+        wei: Uint256,
+        eth_usd_feed: Address,
+        max_age: Duration,
+    ) -> Result<(Uint256, u8), Web3Error> {
+        let (price, decimals, _) = self
+            .get_chainlink_price_checked(eth_usd_feed, max_age)
+            .await?;
+        let one_eth = Uint256::from_u128(1_000_000_000_000_000_000);
+        match wei
+            .checked_mul(price)
+            .and_then(|v| v.divide(one_eth))
+            .map(|(usd, _)| usd)
+        {
+            Some(usd) => Ok((usd, decimals)),
+            None => Err(Web3Error::BadInput(
+                "USD value overflows a Uint256".to_string(),
+            )),
+        }
+    }
+
+    async fn feed_call(&self, feed_address: Address, sig: &str) -> Result<Data, Web3Error> {
+        self.eth_call(TransactionRequest::call(
+            feed_address,
+            encode_call(sig, &[])?,
+        ))
+        .await
+    }
+}
+
+#[test]
+fn test_decode_latest_round_data() {
+    let mut data = vec![0u8; 5 * 32];
+    data[63] = 0x2a;
+    data[127] = 0x10;
+    assert_eq!(
+        decode_latest_round_data(&data).unwrap(),
+        (Uint256::from_u64(0x2a), 0x10)
+    );
+    assert!(decode_latest_round_data(&data[..96]).is_err());
+    // a negative int256 answer
+    data[32..64].copy_from_slice(&[0xff; 32]);
+    assert!(decode_latest_round_data(&data).is_err());
+
+    assert_eq!(staleness(i64::MAX, Duration::from_secs(0)), None);
+    assert!(staleness(0, Duration::from_secs(3600)).is_some());
+}
+
+#[tokio::test]
+async fn test_wei_to_usd() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_call" => {
+            let data = params[0]["data"].as_str().unwrap();
+            let word = |n: u64| format!("{:064x}", n);
+            // decimals()
+            if data.starts_with("0x313ce567") {
+                Ok(json!(format!("0x{}", word(8))))
+            } else {
+                // $2500.50 updated a minute ago
+                Ok(json!(format!(
+                    "0x{}{}{}{}{}",
+                    word(1),
+                    word(250_050_000_000),
+                    word(now - 60),
+                    word(now - 60),
+                    word(1)
+                )))
+            }
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let feed = *ETH_USD_FEED_ADDRESS;
+
+    let (answer, decimals, updated_at) = web3.get_chainlink_price(feed).await.unwrap();
+    assert_eq!(answer, Uint256::from_u64(250_050_000_000));
+    assert_eq!(decimals, 8);
+    assert_eq!(updated_at, (now - 60) as i64);
+
+    // half an ETH
+    let (usd, decimals) = web3
+        .wei_to_usd(
+            Uint256::from_u128(500_000_000_000_000_000),
+            feed,
+            Duration::from_secs(3600),
+        )
+        .await
+        .unwrap();
+    assert_eq!(crate::units::format_units(usd, decimals as u32), "1250.25");
+
+    match web3
+        .get_chainlink_price_checked(feed, Duration::from_secs(10))
+        .await
+    {
+        Err(Web3Error::StalePrice { feed: stale, .. }) => assert_eq!(stale, feed),
+        res => panic!("Expected StalePrice, got {:?}", res),
+    }
+}
@@ -0,0 +1,138 @@
+//! EIP-55 mixed case checksum formatting and validation for addresses.
+//!
+//! See more https://eips.ethereum.org/EIPS/eip-55
+use crate::{client::Web3, jsonrpc::error::Web3Error};
+use clarity::Address;
+use sha3::{Digest, Keccak256};
+
+/// Returns the lowercase hex of an address without the `0x` prefix
+fn lowercase_hex(address: &Address) -> String {
+    address
+        .to_string()
+        .to_lowercase()
+        .trim_start_matches("0x")
+        .to_string()
+}
+
+/// Applies the EIP-55 checksum casing to 40 lowercase hex characters
+fn checksum_hex(lower: &str) -> String {
+    let hash = Keccak256::digest(lower.as_bytes());
+    lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let byte = hash[i / 2];
+            let nibble = if i % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Formats an address as a `0x` prefixed EIP-55 checksummed string
+pub fn to_checksum_string(address: &Address) -> String {
+    format!("0x{}", checksum_hex(&lowercase_hex(address)))
+}
+
+/// Parses an address, rejecting it if it is mixed case and the EIP-55 checksum
+/// is wrong. All lowercase and all uppercase inputs carry no checksum and are accepted.
+pub fn parse_checksummed(input: &str) -> Result<Address, Web3Error> {
+    let hex = input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+        .unwrap_or(input);
+    if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Web3Error::BadInput(format!(
+            "{} is not a 20 byte hex address",
+            input
+        )));
+    }
+
+    let lower = hex.to_lowercase();
+    let has_lower = hex.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = hex.chars().any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper {
+        let expected = checksum_hex(&lower);
+        if expected != hex {
+            return Err(Web3Error::BadInput(format!(
+                "Bad EIP-55 checksum for address {}, expected 0x{}",
+                input, expected
+            )));
+        }
+    }
+
+    format!("0x{}", lower)
+        .parse()
+        .map_err(|e| Web3Error::BadInput(format!("Failed to parse address {:?}", e)))
+}
+
+impl Web3 {
+    /// Sets whether user facing helpers should display addresses with
+    /// EIP-55 checksums, by default addresses are displayed in lowercase
+    pub fn set_checksum_addresses(&mut self, enabled: bool) {
+        self.checksum_addresses = enabled;
+    }
+
+    /// Formats an address for display to users, respecting the
+    /// setting from `set_checksum_addresses`
+    pub fn format_address(&self, address: &Address) -> String {
+        if self.checksum_addresses {
+            to_checksum_string(address)
+        } else {
+            format!("0x{}", lowercase_hex(address))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors from EIP-55
+    const VECTORS: [&str; 8] = [
+        // all caps
+        "0x52908400098527886E0F7030069857D2E4169EE7",
+        "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+        // all lower
+        "0xde709f2102306220921060314715629080e2fb77",
+        "0x27b1fdb04752bbc536007a920d24acb045561c26",
+        // normal
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ];
+
+    #[test]
+    fn test_checksum_vectors() {
+        for vector in VECTORS {
+            let address = parse_checksummed(vector).unwrap();
+            assert_eq!(to_checksum_string(&address), vector);
+        }
+    }
+
+    #[test]
+    fn test_uniform_case_accepted() {
+        for vector in VECTORS {
+            let lower = vector.to_lowercase();
+            let upper = format!("0x{}", vector[2..].to_uppercase());
+            assert_eq!(
+                parse_checksummed(&lower).unwrap(),
+                parse_checksummed(&upper).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_bad_checksum_rejected() {
+        // the first letter of a valid vector with its case flipped
+        assert!(parse_checksummed("0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+        assert!(parse_checksummed("0xfb6916095ca1df60bB79Ce92cE3Ea74c37c5d359").is_err());
+        // wrong length and non hex input
+        assert!(parse_checksummed("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA").is_err());
+        assert!(parse_checksummed("0xzzAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+    }
+}
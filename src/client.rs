@@ -4,35 +4,374 @@
 //! work on big endian. We can do better than that just crafting our own
 //! JSONRPC requests.
 //!
-use crate::jsonrpc::client::HttpClient;
-use crate::jsonrpc::error::Web3Error;
-use crate::types::{Block, Log, NewFilter, SyncingStatus, TransactionRequest, TransactionResponse};
-use crate::types::{ConciseBlock, ConciseXdaiBlock, Data, SendTxOption, XdaiBlock};
-use clarity::utils::bytes_to_hex_str;
+use crate::cache::{CacheConfig, ResponseCache};
+use crate::gas_price::{GasFees, GasStrategy};
+use crate::hash::TxHash;
+use crate::hex::{data_to_hex, quantity_to_hex, word_to_hex};
+use crate::jsonrpc::client::{BodyHook, HttpClient};
+use crate::jsonrpc::error::{JsonRpcErrorCode, Web3Error};
+use crate::jsonrpc::rate_limit::{RateLimitMatcher, RateLimiter};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::jsonrpc::tls::TlsConfig;
+use crate::metrics::MetricsSink;
+use crate::signer::TransactionSigner;
+use crate::time::sleep as delay_for;
+use crate::time::Instant;
+use crate::types::{Block, BlockId, Log, NewFilter, Proof, StateOverride, SyncingStatus};
+use crate::types::{ClientKind, NodeInfo, UncleBlock, UnpaddedHex};
+use crate::types::{ConciseBlock, ConciseXdaiBlock, Data, FeeHistory, SendTxOption, XdaiBlock};
+use crate::types::{PreparedTx, SignedTx};
+use crate::types::{
+    TransactionOutcome, TransactionReceipt, TransactionRequest, TransactionResponse,
+};
 use clarity::{u256, Uint256};
 use clarity::{Address, PrivateKey, Transaction};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use hyper::HeaderMap;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 use std::cmp::max;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::{cmp::min, time::Duration};
-use std::{sync::Arc, time::Instant};
-use tokio::time::sleep as delay_for;
+
+/// The gas used by a plain value transfer to an account without code
+pub const TRANSFER_GAS_LIMIT: u128 = 21000;
+
+/// Polls `get_balance` until it returns at least `minimum` or `timeout` passes, shared by
+/// the helpers that wait for ETH and token balances
+pub(crate) async fn wait_for_balance<F, Fut>(
+    minimum: Uint256,
+    timeout: Duration,
+    poll_interval: Duration,
+    get_balance: F,
+) -> Result<Uint256, Web3Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Uint256, Web3Error>>,
+{
+    let start = Instant::now();
+    let mut last_seen = None;
+    loop {
+        match get_balance().await {
+            Ok(balance) if balance >= minimum => return Ok(balance),
+            Ok(balance) => last_seen = Some(balance),
+            Err(e) if e.is_retriable() => warn!("Failed to get balance, retrying {:?}", e),
+            Err(e) => return Err(e),
+        }
+        let elapsed = Instant::now() - start;
+        if elapsed >= timeout {
+            return Err(Web3Error::BalanceNotReached { minimum, last_seen });
+        }
+        delay_for(min(poll_interval, timeout - elapsed)).await;
+    }
+}
 
 /// An instance of Web3Client.
 #[derive(Clone)]
 pub struct Web3 {
     url: String,
-    jsonrpc_client: Arc<HttpClient>,
+    pub(crate) jsonrpc_client: Arc<HttpClient>,
+    pub(crate) timeout: Duration,
+    /// if user facing helpers should display EIP-55 checksummed addresses
+    pub(crate) checksum_addresses: bool,
+    /// the highest fee per gas `send_transaction` will pay, see `set_max_gas_price`
+    pub(crate) max_gas_price: Option<Uint256>,
+    /// how far behind latest to assume finality when the node does not support the
+    /// "finalized" and "safe" block tags, see `set_finality_fallback_depth`
+    pub(crate) finality_fallback_depth: u64,
+    /// shared between clones, see `set_cache_config`
+    cache: Arc<ResponseCache>,
+    /// overrides the poll interval of every waiting helper if set, see `set_poll_interval`
+    pub(crate) poll_interval: Option<Duration>,
+    /// block timestamps seen by `get_block_by_timestamp`, keyed by block number
+    pub(crate) block_timestamps: Arc<Mutex<BTreeMap<u64, u64>>>,
+    /// the last block time estimate and when it was made, see `estimate_block_time`
+    pub(crate) block_time_estimate: Arc<Mutex<Option<(Instant, Duration)>>>,
+}
+
+/// Configures a `Web3` beyond what `Web3::new` allows, see `Web3::builder`
+#[derive(Clone)]
+pub struct Web3Builder {
+    url: String,
     timeout: Duration,
+    poll_interval: Option<Duration>,
+    retries: u32,
+    headers: HeaderMap,
+    user_agent: Option<String>,
+    app_identifier: Option<String>,
+    request_id_header: bool,
+    body_hook: Option<BodyHook>,
+    max_concurrent_requests: Option<usize>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    rate_limit_matchers: Option<Vec<RateLimitMatcher>>,
+    metrics_sink: Option<Arc<dyn MetricsSink>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    tls: Option<TlsConfig>,
+}
+
+impl std::fmt::Debug for Web3Builder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("Web3Builder");
+        debug
+            .field("url", &self.url)
+            .field("timeout", &self.timeout)
+            .field("poll_interval", &self.poll_interval)
+            .field("retries", &self.retries)
+            .field("headers", &self.headers)
+            .field("user_agent", &self.user_agent)
+            .field("app_identifier", &self.app_identifier)
+            .field("request_id_header", &self.request_id_header)
+            .field("body_hook", &self.body_hook.is_some())
+            .field("max_concurrent_requests", &self.max_concurrent_requests)
+            .field("rate_limiter", &self.rate_limiter)
+            .field("rate_limit_matchers", &self.rate_limit_matchers)
+            .field("metrics_sink", &self.metrics_sink);
+        #[cfg(not(target_arch = "wasm32"))]
+        debug.field("tls", &self.tls);
+        debug.finish()
+    }
+}
+
+impl Web3Builder {
+    /// The timeout of each individual request, `DEFAULT_REQUEST_TIMEOUT` by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How often waiting helpers such as `wait_for_event` and `wait_for_transaction` poll the
+    /// node. By default each helper uses its own interval, mostly one second.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// How many times requests that time out or fail at the network level are retried, by
+    /// default they are not. Only read only methods are retried, see `HttpClient::with_retries`.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Headers sent with every request, such as the API key of a provider
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Replaces the `DEFAULT_USER_AGENT` sent with every request
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Appends an identifier of the application such as "my-indexer/1.2" to the User-Agent,
+    /// see `HttpClient::with_app_identifier`
+    pub fn app_identifier(mut self, app: impl Into<String>) -> Self {
+        self.app_identifier = Some(app.into());
+        self
+    }
+
+    /// Sends the JSON-RPC id of each request as an X-Request-Id header for correlating logs
+    /// with the node operator, off by default
+    pub fn request_id_header(mut self, enabled: bool) -> Self {
+        self.request_id_header = enabled;
+        self
+    }
+
+    /// Headers computed from the serialized body of every request, such as the signature
+    /// required by private transaction relays, see `HttpClient::with_body_hook`
+    pub fn body_hook(mut self, hook: BodyHook) -> Self {
+        self.body_hook = Some(hook);
+        self
+    }
+
+    /// Limits the number of requests in flight at once across the client and its clones,
+    /// further requests wait for earlier ones to finish, zero leaves the number unlimited.
+    /// The timeout starts once a request is sent, not while it waits here.
+    pub fn max_concurrent_requests(mut self, max: usize) -> Self {
+        self.max_concurrent_requests = Some(max);
+        self
+    }
+
+    /// Limits the rate of requests across the client and its clones, further requests wait
+    /// for the limiter instead of failing. Pass the same limiter to several builders to share
+    /// one budget between them, see `RateLimiter`. The timeout starts once a request is sent,
+    /// not while it waits for the limiter.
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Recognizes rate limited responses with `matchers`, see
+    /// `HttpClient::with_rate_limit_matchers`
+    pub fn rate_limit_matchers(mut self, matchers: Vec<RateLimitMatcher>) -> Self {
+        self.rate_limit_matchers = Some(matchers);
+        self
+    }
+
+    /// Observes every request made, see `Web3::set_metrics_sink`
+    pub fn metrics_sink(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics_sink = Some(sink);
+        self
+    }
+
+    /// How servers are authenticated, for nodes behind an internal CA or dev chains with self
+    /// signed certificates, see `TlsConfig`. In the browser TLS is left to the browser.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    pub fn build(self) -> Web3 {
+        let mut client = HttpClient::new(&self.url)
+            .with_headers(self.headers)
+            .with_retries(self.retries)
+            .with_request_id_header(self.request_id_header);
+        if let Some(user_agent) = self.user_agent {
+            client = client.with_user_agent(user_agent);
+        }
+        if let Some(app) = self.app_identifier {
+            client = client.with_app_identifier(&app);
+        }
+        if let Some(hook) = self.body_hook {
+            client = client.with_body_hook(hook);
+        }
+        if let Some(max) = self.max_concurrent_requests {
+            client = client.with_max_concurrent_requests(max);
+        }
+        if let Some(limiter) = self.rate_limiter {
+            client = client.with_rate_limiter(limiter);
+        }
+        if let Some(matchers) = self.rate_limit_matchers {
+            client = client.with_rate_limit_matchers(matchers);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(tls) = &self.tls {
+            client = client.with_tls(tls);
+        }
+        client.set_metrics_sink(self.metrics_sink);
+        let mut web3 = Web3::with_client(&self.url, client, self.timeout);
+        web3.poll_interval = self.poll_interval;
+        web3
+    }
 }
 
+/// The request timeout of a `Web3` created with `Web3::builder`
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default number of confirmations assumed final when a node does not support the
+/// "finalized" and "safe" block tags
+pub const DEFAULT_FINALITY_FALLBACK_DEPTH: u64 = 12;
+
+/// How many receipts are requested at once when a node without eth_getBlockReceipts is
+/// asked for the receipts of a block
+pub const RECEIPT_FETCH_CONCURRENCY: usize = 16;
+
 impl Web3 {
     pub fn new(url: &str, timeout: Duration) -> Self {
+        Self::with_client(url, HttpClient::new(url), timeout)
+    }
+
+    /// Starts configuring a `Web3` for `url`, with the same defaults as `Web3::new`
+    pub fn builder(url: &str) -> Web3Builder {
+        Web3Builder {
+            url: url.to_string(),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            poll_interval: None,
+            retries: 0,
+            headers: HeaderMap::new(),
+            user_agent: None,
+            app_identifier: None,
+            request_id_header: false,
+            body_hook: None,
+            max_concurrent_requests: None,
+            rate_limiter: None,
+            rate_limit_matchers: None,
+            metrics_sink: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            tls: None,
+        }
+    }
+
+    pub(crate) fn with_client(url: &str, client: HttpClient, timeout: Duration) -> Self {
         Self {
-            jsonrpc_client: Arc::new(HttpClient::new(url)),
+            jsonrpc_client: Arc::new(client),
             timeout,
             url: url.to_string(),
+            checksum_addresses: false,
+            max_gas_price: None,
+            finality_fallback_depth: DEFAULT_FINALITY_FALLBACK_DEPTH,
+            cache: Arc::new(ResponseCache::new(CacheConfig::default())),
+            poll_interval: None,
+            block_timestamps: Arc::new(Mutex::new(BTreeMap::new())),
+            block_time_estimate: Arc::new(Mutex::new(None)),
         }
     }
 
+    /// Sets the interval at which all waiting and polling helpers poll the node, `None`
+    /// restores the default interval of each helper
+    pub fn set_poll_interval(&mut self, poll_interval: Option<Duration>) {
+        self.poll_interval = poll_interval;
+    }
+
+    pub fn get_poll_interval(&self) -> Option<Duration> {
+        self.poll_interval
+    }
+
+    /// The configured poll interval, or `default` if none is set
+    pub(crate) fn poll_interval_or(&self, default: Duration) -> Duration {
+        self.poll_interval.unwrap_or(default)
+    }
+
+    /// Sets a cap on the fee per gas of transactions sent with `send_transaction`, which
+    /// returns `Web3Error::GasPriceTooHigh` instead of sending when the fee is above it.
+    /// For EIP-1559 fees the fee cap is compared. `SendTxOption::MaxGasPrice` overrides this.
+    pub fn set_max_gas_price(&mut self, max_gas_price: Option<Uint256>) {
+        self.max_gas_price = max_gas_price;
+    }
+
+    pub fn get_max_gas_price(&self) -> Option<Uint256> {
+        self.max_gas_price
+    }
+
+    /// Sets how many blocks behind latest `eth_finalized_block_number` and
+    /// `eth_safe_block_number` fall back to on chains and older clients that do not support
+    /// the "finalized" and "safe" block tags
+    pub fn set_finality_fallback_depth(&mut self, depth: u64) {
+        self.finality_fallback_depth = depth;
+    }
+
+    pub fn get_finality_fallback_depth(&self) -> u64 {
+        self.finality_fallback_depth
+    }
+
+    /// Enables caching of `eth_chainid`, `eth_block_number` and `eth_gas_price` responses
+    /// according to `config`, caching is disabled by default. This replaces the cache with an
+    /// empty one that is shared by any clones made afterwards.
+    pub fn set_cache_config(&mut self, config: CacheConfig) {
+        self.cache = Arc::new(ResponseCache::new(config));
+    }
+
+    pub fn get_cache_config(&self) -> CacheConfig {
+        self.cache.config()
+    }
+
+    /// Installs a sink that observes the method, latency and outcome of every request this
+    /// client and its clones make, or removes it with `None`
+    pub fn set_metrics_sink(&self, sink: Option<Arc<dyn MetricsSink>>) {
+        self.jsonrpc_client.set_metrics_sink(sink)
+    }
+
+    /// Drops all cached responses so that the next request of each goes to the node
+    pub fn invalidate_caches(&self) {
+        self.cache.invalidate()
+    }
+
     pub fn get_timeout(&self) -> Duration {
         self.timeout
     }
@@ -47,11 +386,42 @@ impl Web3 {
             .await
     }
 
+    /// Returns the account the node mines or builds blocks for, None if it does not have
+    /// one configured. Geth errors and some older nodes return the zero address in that case.
+    pub async fn eth_coinbase(&self) -> Result<Option<Address>, Web3Error> {
+        let res: Result<Address, Web3Error> = self
+            .jsonrpc_client
+            .request_method("eth_coinbase", Vec::<String>::new(), self.timeout)
+            .await;
+        match res {
+            Ok(address) if address == Address::default() => Ok(None),
+            Ok(address) => Ok(Some(address)),
+            Err(Web3Error::JsonRpcError {
+                code: -32000,
+                message,
+                ..
+            }) if message
+                .to_lowercase()
+                .contains("etherbase must be explicitly specified")
+                || message.to_lowercase().contains("coinbase") =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e.map_unsupported("eth_coinbase")),
+        }
+    }
+
     /// Returns the EIP155 chain ID used for transaction signing at the current best block. Null is returned if not available.
     pub async fn eth_chainid(&self) -> Result<Option<Uint256>, Web3Error> {
-        let ret: Result<Uint256, Web3Error> = self
-            .jsonrpc_client
-            .request_method("eth_chainId", Vec::<String>::new(), self.timeout)
+        let ret = self
+            .cache
+            .chain_id(|| {
+                self.jsonrpc_client.request_method(
+                    "eth_chainId",
+                    Vec::<String>::new(),
+                    self.timeout,
+                )
+            })
             .await;
 
         Ok(Some(ret?))
@@ -65,7 +435,81 @@ impl Web3 {
         Ok(ret?.parse()?)
     }
 
+    /// Returns the client name and version string of the node, such as "Geth/v1.11.6-stable"
+    pub async fn web3_client_version(&self) -> Result<String, Web3Error> {
+        self.jsonrpc_client
+            .request_method("web3_clientVersion", Vec::<String>::new(), self.timeout)
+            .await
+    }
+
+    /// Returns the number of peers currently connected to the node
+    pub async fn net_peer_count(&self) -> Result<u64, Web3Error> {
+        let ret: String = self
+            .jsonrpc_client
+            .request_method("net_peerCount", Vec::<String>::new(), self.timeout)
+            .await?;
+        Ok(u64::from_str_radix(ret.trim_start_matches("0x"), 16)?)
+    }
+
+    /// Returns true if the node is actively listening for network connections
+    pub async fn net_listening(&self) -> Result<bool, Web3Error> {
+        self.jsonrpc_client
+            .request_method("net_listening", Vec::<String>::new(), self.timeout)
+            .await
+    }
+
+    /// Fetches the network id, peer count, listening status and client version of the node
+    /// concurrently
+    pub async fn node_info(&self) -> Result<NodeInfo, Web3Error> {
+        let (network_id, peer_count, listening, client_version) = tokio::join!(
+            self.net_version(),
+            self.net_peer_count(),
+            self.net_listening(),
+            self.web3_client_version()
+        );
+        let client_version = client_version?;
+        Ok(NodeInfo {
+            network_id: network_id?,
+            peer_count: peer_count?,
+            listening: listening?,
+            client_kind: ClientKind::from_client_version(&client_version),
+            client_version,
+        })
+    }
+
+    /// Returns the keccak256 hash of `data` as computed by the node, see `keccak256` for
+    /// computing it locally
+    pub async fn web3_sha3(&self, data: &[u8]) -> Result<Uint256, Web3Error> {
+        self.jsonrpc_client
+            .request_method("web3_sha3", vec![data_to_hex(data)], self.timeout)
+            .await
+    }
+
+    /// Sends an arbitrary JSON-RPC request, for vendor specific methods such as `ots_*` or
+    /// `erigon_*` that do not have a dedicated wrapper. The params are serialized as is so
+    /// they must already be in the JSON-RPC wire format, quantities as 0x prefixed hex and
+    /// hashes as full 32 byte hex strings.
+    pub async fn custom_request<P, R>(&self, method: &str, params: P) -> Result<R, Web3Error>
+    where
+        P: Serialize,
+        R: DeserializeOwned + std::fmt::Debug + 'static,
+    {
+        self.jsonrpc_client
+            .request_method(method, params, self.timeout)
+            .await
+    }
+
+    /// Fully dynamic version of `custom_request` that takes and returns raw JSON values
+    pub async fn custom_request_value(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, Web3Error> {
+        self.custom_request(method, params).await
+    }
+
     pub async fn eth_new_filter(&self, new_filter: NewFilter) -> Result<Uint256, Web3Error> {
+        new_filter.validate()?;
         self.jsonrpc_client
             .request_method("eth_newFilter", vec![new_filter], self.timeout)
             .await
@@ -75,7 +519,7 @@ impl Web3 {
         self.jsonrpc_client
             .request_method(
                 "eth_getFilterChanges",
-                vec![format!("{:#x}", filter_id.clone())],
+                vec![quantity_to_hex(filter_id)],
                 self.timeout,
             )
             .await
@@ -85,13 +529,62 @@ impl Web3 {
         self.jsonrpc_client
             .request_method(
                 "eth_uninstallFilter",
-                vec![format!("{:#x}", filter_id.clone())],
+                vec![quantity_to_hex(filter_id)],
+                self.timeout,
+            )
+            .await
+    }
+
+    /// Returns all logs matching an installed log filter, not just the changes since the last poll
+    pub async fn eth_get_filter_logs(&self, filter_id: Uint256) -> Result<Vec<Log>, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_getFilterLogs",
+                vec![quantity_to_hex(filter_id)],
                 self.timeout,
             )
             .await
     }
 
+    /// Installs a filter that reports the hashes of new blocks, poll it with
+    /// `eth_get_block_filter_changes`
+    pub async fn eth_new_block_filter(&self) -> Result<Uint256, Web3Error> {
+        self.jsonrpc_client
+            .request_method("eth_newBlockFilter", Vec::<String>::new(), self.timeout)
+            .await
+    }
+
+    /// Returns the hashes of blocks produced since the last poll of a block filter. This is the
+    /// same RPC as `eth_get_filter_changes` which returns logs for log filters instead.
+    pub async fn eth_get_block_filter_changes(
+        &self,
+        filter_id: Uint256,
+    ) -> Result<Vec<[u8; 32]>, Web3Error> {
+        let hashes: Vec<Data> = self
+            .jsonrpc_client
+            .request_method(
+                "eth_getFilterChanges",
+                vec![quantity_to_hex(filter_id)],
+                self.timeout,
+            )
+            .await?;
+        let mut res: Vec<[u8; 32]> = Vec::new();
+        for hash in hashes {
+            match hash.0.as_slice().try_into() {
+                Ok(hash) => res.push(hash),
+                Err(_) => {
+                    return Err(Web3Error::BadResponse(format!(
+                        "Block filter returned a {} byte hash",
+                        hash.0.len()
+                    )))
+                }
+            }
+        }
+        Ok(res)
+    }
+
     pub async fn eth_get_logs(&self, new_filter: NewFilter) -> Result<Vec<Log>, Web3Error> {
+        new_filter.validate()?;
         self.jsonrpc_client
             .request_method("eth_getLogs", vec![new_filter], self.timeout)
             .await
@@ -119,6 +612,10 @@ impl Web3 {
     /// simply wrap eth_gasPrice, in post London chains it also requests the base
     /// gas from the previous block and prevents the use of a lower value
     pub async fn eth_gas_price(&self) -> Result<Uint256, Web3Error> {
+        self.cache.gas_price(|| self.fetch_gas_price()).await
+    }
+
+    async fn fetch_gas_price(&self) -> Result<Uint256, Web3Error> {
         match self.eth_syncing().await? {
             false => {
                 let median_gas = self
@@ -137,10 +634,43 @@ impl Web3 {
         }
     }
 
+    /// Gets the blob base fee of the next block, the price per unit of blob gas paid by
+    /// EIP-4844 blob transactions. Returns `UnsupportedMethod` on nodes from before the
+    /// dencun hardfork, `blob_base_fee` can compute it from a block header instead.
+    pub async fn eth_blob_base_fee(&self) -> Result<Uint256, Web3Error> {
+        self.jsonrpc_client
+            .request_method("eth_blobBaseFee", Vec::<String>::new(), self.timeout)
+            .await
+            .map_err(|e| e.map_unsupported("eth_blobBaseFee"))
+    }
+
+    /// Returns the base fees of the `block_count` blocks up to `newest_block` along with the
+    /// priority fees paid at each of `reward_percentiles` of the gas used in those blocks
+    pub async fn eth_fee_history(
+        &self,
+        block_count: u64,
+        newest_block: BlockId,
+        reward_percentiles: Vec<f64>,
+    ) -> Result<FeeHistory, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_feeHistory",
+                (
+                    quantity_to_hex(Uint256::from_u64(block_count)),
+                    newest_block,
+                    reward_percentiles,
+                ),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("eth_feeHistory"))
+    }
+
     pub async fn eth_estimate_gas(
         &self,
         transaction: TransactionRequest,
     ) -> Result<Uint256, Web3Error> {
+        transaction.validate()?;
         if let Ok(true) = self.eth_syncing().await {
             warn!("Eth Node is still syncing, request may not work if block is not synced");
         }
@@ -168,28 +698,127 @@ impl Web3 {
         }
     }
 
+    /// Returns the balance of `address` at the end of `block`. Nodes that are not archive
+    /// nodes only keep recent state, older blocks fail with `StateUnavailable`.
+    pub async fn eth_get_balance_at_block(
+        &self,
+        address: Address,
+        block: Uint256,
+    ) -> Result<Uint256, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_getBalance",
+                (address.to_string(), BlockId::Number(block)),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_state_unavailable(block))
+    }
+
+    /// Checks whether the node can answer queries against old state by getting a balance at
+    /// block 1, which only archive nodes keep. Use this to pick an endpoint for historical
+    /// queries among several configured providers, errors other than pruned state are returned.
+    pub async fn supports_archive_queries(&self) -> Result<bool, Web3Error> {
+        match self
+            .eth_get_balance_at_block(Address::default(), Uint256::from_u64(1))
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(Web3Error::StateUnavailable { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the balance of `address` including the effects of pending transactions
+    /// known to the node, as far as the node is able to account for them
+    pub async fn eth_get_pending_balance(&self, address: Address) -> Result<Uint256, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_getBalance",
+                vec![address.to_string(), "pending".to_string()],
+                self.timeout,
+            )
+            .await
+    }
+
+    /// Waits until the balance of `address` is at least `minimum`, checking every
+    /// `poll_interval`, and returns the balance that was seen. Fails with
+    /// `BalanceNotReached` once `timeout` has passed, transient errors are retried.
+    pub async fn wait_for_eth_balance(
+        &self,
+        address: Address,
+        minimum: Uint256,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<Uint256, Web3Error> {
+        wait_for_balance(minimum, timeout, poll_interval, || {
+            self.eth_get_balance(address)
+        })
+        .await
+    }
+
     /// Returns a bool indicating whether our eth node is currently syncing or not
     pub async fn eth_syncing(&self) -> Result<bool, Web3Error> {
-        let res: SyncingStatus = self
-            .jsonrpc_client
-            .request_method("eth_syncing", Vec::<String>::new(), self.timeout)
-            .await?;
-        match res {
+        match self.eth_syncing_status().await? {
             SyncingStatus::Syncing { .. } => Ok(true),
-            SyncingStatus::NotSyncing(..) => Ok(false),
+            SyncingStatus::Synced => Ok(false),
+        }
+    }
+
+    /// Returns the full syncing status of the node, including the sync progress if it is syncing
+    pub async fn eth_syncing_status(&self) -> Result<SyncingStatus, Web3Error> {
+        self.jsonrpc_client
+            .request_method("eth_syncing", Vec::<String>::new(), self.timeout)
+            .await
+    }
+
+    /// Polls eth_syncing every `poll_interval` until the node reports that it is synced,
+    /// returning an error if it is still syncing after `timeout`. Errors from the node are
+    /// treated as still syncing since a node that has just started may not be responsive yet.
+    pub async fn wait_for_sync(
+        &self,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<(), Web3Error> {
+        let start = Instant::now();
+        loop {
+            match self.eth_syncing_status().await {
+                Ok(SyncingStatus::Synced) => return Ok(()),
+                Ok(SyncingStatus::Syncing {
+                    current_block,
+                    highest_block,
+                    ..
+                }) => trace!(
+                    "Node syncing, at block {} of {}",
+                    current_block,
+                    highest_block
+                ),
+                Err(e) => trace!("Failed to get syncing status {:?}", e),
+            }
+            if Instant::now() - start + poll_interval > timeout {
+                return Err(Web3Error::SyncingNode(format!(
+                    "Node did not finish syncing within {} seconds",
+                    timeout.as_secs()
+                )));
+            }
+            delay_for(poll_interval).await;
         }
     }
 
     pub async fn eth_send_transaction(
         &self,
         transactions: Vec<TransactionRequest>,
-    ) -> Result<Uint256, Web3Error> {
+    ) -> Result<TxHash, Web3Error> {
+        for transaction in transactions.iter() {
+            transaction.validate_send()?;
+        }
         self.jsonrpc_client
             .request_method("eth_sendTransaction", transactions, self.timeout)
             .await
     }
 
     pub async fn eth_call(&self, transaction: TransactionRequest) -> Result<Data, Web3Error> {
+        transaction.validate()?;
         //syncing check
         match self.eth_syncing().await? {
             false => {
@@ -203,16 +832,20 @@ impl Web3 {
         }
     }
 
+    /// Performs an eth_call against the state at the end of `block`, which fails with
+    /// `StateUnavailable` if the node has pruned it
     pub async fn eth_call_at_height(
         &self,
         transaction: TransactionRequest,
         block: Uint256,
     ) -> Result<Data, Web3Error> {
+        transaction.validate()?;
         let latest_known_block = self.eth_synced_block_number().await?;
         if block <= latest_known_block {
             self.jsonrpc_client
                 .request_method("eth_call", (transaction, block), self.timeout)
                 .await
+                .map_err(|e| e.map_state_unavailable(block))
         } else if self.eth_syncing().await? {
             Err(Web3Error::SyncingNode(
                 "Cannot perform eth_call_at_height".to_string(),
@@ -225,39 +858,102 @@ impl Web3 {
         }
     }
 
+    /// Performs an eth_call at the given block with the provided state overrides applied,
+    /// allowing calls to be simulated against modified balances, code or storage
+    pub async fn eth_call_with_overrides(
+        &self,
+        transaction: TransactionRequest,
+        block: BlockId,
+        overrides: StateOverride,
+    ) -> Result<Data, Web3Error> {
+        self.jsonrpc_client
+            .request_method("eth_call", (transaction, block, overrides), self.timeout)
+            .await
+    }
+
     /// Retrieves the latest synced block number regardless of state of eth node
     pub async fn eth_synced_block_number(&self) -> Result<Uint256, Web3Error> {
+        self.cache.block_number(|| self.fetch_block_number()).await
+    }
+
+    pub async fn eth_block_number(&self) -> Result<Uint256, Web3Error> {
+        self.cache
+            .block_number(|| async {
+                match self.eth_syncing().await? {
+                    false => self.fetch_block_number().await,
+                    true => Err(Web3Error::SyncingNode(
+                        "Cannot perform eth_block_number".to_string(),
+                    )),
+                }
+            })
+            .await
+    }
+
+    async fn fetch_block_number(&self) -> Result<Uint256, Web3Error> {
         self.jsonrpc_client
             .request_method("eth_blockNumber", Vec::<String>::new(), self.timeout)
             .await
     }
 
-    pub async fn eth_block_number(&self) -> Result<Uint256, Web3Error> {
-        match self.eth_syncing().await? {
-            false => self.eth_synced_block_number().await,
-            true => Err(Web3Error::SyncingNode(
-                "Cannot perform eth_block_number".to_string(),
-            )),
-        }
+    /// Gets the latest finalized block number, falling back to the finality fallback depth
+    /// below latest if the node does not support the "finalized" tag
+    pub async fn eth_finalized_block_number(&self) -> Result<Uint256, Web3Error> {
+        self.tagged_block_number(BlockId::Finalized).await
     }
 
-    /// Gets the latest finalized block
-    pub async fn eth_finalized_block_number(&self) -> Result<Uint256, Web3Error> {
-        match self.eth_syncing().await? {
-            false => Ok(self.eth_get_finalized_block().await?.number),
-            true => Err(Web3Error::SyncingNode(
-                "Cannot perform eth_block_number".to_string(),
-            )),
+    /// Gets the latest safe block number, falling back to the finality fallback depth below
+    /// latest if the node does not support the "safe" tag
+    pub async fn eth_safe_block_number(&self) -> Result<Uint256, Web3Error> {
+        self.tagged_block_number(BlockId::Safe).await
+    }
+
+    async fn tagged_block_number(&self, tag: BlockId) -> Result<Uint256, Web3Error> {
+        if self.eth_syncing().await? {
+            return Err(Web3Error::SyncingNode(format!(
+                "Cannot get the {:?} block number",
+                tag
+            )));
+        }
+        let res: Result<Option<ConciseBlock>, Web3Error> = self
+            .jsonrpc_client
+            .request_method("eth_getBlockByNumber", (tag, false), self.timeout)
+            .await;
+        match res {
+            Ok(Some(block)) => Ok(block.number),
+            // nodes without support for the tag either reject it or return no block, any
+            // other failure is returned so that a throttled request can not report a block
+            // that is not final as finalized
+            Ok(None) => self.tag_fallback_block_number(tag).await,
+            Err(e) if e.is_unsupported_block_tag() => self.tag_fallback_block_number(tag).await,
+            Err(e) => Err(e),
         }
     }
 
-    pub async fn eth_get_block_by_number(&self, block_number: Uint256) -> Result<Block, Web3Error> {
+    /// The finality fallback depth below latest, for nodes that do not support `tag`
+    async fn tag_fallback_block_number(&self, tag: BlockId) -> Result<Uint256, Web3Error> {
+        trace!(
+            "{:?} tag unsupported, falling back to {} blocks behind latest",
+            tag,
+            self.finality_fallback_depth
+        );
+        let latest = self.eth_synced_block_number().await?;
+        Ok(latest
+            .checked_sub(Uint256::from_u64(self.finality_fallback_depth))
+            .unwrap_or_default())
+    }
+
+    /// Gets a full block by number, returning `None` if the node does not have the block,
+    /// including blocks after the head of a synced node
+    pub async fn eth_get_block_by_number(
+        &self,
+        block_number: Uint256,
+    ) -> Result<Option<Block>, Web3Error> {
         let latest_known_block = self.eth_synced_block_number().await?;
         if block_number <= latest_known_block {
             self.jsonrpc_client
                 .request_method(
                     "eth_getBlockByNumber",
-                    (format!("{:#x}", block_number), true),
+                    (quantity_to_hex(block_number), true),
                     self.timeout,
                 )
                 .await
@@ -266,22 +962,21 @@ impl Web3 {
                 "Cannot perform eth_get_block_by_number".to_string(),
             ))
         } else {
-            Err(Web3Error::BadInput(
-                "Cannot perform eth_get_block_by_number, block number invalid".to_string(),
-            ))
+            // a synced node does not have blocks after its head yet
+            Ok(None)
         }
     }
 
     pub async fn xdai_get_block_by_number(
         &self,
         block_number: Uint256,
-    ) -> Result<XdaiBlock, Web3Error> {
+    ) -> Result<Option<XdaiBlock>, Web3Error> {
         let latest_known_block = self.eth_synced_block_number().await?;
         if block_number <= latest_known_block {
             self.jsonrpc_client
                 .request_method(
                     "eth_getBlockByNumber",
-                    (format!("{:#x}", block_number), true),
+                    (quantity_to_hex(block_number), true),
                     self.timeout,
                 )
                 .await
@@ -290,22 +985,21 @@ impl Web3 {
                 "Cannot perform xdai_get_block_by_number".to_string(),
             ))
         } else {
-            Err(Web3Error::BadInput(
-                "Cannot perform xdai_get_block_by_number, block number invalid".to_string(),
-            ))
+            // a synced node does not have blocks after its head yet
+            Ok(None)
         }
     }
 
     pub async fn eth_get_concise_block_by_number(
         &self,
         block_number: Uint256,
-    ) -> Result<ConciseBlock, Web3Error> {
+    ) -> Result<Option<ConciseBlock>, Web3Error> {
         let latest_known_block = self.eth_synced_block_number().await?;
         if block_number <= latest_known_block {
             self.jsonrpc_client
                 .request_method(
                     "eth_getBlockByNumber",
-                    (format!("{:#x}", block_number), false),
+                    (quantity_to_hex(block_number), false),
                     self.timeout,
                 )
                 .await
@@ -314,22 +1008,21 @@ impl Web3 {
                 "Cannot perform eth_get_concise_block_by_number".to_string(),
             ))
         } else {
-            Err(Web3Error::BadInput(
-                "Cannot perform eth_get_concise_block_by_number, block number invalid".to_string(),
-            ))
+            // a synced node does not have blocks after its head yet
+            Ok(None)
         }
     }
 
     pub async fn xdai_get_concise_block_by_number(
         &self,
         block_number: Uint256,
-    ) -> Result<ConciseXdaiBlock, Web3Error> {
+    ) -> Result<Option<ConciseXdaiBlock>, Web3Error> {
         let latest_known_block = self.eth_synced_block_number().await?;
         if block_number <= latest_known_block {
             self.jsonrpc_client
                 .request_method(
                     "eth_getBlockByNumber",
-                    (format!("{:#x}", block_number), false),
+                    (quantity_to_hex(block_number), false),
                     self.timeout,
                 )
                 .await
@@ -338,12 +1031,29 @@ impl Web3 {
                 "Cannot perform xdai_get_concise_block_by_number".to_string(),
             ))
         } else {
-            Err(Web3Error::BadInput(
-                "Cannot perform xdai_get_concise_block_by_number, block number invalid".to_string(),
-            ))
+            // a synced node does not have blocks after its head yet
+            Ok(None)
         }
     }
 
+    /// Gets a full block by number or tag, returning `None` if the block does not exist yet
+    pub async fn eth_get_block_by_id(&self, block: BlockId) -> Result<Option<Block>, Web3Error> {
+        self.jsonrpc_client
+            .request_method("eth_getBlockByNumber", (block, true), self.timeout)
+            .await
+    }
+
+    /// Gets a block without full transactions by number or tag, returning `None`
+    /// if the block does not exist yet
+    pub async fn eth_get_concise_block_by_id(
+        &self,
+        block: BlockId,
+    ) -> Result<Option<ConciseBlock>, Web3Error> {
+        self.jsonrpc_client
+            .request_method("eth_getBlockByNumber", (block, false), self.timeout)
+            .await
+    }
+
     pub async fn eth_get_latest_block(&self) -> Result<ConciseBlock, Web3Error> {
         match self.eth_syncing().await? {
             false => {
@@ -411,46 +1121,183 @@ impl Web3 {
         }
     }
 
-    pub async fn eth_send_raw_transaction(&self, data: Vec<u8>) -> Result<Uint256, Web3Error> {
+    /// Retrieves the account and storage Merkle proofs for an address at the given block,
+    /// `storage_keys` are the storage slots to produce proofs for and may be empty
+    pub async fn eth_get_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<Uint256>,
+        block: BlockId,
+    ) -> Result<Proof, Web3Error> {
+        let storage_keys: Vec<String> = storage_keys.into_iter().map(word_to_hex).collect();
+        self.jsonrpc_client
+            .request_method(
+                "eth_getProof",
+                (address.to_string(), storage_keys, block),
+                self.timeout,
+            )
+            .await
+    }
+
+    /// Returns the runtime bytecode deployed at `address`, empty if it is not a contract
+    pub async fn eth_get_code(&self, address: Address, block: BlockId) -> Result<Data, Web3Error> {
+        self.jsonrpc_client
+            .request_method("eth_getCode", (address.to_string(), block), self.timeout)
+            .await
+    }
+
+    /// Returns the 32 byte word stored in `slot` of the storage of `address`
+    pub async fn eth_get_storage_at(
+        &self,
+        address: Address,
+        slot: Uint256,
+        block: BlockId,
+    ) -> Result<Uint256, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_getStorageAt",
+                (address.to_string(), word_to_hex(slot), block),
+                self.timeout,
+            )
+            .await
+    }
+
+    pub async fn eth_send_raw_transaction(&self, data: Vec<u8>) -> Result<TxHash, Web3Error> {
         self.jsonrpc_client
             .request_method(
                 "eth_sendRawTransaction",
-                vec![format!("0x{}", bytes_to_hex_str(&data))],
+                vec![data_to_hex(&data)],
                 self.timeout,
             )
             .await
     }
 
+    /// Returns the receipt of a mined transaction, or `None` if it has not been mined
+    pub async fn eth_get_transaction_receipt(
+        &self,
+        hash: impl Into<TxHash>,
+    ) -> Result<Option<TransactionReceipt>, Web3Error> {
+        self.jsonrpc_client
+            .request_method("eth_getTransactionReceipt", vec![hash.into()], self.timeout)
+            .await
+    }
+
+    /// Returns the receipts of every transaction in a block in transaction order, or `None`
+    /// if the block does not exist. Nodes without eth_getBlockReceipts are handled by fetching
+    /// up to `RECEIPT_FETCH_CONCURRENCY` receipts at a time, with identical results.
+    pub async fn eth_get_block_receipts(
+        &self,
+        block: BlockId,
+    ) -> Result<Option<Vec<TransactionReceipt>>, Web3Error> {
+        let res: Result<Option<Vec<TransactionReceipt>>, Web3Error> = self
+            .jsonrpc_client
+            .request_method("eth_getBlockReceipts", vec![block], self.timeout)
+            .await;
+        match res {
+            Ok(receipts) => Ok(receipts),
+            Err(e) if e.json_rpc_code() == Some(JsonRpcErrorCode::MethodNotFound) => {
+                trace!("eth_getBlockReceipts not available, fetching receipts individually");
+                self.block_receipts_individually(block).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn block_receipts_individually(
+        &self,
+        block: BlockId,
+    ) -> Result<Option<Vec<TransactionReceipt>>, Web3Error> {
+        let block = match self.eth_get_concise_block_by_id(block).await? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        // the futures are driven here rather than spawned, so this also works without a
+        // tokio runtime, and at most RECEIPT_FETCH_CONCURRENCY requests are in flight
+        let number = block.number;
+        let receipts: Vec<_> = stream::iter(block.transactions)
+            .map(|hash| async move {
+                match self.eth_get_transaction_receipt(hash).await? {
+                    Some(receipt) => Ok::<_, Web3Error>(receipt),
+                    None => Err(Web3Error::BadResponse(format!(
+                        "No receipt for transaction {} in block {:#x}",
+                        hash, number
+                    ))),
+                }
+            })
+            .buffered(RECEIPT_FETCH_CONCURRENCY)
+            .try_collect()
+            .await?;
+        Ok(Some(receipts))
+    }
+
     pub async fn eth_get_transaction_by_hash(
         &self,
-        hash: Uint256,
+        hash: impl Into<TxHash>,
     ) -> Result<Option<TransactionResponse>, Web3Error> {
         if let Ok(true) = self.eth_syncing().await {
             warn!("Eth node is currently syncing, eth_get_transaction_by_hash may not work if transaction is not synced");
         }
 
+        self.jsonrpc_client
+            .request_method("eth_getTransactionByHash", vec![hash.into()], self.timeout)
+            .await
+    }
+
+    /// Gets the transaction at `index` in a block, returning `None` if the block does not
+    /// exist or has fewer transactions
+    pub async fn eth_get_transaction_by_block_number_and_index(
+        &self,
+        block: BlockId,
+        index: u64,
+    ) -> Result<Option<TransactionResponse>, Web3Error> {
         self.jsonrpc_client
             .request_method(
-                "eth_getTransactionByHash",
-                // XXX: Technically it doesn't need to be Uint256, but since send_raw_transaction is
-                // returning it we'll keep it consistent.
-                vec![format!("{:#066x}", hash)],
+                "eth_getTransactionByBlockNumberAndIndex",
+                (block, UnpaddedHex::from(index)),
                 self.timeout,
             )
             .await
     }
 
+    /// Gets the header of the uncle at `index` in a block, returning `None` if the block
+    /// does not exist or has fewer uncles
+    pub async fn eth_get_uncle_by_block_number_and_index(
+        &self,
+        block: BlockId,
+        index: u64,
+    ) -> Result<Option<UncleBlock>, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_getUncleByBlockNumberAndIndex",
+                (block, UnpaddedHex::from(index)),
+                self.timeout,
+            )
+            .await
+    }
+
+    /// Gets the number of uncles in a block, returning `None` if the block does not exist
+    pub async fn eth_get_uncle_count_by_block_number(
+        &self,
+        block: BlockId,
+    ) -> Result<Option<Uint256>, Web3Error> {
+        self.jsonrpc_client
+            .request_method("eth_getUncleCountByBlockNumber", vec![block], self.timeout)
+            .await
+    }
+
     pub async fn evm_snapshot(&self) -> Result<Uint256, Web3Error> {
         self.jsonrpc_client
             .request_method("evm_snapshot", Vec::<String>::new(), self.timeout)
             .await
     }
 
-    pub async fn evm_revert(&self, snapshot_id: Uint256) -> Result<Uint256, Web3Error> {
+    /// Reverts the chain to the given snapshot, returning false if the snapshot could not
+    /// be found. Note that a snapshot can only be reverted to once.
+    pub async fn evm_revert(&self, snapshot_id: Uint256) -> Result<bool, Web3Error> {
         self.jsonrpc_client
             .request_method(
                 "evm_revert",
-                vec![format!("{:#066x}", snapshot_id)],
+                vec![quantity_to_hex(snapshot_id)],
                 self.timeout,
             )
             .await
@@ -468,19 +1315,119 @@ impl Web3 {
     pub async fn send_transaction(
         &self,
         to_address: Address,
-        data: Vec<u8>,
+        data: impl Into<Data>,
         value: Uint256,
         own_address: Address,
         secret: &PrivateKey,
         options: Vec<SendTxOption>,
-    ) -> Result<Uint256, Web3Error> {
+    ) -> Result<TxHash, Web3Error> {
+        let data = data.into().0;
+        self.send_transaction_from(to_address, data, value, own_address, secret, options)
+            .await
+    }
+
+    /// The same as `send_transaction` but signs with any `TransactionSigner`, such as a
+    /// remote signer or a `NodeSigner`, instead of a private key held in this process
+    pub async fn send_transaction_with_signer(
+        &self,
+        to_address: Address,
+        data: impl Into<Data>,
+        value: Uint256,
+        signer: &dyn TransactionSigner,
+        options: Vec<SendTxOption>,
+    ) -> Result<TxHash, Web3Error> {
+        let data = data.into().0;
+        self.send_transaction_from(to_address, data, value, signer.address(), signer, options)
+            .await
+    }
+
+    /// Builds and signs a transaction exactly as `send_transaction_with_signer` would without
+    /// broadcasting it, send it with `broadcast`. The hash is computed locally so that the
+    /// transaction can be tracked even if the response from the node is lost.
+    /// Preparing is the slow part, so batch senders can sign their next transactions while
+    /// earlier ones are still being broadcast.
+    pub async fn sign_transaction(
+        &self,
+        to_address: Address,
+        data: impl Into<Data>,
+        value: Uint256,
+        signer: &dyn TransactionSigner,
+        options: Vec<SendTxOption>,
+    ) -> Result<SignedTx, Web3Error> {
+        let data = data.into().0;
+        self.sign_transaction_from(to_address, data, value, signer.address(), signer, options)
+            .await
+    }
+
+    /// Prepares a transaction for `broadcast`, this is `sign_transaction` for batch senders.
+    /// The balance, nonce, chain id and gas price are requested concurrently, so a transaction
+    /// takes about as long to prepare as the slowest of them. The chain id is only requested
+    /// once with `CacheConfig::chain_id` set and the node suggested gas price is reused for
+    /// `CacheConfig::gas_price_ttl`, so with both set preparing many transactions at once
+    /// costs little more than their nonce and balance lookups.
+    pub async fn prepare_transaction(
+        &self,
+        to_address: Address,
+        data: impl Into<Data>,
+        value: Uint256,
+        signer: &dyn TransactionSigner,
+        options: Vec<SendTxOption>,
+    ) -> Result<PreparedTx, Web3Error> {
+        self.sign_transaction(to_address, data, value, signer, options)
+            .await
+    }
+
+    /// Broadcasts a signed transaction, returning its hash. A transaction the node already
+    /// has is not an error, so this can be used to rebroadcast through any number of nodes.
+    pub async fn broadcast(&self, transaction: &SignedTx) -> Result<TxHash, Web3Error> {
+        match self.eth_send_raw_transaction(transaction.raw.clone()).await {
+            Ok(hash) if hash == transaction.hash => Ok(hash),
+            Ok(hash) => Err(Web3Error::BadResponse(format!(
+                "Node returned txid {} for transaction {}",
+                hash, transaction.hash
+            ))),
+            Err(e) if e.is_already_known() => Ok(transaction.hash),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "web30.send_transaction", skip_all, fields(to = %to_address, from = %own_address))
+    )]
+    async fn send_transaction_from(
+        &self,
+        to_address: Address,
+        data: Vec<u8>,
+        value: Uint256,
+        own_address: Address,
+        signer: &dyn TransactionSigner,
+        options: Vec<SendTxOption>,
+    ) -> Result<TxHash, Web3Error> {
+        let transaction = self
+            .sign_transaction_from(to_address, data, value, own_address, signer, options)
+            .await?;
+        self.broadcast(&transaction).await
+    }
+
+    async fn sign_transaction_from(
+        &self,
+        to_address: Address,
+        data: Vec<u8>,
+        value: Uint256,
+        own_address: Address,
+        signer: &dyn TransactionSigner,
+        options: Vec<SendTxOption>,
+    ) -> Result<SignedTx, Web3Error> {
         let mut gas_price = None;
+        let mut gas_strategy = None;
+        let mut max_gas_price = self.max_gas_price;
+        let mut check_balance = false;
         let mut gas_price_multiplier = 1f32;
         let mut gas_limit_multiplier = 1f32;
         let mut gas_limit = None;
         let mut network_id = None;
-        let our_balance = self.eth_get_balance(own_address).await?;
-        let mut nonce = self.eth_get_transaction_count(own_address).await?;
+        let mut nonce = None;
 
         for option in options {
             match option {
@@ -489,48 +1436,51 @@ impl Web3 {
                 SendTxOption::GasLimitMultiplier(glm) => gas_limit_multiplier = glm,
                 SendTxOption::GasLimit(gl) => gas_limit = Some(gl),
                 SendTxOption::NetworkId(ni) => network_id = Some(ni),
-                SendTxOption::Nonce(n) => nonce = n,
+                SendTxOption::Nonce(n) => nonce = Some(n),
+                SendTxOption::GasStrategy(s) => gas_strategy = Some(s),
+                SendTxOption::MaxGasPrice(cap) => max_gas_price = Some(cap),
+                SendTxOption::CheckBalance => check_balance = true,
             }
         }
 
-        let mut gas_price = if let Some(gp) = gas_price {
-            gp
-        } else {
-            let gas_price = self.eth_gas_price().await?;
-            if gas_price.sig_bits() <= 128 {
-                // convert to f64, multiply, then convert back, this
-                // will be lossy but you want an exact price you can set it
-                Uint256::from_u128(
-                    (gas_price.resize_to_u128() as f64 * (gas_price_multiplier as f64)) as u128,
-                )
-            } else {
-                // gas price is insanely high, best effort rounding
-                //gas_price = gas_price
-                //    .checked_mul(Uint256::from_u128(gas_price_multiplier.round() as u128))
-                //    .unwrap()
-
-                // let's return an error because it should not be possible,
-                // the total supply of most chains is in the 10^26 range and u128 fits 10^38
-                return Err(Web3Error::BadInput(
-                    "the gas price is higher than should be possible".to_owned(),
-                ));
-            }
-        };
+        // none of these depend on each other, so they are fetched concurrently rather than
+        // paying for a round trip each
+        let (our_balance, nonce, network_id, mut gas_price) = tokio::try_join!(
+            self.eth_get_balance(own_address),
+            async {
+                match nonce {
+                    Some(nonce) => Ok(nonce),
+                    None => self.eth_get_transaction_count(own_address).await,
+                }
+            },
+            async {
+                match network_id {
+                    Some(network_id) => Ok(network_id),
+                    None => self.signing_chain_id().await,
+                }
+            },
+            self.transaction_gas_price(
+                gas_price,
+                gas_strategy,
+                gas_price_multiplier,
+                max_gas_price,
+            ),
+        )?;
 
         let mut gas_limit = if let Some(gl) = gas_limit {
             gl
         } else {
             let gas = self.simulated_gas_price_and_limit(our_balance).await?;
-            self.eth_estimate_gas(TransactionRequest {
-                from: Some(own_address),
-                to: to_address,
-                nonce: Some(nonce.into()),
-                gas_price: Some(gas.price.into()),
-                gas: Some(gas.limit.into()),
-                value: Some(value.into()),
-                data: Some(data.clone().into()),
-            })
-            .await?
+            let request = TransactionRequest::builder()
+                .from(own_address)
+                .to(to_address)
+                .nonce(nonce)
+                .gas_price(gas.price)
+                .gas(gas.limit)
+                .value(value)
+                .data(data.clone())
+                .build()?;
+            self.eth_estimate_gas(request).await?
         };
 
         // multiply limit by gasLimitMultiplier
@@ -553,11 +1503,20 @@ impl Web3 {
             ));
         }
 
-        let network_id = if let Some(ni) = network_id {
-            ni
-        } else {
-            self.net_version().await?
-        };
+        if check_balance {
+            let need = gas_limit
+                .checked_mul(gas_price)
+                .and_then(|fee| fee.checked_add(value));
+            let need = match need {
+                Some(need) => need,
+                None => return Err(Web3Error::BadInput("Cost overflows a Uint256".to_string())),
+            };
+            // the pending balance accounts for transactions that have not been mined yet
+            let have = self.eth_get_pending_balance(own_address).await?;
+            if have < need {
+                return Err(Web3Error::InsufficientBalance { have, need });
+            }
+        }
 
         // this is an edge case where we are about to send a transaction that can't possibly
         // be valid, we simply don't have the the funds to pay the full gas amount we are promising
@@ -589,12 +1548,102 @@ impl Web3 {
             signature: None,
         };
 
-        let transaction = transaction.sign(secret, Some(network_id));
+        let raw = signer.sign_transaction(transaction, network_id).await?;
+        Ok(SignedTx::new(raw, nonce))
+    }
 
-        self.eth_send_raw_transaction(
-            transaction
-                .to_bytes()
-                .expect("transaction.to_bytes() failed"),
+    /// The gas price to send a legacy transaction with, from a fixed price, a strategy or the
+    /// node suggested price. Errors with `GasPriceTooHigh` if the most that could be paid per
+    /// gas is over `max_gas_price`, which for EIP-1559 fees is their fee cap even if less
+    /// would be paid.
+    async fn transaction_gas_price(
+        &self,
+        gas_price: Option<Uint256>,
+        gas_strategy: Option<GasStrategy>,
+        gas_price_multiplier: f32,
+        max_gas_price: Option<Uint256>,
+    ) -> Result<Uint256, Web3Error> {
+        let check_cap = |suggested: Uint256| match max_gas_price {
+            Some(cap) if suggested > cap => Err(Web3Error::GasPriceTooHigh { suggested, cap }),
+            _ => Ok(suggested),
+        };
+        if let Some(gp) = gas_price {
+            return check_cap(gp);
+        }
+        if let Some(strategy) = gas_strategy {
+            let fees = strategy.0.fetch(self).await?;
+            // checked before anything else is requested, the base fee is not needed to refuse
+            check_cap(fees.max_fee())?;
+            let price = match fees {
+                GasFees::Legacy { price } => price,
+                // this sends legacy transactions, which pay their whole gas price, so pay
+                // the current base fee plus the priority fee without exceeding the fee cap
+                GasFees::Eip1559 {
+                    max_fee,
+                    priority_fee,
+                } => match self.get_base_fee_per_gas().await? {
+                    Some(base_fee) => match base_fee.checked_add(priority_fee) {
+                        Some(price) => min(price, max_fee),
+                        None => max_fee,
+                    },
+                    None => max_fee,
+                },
+            };
+            return Ok(price);
+        }
+        let gas_price = self.eth_gas_price().await?;
+        if gas_price.sig_bits() <= 128 {
+            // convert to f64, multiply, then convert back, this
+            // will be lossy but you want an exact price you can set it
+            let price = Uint256::from_u128(
+                (gas_price.resize_to_u128() as f64 * (gas_price_multiplier as f64)) as u128,
+            );
+            check_cap(price)
+        } else {
+            // let's return an error because it should not be possible,
+            // the total supply of most chains is in the 10^26 range and u128 fits 10^38
+            Err(Web3Error::BadInput(
+                "the gas price is higher than should be possible".to_owned(),
+            ))
+        }
+    }
+
+    /// The chain id to sign transactions for. With `CacheConfig::chain_id` set eth_chainId is
+    /// cached, so this only costs a request for the first transaction. Nodes without it fall
+    /// back to net_version which is the same on nearly every chain.
+    async fn signing_chain_id(&self) -> Result<u64, Web3Error> {
+        match self.eth_chainid().await {
+            Ok(Some(chain_id)) if chain_id.sig_bits() <= 64 => Ok(chain_id.resize_to_u128() as u64),
+            Ok(Some(chain_id)) => Err(Web3Error::BadResponse(format!(
+                "Chain id {} does not fit in a u64",
+                chain_id
+            ))),
+            Ok(None) => self.net_version().await,
+            Err(e) if e.json_rpc_code() == Some(JsonRpcErrorCode::MethodNotFound) => {
+                self.net_version().await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends `amount` wei to `to` in a plain transfer, failing with `InsufficientBalance` before
+    /// sending if the sender can not pay for the amount and gas
+    pub async fn send_eth(
+        &self,
+        to: Address,
+        amount: Uint256,
+        secret: &PrivateKey,
+    ) -> Result<TxHash, Web3Error> {
+        self.send_transaction(
+            to,
+            Vec::new(),
+            amount,
+            secret.to_address(),
+            secret,
+            vec![
+                SendTxOption::GasLimit(Uint256::from_u128(TRANSFER_GAS_LIMIT)),
+                SendTxOption::CheckBalance,
+            ],
         )
         .await
     }
@@ -613,39 +1662,27 @@ impl Web3 {
         &self,
         contract_address: Address,
         value: Uint256,
-        data: Vec<u8>,
+        data: impl Into<Data>,
         own_address: Address,
         height: Option<Uint256>,
-    ) -> Result<Vec<u8>, Web3Error> {
+    ) -> Result<Data, Web3Error> {
         let our_balance = self.eth_get_balance(own_address).await?;
         let nonce = self.eth_get_transaction_count(own_address).await?;
 
         let gas = self.simulated_gas_price_and_limit(our_balance).await?;
-        let transaction = TransactionRequest {
-            from: Some(own_address),
-            to: contract_address,
-            gas: Some(gas.limit.into()),
-            nonce: Some(nonce.into()),
-            gas_price: Some(gas.price.into()),
-            value: Some(value.into()),
-            data: Some(data.clone().into()),
-        };
+        let transaction = TransactionRequest::builder()
+            .from(own_address)
+            .to(contract_address)
+            .gas(gas.limit)
+            .nonce(nonce)
+            .gas_price(gas.price)
+            .value(value)
+            .data(data)
+            .build()?;
 
         match height {
-            Some(height) => {
-                let bytes = match self.eth_call_at_height(transaction, height).await {
-                    Ok(val) => val,
-                    Err(e) => return Err(e),
-                };
-                Ok(bytes.0)
-            }
-            None => {
-                let bytes = match self.eth_call(transaction).await {
-                    Ok(val) => val,
-                    Err(e) => return Err(e),
-                };
-                Ok(bytes.0)
-            }
+            Some(height) => self.eth_call_at_height(transaction, height).await,
+            None => self.eth_call(transaction).await,
         }
     }
 
@@ -654,43 +1691,122 @@ impl Web3 {
     /// blocks to have passed
     pub async fn wait_for_transaction(
         &self,
-        tx_hash: Uint256,
+        tx_hash: impl Into<TxHash>,
+        timeout: Duration,
+        blocks_to_wait: Option<Uint256>,
+    ) -> Result<TransactionResponse, Web3Error> {
+        self.wait_for_transaction_with_options(tx_hash, Some(timeout), blocks_to_wait, None)
+            .await
+    }
+
+    /// Waits for a transaction sent by `sender` with `nonce` to be mined, returning its receipt.
+    /// Unlike `wait_for_transaction` this reports whether the transaction was replaced, meaning
+    /// that the sender's nonce moved past `nonce` without this transaction being mined, which
+    /// happens when it is replaced with a higher fee or dropped and the nonce reused.
+    pub async fn wait_for_transaction_outcome(
+        &self,
+        tx_hash: impl Into<TxHash>,
+        sender: Address,
+        nonce: Uint256,
         timeout: Duration,
+    ) -> Result<TransactionOutcome, Web3Error> {
+        let tx_hash = tx_hash.into();
+        let start = Instant::now();
+        loop {
+            // the nonce is checked before the receipt so that a transaction mined in
+            // between the two requests is found rather than reported as replaced
+            let on_chain_nonce = self.eth_get_transaction_count(sender).await?;
+            if let Some(receipt) = self.eth_get_transaction_receipt(tx_hash).await? {
+                return Ok(TransactionOutcome::Mined(receipt));
+            }
+            if on_chain_nonce > nonce {
+                return Ok(TransactionOutcome::Replaced { mined_nonce: nonce });
+            }
+
+            if Instant::now() - start > timeout {
+                return Ok(TransactionOutcome::TimedOut);
+            }
+            delay_for(self.poll_interval_or(Duration::from_secs(1))).await;
+        }
+    }
+
+    /// Like `wait_for_transaction` but waits forever if no timeout is given. If the sender and
+    /// nonce of the transaction are provided its nonce is checked on every poll, so that if a
+    /// different transaction with the same nonce is mined this fails fast with
+    /// `TransactionReplaced` instead of waiting for a transaction that will never be included.
+    pub async fn wait_for_transaction_with_options(
+        &self,
+        tx_hash: impl Into<TxHash>,
+        timeout: Option<Duration>,
+        blocks_to_wait: Option<Uint256>,
+        sender_nonce: Option<(Address, Uint256)>,
+    ) -> Result<TransactionResponse, Web3Error> {
+        self.wait_for_tx_hash(tx_hash.into(), timeout, blocks_to_wait, sender_nonce)
+            .await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "web30.wait_for_transaction", skip_all, fields(hash = %tx_hash))
+    )]
+    async fn wait_for_tx_hash(
+        &self,
+        tx_hash: TxHash,
+        timeout: Option<Duration>,
         blocks_to_wait: Option<Uint256>,
+        sender_nonce: Option<(Address, Uint256)>,
     ) -> Result<TransactionResponse, Web3Error> {
         let start = Instant::now();
         loop {
-            delay_for(Duration::from_secs(1)).await;
-            match self.eth_get_transaction_by_hash(tx_hash).await {
-                Ok(maybe_transaction) => {
-                    if let Some(transaction) = maybe_transaction {
+            delay_for(self.poll_interval_or(Duration::from_secs(1))).await;
+            // the nonce is checked before the transaction so that a transaction mined in
+            // between the two requests is found rather than reported as replaced
+            let on_chain_nonce = match sender_nonce {
+                Some((sender, _)) => Some(self.eth_get_transaction_count(sender).await?),
+                None => None,
+            };
+            let mined = self
+                .eth_get_transaction_by_hash(tx_hash)
+                .await?
+                .and_then(|tx| tx.block_number.map(|block| (tx, block)));
+            match mined {
+                Some((transaction, tx_block)) => {
+                    match blocks_to_wait {
                         // if no wait time is specified and the tx is in a block return right away
-                        if blocks_to_wait.is_none() && transaction.block_number.is_some() {
-                            return Ok(transaction);
-                        }
+                        None => return Ok(transaction),
                         // One the tx is in a block we start waiting here
-                        else if let (Some(blocks_to_wait), Some(tx_block)) =
-                            (blocks_to_wait, transaction.block_number)
-                        {
+                        Some(blocks_to_wait) => {
                             let current_block = if cfg!(feature = "extra_finalization") {
                                 self.eth_finalized_block_number().await?
                             } else {
                                 self.eth_block_number().await?
                             };
                             // we check for underflow, which is possible on testnets
-                            if current_block > blocks_to_wait
-                                && current_block.checked_sub(blocks_to_wait).unwrap() >= tx_block
-                            {
-                                return Ok(transaction);
+                            if let Some(confirmed) = current_block.checked_sub(blocks_to_wait) {
+                                if confirmed >= tx_block {
+                                    return Ok(transaction);
+                                }
                             }
                         }
                     }
                 }
-                Err(e) => return Err(e),
+                None => {
+                    if let (Some((_, nonce)), Some(on_chain_nonce)) = (sender_nonce, on_chain_nonce)
+                    {
+                        if on_chain_nonce > nonce {
+                            return Err(Web3Error::TransactionReplaced {
+                                hash: tx_hash,
+                                nonce,
+                            });
+                        }
+                    }
+                }
             }
 
-            if Instant::now() - start > timeout {
-                return Err(Web3Error::TransactionTimeout);
+            if let Some(timeout) = timeout {
+                if Instant::now() - start > timeout {
+                    return Err(Web3Error::TransactionTimeout(Some(tx_hash)));
+                }
             }
         }
     }
@@ -776,8 +1892,39 @@ impl Web3 {
         }
     }
 
-    /// Waits for the next Ethereum block to be produced
+    /// Waits for the next Ethereum block to be produced, using a block filter if the node
+    /// supports them and otherwise polling the block number
     pub async fn wait_for_next_block(&self, timeout: Duration) -> Result<(), Web3Error> {
+        let filter_id = match self.eth_new_block_filter().await {
+            Ok(filter_id) => filter_id,
+            Err(e) => {
+                trace!("Block filter not available, polling instead {:?}", e);
+                return self.wait_for_next_block_polling(timeout).await;
+            }
+        };
+
+        let start = Instant::now();
+        let mut res = Err(Web3Error::NoBlockProduced { time: timeout });
+        while Instant::now() - start < timeout {
+            delay_for(self.poll_interval_or(Duration::from_secs(1))).await;
+            match self.eth_get_block_filter_changes(filter_id).await {
+                Ok(hashes) if !hashes.is_empty() => {
+                    res = Ok(());
+                    break;
+                }
+                // errors should not exit early
+                Ok(_) | Err(_) => {}
+            }
+        }
+
+        if let Err(e) = self.eth_uninstall_filter(filter_id).await {
+            warn!("Failed to uninstall block filter {:#x} {:?}", filter_id, e);
+        }
+        res
+    }
+
+    /// Waits for the next Ethereum block to be produced by polling the block number
+    async fn wait_for_next_block_polling(&self, timeout: Duration) -> Result<(), Web3Error> {
         let start = Instant::now();
         let mut last_height: Option<Uint256> = None;
         while Instant::now() - start < timeout {
@@ -793,7 +1940,7 @@ impl Web3 {
             }
             // without this, under the block production speeds of some chains
             // this while loop would run as fast as it can and cause issues.
-            delay_for(Duration::from_secs(1)).await;
+            delay_for(self.poll_interval_or(Duration::from_secs(1))).await;
         }
         Err(Web3Error::NoBlockProduced { time: timeout })
     }
@@ -881,3 +2028,1124 @@ async fn test_request_timeout() {
     let val = web3.xdai_get_latest_block().await;
     val.unwrap_err();
 }
+
+#[ignore]
+#[tokio::test]
+async fn test_custom_request() {
+    let web3 = Web3::new("https://eth.althea.net", Duration::from_secs(5));
+
+    let block: Uint256 = web3
+        .custom_request("eth_blockNumber", Vec::<String>::new())
+        .await
+        .unwrap();
+    assert!(block > u256!(10));
+
+    let chain_id = web3
+        .custom_request_value("eth_chainId", serde_json::json!([]))
+        .await
+        .unwrap();
+    assert_eq!(chain_id, serde_json::json!("0x1"));
+}
+
+#[tokio::test]
+async fn test_node_info() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "net_version" => Ok(json!("31337")),
+        "net_peerCount" => Ok(json!("0x1a")),
+        "net_listening" => Ok(json!(true)),
+        "web3_clientVersion" => Ok(json!("anvil/v0.2.0")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let info = web3.node_info().await.unwrap();
+    assert_eq!(info.network_id, 31337);
+    assert_eq!(info.peer_count, 26);
+    assert!(info.listening);
+    assert_eq!(info.client_version, "anvil/v0.2.0");
+    assert_eq!(info.client_kind, ClientKind::Anvil);
+    assert!(info.client_kind.is_dev_chain());
+}
+
+#[tokio::test]
+async fn test_web3_sha3() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, params| match method {
+        "web3_sha3" if params[0] == json!("0x68656c6c6f") => Ok(json!(
+            "0x1c8aff950685c2ed4bc3174f3472287b56d9517b9c948127319a09a7a36deac8"
+        )),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let hash = web3.web3_sha3(b"hello").await.unwrap();
+    assert_eq!(word_to_hex(hash), data_to_hex(&crate::keccak256(b"hello")));
+}
+
+#[tokio::test]
+async fn test_wait_for_next_block_filter() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_newBlockFilter" => Ok(json!("0x2")),
+        "eth_getFilterChanges" => Ok(json!([
+            "0x8b9ef028f99016cd3cb8d4168df7491a0bf44f08b678d37f63ab61e782c500ab"
+        ])),
+        "eth_uninstallFilter" => Ok(json!(true)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let hashes = web3.eth_get_block_filter_changes(u256!(2)).await.unwrap();
+    assert_eq!(hashes.len(), 1);
+    assert_eq!(hashes[0][0], 0x8b);
+
+    web3.wait_for_next_block(Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(node.calls("eth_blockNumber"), 0);
+    assert_eq!(node.calls("eth_uninstallFilter"), 1);
+}
+
+#[tokio::test]
+async fn test_wait_for_next_block_fallback() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let height = AtomicU64::new(100);
+    let node = MockNode::start(move |method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_blockNumber" => Ok(json!(format!(
+            "{:#x}",
+            height.fetch_add(1, Ordering::SeqCst)
+        ))),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    web3.wait_for_next_block(Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(node.calls("eth_newBlockFilter"), 1);
+    assert_eq!(node.calls("eth_blockNumber"), 2);
+}
+
+#[tokio::test]
+async fn test_get_block_params() {
+    use crate::jsonrpc::mock::{geth_block, MockNode};
+    use serde_json::{json, Value};
+    use std::fs::read_to_string;
+
+    let block = geth_block();
+    let concise_block: Value =
+        serde_json::from_str(&read_to_string("test_files/concise_geth_eth_block.json").unwrap())
+            .unwrap();
+    let node = MockNode::start(move |method, params| match method {
+        "eth_blockNumber" => Ok(json!("0x1000000")),
+        // blocks past the head do not exist yet
+        "eth_getBlockByNumber" if params[0] == json!("0x2000000") => Ok(Value::Null),
+        // only transaction hashes are returned unless full transactions are asked for
+        "eth_getBlockByNumber" if params[1] == json!(false) => Ok(concise_block.clone()),
+        "eth_getBlockByNumber" => Ok(block.clone()),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    web3.eth_get_block_by_number(u256!(0xbeef)).await.unwrap();
+    assert!(web3
+        .eth_get_block_by_id(BlockId::Finalized)
+        .await
+        .unwrap()
+        .is_some());
+    assert!(web3
+        .eth_get_block_by_id(BlockId::Number(u256!(0x2000000)))
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .eth_get_concise_block_by_id(BlockId::Safe)
+        .await
+        .unwrap()
+        .is_some());
+
+    assert_eq!(
+        node.params("eth_getBlockByNumber"),
+        vec![
+            json!(["0xbeef", true]),
+            json!(["finalized", true]),
+            json!(["0x2000000", true]),
+            json!(["safe", false]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_null_results() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::{json, Value};
+
+    // a node that has none of what is asked for, as when it is lagging or pruned
+    let node = MockNode::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!("0x1000000")),
+        "eth_syncing" => Ok(json!(false)),
+        _ => Ok(Value::Null),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let hash = u256!(1);
+    let block = BlockId::Number(u256!(0xbeef));
+
+    assert!(web3
+        .eth_get_block_by_number(u256!(0xbeef))
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .xdai_get_block_by_number(u256!(0xbeef))
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .eth_get_concise_block_by_number(u256!(0xbeef))
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .xdai_get_concise_block_by_number(u256!(0xbeef))
+        .await
+        .unwrap()
+        .is_none());
+    // blocks after the head are not an error either
+    let future = u256!(0x1000001);
+    assert!(web3
+        .eth_get_block_by_number(future)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .xdai_get_block_by_number(future)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .eth_get_concise_block_by_number(future)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .xdai_get_concise_block_by_number(future)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3.eth_get_block_by_id(block).await.unwrap().is_none());
+    assert!(web3
+        .eth_get_concise_block_by_id(block)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .eth_get_transaction_receipt(hash)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .eth_get_transaction_by_hash(hash)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .eth_get_transaction_by_block_number_and_index(block, 0)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .eth_get_uncle_by_block_number_and_index(block, 0)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .eth_get_uncle_count_by_block_number(block)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3.eth_get_block_receipts(block).await.unwrap().is_none());
+
+    // the same for the receipts of a missing block on a node without eth_getBlockReceipts
+    let node = MockNode::start(|method, _| match method {
+        "eth_getBlockReceipts" => Err((-32601, "Method not found".to_string())),
+        _ => Ok(Value::Null),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    assert!(web3.eth_get_block_receipts(block).await.unwrap().is_none());
+    assert_eq!(node.calls("eth_getBlockByNumber"), 1);
+}
+
+#[tokio::test]
+async fn test_finalized_block_number() {
+    use crate::jsonrpc::mock::{empty_block, MockNode};
+    use serde_json::json;
+
+    let block = empty_block(100);
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_getBlockByNumber" if params[0] == json!("finalized") => Ok(block.clone()),
+        "eth_getBlockByNumber" => Err((-32602, "invalid block tag".to_string())),
+        "eth_blockNumber" => Ok(json!("0x70")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let mut web3 = Web3::new(&node.url, Duration::from_secs(5));
+    assert_eq!(
+        web3.get_finality_fallback_depth(),
+        DEFAULT_FINALITY_FALLBACK_DEPTH
+    );
+
+    assert_eq!(web3.eth_finalized_block_number().await.unwrap(), u256!(100));
+    assert_eq!(node.calls("eth_blockNumber"), 0);
+
+    // "safe" is rejected so latest minus the fallback depth is used
+    assert_eq!(web3.eth_safe_block_number().await.unwrap(), u256!(100));
+    web3.set_finality_fallback_depth(200);
+    assert_eq!(web3.eth_safe_block_number().await.unwrap(), u256!(0));
+    assert_eq!(node.calls("eth_blockNumber"), 2);
+
+    // a throttled or failing node must not be mistaken for one without the tag
+    for (code, message) in [
+        (-32005, "rate limit exceeded"),
+        (-32000, "request timed out"),
+        (-32603, "internal error"),
+    ] {
+        let node = MockNode::start(move |method, _| match method {
+            "eth_syncing" => Ok(json!(false)),
+            "eth_getBlockByNumber" => Err((code, message.to_string())),
+            "eth_blockNumber" => Ok(json!("0x70")),
+            _ => Err((-32601, "Method not found".to_string())),
+        });
+        let web3 = Web3::new(&node.url, Duration::from_secs(5));
+        assert!(
+            web3.eth_finalized_block_number().await.is_err(),
+            "{}",
+            message
+        );
+        assert_eq!(node.calls("eth_blockNumber"), 0);
+    }
+}
+
+#[tokio::test]
+async fn test_response_cache() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_chainId" => Ok(json!("0x1")),
+        "eth_blockNumber" => Ok(json!("0x10")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let mut web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    // without caching every call is a request
+    for _ in 0..10 {
+        web3.eth_block_number().await.unwrap();
+    }
+    assert_eq!(node.calls("eth_blockNumber"), 10);
+
+    web3.set_cache_config(CacheConfig::with_ttl(Duration::from_secs(60)));
+    // 50 concurrent callers share the one refresh in flight
+    let handles: Vec<_> = (0..50)
+        .map(|_| {
+            let web3 = web3.clone();
+            tokio::spawn(async move { web3.eth_block_number().await })
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(handle.await.unwrap().unwrap(), u256!(0x10));
+    }
+    for _ in 0..50 {
+        web3.eth_chainid().await.unwrap();
+        web3.eth_synced_block_number().await.unwrap();
+    }
+    // 100 block number and 50 chain id requests turned into one each
+    assert_eq!(node.calls("eth_blockNumber"), 11);
+    assert_eq!(node.calls("eth_chainId"), 1);
+
+    web3.invalidate_caches();
+    web3.eth_block_number().await.unwrap();
+    web3.eth_chainid().await.unwrap();
+    assert_eq!(node.calls("eth_blockNumber"), 12);
+    assert_eq!(node.calls("eth_chainId"), 2);
+}
+
+#[tokio::test]
+async fn test_metrics_sink() {
+    use crate::jsonrpc::mock::MockNode;
+    use crate::metrics::MetricsCollector;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!("0x10")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let collector = Arc::new(MetricsCollector::new());
+    let web3 = Web3::builder(&node.url)
+        .timeout(Duration::from_secs(5))
+        .metrics_sink(collector.clone())
+        .build();
+
+    for _ in 0..3 {
+        web3.eth_synced_block_number().await.unwrap();
+    }
+    web3.net_version().await.unwrap_err();
+    // the second call shares the response of the first, which is the only request counted
+    let block_number = || {
+        web3.jsonrpc_client.request_method::<_, Uint256>(
+            "eth_blockNumber",
+            Vec::<String>::new(),
+            web3.timeout,
+        )
+    };
+    let (first, second) = tokio::join!(block_number(), block_number());
+    assert_eq!(first.unwrap(), second.unwrap());
+    assert_eq!(node.calls("eth_blockNumber"), 4);
+
+    let snapshot = collector.snapshot();
+    assert_eq!(snapshot["eth_blockNumber"].requests, 4);
+    assert_eq!(snapshot["eth_blockNumber"].errors, 0);
+    assert_eq!(snapshot["net_version"].requests, 1);
+    assert_eq!(snapshot["net_version"].errors, 1);
+    assert_eq!(
+        snapshot["net_version"].latency_buckets.iter().sum::<u64>(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_archive_queries() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    // a full node that only has the state of the last 128 blocks
+    let pruned = MockNode::start(|method, params| {
+        match method {
+        "eth_getBalance" if params[1] == json!("0x1") => Err((
+            -32000,
+            "missing trie node 9a5c1e7bd2ec03d0a3bd5c6b1e4d85d1c3e7c1f39d8b1f0c0d6ba1c4e8f2a7d3 (path )"
+                .to_string(),
+        )),
+        "eth_getBalance" => Ok(json!("0x64")),
+        _ => Err((-32601, "Method not found".to_string())),
+    }
+    });
+    let web3 = Web3::new(&pruned.url, Duration::from_secs(5));
+    let address: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+
+    assert_eq!(
+        web3.eth_get_balance_at_block(address, u256!(20000000))
+            .await
+            .unwrap(),
+        u256!(100)
+    );
+    assert_eq!(
+        pruned.params("eth_getBalance")[0],
+        json!([address.to_string(), "0x1312d00"])
+    );
+    assert!(matches!(
+        web3.eth_get_balance_at_block(address, u256!(1)).await,
+        Err(Web3Error::StateUnavailable { block }) if block == u256!(1)
+    ));
+    assert!(!web3.supports_archive_queries().await.unwrap());
+
+    let archive = MockNode::start(|method, _| match method {
+        "eth_getBalance" => Ok(json!("0x0")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&archive.url, Duration::from_secs(5));
+    assert!(web3.supports_archive_queries().await.unwrap());
+
+    // other failures are not mistaken for a missing archive
+    let down = MockNode::start(|_, _| Err((-32603, "internal error".to_string())));
+    let web3 = Web3::new(&down.url, Duration::from_secs(5));
+    assert!(web3.supports_archive_queries().await.is_err());
+}
+
+#[tokio::test]
+async fn test_builder_poll_interval() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    // the block number increases every few polls
+    let polls = Arc::new(AtomicU64::new(0));
+    let node = MockNode::start(move |method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_blockNumber" => Ok(json!(format!(
+            "{:#x}",
+            polls.fetch_add(1, Ordering::SeqCst) / 5
+        ))),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::builder(&node.url)
+        .timeout(Duration::from_secs(5))
+        .poll_interval(Duration::from_millis(50))
+        .retries(2)
+        .max_concurrent_requests(4)
+        .build();
+    assert_eq!(web3.get_timeout(), Duration::from_secs(5));
+    assert_eq!(web3.get_poll_interval(), Some(Duration::from_millis(50)));
+
+    let start = Instant::now();
+    web3.wait_for_next_block(Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(node.calls("eth_blockNumber") >= 2);
+
+    // a limit of zero is no limit rather than a client that never sends anything
+    let web3 = Web3::builder(&node.url)
+        .timeout(Duration::from_secs(5))
+        .max_concurrent_requests(0)
+        .build();
+    let both = async { tokio::join!(web3.eth_block_number(), web3.eth_block_number()) };
+    let (a, b) = tokio::time::timeout(Duration::from_secs(5), both)
+        .await
+        .unwrap();
+    assert!(a.is_ok() && b.is_ok());
+}
+
+#[tokio::test]
+async fn test_builder_rate_limiter() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_chainId" => Ok(json!("0x1")),
+        "net_version" => Ok(json!("1")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    // net_version is twice as expensive, 2 + 1 tokens then 2 tokens owed at 20 per second
+    let limiter = Arc::new(RateLimiter::new(20.0, 3.0).with_method_weight("net_version", 2));
+    let web3 = Web3::builder(&node.url)
+        .rate_limiter(limiter.clone())
+        .build();
+    let other = Web3::builder(&node.url)
+        .rate_limiter(limiter.clone())
+        .build();
+
+    let start = Instant::now();
+    web3.net_version().await.unwrap();
+    other.eth_chainid().await.unwrap();
+    assert!(start.elapsed() < Duration::from_millis(50));
+    assert!(limiter.utilization().available < 0.5);
+    // waits for the shared budget instead of failing
+    web3.net_version().await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(90));
+    assert_eq!(node.calls("net_version"), 2);
+}
+
+#[tokio::test]
+async fn test_identification_headers() {
+    use crate::jsonrpc::client::{DEFAULT_USER_AGENT, REQUEST_ID_HEADER};
+    use crate::jsonrpc::mock::MockNode;
+    use hyper::header::{HeaderValue, USER_AGENT};
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_chainId" => Ok(json!("0x1")),
+        "net_version" => Ok(json!("1")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    web3.eth_chainid().await.unwrap();
+    let (headers, _) = &node.raw_requests("eth_chainId")[0];
+    assert_eq!(headers[USER_AGENT], DEFAULT_USER_AGENT);
+    assert!(DEFAULT_USER_AGENT.starts_with("web30/0."));
+    assert!(headers.get(REQUEST_ID_HEADER).is_none());
+
+    let web3 = Web3::builder(&node.url)
+        .app_identifier("my-indexer/1.2")
+        .request_id_header(true)
+        .build();
+    web3.eth_chainid().await.unwrap();
+    web3.net_version().await.unwrap();
+    let (headers, body) = &node.raw_requests("net_version")[0];
+    assert_eq!(
+        headers[USER_AGENT],
+        format!("{} my-indexer/1.2", DEFAULT_USER_AGENT)
+    );
+    // the header carries the same id as the JSON-RPC request
+    let body: serde_json::Value = serde_json::from_slice(body).unwrap();
+    assert_eq!(headers[REQUEST_ID_HEADER], body["id"].to_string());
+    let (first, _) = &node.raw_requests("eth_chainId")[1];
+    assert_ne!(first[REQUEST_ID_HEADER], headers[REQUEST_ID_HEADER]);
+
+    // explicit headers take precedence over the configured user agent
+    let mut explicit = HeaderMap::new();
+    explicit.insert(USER_AGENT, HeaderValue::from_static("curl/8.0"));
+    let web3 = Web3::builder(&node.url)
+        .user_agent("replaced/1.0")
+        .headers(explicit)
+        .build();
+    web3.net_version().await.unwrap();
+    let (headers, _) = &node.raw_requests("net_version")[1];
+    assert_eq!(headers.get_all(USER_AGENT).iter().count(), 1);
+    assert_eq!(headers[USER_AGENT], "curl/8.0");
+
+    let web3 = Web3::builder(&node.url).user_agent("replaced/1.0").build();
+    web3.net_version().await.unwrap();
+    let (headers, _) = &node.raw_requests("net_version")[2];
+    assert_eq!(headers[USER_AGENT], "replaced/1.0");
+}
+
+#[cfg(test)]
+fn mock_receipt(hash: &serde_json::Value, index: usize) -> serde_json::Value {
+    serde_json::json!({
+        "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+        "blockNumber": "0xeff35f",
+        "contractAddress": null,
+        "cumulativeGasUsed": "0xa12515",
+        "effectiveGasPrice": "0x5a9c688d4",
+        "from": "0x6221a9c005f6e47eb398fd867784cacfdcfff4e7",
+        "gasUsed": "0xb4c8",
+        "logs": [],
+        "logsBloom": "0x00",
+        "status": "0x1",
+        "to": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        "transactionHash": hash,
+        "transactionIndex": format!("{:#x}", index),
+        "type": "0x2"
+    })
+}
+
+#[tokio::test]
+async fn test_block_receipts_fallback() {
+    use crate::jsonrpc::mock::{geth_block, MockNode};
+    use serde_json::{json, Value};
+
+    let hashes: Vec<Value> = (1..=5).map(|i| json!(format!("{:#066x}", i))).collect();
+    let mut block = geth_block();
+    block["transactions"] = json!(hashes);
+    let receipts: Vec<Value> = hashes
+        .iter()
+        .enumerate()
+        .map(|(i, hash)| mock_receipt(hash, i))
+        .collect();
+
+    let native_receipts = receipts.clone();
+    let native = MockNode::start(move |method, _| match method {
+        "eth_getBlockReceipts" => Ok(json!(native_receipts)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let fallback = MockNode::start(move |method, params| match method {
+        "eth_getBlockByNumber" => Ok(block.clone()),
+        "eth_getTransactionReceipt" => Ok(receipts
+            .iter()
+            .find(|r| r["transactionHash"] == params[0])
+            .cloned()
+            .unwrap_or(Value::Null)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+
+    let native_res = Web3::new(&native.url, Duration::from_secs(5))
+        .eth_get_block_receipts(BlockId::Number(u256!(0xeff35f)))
+        .await
+        .unwrap()
+        .unwrap();
+    let fallback_res = Web3::new(&fallback.url, Duration::from_secs(5))
+        .eth_get_block_receipts(BlockId::Number(u256!(0xeff35f)))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(native_res.len(), 5);
+    assert_eq!(native_res, fallback_res);
+    assert_eq!(fallback.calls("eth_getBlockReceipts"), 1);
+    assert_eq!(fallback.calls("eth_getTransactionReceipt"), 5);
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_block_receipts_geth() {
+    // run against a geth dev node with some transactions in the latest block
+    let web3 = Web3::new("http://localhost:8545", Duration::from_secs(30));
+    let native = web3
+        .eth_get_block_receipts(BlockId::Latest)
+        .await
+        .unwrap()
+        .unwrap();
+    let block = BlockId::Number(native[0].block_number);
+    assert_eq!(
+        web3.eth_get_block_receipts(block).await.unwrap(),
+        web3.block_receipts_individually(block).await.unwrap()
+    );
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_blob_transactions_mainnet() {
+    // rollups post blobs in nearly every mainnet block, so the search is short
+    let web3 = Web3::new("https://eth.althea.net", Duration::from_secs(30));
+    let mut number = web3.eth_block_number().await.unwrap();
+    let (block, tx) = loop {
+        let block = web3.eth_get_block_by_number(number).await.unwrap().unwrap();
+        match block.transactions.iter().find(|tx| tx.tx_type() == 3) {
+            Some(tx) => break (block.clone(), tx.clone()),
+            None => number = number.checked_sub(u256!(1)).unwrap(),
+        }
+    };
+    // extra only holds fields such as yParity, not the blob fields
+    assert!(
+        !tx.extra.keys().any(|k| k.to_lowercase().contains("blob")),
+        "{:?}",
+        tx.extra
+    );
+    assert!(tx.max_fee_per_blob_gas.is_some());
+    let blobs = tx.blob_versioned_hashes.as_ref().unwrap().len() as u64;
+    assert!(blobs > 0);
+    assert!(block.excess_blob_gas.is_some());
+    assert!(block.parent_beacon_block_root.is_some());
+
+    // every blob uses 2^17 blob gas
+    let receipt = web3
+        .eth_get_transaction_receipt(tx.hash)
+        .await
+        .unwrap()
+        .unwrap();
+    let blob_gas = Uint256::from_u64(blobs << 17);
+    assert_eq!(receipt.blob_gas_used, Some(blob_gas));
+    assert!(block.blob_gas_used.unwrap() >= blob_gas);
+    assert!(receipt.blob_gas_price.unwrap() >= u256!(1));
+}
+
+#[tokio::test]
+async fn test_block_contents_by_index() {
+    use crate::jsonrpc::mock::{geth_block, MockNode};
+    use serde_json::{json, Value};
+
+    let block = geth_block();
+    let mut uncle = block.clone();
+    for field in ["transactions", "uncles", "size", "totalDifficulty"] {
+        uncle.as_object_mut().unwrap().remove(field);
+    }
+    let node = MockNode::start(move |method, params| {
+        let index = |i: usize| {
+            let s = params[i].as_str().unwrap().trim_start_matches("0x");
+            usize::from_str_radix(s, 16).unwrap()
+        };
+        match method {
+            "eth_getTransactionByBlockNumberAndIndex" => Ok(block["transactions"]
+                .get(index(1))
+                .cloned()
+                .unwrap_or(Value::Null)),
+            "eth_getUncleByBlockNumberAndIndex" if index(1) == 0 => Ok(uncle.clone()),
+            "eth_getUncleByBlockNumberAndIndex" => Ok(Value::Null),
+            "eth_getUncleCountByBlockNumber" => Ok(json!("0x1")),
+            _ => Err((-32601, "Method not found".to_string())),
+        }
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let block_id = BlockId::Number(u256!(0xb086d6));
+
+    let tx = web3
+        .eth_get_transaction_by_block_number_and_index(block_id, 17)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(tx.block_number, Some(u256!(0xb086d6)));
+    assert!(web3
+        .eth_get_transaction_by_block_number_and_index(block_id, 100_000)
+        .await
+        .unwrap()
+        .is_none());
+    // both params are unpadded hex quantities
+    assert_eq!(
+        node.params("eth_getTransactionByBlockNumberAndIndex")[0],
+        json!(["0xb086d6", "0x11"])
+    );
+
+    let uncle = web3
+        .eth_get_uncle_by_block_number_and_index(block_id, 0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(uncle.number, u256!(0xb086d6));
+    assert!(uncle.size.is_none());
+    assert!(web3
+        .eth_get_uncle_by_block_number_and_index(block_id, 1)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        web3.eth_get_uncle_count_by_block_number(block_id)
+            .await
+            .unwrap(),
+        Some(u256!(1))
+    );
+}
+
+#[tokio::test]
+async fn test_wait_for_transaction_replaced() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::{json, Value};
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        // the sender has already used nonce 5 but our transaction is unknown
+        "eth_getTransactionCount" => Ok(json!("0x6")),
+        "eth_getTransactionByHash" => Ok(Value::Null),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let sender: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+
+    let res = web3
+        .wait_for_transaction_with_options(u256!(1), None, None, Some((sender, u256!(5))))
+        .await;
+    assert!(matches!(
+        res,
+        Err(Web3Error::TransactionReplaced { nonce, .. }) if nonce == u256!(5)
+    ));
+
+    let res = web3
+        .wait_for_transaction(u256!(1), Duration::from_millis(1), None)
+        .await;
+    assert!(matches!(res, Err(Web3Error::TransactionTimeout(Some(_)))));
+}
+
+#[tokio::test]
+async fn test_wait_for_transaction_outcome() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::{json, Value};
+
+    let receipt = json!({
+        "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+        "blockNumber": "0xeff35f",
+        "contractAddress": null,
+        "cumulativeGasUsed": "0xa12515",
+        "from": "0x1111111111111111111111111111111111111111",
+        "gasUsed": "0xb4c8",
+        "logs": [],
+        "status": "0x1",
+        "to": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+        "transactionHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+        "transactionIndex": "0x66"
+    });
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_getTransactionCount" => Ok(json!("0x6")),
+        // only the first transaction was mined
+        "eth_getTransactionReceipt" if params[0] == json!(format!("{:#066x}", 1)) => {
+            Ok(receipt.clone())
+        }
+        "eth_getTransactionReceipt" => Ok(Value::Null),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let sender: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let timeout = Duration::from_secs(5);
+
+    match web3
+        .wait_for_transaction_outcome(u256!(1), sender, u256!(5), timeout)
+        .await
+        .unwrap()
+    {
+        TransactionOutcome::Mined(receipt) => assert_eq!(receipt.gas_used, u256!(0xb4c8)),
+        outcome => panic!("Unexpected outcome {:?}", outcome),
+    }
+    assert_eq!(
+        web3.wait_for_transaction_outcome(u256!(2), sender, u256!(5), timeout)
+            .await
+            .unwrap(),
+        TransactionOutcome::Replaced {
+            mined_nonce: u256!(5)
+        }
+    );
+    assert_eq!(
+        web3.wait_for_transaction_outcome(u256!(2), sender, u256!(6), Duration::from_millis(1))
+            .await
+            .unwrap(),
+        TransactionOutcome::TimedOut
+    );
+}
+
+#[tokio::test]
+async fn test_send_transaction_gas_price_cap() {
+    use crate::gas_price::{Fixed, GasStrategy};
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::{json, Value};
+    use std::fs::read_to_string;
+
+    let block: Value = serde_json::from_str(
+        &read_to_string("test_files/concise_geth_shanghai_block.json").unwrap(),
+    )
+    .unwrap();
+    let node = MockNode::start(move |method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_chainId" => Ok(json!("0x1")),
+        "eth_getBalance" => Ok(json!("0xde0b6b3a7640000")),
+        "eth_getTransactionCount" => Ok(json!("0x0")),
+        "eth_getBlockByNumber" => Ok(block.clone()),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let mut web3 = Web3::new(&node.url, Duration::from_secs(5));
+    web3.set_max_gas_price(Some(u256!(100)));
+    let secret: PrivateKey = "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
+        .parse()
+        .unwrap();
+    let fees = GasStrategy::new(Fixed(GasFees::Eip1559 {
+        max_fee: u256!(150),
+        priority_fee: u256!(1),
+    }));
+
+    // the fee cap of EIP-1559 fees is compared even if less would be paid, before the base
+    // fee is looked up
+    let res = web3
+        .send_transaction(
+            secret.to_address(),
+            Vec::new(),
+            u256!(1),
+            secret.to_address(),
+            &secret,
+            vec![SendTxOption::GasStrategy(fees)],
+        )
+        .await;
+    assert!(matches!(
+        res,
+        Err(Web3Error::GasPriceTooHigh { suggested, cap })
+            if suggested == u256!(150) && cap == u256!(100)
+    ));
+    assert_eq!(node.calls("eth_getBlockByNumber"), 0);
+
+    let res = web3
+        .send_transaction(
+            secret.to_address(),
+            Vec::new(),
+            u256!(1),
+            secret.to_address(),
+            &secret,
+            vec![
+                SendTxOption::GasPrice(u256!(200)),
+                SendTxOption::MaxGasPrice(u256!(199)),
+            ],
+        )
+        .await;
+    assert!(matches!(res, Err(Web3Error::GasPriceTooHigh { .. })));
+    assert_eq!(node.calls("eth_sendRawTransaction"), 0);
+}
+
+#[tokio::test]
+async fn test_send_transaction_insufficient_balance() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        // a pending transaction has spent most of the confirmed balance
+        "eth_getBalance" if params[1] == "pending" => Ok(json!("0x3e8")),
+        "eth_getBalance" => Ok(json!("0xde0b6b3a7640000")),
+        "eth_getTransactionCount" => Ok(json!("0x0")),
+        "eth_chainId" => Ok(json!("0x1")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let secret: PrivateKey = "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
+        .parse()
+        .unwrap();
+
+    let res = web3
+        .send_transaction(
+            secret.to_address(),
+            Vec::new(),
+            u256!(1),
+            secret.to_address(),
+            &secret,
+            vec![
+                SendTxOption::GasPrice(u256!(1)),
+                SendTxOption::GasLimit(u256!(21000)),
+                SendTxOption::CheckBalance,
+            ],
+        )
+        .await;
+    assert!(matches!(
+        res,
+        Err(Web3Error::InsufficientBalance { have, need })
+            if have == u256!(1000) && need == u256!(21001)
+    ));
+    assert_eq!(node.calls("eth_sendRawTransaction"), 0);
+}
+
+#[tokio::test]
+async fn test_sign_and_broadcast() {
+    use crate::jsonrpc::mock::MockNode;
+    use clarity::utils::hex_str_to_bytes;
+    use serde_json::json;
+    use sha3::{Digest, Keccak256};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    // the first node hashes what it is sent like a real node, the second already has it
+    let broadcasts = AtomicU64::new(0);
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_getBalance" => Ok(json!("0xde0b6b3a7640000")),
+        "eth_getTransactionCount" => Ok(json!("0x7")),
+        "net_version" => Ok(json!("1")),
+        "eth_sendRawTransaction" => match broadcasts.fetch_add(1, Ordering::SeqCst) {
+            0 => {
+                let raw = hex_str_to_bytes(params[0].as_str().unwrap()).unwrap();
+                Ok(json!(data_to_hex(&Keccak256::digest(raw))))
+            }
+            1 => Err((-32000, "already known".to_string())),
+            _ => Ok(json!(format!("{:#066x}", 1))),
+        },
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let secret: PrivateKey = "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
+        .parse()
+        .unwrap();
+
+    let signed = web3
+        .sign_transaction(
+            secret.to_address(),
+            Vec::new(),
+            u256!(1),
+            &secret,
+            vec![
+                SendTxOption::GasPrice(u256!(1)),
+                SendTxOption::GasLimit(u256!(21000)),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(signed.nonce, u256!(7));
+    assert_eq!(node.calls("eth_sendRawTransaction"), 0);
+
+    assert_eq!(web3.broadcast(&signed).await.unwrap(), signed.hash);
+    // rebroadcasting a transaction the node has is not an error
+    assert_eq!(web3.broadcast(&signed).await.unwrap(), signed.hash);
+    assert!(matches!(
+        web3.broadcast(&signed).await,
+        Err(Web3Error::BadResponse(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_prepare_transaction_prefetch() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::{json, Value};
+    use std::fs::read_to_string;
+
+    let block: Value = serde_json::from_str(
+        &read_to_string("test_files/concise_geth_shanghai_block.json").unwrap(),
+    )
+    .unwrap();
+    let latency = Duration::from_millis(100);
+    let node = MockNode::start_with_latency(latency, move |method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_getBalance" => Ok(json!("0xde0b6b3a7640000")),
+        "eth_getTransactionCount" => Ok(json!("0x7")),
+        "eth_chainId" => Ok(json!("0x1")),
+        "eth_gasPrice" => Ok(json!("0x3b9aca00")),
+        "eth_getBlockByNumber" => Ok(block.clone()),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    // nothing is cached, every request goes to the node
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let secret: PrivateKey = "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
+        .parse()
+        .unwrap();
+
+    // the gas price is the slowest of the prefetched values, it needs several round trips
+    // of its own to check the base fee
+    let start = Instant::now();
+    web3.eth_gas_price().await.unwrap();
+    let gas_price_time = start.elapsed();
+
+    let start = Instant::now();
+    let prepared = web3
+        .prepare_transaction(
+            secret.to_address(),
+            Vec::new(),
+            u256!(1),
+            &secret,
+            vec![SendTxOption::GasLimit(u256!(21000))],
+        )
+        .await
+        .unwrap();
+    let elapsed = start.elapsed();
+    assert_eq!(prepared.nonce, u256!(7));
+    assert_eq!(node.calls("eth_chainId"), 1);
+    assert_eq!(node.calls("eth_gasPrice"), 2);
+    assert_eq!(node.calls("eth_getBalance"), 1);
+    assert_eq!(node.calls("eth_getTransactionCount"), 1);
+    assert_eq!(node.calls("eth_sendRawTransaction"), 0);
+
+    // the balance, nonce and chain id are fetched while waiting on the gas price. One after
+    // the other they would add at least five round trips, two each for the balance and nonce
+    // with their syncing checks and one for the chain id.
+    assert!(
+        elapsed < gas_price_time + latency * 2,
+        "{:?} with the gas price taking {:?}",
+        elapsed,
+        gas_price_time
+    );
+}
+
+#[tokio::test]
+async fn test_wait_for_eth_balance() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let polls = AtomicU64::new(0);
+    let node = MockNode::start(move |method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        // the balance grows by 10 wei per poll, the second poll is rate limited
+        "eth_getBalance" => match polls.fetch_add(1, Ordering::SeqCst) {
+            1 => Err((-32005, "limit exceeded".to_string())),
+            n => Ok(json!(format!("{:#x}", n * 10))),
+        },
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let address: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+
+    let balance = web3
+        .wait_for_eth_balance(
+            address,
+            u256!(25),
+            Duration::from_secs(5),
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap();
+    assert_eq!(balance, u256!(30));
+
+    let res = web3
+        .wait_for_eth_balance(
+            address,
+            u256!(1000000),
+            Duration::from_millis(50),
+            Duration::from_millis(10),
+        )
+        .await;
+    assert!(matches!(
+        res,
+        Err(Web3Error::BalanceNotReached {
+            last_seen: Some(_),
+            ..
+        })
+    ));
+}
@@ -0,0 +1,446 @@
+//! Calling contracts by function name using a Solidity ABI JSON loaded at runtime, instead of
+//! writing out the signature of every function and decoding its return data by hand
+use crate::client::Web3;
+use crate::event_utils::{decode_events, is_decodable_event, ParamKind};
+use crate::hash::TxHash;
+use crate::jsonrpc::error::Web3Error;
+use crate::signer::TransactionSigner;
+use crate::types::{Log, SendTxOption, TransactionRequest};
+use clarity::abi::{encode_call, Token};
+use clarity::{Address, Uint256};
+
+/// A function or event parameter as it appears in ABI JSON
+#[derive(Debug, Clone, Deserialize)]
+struct AbiParam {
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(default)]
+    indexed: bool,
+    #[serde(default)]
+    components: Vec<AbiParam>,
+}
+
+impl AbiParam {
+    /// The canonical type used in signatures, tuples are written out as their components
+    fn canonical_type(&self) -> String {
+        match self.ty.strip_prefix("tuple") {
+            Some(suffix) => {
+                let components: Vec<String> =
+                    self.components.iter().map(|c| c.canonical_type()).collect();
+                format!("({}){}", components.join(","), suffix)
+            }
+            None => self.ty.clone(),
+        }
+    }
+}
+
+/// An entry of ABI JSON, constructors, errors, fallback and receive entries are ignored
+#[derive(Debug, Clone, Deserialize)]
+struct AbiEntry {
+    #[serde(rename = "type", default = "default_entry_type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    inputs: Vec<AbiParam>,
+    #[serde(default)]
+    outputs: Vec<AbiParam>,
+    #[serde(default)]
+    anonymous: bool,
+    #[serde(rename = "stateMutability")]
+    state_mutability: Option<String>,
+}
+
+/// Old compilers omitted the type of function entries
+fn default_entry_type() -> String {
+    "function".to_string()
+}
+
+/// A function from a contract ABI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbiFunction {
+    pub name: String,
+    /// canonical input types, such as "address" or "(uint256,bool)[]"
+    pub inputs: Vec<String>,
+    /// canonical output types
+    pub outputs: Vec<String>,
+    /// "pure", "view", "nonpayable" or "payable"
+    pub state_mutability: Option<String>,
+}
+
+impl AbiFunction {
+    /// The signature used to derive the method id, such as "transfer(address,uint256)"
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.inputs.join(","))
+    }
+
+    /// Returns true if `args` could be encoded as the inputs of this function
+    fn accepts(&self, args: &[Token]) -> bool {
+        self.inputs.len() == args.len()
+            && self
+                .inputs
+                .iter()
+                .zip(args)
+                .all(|(ty, token)| token_fits(ty, token))
+    }
+}
+
+/// An event from a contract ABI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbiEvent {
+    pub name: String,
+    /// canonical input types and whether they are indexed
+    pub inputs: Vec<(String, bool)>,
+    pub anonymous: bool,
+}
+
+impl AbiEvent {
+    /// The signature in the form accepted by `decode_events`, with indexed markers
+    fn decode_signature(&self) -> String {
+        let inputs: Vec<String> = self
+            .inputs
+            .iter()
+            .map(|(ty, indexed)| {
+                if *indexed {
+                    format!("{} indexed", ty)
+                } else {
+                    ty.clone()
+                }
+            })
+            .collect();
+        let prefix = if self.anonymous { "anonymous " } else { "" };
+        format!("{}{}({})", prefix, self.name, inputs.join(","))
+    }
+}
+
+/// The functions and events of a parsed contract ABI
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Abi {
+    pub functions: Vec<AbiFunction>,
+    pub events: Vec<AbiEvent>,
+}
+
+impl Abi {
+    /// Parses a standard Solidity ABI JSON array, as output by solc and in hardhat and
+    /// foundry artifacts under the "abi" key
+    pub fn parse(json: &str) -> Result<Abi, Web3Error> {
+        let entries: Vec<AbiEntry> = match serde_json::from_str(json) {
+            Ok(entries) => entries,
+            Err(e) => return Err(Web3Error::BadInput(format!("Invalid ABI JSON: {}", e))),
+        };
+        let mut abi = Abi::default();
+        for entry in entries {
+            let types = |params: &[AbiParam]| params.iter().map(|p| p.canonical_type()).collect();
+            match entry.kind.as_str() {
+                "function" => abi.functions.push(AbiFunction {
+                    inputs: types(&entry.inputs),
+                    outputs: types(&entry.outputs),
+                    name: entry.name,
+                    state_mutability: entry.state_mutability,
+                }),
+                "event" => abi.events.push(AbiEvent {
+                    inputs: entry
+                        .inputs
+                        .iter()
+                        .map(|p| (p.canonical_type(), p.indexed))
+                        .collect(),
+                    name: entry.name,
+                    anonymous: entry.anonymous,
+                }),
+                _ => {}
+            }
+        }
+        Ok(abi)
+    }
+
+    /// Finds the function to call with `args`, `name` may be a plain name or a full signature
+    /// such as "safeTransferFrom(address,address,uint256)". Overloaded functions are told
+    /// apart by the number and types of the arguments, an ambiguous call is an error.
+    pub fn function(&self, name: &str, args: &[Token]) -> Result<&AbiFunction, Web3Error> {
+        if name.contains('(') {
+            return match self.functions.iter().find(|f| f.signature() == name) {
+                Some(function) => Ok(function),
+                None => Err(Web3Error::BadInput(format!("No function {} in ABI", name))),
+            };
+        }
+        let mut matches = self
+            .functions
+            .iter()
+            .filter(|f| f.name == name && f.accepts(args));
+        match (matches.next(), matches.next()) {
+            (Some(function), None) => Ok(function),
+            (Some(a), Some(b)) => Err(Web3Error::BadInput(format!(
+                "Call to {} is ambiguous between {} and {}, use the full signature",
+                name,
+                a.signature(),
+                b.signature()
+            ))),
+            (None, _) => Err(Web3Error::BadInput(format!(
+                "No function {} in ABI taking {} arguments of these types",
+                name,
+                args.len()
+            ))),
+        }
+    }
+}
+
+/// Returns false if `token` can not be encoded as the canonical type `ty`, tokens that this
+/// can not check such as arrays and structs are assumed to fit
+fn token_fits(ty: &str, token: &Token) -> bool {
+    match token {
+        Token::Address(_) => ty == "address",
+        Token::Bool(_) => ty == "bool",
+        Token::Uint(_) => ty.starts_with("uint") || ty.starts_with("int"),
+        Token::String(_) => ty == "string",
+        Token::UnboundedBytes(_) => ty == "bytes",
+        Token::Bytes(_) => ty.starts_with("bytes") && ty != "bytes",
+        _ => true,
+    }
+}
+
+/// Decodes abi encoded return data into one token per output type
+fn decode_outputs(outputs: &[String], data: &[u8]) -> Result<Vec<Token>, Web3Error> {
+    let mut res = Vec::new();
+    for (i, ty) in outputs.iter().enumerate() {
+        let kind = match ParamKind::parse(ty) {
+            Some(kind) => kind,
+            None => {
+                return Err(Web3Error::BadInput(format!(
+                    "Can not decode return values of type {}",
+                    ty
+                )))
+            }
+        };
+        match kind.decode_data(data, i * 32) {
+            Some(token) => res.push(token),
+            None => {
+                return Err(Web3Error::ContractCallError(format!(
+                    "Return data does not match output types {:?}",
+                    outputs
+                )))
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// A contract bound to an address and a `Web3` handle, with its ABI loaded at runtime
+#[derive(Clone)]
+pub struct Contract {
+    pub address: Address,
+    pub abi: Abi,
+    web3: Web3,
+}
+
+impl Contract {
+    /// Parses `abi_json`, see `Abi::parse`, and binds it to the contract at `address`
+    pub fn new(address: Address, abi_json: &str, web3: Web3) -> Result<Contract, Web3Error> {
+        Ok(Contract {
+            address,
+            abi: Abi::parse(abi_json)?,
+            web3,
+        })
+    }
+
+    /// Encodes a call to the function `name` with `args`, for use with `send_transaction`
+    /// when more control is needed, such as sending value to a payable function
+    pub fn encode(&self, name: &str, args: &[Token]) -> Result<Vec<u8>, Web3Error> {
+        let function = self.abi.function(name, args)?;
+        Ok(encode_call(&function.signature(), args)?)
+    }
+
+    /// Calls the function `name` with eth_call and decodes its return values according to
+    /// the outputs in the ABI. Outputs can be any type that `decode_events` can decode,
+    /// functions returning arrays or structs should be called with `eth_call` directly.
+    pub async fn call(&self, name: &str, args: &[Token]) -> Result<Vec<Token>, Web3Error> {
+        let function = self.abi.function(name, args)?;
+        let payload = encode_call(&function.signature(), args)?;
+        let res = self
+            .web3
+            .eth_call(TransactionRequest::call(self.address, payload))
+            .await?;
+        decode_outputs(&function.outputs, &res)
+    }
+
+    /// Sends a transaction calling the function `name` with `args`, returning the txid.
+    /// A `PrivateKey` can be passed directly as the signer.
+    pub async fn send(
+        &self,
+        name: &str,
+        args: &[Token],
+        signer: &dyn TransactionSigner,
+        options: Vec<SendTxOption>,
+    ) -> Result<TxHash, Web3Error> {
+        let payload = self.encode(name, args)?;
+        self.web3
+            .send_transaction_with_signer(
+                self.address,
+                payload,
+                Uint256::from_u64(0),
+                signer,
+                options,
+            )
+            .await
+    }
+
+    /// Decodes a log emitted by this contract using the events in the ABI, returning the
+    /// event name and its arguments, or None if the log is from another contract or matches
+    /// no event. Events with array or struct data, such as the TransferBatch of ERC-1155, can
+    /// not be decoded and are skipped.
+    pub fn decode_event(&self, log: &Log) -> Result<Option<(String, Vec<Token>)>, Web3Error> {
+        let signatures: Vec<String> = self
+            .abi
+            .events
+            .iter()
+            .map(|event| event.decode_signature())
+            .filter(|signature| {
+                let decodable = is_decodable_event(signature);
+                if !decodable {
+                    trace!("Skipping event {} that can not be decoded", signature);
+                }
+                decodable
+            })
+            .collect();
+        let signatures: Vec<&str> = signatures.iter().map(|s| s.as_str()).collect();
+        let decoded = decode_events(std::slice::from_ref(log), Some(self.address), &signatures)?;
+        Ok(decoded.into_iter().next().map(|(signature, tokens)| {
+            let name = signature.split('(').next().unwrap_or_default().to_string();
+            (name, tokens)
+        }))
+    }
+}
+
+#[cfg(test)]
+const TEST_ABI: &str = r#"[
+    {"type": "constructor", "inputs": [{"name": "supply", "type": "uint256"}]},
+    {"type": "function", "name": "balanceOf", "stateMutability": "view",
+     "inputs": [{"name": "owner", "type": "address"}],
+     "outputs": [{"name": "", "type": "uint256"}]},
+    {"type": "function", "name": "name", "stateMutability": "view",
+     "inputs": [], "outputs": [{"name": "", "type": "string"}]},
+    {"type": "function", "name": "safeTransferFrom", "stateMutability": "nonpayable",
+     "inputs": [{"name": "from", "type": "address"}, {"name": "to", "type": "address"},
+                {"name": "id", "type": "uint256"}], "outputs": []},
+    {"type": "function", "name": "safeTransferFrom", "stateMutability": "nonpayable",
+     "inputs": [{"name": "from", "type": "address"}, {"name": "to", "type": "address"},
+                {"name": "id", "type": "uint256"}, {"name": "data", "type": "bytes"}],
+     "outputs": []},
+    {"type": "function", "name": "swap", "stateMutability": "nonpayable",
+     "inputs": [{"name": "params", "type": "tuple", "components": [
+         {"name": "token", "type": "address"}, {"name": "amounts", "type": "uint256[]"}]}],
+     "outputs": []},
+    {"type": "event", "name": "Transfer", "anonymous": false,
+     "inputs": [{"name": "from", "type": "address", "indexed": true},
+                {"name": "to", "type": "address", "indexed": true},
+                {"name": "value", "type": "uint256", "indexed": false}]},
+    {"type": "event", "name": "TransferBatch", "anonymous": false,
+     "inputs": [{"name": "operator", "type": "address", "indexed": true},
+                {"name": "from", "type": "address", "indexed": true},
+                {"name": "to", "type": "address", "indexed": true},
+                {"name": "ids", "type": "uint256[]", "indexed": false},
+                {"name": "values", "type": "uint256[]", "indexed": false}]}
+]"#;
+
+#[test]
+fn test_abi_function_lookup() {
+    let abi = Abi::parse(TEST_ABI).unwrap();
+    assert_eq!(abi.functions.len(), 5);
+    assert_eq!(abi.events.len(), 2);
+    assert_eq!(abi.functions[4].signature(), "swap((address,uint256[]))");
+
+    let a: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let args = [a.into(), a.into(), Uint256::from_u64(1).into()];
+    assert_eq!(
+        abi.function("safeTransferFrom", &args).unwrap().signature(),
+        "safeTransferFrom(address,address,uint256)"
+    );
+    let args = [
+        a.into(),
+        a.into(),
+        Uint256::from_u64(1).into(),
+        Token::UnboundedBytes(vec![1]),
+    ];
+    assert_eq!(
+        abi.function("safeTransferFrom", &args).unwrap().signature(),
+        "safeTransferFrom(address,address,uint256,bytes)"
+    );
+    assert!(abi
+        .function("safeTransferFrom(address,address,uint256)", &[])
+        .is_ok());
+    // wrong argument type
+    assert!(abi.function("balanceOf", &[Token::Bool(true)]).is_err());
+    assert!(abi.function("missing", &[]).is_err());
+    assert!(Abi::parse("{}").is_err());
+}
+
+#[tokio::test]
+async fn test_contract_call_and_decode_event() {
+    use crate::jsonrpc::mock::MockNode;
+    use crate::types::Data;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let node = MockNode::start(|method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_call" => match params[0]["data"].as_str().unwrap() {
+            // balanceOf(address)
+            data if data.starts_with("0x70a08231") => Ok(json!(
+                "0x00000000000000000000000000000000000000000000000000000000000003e8"
+            )),
+            // name(), "Token"
+            _ => Ok(json!(
+                "0x0000000000000000000000000000000000000000000000000000000000000020\
+                 0000000000000000000000000000000000000000000000000000000000000005\
+                 546f6b656e000000000000000000000000000000000000000000000000000000"
+            )),
+        },
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let address: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let owner: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let contract = Contract::new(address, TEST_ABI, web3).unwrap();
+
+    let balance = contract
+        .call("balanceOf", &[Token::Address(owner)])
+        .await
+        .unwrap();
+    assert_eq!(balance.len(), 1);
+    assert!(matches!(&balance[0], Token::Uint(v) if *v == Uint256::from_u64(1000)));
+    let name = contract.call("name", &[]).await.unwrap();
+    assert!(matches!(&name[0], Token::String(s) if s == "Token"));
+
+    let mut value = vec![0u8; 32];
+    value[31] = 7;
+    let log = Log {
+        address,
+        topics: vec![
+            Data(
+                clarity::abi::derive_signature("Transfer(address,address,uint256)")
+                    .unwrap()
+                    .to_vec(),
+            ),
+            Data(crate::address_to_event(owner).to_vec()),
+            Data(crate::address_to_event(address).to_vec()),
+        ],
+        data: Data(value),
+        ..Default::default()
+    };
+    // the TransferBatch event in the ABI can not be decoded, which does not stop Transfer
+    let (name, tokens) = contract.decode_event(&log).unwrap().unwrap();
+    assert_eq!(name, "Transfer");
+    assert!(matches!(&tokens[0], Token::Address(a) if *a == owner));
+    assert!(matches!(&tokens[1], Token::Address(a) if *a == address));
+    assert!(matches!(&tokens[2], Token::Uint(v) if *v == Uint256::from_u64(7)));
+    let other = Log {
+        address: owner,
+        ..log
+    };
+    assert!(contract.decode_event(&other).unwrap().is_none());
+}
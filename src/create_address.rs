@@ -0,0 +1,161 @@
+//! Predicting the address of a contract before the transaction deploying it is mined
+//!
+//! See more: https://eips.ethereum.org/EIPS/eip-1014
+use crate::event_utils::address_to_event;
+use crate::hex::data_to_hex;
+use crate::{client::Web3, jsonrpc::error::Web3Error};
+use clarity::{Address, Uint256};
+use sha3::{Digest, Keccak256};
+
+/// Returns the 20 bytes of an address
+fn address_bytes(address: Address) -> [u8; 20] {
+    let mut bytes = [0u8; 20];
+    bytes.copy_from_slice(&address_to_event(address)[12..]);
+    bytes
+}
+
+/// Builds an address from the last 20 bytes of a keccak hash
+fn address_from_hash(hash: &[u8]) -> Address {
+    data_to_hex(&hash[12..32]).parse().unwrap()
+}
+
+/// Returns the big endian bytes of `value` with no leading zeros, zero is empty
+fn minimal_bytes(value: Uint256) -> Vec<u8> {
+    let mut hex = format!("{:x}", value);
+    if hex == "0" {
+        return Vec::new();
+    }
+    if hex.len() % 2 == 1 {
+        hex.insert(0, '0');
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// RLP encodes the list `[deployer, nonce]`, the payload is at most 54 bytes so
+/// only the short forms of strings and lists are ever needed
+fn rlp_deployer_and_nonce(deployer: Address, nonce: Uint256) -> Vec<u8> {
+    let mut payload = vec![0x80 + 20];
+    payload.extend_from_slice(&address_bytes(deployer));
+    let nonce = minimal_bytes(nonce);
+    match nonce.as_slice() {
+        [byte] if *byte < 0x80 => payload.push(*byte),
+        _ => {
+            payload.push(0x80 + nonce.len() as u8);
+            payload.extend_from_slice(&nonce);
+        }
+    }
+
+    let mut res = vec![0xc0 + payload.len() as u8];
+    res.extend(payload);
+    res
+}
+
+/// Returns the address of the contract created by a CREATE from `deployer` with `nonce`,
+/// for an externally owned account this is the nonce of the deploying transaction and for
+/// a contract it is the number of contracts it has created plus one
+pub fn predict_create_address(deployer: Address, nonce: Uint256) -> Address {
+    address_from_hash(&Keccak256::digest(rlp_deployer_and_nonce(deployer, nonce)))
+}
+
+/// Returns the address of the contract created by a CREATE2 from `deployer` with `salt`
+/// and the keccak256 hash of the init code, as specified by EIP-1014
+pub fn predict_create2_address(
+    deployer: Address,
+    salt: [u8; 32],
+    init_code_hash: [u8; 32],
+) -> Address {
+    let mut preimage = vec![0xff];
+    preimage.extend_from_slice(&address_bytes(deployer));
+    preimage.extend_from_slice(&salt);
+    preimage.extend_from_slice(&init_code_hash);
+    address_from_hash(&Keccak256::digest(preimage))
+}
+
+impl Web3 {
+    /// Returns the address of the contract that the next transaction from `deployer` would
+    /// create if it were a deployment, check it against the `contract_address` of the receipt
+    pub async fn predict_next_create_address(
+        &self,
+        deployer: Address,
+    ) -> Result<Address, Web3Error> {
+        let nonce = self.eth_get_transaction_count(deployer).await?;
+        Ok(predict_create_address(deployer, nonce))
+    }
+}
+
+#[test]
+fn test_predict_create_address() {
+    let deployer: Address = "0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0"
+        .parse()
+        .unwrap();
+    let expected = [
+        "0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d",
+        "0x343c43a37d37dff08ae8c4a11544c718abb4fcf8",
+        "0xf778b86fa74e846c4f0a1fbd1335fe81c00a0c91",
+        "0xfffd933a0bc612844eaf0c6fe3e5b8e9b6c1d19c",
+    ];
+    for (nonce, expected) in expected.iter().enumerate() {
+        assert_eq!(
+            predict_create_address(deployer, Uint256::from_u64(nonce as u64)),
+            expected.parse().unwrap()
+        );
+    }
+
+    assert_eq!(minimal_bytes(Uint256::from_u64(0x7f)), vec![0x7f]);
+    assert_eq!(minimal_bytes(Uint256::from_u64(0x100)), vec![0x01, 0x00]);
+    let rlp = rlp_deployer_and_nonce(deployer, Uint256::from_u64(0x80));
+    assert_eq!(rlp[..2], [0xd7, 0x94]);
+    assert_eq!(rlp[22..], [0x81, 0x80]);
+}
+
+#[test]
+fn test_predict_create2_address() {
+    use clarity::utils::hex_str_to_bytes;
+
+    // examples from EIP-1014
+    let vectors = [
+        (
+            "0x0000000000000000000000000000000000000000",
+            "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "0x00",
+            "0x4D1A2e2bB4F88F0250f26Ffff098B0b30B26BF38",
+        ),
+        (
+            "0xdeadbeef00000000000000000000000000000000",
+            "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "0x00",
+            "0xB928f69Bb1D91Cd65274e3c79d8986362984fDA3",
+        ),
+        (
+            "0xdeadbeef00000000000000000000000000000000",
+            "0x000000000000000000000000feed000000000000000000000000000000000000",
+            "0x00",
+            "0xD04116cDd17beBE565EB2422F2497E06cC1C9833",
+        ),
+        (
+            "0x00000000000000000000000000000000deadbeef",
+            "0x00000000000000000000000000000000000000000000000000000000cafebabe",
+            "0xdeadbeef",
+            "0x60f3f640a8508fC6a86d45DF051962668E1e8AC7",
+        ),
+        (
+            "0x0000000000000000000000000000000000000000",
+            "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "0x",
+            "0xE33C0C7F7df4809055C3ebA6c09CFe4BaF1BD9e0",
+        ),
+    ];
+    for (deployer, salt, init_code, expected) in vectors {
+        let mut salt_bytes = [0u8; 32];
+        salt_bytes.copy_from_slice(&hex_str_to_bytes(salt).unwrap());
+        let mut init_code_hash = [0u8; 32];
+        init_code_hash.copy_from_slice(&Keccak256::digest(hex_str_to_bytes(init_code).unwrap()));
+        assert_eq!(
+            predict_create2_address(deployer.parse().unwrap(), salt_bytes, init_code_hash),
+            expected.to_lowercase().parse().unwrap()
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! This module contains wrappers for the debug_ namespace used to trace transactions,
+//! these methods are only available on nodes that have explicitly enabled them
+use crate::hash::TxHash;
+use crate::jsonrpc::error::Web3Error;
+use crate::{
+    client::Web3,
+    types::{BlockId, CallFrame, StateOverride, TraceOptions, TraceResult, TransactionRequest},
+};
+use clarity::{Address, Uint256};
+
+/// The config object of debug_traceCall, which is the regular
+/// trace config with optional state overrides
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceCallConfig {
+    #[serde(flatten)]
+    options: TraceOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state_overrides: Option<StateOverride>,
+}
+
+/// Walks a callTracer call tree and returns the deepest frame that reverted, as the address
+/// of the reverting contract and its revert data. This is usually the call that actually caused
+/// a transaction to fail, outer frames typically just bubble the revert up. Failed contract
+/// creations have no address so the creating address is returned for them instead.
+pub fn find_revert_in_trace(frame: &CallFrame) -> Option<(Address, Vec<u8>)> {
+    fn deepest(frame: &CallFrame, depth: usize) -> Option<(usize, &CallFrame)> {
+        let mut best = if frame.error.is_some() {
+            Some((depth, frame))
+        } else {
+            None
+        };
+        for call in frame.calls.iter() {
+            if let Some((call_depth, call_frame)) = deepest(call, depth + 1) {
+                if best.map_or(true, |(best_depth, _)| call_depth > best_depth) {
+                    best = Some((call_depth, call_frame));
+                }
+            }
+        }
+        best
+    }
+
+    deepest(frame, 0).map(|(_, frame)| {
+        (
+            frame.to.unwrap_or(frame.from),
+            frame.output.clone().map(|v| v.0).unwrap_or_default(),
+        )
+    })
+}
+
+impl Web3 {
+    /// Traces the execution of an already mined transaction. The shape of the result depends on
+    /// the tracer selected in `options`, use `TraceOptions::struct_logger()` for a step by step
+    /// trace or `TraceOptions::call_tracer()` for a call tree. Returns `UnsupportedMethod` if the
+    /// node does not have the debug namespace enabled.
+    pub async fn debug_trace_transaction(
+        &self,
+        tx_hash: impl Into<TxHash>,
+        options: TraceOptions,
+    ) -> Result<TraceResult, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "debug_traceTransaction",
+                (tx_hash.into(), options),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("debug_traceTransaction"))
+    }
+
+    /// Traces a transaction that has not been sent by executing it on top of the given block,
+    /// optionally with state overrides applied in the same format as `eth_call_with_overrides`.
+    /// Combined with `TraceOptions::call_tracer()` and `find_revert_in_trace` this identifies
+    /// which inner call of a transaction would revert.
+    pub async fn debug_trace_call(
+        &self,
+        transaction: TransactionRequest,
+        block: BlockId,
+        options: TraceOptions,
+        state_overrides: Option<StateOverride>,
+    ) -> Result<TraceResult, Web3Error> {
+        let config = TraceCallConfig {
+            options,
+            state_overrides,
+        };
+        self.jsonrpc_client
+            .request_method(
+                "debug_traceCall",
+                (transaction, block, config),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("debug_traceCall"))
+    }
+}
+
+#[test]
+fn test_find_revert_in_trace() {
+    let frame: CallFrame = serde_json::from_str(
+        r#"{
+      "type": "CALL",
+      "from": "0x5a0b54d5dc17e0aadc383d2db43b0a0d3e029c4c",
+      "to": "0x1111111111111111111111111111111111111111",
+      "gas": "0x7148",
+      "gasUsed": "0x5a2b",
+      "input": "0x",
+      "output": "0x01",
+      "error": "execution reverted",
+      "calls": [
+        {
+          "type": "CALL",
+          "from": "0x1111111111111111111111111111111111111111",
+          "to": "0x2222222222222222222222222222222222222222",
+          "gas": "0x1000",
+          "gasUsed": "0x100",
+          "input": "0x"
+        },
+        {
+          "type": "CALL",
+          "from": "0x1111111111111111111111111111111111111111",
+          "to": "0x3333333333333333333333333333333333333333",
+          "gas": "0x1000",
+          "gasUsed": "0x100",
+          "input": "0x",
+          "output": "0x08c379a0",
+          "error": "execution reverted"
+        }
+      ]
+    }"#,
+    )
+    .unwrap();
+
+    let (address, data) = find_revert_in_trace(&frame).unwrap();
+    let expected: Address = "0x3333333333333333333333333333333333333333"
+        .parse()
+        .unwrap();
+    assert_eq!(address, expected);
+    assert_eq!(data, vec![0x08, 0xc3, 0x79, 0xa0]);
+
+    let mut ok = frame.calls[0].clone();
+    ok.error = None;
+    assert!(find_revert_in_trace(&ok).is_none());
+}
@@ -0,0 +1,469 @@
+//! Reading spot and time weighted average prices directly from Uniswap V2 pairs and V3 pools,
+//! and their many forks. Prices are computed exactly with big integers and returned as
+//! decimal strings, never as floats.
+//!
+//! See `amm` for quoting and performing swaps through the Uniswap V3 router.
+use crate::event_utils::{decode_uint_array, ParamKind};
+use crate::jsonrpc::error::Web3Error;
+use crate::units::format_digits;
+use crate::{
+    client::Web3,
+    hash::H256,
+    multicall::word,
+    types::{Data, TransactionRequest},
+};
+use clarity::abi::{derive_method_id, encode_call, Token};
+use clarity::{Address, Uint256};
+use num::{BigUint, One, Zero};
+use std::time::Duration;
+
+/// The least number of significant digits in prices returned by the functions in this module,
+/// digits beyond these are truncated
+pub const PRICE_SIGNIFICANT_DIGITS: usize = 18;
+
+/// The lowest tick a Uniswap V3 pool can be at
+pub const MIN_TICK: i32 = -887272;
+/// The highest tick a Uniswap V3 pool can be at
+pub const MAX_TICK: i32 = 887272;
+
+/// The magic numbers of Uniswap's `TickMath.getSqrtRatioAtTick`, `2^128 / 1.0001^(2^i / 2)`
+/// for each bit `i` of the absolute value of the tick
+const TICK_RATIOS: [u128; 20] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+
+/// The price of a pool's tokens in terms of each other, adjusted for the decimals of both
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DexPrice {
+    /// the pool's token0, which the pool orders before token1 by address
+    pub token0: Address,
+    pub token1: Address,
+    pub token0_decimals: u8,
+    pub token1_decimals: u8,
+    /// how much token1 one whole token0 is worth, as a decimal string
+    pub token0_price: String,
+    /// how much token0 one whole token1 is worth, as a decimal string
+    pub token1_price: String,
+}
+
+fn to_big(n: Uint256) -> BigUint {
+    BigUint::new(n.to_u32_array().into())
+}
+
+/// Reads the 32 byte word at `index` of abi encoded return data
+fn return_word(data: &[u8], index: usize) -> Option<Uint256> {
+    data.get(index * 32..(index + 1) * 32)
+        .and_then(Uint256::from_bytes_be)
+}
+
+/// Reads a signed integer of at most 64 bits, such as an int24 or int56, from its sign
+/// extended 32 byte word
+fn return_i64(word: Uint256) -> Option<i64> {
+    let bytes = H256::from(word).0;
+    let value = i64::from_be_bytes(bytes[24..32].try_into().unwrap());
+    let extension = if value < 0 { 0xff } else { 0 };
+    if bytes[..24].iter().all(|b| *b == extension) {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Formats `numerator / denominator` as a decimal string with at least
+/// `PRICE_SIGNIFICANT_DIGITS` significant digits, `denominator` must not be zero
+fn format_ratio(numerator: &BigUint, denominator: &BigUint) -> String {
+    // enough fractional digits that very small ratios keep their significant digits
+    let decimals = PRICE_SIGNIFICANT_DIGITS
+        + denominator
+            .to_string()
+            .len()
+            .saturating_sub(numerator.to_string().len());
+    let scaled = numerator * BigUint::from(10u32).pow(decimals as u32) / denominator;
+    format_digits(&scaled.to_string(), decimals as u32)
+}
+
+/// Builds a `DexPrice` from the raw price of token0 in token1 base units, `numerator /
+/// denominator`, which must both be nonzero
+fn dex_price(
+    tokens: (Address, Address, u8, u8),
+    numerator: BigUint,
+    denominator: BigUint,
+) -> DexPrice {
+    let (token0, token1, token0_decimals, token1_decimals) = tokens;
+    let ten = BigUint::from(10u32);
+    // a whole token0 is 10^decimals0 base units and is priced in whole token1
+    let numerator = numerator * ten.pow(token0_decimals as u32);
+    let denominator = denominator * ten.pow(token1_decimals as u32);
+    DexPrice {
+        token0,
+        token1,
+        token0_decimals,
+        token1_decimals,
+        token0_price: format_ratio(&numerator, &denominator),
+        token1_price: format_ratio(&denominator, &numerator),
+    }
+}
+
+/// Converts a V3 `sqrtPriceX96`, the square root of the raw price of token0 in token1 as a
+/// Q64.96 fixed point number, into a `DexPrice`
+fn sqrt_price_to_dex_price(
+    tokens: (Address, Address, u8, u8),
+    sqrt_price_x96: Uint256,
+) -> Option<DexPrice> {
+    let sqrt_price = to_big(sqrt_price_x96);
+    if sqrt_price.is_zero() {
+        return None;
+    }
+    Some(dex_price(
+        tokens,
+        &sqrt_price * &sqrt_price,
+        BigUint::one() << 192usize,
+    ))
+}
+
+/// Computes the `sqrtPriceX96` at `tick` exactly as Uniswap's `TickMath.getSqrtRatioAtTick`
+/// does, returning None for ticks outside of `MIN_TICK..=MAX_TICK`
+pub fn sqrt_price_at_tick(tick: i32) -> Option<Uint256> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 1 != 0 {
+        BigUint::from(TICK_RATIOS[0])
+    } else {
+        BigUint::one() << 128usize
+    };
+    for (i, magic) in TICK_RATIOS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << i) != 0 {
+            ratio = (ratio * BigUint::from(*magic)) >> 128usize;
+        }
+    }
+    if tick > 0 {
+        ratio = ((BigUint::one() << 256usize) - 1u32) / ratio;
+    }
+    // from Q128.128 to Q128.96, rounding up
+    let round_up = !(&ratio % (BigUint::one() << 32usize)).is_zero();
+    let ratio: BigUint = (ratio >> 32usize) + if round_up { 1u32 } else { 0u32 };
+
+    let mut res = [0u64; 4];
+    let digits = ratio.to_u64_digits();
+    // at most 160 bits as MAX_TICK is the highest allowed tick
+    res[..digits.len()].copy_from_slice(&digits);
+    Some(Uint256::from_u64_array(res))
+}
+
+/// The arithmetic mean tick over `seconds` given the tick cumulatives at the start and end of
+/// that window, rounded towards negative infinity as Uniswap's `OracleLibrary.consult` does
+fn mean_tick(start_cumulative: i64, end_cumulative: i64, seconds: u32) -> Option<i32> {
+    let delta = end_cumulative.checked_sub(start_cumulative)?;
+    delta.div_euclid(seconds as i64).try_into().ok()
+}
+
+/// Encodes `observe(uint32[])` for the two observations at the start and end of a window
+/// of `seconds` ending now
+fn encode_observe(seconds: u32) -> Result<Vec<u8>, Web3Error> {
+    let mut res = derive_method_id("observe(uint32[])")?.to_vec();
+    res.extend_from_slice(&word(32));
+    res.extend_from_slice(&word(2));
+    res.extend_from_slice(&word(seconds as usize));
+    res.extend_from_slice(&word(0));
+    Ok(res)
+}
+
+/// Decodes the `int56[] tickCumulatives` out of the return data of `observe(uint32[])`
+fn decode_observe(data: &[u8]) -> Option<Vec<i64>> {
+    decode_uint_array(data, 0)?
+        .into_iter()
+        .map(return_i64)
+        .collect()
+}
+
+fn bad_response(function: &str) -> Web3Error {
+    Web3Error::ContractCallError(format!("Bad response from {}", function))
+}
+
+impl Web3 {
+    /// Gets the spot price of a Uniswap V2 pair, or of a pair of any of its forks, from
+    /// the pair's current reserves
+    pub async fn get_uniswap_v2_price(&self, pair: Address) -> Result<DexPrice, Web3Error> {
+        let (tokens, reserves) = tokio::try_join!(
+            self.pool_tokens(pair),
+            self.pool_call(pair, "getReserves()")
+        )?;
+        match (return_word(&reserves, 0), return_word(&reserves, 1)) {
+            (Some(reserve0), Some(reserve1))
+                if reserve0 != Uint256::default() && reserve1 != Uint256::default() =>
+            {
+                Ok(dex_price(tokens, to_big(reserve1), to_big(reserve0)))
+            }
+            (Some(_), Some(_)) => Err(Web3Error::ContractCallError(
+                "Uniswap V2 pair has no liquidity".to_string(),
+            )),
+            _ => Err(bad_response("getReserves")),
+        }
+    }
+
+    /// Gets the spot price of a Uniswap V3 pool from the `sqrtPriceX96` in its `slot0()`. The
+    /// spot price of a pool can be moved within a single block, so anything that needs to resist
+    /// manipulation should use `get_uniswap_v3_twap` instead.
+    pub async fn get_uniswap_v3_price(&self, pool: Address) -> Result<DexPrice, Web3Error> {
+        let (tokens, slot0) =
+            tokio::try_join!(self.pool_tokens(pool), self.pool_call(pool, "slot0()"))?;
+        let sqrt_price_x96 = return_word(&slot0, 0).ok_or_else(|| bad_response("slot0"))?;
+        sqrt_price_to_dex_price(tokens, sqrt_price_x96).ok_or_else(|| {
+            Web3Error::ContractCallError("Uniswap V3 pool is not initialized".to_string())
+        })
+    }
+
+    /// Gets the time weighted average price of a Uniswap V3 pool over the last `window`, using
+    /// the mean tick from the pool's `observe()` oracle, which is a geometric mean of the price,
+    /// like Uniswap's own `OracleLibrary.consult`. The pool must have enough observations stored
+    /// to cover the window, otherwise it reverts with "OLD", see
+    /// `increaseObservationCardinalityNext`.
+    pub async fn get_uniswap_v3_twap(
+        &self,
+        pool: Address,
+        window: Duration,
+    ) -> Result<DexPrice, Web3Error> {
+        let seconds = match u32::try_from(window.as_secs()) {
+            Ok(seconds) if seconds > 0 => seconds,
+            _ => {
+                return Err(Web3Error::BadInput(format!(
+                    "TWAP window of {:?} must be between 1 second and u32::MAX seconds",
+                    window
+                )))
+            }
+        };
+        let (tokens, (start, end)) =
+            tokio::try_join!(self.pool_tokens(pool), self.observe_window(pool, seconds))?;
+
+        let sqrt_price_x96 = mean_tick(start, end, seconds)
+            .and_then(sqrt_price_at_tick)
+            .ok_or_else(|| bad_response("observe"))?;
+        Ok(sqrt_price_to_dex_price(tokens, sqrt_price_x96).expect("tick prices are nonzero"))
+    }
+
+    /// Gets the tick cumulatives at the start and end of a window of `seconds` ending now
+    async fn observe_window(&self, pool: Address, seconds: u32) -> Result<(i64, i64), Web3Error> {
+        let output = self
+            .eth_call(TransactionRequest::call(pool, encode_observe(seconds)?))
+            .await?;
+        match decode_observe(&output).as_deref() {
+            Some([start, end]) => Ok((*start, *end)),
+            _ => Err(bad_response("observe")),
+        }
+    }
+
+    /// Gets the token0 and token1 of a V2 pair or V3 pool along with their decimals
+    async fn pool_tokens(&self, pool: Address) -> Result<(Address, Address, u8, u8), Web3Error> {
+        let (token0, token1) = tokio::try_join!(
+            self.pool_address(pool, "token0()"),
+            self.pool_address(pool, "token1()")
+        )?;
+        let (decimals0, decimals1) =
+            tokio::try_join!(self.token_decimals(token0), self.token_decimals(token1))?;
+        Ok((token0, token1, decimals0, decimals1))
+    }
+
+    async fn pool_address(&self, pool: Address, sig: &str) -> Result<Address, Web3Error> {
+        let data = self.pool_call(pool, sig).await?;
+        match ParamKind::Address.decode_data(&data, 0) {
+            Some(Token::Address(address)) => Ok(address),
+            _ => Err(bad_response(sig)),
+        }
+    }
+
+    /// Reads `decimals()` with a plain eth_call, unlike `get_erc20_decimals` this needs no
+    /// caller address with funds to simulate from
+    async fn token_decimals(&self, token: Address) -> Result<u8, Web3Error> {
+        let data = self.pool_call(token, "decimals()").await?;
+        match return_word(&data, 0) {
+            Some(decimals) if decimals <= Uint256::from_u64(u8::MAX as u64) => {
+                Ok(decimals.resize_to_u128() as u8)
+            }
+            _ => Err(bad_response("decimals")),
+        }
+    }
+
+    async fn pool_call(&self, contract: Address, sig: &str) -> Result<Data, Web3Error> {
+        self.eth_call(TransactionRequest::call(contract, encode_call(sig, &[])?))
+            .await
+    }
+}
+
+#[test]
+fn test_sqrt_price_at_tick() {
+    // vectors from Uniswap's TickMath tests
+    assert_eq!(sqrt_price_at_tick(0).unwrap(), Uint256::from_u128(1 << 96));
+    assert_eq!(
+        sqrt_price_at_tick(MIN_TICK).unwrap(),
+        Uint256::from_u64(4295128739)
+    );
+    assert_eq!(
+        sqrt_price_at_tick(MAX_TICK).unwrap().to_string(),
+        "1461446703485210103287273052203988822378723970342"
+    );
+    assert_eq!(
+        sqrt_price_at_tick(1).unwrap().to_string(),
+        "79232123823359799118286999568"
+    );
+    assert_eq!(
+        sqrt_price_at_tick(-1).unwrap().to_string(),
+        "79224201403219477170569942574"
+    );
+    assert_eq!(
+        sqrt_price_at_tick(-200000).unwrap().to_string(),
+        "3598751819609688046946419"
+    );
+    assert!(sqrt_price_at_tick(MIN_TICK - 1).is_none());
+    assert!(sqrt_price_at_tick(MAX_TICK + 1).is_none());
+}
+
+#[test]
+fn test_mean_tick_rounding() {
+    assert_eq!(mean_tick(0, 6000, 600), Some(10));
+    assert_eq!(mean_tick(100, 100, 600), Some(0));
+    // negative means round down rather than towards zero
+    assert_eq!(mean_tick(0, -6000, 600), Some(-10));
+    assert_eq!(mean_tick(0, -6001, 600), Some(-11));
+    assert_eq!(mean_tick(0, 6001, 600), Some(10));
+    assert_eq!(mean_tick(i64::MIN, i64::MAX, 1), None);
+}
+
+#[test]
+fn test_format_ratio() {
+    let big = |n: u64| BigUint::from(n);
+    assert_eq!(format_ratio(&big(4), &big(1)), "4");
+    assert_eq!(format_ratio(&big(1), &big(4)), "0.25");
+    assert_eq!(format_ratio(&big(1), &big(3)), "0.333333333333333333");
+    // tiny ratios keep their significant digits instead of truncating to zero
+    assert_eq!(
+        format_ratio(&big(1), &(big(3) * BigUint::from(10u32).pow(30))),
+        "0.000000000000000000000000000000333333333333333333"
+    );
+    assert_eq!(format_ratio(&big(2000), &big(1)), "2000");
+}
+
+#[test]
+fn test_return_i64() {
+    let word = |bytes: [u8; 32]| Uint256::from_bytes_be(&bytes).unwrap();
+    let mut negative = [0xff; 32];
+    negative[31] = 0xfe;
+    assert_eq!(return_i64(word(negative)), Some(-2));
+    let mut positive = [0; 32];
+    positive[31] = 2;
+    assert_eq!(return_i64(word(positive)), Some(2));
+    positive[0] = 1;
+    assert_eq!(return_i64(word(positive)), None);
+}
+
+#[tokio::test]
+async fn test_uniswap_prices() {
+    use crate::jsonrpc::mock::MockNode;
+    use clarity::utils::bytes_to_hex_str;
+    use serde_json::json;
+
+    let usdc = "0x1111111111111111111111111111111111111111";
+    let weth = "0x2222222222222222222222222222222222222222";
+    let word_hex = |n: Uint256| bytes_to_hex_str(&H256::from(n).0);
+    let int_hex = |n: i64| {
+        let mut bytes = [if n < 0 { 0xff } else { 0 }; 32];
+        bytes[24..].copy_from_slice(&n.to_be_bytes());
+        bytes_to_hex_str(&bytes)
+    };
+    let address_hex = |a: &str| format!("{:0>64}", a.trim_start_matches("0x"));
+    let reserves = format!(
+        "0x{}{}{}",
+        // 2,000,000 USDC and 1,000 WETH
+        word_hex(Uint256::from_u64(2_000_000_000_000)),
+        word_hex(Uint256::from_u128(1_000_000_000_000_000_000_000)),
+        word_hex(Uint256::from_u64(1_700_000_000)),
+    );
+    // a raw price of exactly 4
+    let slot0 = format!(
+        "0x{}{}",
+        word_hex(Uint256::from_u128(1 << 97)),
+        "0".repeat(64 * 6)
+    );
+    // a mean tick of 200311 over 10 minutes
+    let observe = format!(
+        "0x{}{}{}{}{}",
+        word_hex(Uint256::from_u64(64)),
+        word_hex(Uint256::from_u64(64 + 3 * 32)),
+        word_hex(Uint256::from_u64(2)),
+        int_hex(-1_000_000),
+        int_hex(-1_000_000 + 200311 * 600 + 599),
+    ) + &word_hex(Uint256::from_u64(2))
+        + &"0".repeat(128);
+
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_call" => {
+            let to = params[0]["to"].as_str().unwrap().to_lowercase();
+            let data = params[0]["data"].as_str().unwrap();
+            Ok(json!(match &data[..10] {
+                "0x0dfe1681" => format!("0x{}", address_hex(usdc)),
+                "0xd21220a7" => format!("0x{}", address_hex(weth)),
+                "0x313ce567" if to == usdc => format!("0x{}", word_hex(Uint256::from_u64(6))),
+                "0x313ce567" => format!("0x{}", word_hex(Uint256::from_u64(18))),
+                "0x0902f1ac" => reserves.clone(),
+                "0x3850c7bd" => slot0.clone(),
+                "0x883bdbfd" => {
+                    assert_eq!(
+                        &data[10 + 64 * 2..],
+                        format!("{}{}", word_hex(Uint256::from_u64(600)), "0".repeat(64))
+                    );
+                    observe.clone()
+                }
+                _ => return Err((3, "execution reverted".to_string())),
+            }))
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let pool: Address = "0x00000000000000000000000000000000000000aa"
+        .parse()
+        .unwrap();
+
+    let v2 = web3.get_uniswap_v2_price(pool).await.unwrap();
+    assert_eq!(v2.token0, usdc.parse().unwrap());
+    assert_eq!(v2.token1, weth.parse().unwrap());
+    assert_eq!((v2.token0_decimals, v2.token1_decimals), (6, 18));
+    assert_eq!(v2.token0_price, "0.0005");
+    assert_eq!(v2.token1_price, "2000");
+
+    let v3 = web3.get_uniswap_v3_price(pool).await.unwrap();
+    assert_eq!(v3.token0_price, "0.000000000004");
+    assert_eq!(v3.token1_price, "250000000000");
+
+    let twap = web3
+        .get_uniswap_v3_twap(pool, Duration::from_secs(600))
+        .await
+        .unwrap();
+    assert_eq!(twap.token0_price, "0.000499989927790880815");
+    assert_eq!(twap.token1_price, "2000.040289648088261463");
+
+    assert!(web3
+        .get_uniswap_v3_twap(pool, Duration::from_millis(500))
+        .await
+        .is_err());
+}
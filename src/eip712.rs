@@ -0,0 +1,477 @@
+//! Hashing and signing of EIP-712 typed structured data, used by permit style approvals
+//! and many protocols to authorize actions with an offchain signature
+//!
+//! See more: https://eips.ethereum.org/EIPS/eip-712
+use crate::event_utils::{address_to_event, ParamKind};
+use crate::hex::data_to_hex;
+use crate::jsonrpc::error::Web3Error;
+use crate::keccak::keccak256 as keccak;
+use crate::units::parse_units;
+use crate::{
+    client::Web3,
+    types::{Data, TransactionRequest},
+};
+use clarity::abi::{encode_call, SerializedToken, Token};
+use clarity::utils::hex_str_to_bytes;
+use clarity::{Address, PrivateKey, Uint256};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashMap};
+
+/// A field of a typed data struct, deserializes from the entries of the standard "types" JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypedDataField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+impl TypedDataField {
+    pub fn new(name: &str, ty: &str) -> Self {
+        TypedDataField {
+            name: name.to_string(),
+            ty: ty.to_string(),
+        }
+    }
+}
+
+/// The struct types referenced by typed data, by name, as in the "types" JSON of
+/// `eth_signTypedData_v4`. An "EIP712Domain" entry is allowed and ignored.
+pub type TypedDataTypes = HashMap<String, Vec<TypedDataField>>;
+
+/// The EIP-712 domain, only the fields that are set are included in the domain separator
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Eip712Domain {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub chain_id: Option<Uint256>,
+    pub verifying_contract: Option<Address>,
+    pub salt: Option<[u8; 32]>,
+}
+
+impl Eip712Domain {
+    /// Returns the domain separator, the hashStruct of the domain
+    pub fn separator(&self) -> Result<[u8; 32], Web3Error> {
+        let mut fields = Vec::new();
+        let mut values = Map::new();
+        if let Some(name) = &self.name {
+            fields.push(TypedDataField::new("name", "string"));
+            values.insert("name".to_string(), json!(name));
+        }
+        if let Some(version) = &self.version {
+            fields.push(TypedDataField::new("version", "string"));
+            values.insert("version".to_string(), json!(version));
+        }
+        if let Some(chain_id) = &self.chain_id {
+            fields.push(TypedDataField::new("chainId", "uint256"));
+            values.insert("chainId".to_string(), json!(chain_id.to_string()));
+        }
+        if let Some(contract) = &self.verifying_contract {
+            fields.push(TypedDataField::new("verifyingContract", "address"));
+            values.insert("verifyingContract".to_string(), json!(contract.to_string()));
+        }
+        if let Some(salt) = &self.salt {
+            fields.push(TypedDataField::new("salt", "bytes32"));
+            values.insert("salt".to_string(), json!(data_to_hex(salt)));
+        }
+        let mut types = TypedDataTypes::new();
+        types.insert("EIP712Domain".to_string(), fields);
+        hash_struct("EIP712Domain", &Value::Object(values), &types)
+    }
+}
+
+fn bad(msg: String) -> Web3Error {
+    Web3Error::BadInput(msg)
+}
+
+/// Strips any array suffixes, "Person[][2]" is "Person"
+fn base_type(ty: &str) -> &str {
+    ty.split('[').next().unwrap_or(ty)
+}
+
+/// Adds the struct types that `ty` references, directly or through other structs
+fn collect_dependencies(ty: &str, types: &TypedDataTypes, found: &mut BTreeSet<String>) {
+    let ty = base_type(ty);
+    if found.contains(ty) {
+        return;
+    }
+    if let Some(fields) = types.get(ty) {
+        found.insert(ty.to_string());
+        for field in fields {
+            collect_dependencies(&field.ty, types, found);
+        }
+    }
+}
+
+/// encodeType, the primary type followed by the types it references sorted by name, such as
+/// "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+pub fn encode_type(primary_type: &str, types: &TypedDataTypes) -> Result<String, Web3Error> {
+    if !types.contains_key(primary_type) {
+        return Err(bad(format!("Unknown typed data type {}", primary_type)));
+    }
+    let mut dependencies = BTreeSet::new();
+    collect_dependencies(primary_type, types, &mut dependencies);
+    dependencies.remove(primary_type);
+
+    let mut res = String::new();
+    for ty in std::iter::once(primary_type).chain(dependencies.iter().map(|d| d.as_str())) {
+        let fields: Vec<String> = types[ty]
+            .iter()
+            .map(|f| format!("{} {}", f.ty, f.name))
+            .collect();
+        res += &format!("{}({})", ty, fields.join(","));
+    }
+    Ok(res)
+}
+
+/// Parses a JSON number or decimal or hex string as a uintN, or an intN in two's complement
+fn parse_integer(ty: &str, value: &Value) -> Result<Uint256, Web3Error> {
+    let invalid = || bad(format!("{} is not a valid {}", value, ty));
+    let text = match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => return Err(invalid()),
+    };
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) if ty.starts_with("int") => (true, digits),
+        _ => (false, text.as_str()),
+    };
+    let magnitude = match digits.strip_prefix("0x") {
+        Some(hex) => {
+            let hex = if hex.len() % 2 == 1 {
+                format!("0{}", hex)
+            } else {
+                hex.to_string()
+            };
+            hex_str_to_bytes(&hex)
+                .ok()
+                .filter(|bytes| bytes.len() <= 32)
+                .and_then(|bytes| {
+                    let mut word = [0u8; 32];
+                    word[32 - bytes.len()..].copy_from_slice(&bytes);
+                    Uint256::from_bytes_be(&word)
+                })
+                .ok_or_else(invalid)?
+        }
+        None => parse_units(digits, 0).map_err(|_| invalid())?,
+    };
+    if !negative || magnitude == Uint256::default() {
+        return Ok(magnitude);
+    }
+    // -x is 2^256 - x
+    Uint256::max_value()
+        .checked_sub(magnitude)
+        .and_then(|v| v.checked_add(Uint256::from_u64(1)))
+        .ok_or_else(invalid)
+}
+
+fn parse_hex(ty: &str, value: &Value) -> Result<Vec<u8>, Web3Error> {
+    match value.as_str().map(hex_str_to_bytes) {
+        Some(Ok(bytes)) => Ok(bytes),
+        _ => Err(bad(format!("{} is not valid hex for a {}", value, ty))),
+    }
+}
+
+/// encodeData of a single value as its 32 byte word
+fn encode_value(ty: &str, value: &Value, types: &TypedDataTypes) -> Result<[u8; 32], Web3Error> {
+    if let Some(element) = ty.strip_suffix(']') {
+        let element = match element.rfind('[') {
+            Some(i) => &element[..i],
+            None => return Err(bad(format!("Invalid array type {}", ty))),
+        };
+        let items = match value.as_array() {
+            Some(items) => items,
+            None => return Err(bad(format!("{} is not an array", value))),
+        };
+        let mut encoded = Vec::new();
+        for item in items {
+            encoded.extend_from_slice(&encode_value(element, item, types)?);
+        }
+        return Ok(keccak(&encoded));
+    }
+    if types.contains_key(ty) {
+        return hash_struct(ty, value, types);
+    }
+
+    let mut word = [0u8; 32];
+    match ParamKind::parse(ty) {
+        Some(ParamKind::Address) => {
+            let address: Address = match value.as_str().map(|s| s.to_lowercase().parse()) {
+                Some(Ok(address)) => address,
+                _ => return Err(bad(format!("{} is not an address", value))),
+            };
+            word = address_to_event(address);
+        }
+        Some(ParamKind::Bool) => match value.as_bool() {
+            Some(b) => word[31] = b as u8,
+            None => return Err(bad(format!("{} is not a bool", value))),
+        },
+        Some(ParamKind::Uint) => {
+            let n = parse_integer(ty, value)?;
+            word = match Token::Uint(n).serialize() {
+                SerializedToken::Static(v) => v,
+                SerializedToken::Dynamic(_) => unreachable!(),
+            };
+        }
+        Some(ParamKind::FixedBytes(len)) => {
+            let bytes = parse_hex(ty, value)?;
+            if bytes.len() != len {
+                return Err(bad(format!("{} is not {} bytes", value, len)));
+            }
+            word[..len].copy_from_slice(&bytes);
+        }
+        Some(ParamKind::Bytes) => word = keccak(&parse_hex(ty, value)?),
+        Some(ParamKind::String) => match value.as_str() {
+            Some(s) => word = keccak(s.as_bytes()),
+            None => return Err(bad(format!("{} is not a string", value))),
+        },
+        None => return Err(bad(format!("Unknown typed data type {}", ty))),
+    }
+    Ok(word)
+}
+
+/// hashStruct, the keccak256 of the type hash followed by the encoded fields of `value`
+pub fn hash_struct(
+    primary_type: &str,
+    value: &Value,
+    types: &TypedDataTypes,
+) -> Result<[u8; 32], Web3Error> {
+    let mut encoded = keccak(encode_type(primary_type, types)?.as_bytes()).to_vec();
+    for field in types[primary_type].iter() {
+        let field_value = match value.get(&field.name) {
+            Some(v) => v,
+            None => {
+                return Err(bad(format!(
+                    "Typed data {} is missing field {}",
+                    primary_type, field.name
+                )))
+            }
+        };
+        encoded.extend_from_slice(&encode_value(&field.ty, field_value, types)?);
+    }
+    Ok(keccak(&encoded))
+}
+
+/// Returns the hash that is signed for typed data, `keccak256("\x19\x01" ‖ domainSeparator ‖
+/// hashStruct(message))`. `message` is JSON as passed to `eth_signTypedData_v4`, integers
+/// may be numbers or decimal or hex strings, bytes are hex strings.
+pub fn hash_typed_data(
+    domain: &Eip712Domain,
+    types: &TypedDataTypes,
+    primary_type: &str,
+    message: &Value,
+) -> Result<[u8; 32], Web3Error> {
+    let mut encoded = vec![0x19, 0x01];
+    encoded.extend_from_slice(&domain.separator()?);
+    encoded.extend_from_slice(&hash_struct(primary_type, message, types)?);
+    Ok(keccak(&encoded))
+}
+
+/// Signs typed data, returning the 65 byte `r || s || v` signature with a v of 27 or 28
+pub fn sign_typed_data(
+    domain: &Eip712Domain,
+    types: &TypedDataTypes,
+    primary_type: &str,
+    message: &Value,
+    key: &PrivateKey,
+) -> Result<[u8; 65], Web3Error> {
+    let hash = hash_typed_data(domain, types, primary_type, message)?;
+    Ok(key.sign_hash(&hash).to_bytes())
+}
+
+impl Web3 {
+    /// Produces an EIP-2612 permit signature allowing `spender` to spend `value` of the owner's
+    /// `token` until the unix timestamp `deadline`, ready to be split into v, r and s for
+    /// `permit()`. The token's name, version and the owner's nonce are read from the chain,
+    /// tokens without a `version()` function are assumed to use version "1".
+    pub async fn sign_erc20_permit(
+        &self,
+        token: Address,
+        owner_key: &PrivateKey,
+        spender: Address,
+        value: Uint256,
+        deadline: Uint256,
+    ) -> Result<[u8; 65], Web3Error> {
+        let owner = owner_key.to_address();
+        let name = self.permit_call(token, "name()", &[]).await?;
+        let name = match ParamKind::String.decode_data(&name, 0) {
+            Some(Token::String(name)) => name,
+            _ => {
+                return Err(Web3Error::ContractCallError(
+                    "Bad response from name()".to_string(),
+                ))
+            }
+        };
+        let version = match self.permit_call(token, "version()", &[]).await {
+            Ok(version) => match ParamKind::String.decode_data(&version, 0) {
+                Some(Token::String(version)) => version,
+                _ => "1".to_string(),
+            },
+            Err(Web3Error::JsonRpcError { .. }) => "1".to_string(),
+            Err(e) => return Err(e),
+        };
+        let nonce = self
+            .permit_call(token, "nonces(address)", &[owner.into()])
+            .await?;
+        let nonce = match nonce.get(0..32).and_then(Uint256::from_bytes_be) {
+            Some(nonce) => nonce,
+            None => {
+                return Err(Web3Error::ContractCallError(
+                    "Bad response from nonces(address)".to_string(),
+                ))
+            }
+        };
+        let chain_id = match self.eth_chainid().await? {
+            Some(chain_id) => chain_id,
+            None => return Err(Web3Error::BadResponse("No chain id".to_string())),
+        };
+
+        let domain = Eip712Domain {
+            name: Some(name),
+            version: Some(version),
+            chain_id: Some(chain_id),
+            verifying_contract: Some(token),
+            salt: None,
+        };
+        let mut types = TypedDataTypes::new();
+        types.insert(
+            "Permit".to_string(),
+            vec![
+                TypedDataField::new("owner", "address"),
+                TypedDataField::new("spender", "address"),
+                TypedDataField::new("value", "uint256"),
+                TypedDataField::new("nonce", "uint256"),
+                TypedDataField::new("deadline", "uint256"),
+            ],
+        );
+        let message = json!({
+            "owner": owner.to_string(),
+            "spender": spender.to_string(),
+            "value": value.to_string(),
+            "nonce": nonce.to_string(),
+            "deadline": deadline.to_string(),
+        });
+        sign_typed_data(&domain, &types, "Permit", &message, owner_key)
+    }
+
+    async fn permit_call(
+        &self,
+        token: Address,
+        sig: &str,
+        args: &[Token],
+    ) -> Result<Data, Web3Error> {
+        self.eth_call(TransactionRequest::call(token, encode_call(sig, args)?))
+            .await
+    }
+}
+
+#[cfg(test)]
+fn mail_types() -> TypedDataTypes {
+    serde_json::from_value(json!({
+        "EIP712Domain": [
+            {"name": "name", "type": "string"},
+            {"name": "version", "type": "string"},
+            {"name": "chainId", "type": "uint256"},
+            {"name": "verifyingContract", "type": "address"}
+        ],
+        "Person": [
+            {"name": "name", "type": "string"},
+            {"name": "wallet", "type": "address"}
+        ],
+        "Mail": [
+            {"name": "from", "type": "Person"},
+            {"name": "to", "type": "Person"},
+            {"name": "contents", "type": "string"}
+        ],
+        "Group": [
+            {"name": "name", "type": "string"},
+            {"name": "members", "type": "Person[]"},
+            {"name": "ids", "type": "uint8[2]"}
+        ],
+        "Misc": [
+            {"name": "delta", "type": "int8"},
+            {"name": "tag", "type": "bytes4"},
+            {"name": "blob", "type": "bytes"},
+            {"name": "flag", "type": "bool"}
+        ]
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_eip712_mail_vector() {
+    use clarity::utils::bytes_to_hex_str;
+
+    // the example from EIP-712
+    let domain = Eip712Domain {
+        name: Some("Ether Mail".to_string()),
+        version: Some("1".to_string()),
+        chain_id: Some(Uint256::from_u64(1)),
+        verifying_contract: Some(
+            "0xcccccccccccccccccccccccccccccccccccccccc"
+                .parse()
+                .unwrap(),
+        ),
+        salt: None,
+    };
+    let types = mail_types();
+    let message = json!({
+        "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+        "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+        "contents": "Hello, Bob!"
+    });
+    assert_eq!(
+        encode_type("Mail", &types).unwrap(),
+        "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&domain.separator().unwrap()),
+        "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&hash_struct("Mail", &message, &types).unwrap()),
+        "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&hash_typed_data(&domain, &types, "Mail", &message).unwrap()),
+        "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+    );
+
+    // keccak256("cow")
+    let key: PrivateKey = "0xc85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4"
+        .parse()
+        .unwrap();
+    let signature = sign_typed_data(&domain, &types, "Mail", &message, &key).unwrap();
+    assert_eq!(
+        bytes_to_hex_str(&signature),
+        "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d\
+         07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562\
+         1c"
+    );
+}
+
+#[test]
+fn test_eip712_arrays_and_atomic_types() {
+    use clarity::utils::bytes_to_hex_str;
+
+    let types = mail_types();
+    let group = json!({
+        "name": "Farm",
+        "members": [
+            {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+            {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"}
+        ],
+        "ids": [1, "0x2"]
+    });
+    assert_eq!(
+        bytes_to_hex_str(&hash_struct("Group", &group, &types).unwrap()),
+        "75c64c14d24c196ac19bf0f5f6d7cbfbe23cdcc0befb1e28f74c3423ef457954"
+    );
+    let misc = json!({"delta": -1, "tag": "0xdeadbeef", "blob": "0x0102", "flag": true});
+    assert_eq!(
+        bytes_to_hex_str(&hash_struct("Misc", &misc, &types).unwrap()),
+        "c21eb6de652f724f6fca7675e03238c775bbdedc4fdbf2c02da57431e6385ebe"
+    );
+
+    assert!(hash_struct("Misc", &json!({"delta": -1}), &types).is_err());
+    assert!(hash_struct("Unknown", &misc, &types).is_err());
+}
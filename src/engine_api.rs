@@ -0,0 +1,386 @@
+//! The Engine API that consensus clients use to drive execution clients, authenticated with
+//! a JWT signed by the secret the two share. Useful for devnets without a consensus client
+//! and for checking the health of a node pair.
+//!
+//! See more: https://github.com/ethereum/execution-apis/tree/main/src/engine
+use crate::client::Web3;
+use crate::hash::BlockHash;
+use crate::jsonrpc::client::BodyHook;
+use crate::jsonrpc::error::Web3Error;
+use crate::types::{Data, UnpaddedHex, Withdrawal};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use clarity::utils::hex_str_to_bytes;
+use clarity::{Address, Uint256};
+use hmac::{Hmac, Mac};
+use hyper::header::{HeaderValue, AUTHORIZATION};
+use hyper::HeaderMap;
+use sha2::Sha256;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The length of the shared JWT secret in bytes
+pub const JWT_SECRET_BYTES: usize = 32;
+
+/// Parses the hex encoded secret of a jwt.hex file as written by execution clients, with or
+/// without a 0x prefix
+pub fn parse_jwt_secret(contents: &str) -> Result<[u8; JWT_SECRET_BYTES], Web3Error> {
+    let contents = contents.trim();
+    let hex = contents.strip_prefix("0x").unwrap_or(contents);
+    let bytes = hex_str_to_bytes(hex)
+        .map_err(|_| Web3Error::BadInput("JWT secret is not hex".to_string()))?;
+    bytes.try_into().map_err(|bytes: Vec<u8>| {
+        Web3Error::BadInput(format!(
+            "JWT secret is {} bytes, expected {}",
+            bytes.len(),
+            JWT_SECRET_BYTES
+        ))
+    })
+}
+
+/// Reads the JWT secret from the file shared by the execution and consensus clients
+pub fn read_jwt_secret(path: &Path) -> Result<[u8; JWT_SECRET_BYTES], Web3Error> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| Web3Error::BadInput(format!("Could not read {}: {}", path.display(), e)))?;
+    parse_jwt_secret(&contents)
+}
+
+/// Creates an HS256 JWT with only the issued at claim, which execution clients reject once
+/// it is more than a minute away from their clock
+pub fn engine_jwt(secret: &[u8; JWT_SECRET_BYTES], issued_at: u64) -> String {
+    let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"HS256","typ":"JWT"}"#);
+    let claims = URL_SAFE_NO_PAD.encode(format!(r#"{{"iat":{}}}"#, issued_at));
+    let message = format!("{}.{}", header, claims);
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(message.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+    format!("{}.{}", message, signature)
+}
+
+/// A body hook that authorizes every request with a freshly issued JWT
+pub fn engine_auth_hook(secret: [u8; JWT_SECRET_BYTES]) -> BodyHook {
+    Arc::new(move |_: &[u8]| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut headers = HeaderMap::new();
+        let value = HeaderValue::from_str(&format!("Bearer {}", engine_jwt(&secret, now)))
+            .expect("JWT is ascii");
+        headers.insert(AUTHORIZATION, value);
+        headers
+    })
+}
+
+/// An execution payload, the body of a block as exchanged over the Engine API. The blob
+/// fields are only present from V3 on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionPayload {
+    pub parent_hash: BlockHash,
+    pub fee_recipient: Address,
+    pub state_root: Data,
+    pub receipts_root: Data,
+    pub logs_bloom: Data,
+    pub prev_randao: Data,
+    pub block_number: UnpaddedHex,
+    pub gas_limit: UnpaddedHex,
+    pub gas_used: UnpaddedHex,
+    pub timestamp: UnpaddedHex,
+    pub extra_data: Data,
+    pub base_fee_per_gas: UnpaddedHex,
+    pub block_hash: BlockHash,
+    /// the raw signed transactions of the block
+    pub transactions: Vec<Data>,
+    pub withdrawals: Vec<Withdrawal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob_gas_used: Option<UnpaddedHex>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excess_blob_gas: Option<UnpaddedHex>,
+}
+
+/// The blobs of the transactions in a built payload along with their KZG commitments
+/// and proofs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlobsBundle {
+    pub commitments: Vec<Data>,
+    pub proofs: Vec<Data>,
+    pub blobs: Vec<Data>,
+}
+
+/// A payload built by the execution client, as returned by engine_getPayload
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuiltPayload {
+    pub execution_payload: ExecutionPayload,
+    /// the fees paid to the fee recipient in wei
+    pub block_value: Uint256,
+    /// only returned by V3
+    #[serde(default)]
+    pub blobs_bundle: Option<BlobsBundle>,
+    /// only returned by V3, whether the client suggests not using an external builder
+    #[serde(default)]
+    pub should_override_builder: Option<bool>,
+}
+
+/// The status of a payload after engine_newPayload or engine_forkchoiceUpdated
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PayloadValidity {
+    Valid,
+    Invalid,
+    /// the client is missing ancestors of the payload and can not validate it yet
+    Syncing,
+    /// the payload is valid on its own but not yet connected to the canonical chain
+    Accepted,
+    InvalidBlockHash,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadStatus {
+    pub status: PayloadValidity,
+    /// the most recent valid ancestor when the payload is invalid
+    #[serde(default)]
+    pub latest_valid_hash: Option<BlockHash>,
+    #[serde(default)]
+    pub validation_error: Option<String>,
+}
+
+/// The blocks the consensus client considers the head, safe and finalized
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ForkchoiceState {
+    pub head_block_hash: BlockHash,
+    pub safe_block_hash: BlockHash,
+    pub finalized_block_hash: BlockHash,
+}
+
+/// Asks the execution client to start building a payload on top of the new head
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadAttributes {
+    pub timestamp: UnpaddedHex,
+    pub prev_randao: Data,
+    pub suggested_fee_recipient: Address,
+    pub withdrawals: Vec<Withdrawal>,
+    pub parent_beacon_block_root: Data,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ForkchoiceUpdated {
+    pub payload_status: PayloadStatus,
+    /// identifies the payload being built when attributes were given, for engine_getPayload
+    #[serde(default)]
+    pub payload_id: Option<Data>,
+}
+
+impl Web3 {
+    /// Creates a client for the authenticated Engine API port of an execution client, use
+    /// `read_jwt_secret` to load the secret it was started with
+    pub fn engine(url: &str, jwt_secret: [u8; JWT_SECRET_BYTES], timeout: Duration) -> Web3 {
+        Web3::builder(url)
+            .timeout(timeout)
+            .body_hook(engine_auth_hook(jwt_secret))
+            .build()
+    }
+
+    /// Exchanges the list of Engine API methods supported by the caller for those supported
+    /// by the execution client
+    pub async fn engine_exchange_capabilities(
+        &self,
+        capabilities: Vec<String>,
+    ) -> Result<Vec<String>, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "engine_exchangeCapabilities",
+                vec![capabilities],
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("engine_exchangeCapabilities"))
+    }
+
+    /// Gets a payload built since the shanghai hardfork
+    pub async fn engine_get_payload_v2(
+        &self,
+        payload_id: &Data,
+    ) -> Result<BuiltPayload, Web3Error> {
+        self.jsonrpc_client
+            .request_method("engine_getPayloadV2", vec![payload_id], self.timeout)
+            .await
+            .map_err(|e| e.map_unsupported("engine_getPayloadV2"))
+    }
+
+    /// Gets a payload built since the cancun hardfork, along with its blobs
+    pub async fn engine_get_payload_v3(
+        &self,
+        payload_id: &Data,
+    ) -> Result<BuiltPayload, Web3Error> {
+        self.jsonrpc_client
+            .request_method("engine_getPayloadV3", vec![payload_id], self.timeout)
+            .await
+            .map_err(|e| e.map_unsupported("engine_getPayloadV3"))
+    }
+
+    /// Has the execution client validate and import a payload, `expected_blob_versioned_hashes`
+    /// must list the blob hashes of its transactions in order
+    pub async fn engine_new_payload_v3(
+        &self,
+        payload: &ExecutionPayload,
+        expected_blob_versioned_hashes: Vec<Data>,
+        parent_beacon_block_root: Data,
+    ) -> Result<PayloadStatus, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "engine_newPayloadV3",
+                (
+                    payload,
+                    expected_blob_versioned_hashes,
+                    parent_beacon_block_root,
+                ),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("engine_newPayloadV3"))
+    }
+
+    /// Updates the head, safe and finalized blocks and, if `attributes` are given, starts
+    /// building a payload on the new head
+    pub async fn engine_forkchoice_updated_v3(
+        &self,
+        state: ForkchoiceState,
+        attributes: Option<PayloadAttributes>,
+    ) -> Result<ForkchoiceUpdated, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "engine_forkchoiceUpdatedV3",
+                (state, attributes),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("engine_forkchoiceUpdatedV3"))
+    }
+}
+
+#[test]
+fn test_engine_jwt() {
+    let secret = parse_jwt_secret(&format!("0x{}\n", "11".repeat(32))).unwrap();
+    assert_eq!(secret, [0x11; JWT_SECRET_BYTES]);
+    assert_eq!(parse_jwt_secret(&"11".repeat(32)).unwrap(), secret);
+    assert!(parse_jwt_secret("0x1111").is_err());
+    assert!(parse_jwt_secret("not hex").is_err());
+
+    assert_eq!(
+        engine_jwt(&secret, 1_700_000_000),
+        "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJpYXQiOjE3MDAwMDAwMDB9.\
+         Hb09lNHg9IiC1UttHzXRW1voUqjM97m4oEuKWkSux5g"
+    );
+}
+
+#[tokio::test]
+async fn test_engine_methods() {
+    use crate::hash::H256;
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let hash = |n: u64| format!("{:#066x}", n);
+    let payload = json!({
+        "parentHash": hash(1),
+        "feeRecipient": "0x1111111111111111111111111111111111111111",
+        "stateRoot": hash(2),
+        "receiptsRoot": hash(3),
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "prevRandao": hash(4),
+        "blockNumber": "0x1",
+        "gasLimit": "0x1c9c380",
+        "gasUsed": "0x0",
+        "timestamp": "0x6553f100",
+        "extraData": "0x",
+        "baseFeePerGas": "0x7",
+        "blockHash": hash(5),
+        "transactions": [],
+        "withdrawals": [],
+        "blobGasUsed": "0x0",
+        "excessBlobGas": "0x0"
+    });
+    let response_payload = payload.clone();
+    let node = MockNode::start(move |method, _| match method {
+        "engine_exchangeCapabilities" => Ok(json!(["engine_newPayloadV3"])),
+        "engine_forkchoiceUpdatedV3" => Ok(json!({
+            "payloadStatus": {"status": "VALID", "latestValidHash": hash(5), "validationError": null},
+            "payloadId": "0x0000000000000001"
+        })),
+        "engine_getPayloadV3" => Ok(json!({
+            "executionPayload": response_payload.clone(),
+            "blockValue": "0x0",
+            "blobsBundle": {"commitments": [], "proofs": [], "blobs": []},
+            "shouldOverrideBuilder": false
+        })),
+        "engine_newPayloadV3" => Ok(json!({
+            "status": "SYNCING",
+            "latestValidHash": null,
+            "validationError": null
+        })),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let secret = [0x11; JWT_SECRET_BYTES];
+    let web3 = Web3::engine(&node.url, secret, Duration::from_secs(5));
+
+    assert_eq!(
+        web3.engine_exchange_capabilities(vec!["engine_newPayloadV3".to_string()])
+            .await
+            .unwrap(),
+        vec!["engine_newPayloadV3".to_string()]
+    );
+    let state = ForkchoiceState {
+        head_block_hash: H256([5; 32]),
+        safe_block_hash: H256([5; 32]),
+        finalized_block_hash: H256([0; 32]),
+    };
+    let updated = web3
+        .engine_forkchoice_updated_v3(state, None)
+        .await
+        .unwrap();
+    assert_eq!(updated.payload_status.status, PayloadValidity::Valid);
+    let payload_id = updated.payload_id.unwrap();
+    assert_eq!(payload_id.len(), 8);
+    assert_eq!(
+        node.params("engine_forkchoiceUpdatedV3")[0][1],
+        serde_json::Value::Null
+    );
+
+    let built = web3.engine_get_payload_v3(&payload_id).await.unwrap();
+    assert_eq!(built.execution_payload.block_number, 1u64.into());
+    assert_eq!(built.should_override_builder, Some(false));
+    assert_eq!(
+        node.params("engine_getPayloadV3")[0],
+        json!(["0x0000000000000001"])
+    );
+
+    let status = web3
+        .engine_new_payload_v3(&built.execution_payload, Vec::new(), vec![6; 32].into())
+        .await
+        .unwrap();
+    assert_eq!(status.status, PayloadValidity::Syncing);
+    // the payload is sent back exactly as the client encoded it
+    assert_eq!(node.params("engine_newPayloadV3")[0][0], payload);
+
+    // every request carries a fresh token signed with the secret
+    let (headers, _) = node.raw_requests("engine_newPayloadV3").remove(0);
+    let token = headers[AUTHORIZATION].to_str().unwrap();
+    let token = token.strip_prefix("Bearer ").unwrap();
+    let claims = URL_SAFE_NO_PAD
+        .decode(token.split('.').nth(1).unwrap())
+        .unwrap();
+    let claims: serde_json::Value = serde_json::from_slice(&claims).unwrap();
+    let issued_at = claims["iat"].as_u64().unwrap();
+    assert_eq!(token, engine_jwt(&secret, issued_at));
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert!(now - issued_at < 60);
+}
@@ -0,0 +1,704 @@
+//! This module contains utility functions for interacting with ERC-1155 multi token contracts
+//! and decoding their transfer events
+//!
+//! See more: https://eips.ethereum.org/EIPS/eip-1155
+use crate::client::Web3;
+use crate::event_utils::{decode_uint_array, ParamKind};
+use crate::hash::TxHash;
+use crate::jsonrpc::error::Web3Error;
+use crate::types::{Log, SendTxOption, TransactionRequest};
+use clarity::abi::{encode_call, Token};
+use clarity::PrivateKey as EthPrivateKey;
+use clarity::{u256, Address, Uint256};
+
+/// keccak256("TransferSingle(address,address,address,uint256,uint256)")
+pub const TRANSFER_SINGLE_TOPIC: [u8; 32] = [
+    0xc3, 0xd5, 0x81, 0x68, 0xc5, 0xae, 0x73, 0x97, 0x73, 0x1d, 0x06, 0x3d, 0x5b, 0xbf, 0x3d, 0x65,
+    0x78, 0x54, 0x42, 0x73, 0x43, 0xf4, 0xc0, 0x83, 0x24, 0x0f, 0x7a, 0xac, 0xaa, 0x2d, 0x0f, 0x62,
+];
+/// keccak256("TransferBatch(address,address,address,uint256[],uint256[])")
+pub const TRANSFER_BATCH_TOPIC: [u8; 32] = [
+    0x4a, 0x39, 0xdc, 0x06, 0xd4, 0xc0, 0xdb, 0xc6, 0x4b, 0x70, 0xaf, 0x90, 0xfd, 0x69, 0x8a, 0x23,
+    0x3a, 0x51, 0x8a, 0xa5, 0xd0, 0x7e, 0x59, 0x5d, 0x98, 0x3b, 0x8c, 0x05, 0x26, 0xc8, 0xf7, 0xfb,
+];
+
+/// A decoded `TransferSingle` or `TransferBatch` event, single transfers have exactly one
+/// id and value. Mints have `from` set to the zero address and burns have `to` set to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Erc1155Transfer {
+    /// the token contract that emitted the event
+    pub contract: Address,
+    pub operator: Address,
+    pub from: Address,
+    pub to: Address,
+    pub ids: Vec<Uint256>,
+    /// the amount transferred of the token at the same position in `ids`
+    pub values: Vec<Uint256>,
+    /// true if this was a `TransferBatch` event
+    pub batch: bool,
+    pub block_number: Option<Uint256>,
+    pub transaction_hash: Option<TxHash>,
+}
+
+fn uint_array(values: &[Uint256]) -> Token {
+    Token::Dynamic(values.iter().map(|value| (*value).into()).collect())
+}
+
+fn encode_balance_of_batch(owners: &[Address], ids: &[Uint256]) -> Result<Vec<u8>, Web3Error> {
+    Ok(encode_call(
+        "balanceOfBatch(address[],uint256[])",
+        &[
+            Token::Dynamic(owners.iter().map(|owner| (*owner).into()).collect()),
+            uint_array(ids),
+        ],
+    )?)
+}
+
+fn encode_safe_batch_transfer_from(
+    from: Address,
+    to: Address,
+    transfers: &[(Uint256, Uint256)],
+    data: &[u8],
+) -> Result<Vec<u8>, Web3Error> {
+    let (ids, values): (Vec<Uint256>, Vec<Uint256>) = transfers.iter().copied().unzip();
+    Ok(encode_call(
+        "safeBatchTransferFrom(address,address,uint256[],uint256[],bytes)",
+        &[
+            from.into(),
+            to.into(),
+            uint_array(&ids),
+            uint_array(&values),
+            Token::UnboundedBytes(data.to_vec()),
+        ],
+    )?)
+}
+
+fn topic_address(topic: &[u8]) -> Option<Address> {
+    match ParamKind::Address.decode_data(topic, 0)? {
+        Token::Address(address) => Some(address),
+        _ => None,
+    }
+}
+
+fn data_uint(data: &[u8], head: usize) -> Option<Uint256> {
+    match ParamKind::Uint.decode_data(data, head)? {
+        Token::Uint(n) => Some(n),
+        _ => None,
+    }
+}
+
+/// Applies the ERC-1155 metadata uri convention, where `{id}` is replaced by the token id as
+/// 64 lowercase hex digits without a 0x prefix
+pub fn substitute_uri_id(uri: &str, id: Uint256) -> String {
+    uri.replace("{id}", &format!("{:064x}", id))
+}
+
+/// Decodes a `TransferSingle` or `TransferBatch` log, returning None for any other log or if
+/// the log is malformed
+pub fn decode_erc1155_transfer(log: &Log) -> Option<Erc1155Transfer> {
+    if log.topics.len() != 4 {
+        return None;
+    }
+    let batch = match &log.topics[0][..] {
+        t if t == TRANSFER_SINGLE_TOPIC => false,
+        t if t == TRANSFER_BATCH_TOPIC => true,
+        _ => return None,
+    };
+    let (ids, values) = if batch {
+        let ids = decode_uint_array(&log.data, 0)?;
+        let values = decode_uint_array(&log.data, 32)?;
+        if ids.len() != values.len() {
+            return None;
+        }
+        (ids, values)
+    } else {
+        (
+            vec![data_uint(&log.data, 0)?],
+            vec![data_uint(&log.data, 32)?],
+        )
+    };
+    Some(Erc1155Transfer {
+        contract: log.address,
+        operator: topic_address(&log.topics[1])?,
+        from: topic_address(&log.topics[2])?,
+        to: topic_address(&log.topics[3])?,
+        ids,
+        values,
+        batch,
+        block_number: log.block_number,
+        transaction_hash: log.transaction_hash,
+    })
+}
+
+/// Decodes all of the ERC-1155 transfer events in `logs`, optionally only those emitted by
+/// `contract`, other logs are skipped
+pub fn decode_erc1155_transfers(logs: &[Log], contract: Option<Address>) -> Vec<Erc1155Transfer> {
+    logs.iter()
+        .filter(|log| contract.map_or(true, |c| c == log.address))
+        .filter_map(|log| {
+            let transfer = decode_erc1155_transfer(log);
+            if transfer.is_none() {
+                trace!(
+                    "Skipping non ERC-1155 transfer log {:?}",
+                    log.topics.first()
+                );
+            }
+            transfer
+        })
+        .collect()
+}
+
+impl Web3 {
+    /// Gets the balance of `owner` for token `id` in the ERC-1155 contract
+    pub async fn erc1155_balance_of(
+        &self,
+        contract: Address,
+        owner: Address,
+        id: Uint256,
+    ) -> Result<Uint256, Web3Error> {
+        let payload = encode_call("balanceOf(address,uint256)", &[owner.into(), id.into()])?;
+        let balance = self
+            .eth_call(TransactionRequest::call(contract, payload))
+            .await?;
+        match balance.get(0..32).and_then(Uint256::from_bytes_be) {
+            Some(balance) => Ok(balance),
+            None => Err(Web3Error::ContractCallError(
+                "Bad response from ERC1155 balanceOf".to_string(),
+            )),
+        }
+    }
+
+    /// Gets the balance of each owner for the token id at the same position in `ids` in a
+    /// single call, the balances are returned in the same order
+    pub async fn erc1155_balance_of_batch(
+        &self,
+        contract: Address,
+        owners: &[Address],
+        ids: &[Uint256],
+    ) -> Result<Vec<Uint256>, Web3Error> {
+        if owners.len() != ids.len() {
+            return Err(Web3Error::BadInput(format!(
+                "balanceOfBatch needs as many owners as ids, got {} and {}",
+                owners.len(),
+                ids.len()
+            )));
+        }
+        let payload = encode_balance_of_batch(owners, ids)?;
+        let output = self
+            .eth_call(TransactionRequest::call(contract, payload))
+            .await?;
+        match decode_uint_array(&output, 0) {
+            Some(balances) if balances.len() == ids.len() => Ok(balances),
+            _ => Err(Web3Error::ContractCallError(
+                "Bad response from ERC1155 balanceOfBatch".to_string(),
+            )),
+        }
+    }
+
+    /// Gets the metadata uri of token `id` with the `{id}` placeholder already substituted
+    pub async fn erc1155_uri(&self, contract: Address, id: Uint256) -> Result<String, Web3Error> {
+        let payload = encode_call("uri(uint256)", &[id.into()])?;
+        let output = self
+            .eth_call(TransactionRequest::call(contract, payload))
+            .await?;
+        match ParamKind::String.decode_data(&output, 0) {
+            Some(Token::String(uri)) => Ok(substitute_uri_id(&uri, id)),
+            _ => Err(Web3Error::ContractCallError(
+                "Bad response from ERC1155 uri".to_string(),
+            )),
+        }
+    }
+
+    /// Transfers `amount` of token `id` from `from` to `to`, the sender must be `from` or an
+    /// approved operator of it. `data` is passed to the recipient's `onERC1155Received` hook
+    /// if it is a contract. Returns the transaction hash without waiting for it to enter the chain.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn erc1155_safe_transfer_from(
+        &self,
+        contract: Address,
+        from: Address,
+        to: Address,
+        id: Uint256,
+        amount: Uint256,
+        data: Vec<u8>,
+        sender_private_key: &EthPrivateKey,
+        options: Vec<SendTxOption>,
+    ) -> Result<TxHash, Web3Error> {
+        let payload = encode_call(
+            "safeTransferFrom(address,address,uint256,uint256,bytes)",
+            &[
+                from.into(),
+                to.into(),
+                id.into(),
+                amount.into(),
+                Token::UnboundedBytes(data),
+            ],
+        )?;
+        self.send_transaction(
+            contract,
+            payload,
+            u256!(0),
+            sender_private_key.to_address(),
+            sender_private_key,
+            options,
+        )
+        .await
+    }
+
+    /// Transfers several token ids from `from` to `to` in one transaction, `transfers` are
+    /// pairs of token id and amount. See `erc1155_safe_transfer_from`
+    #[allow(clippy::too_many_arguments)]
+    pub async fn erc1155_safe_batch_transfer_from(
+        &self,
+        contract: Address,
+        from: Address,
+        to: Address,
+        transfers: &[(Uint256, Uint256)],
+        data: Vec<u8>,
+        sender_private_key: &EthPrivateKey,
+        options: Vec<SendTxOption>,
+    ) -> Result<TxHash, Web3Error> {
+        let payload = encode_safe_batch_transfer_from(from, to, transfers, &data)?;
+        self.send_transaction(
+            contract,
+            payload,
+            u256!(0),
+            sender_private_key.to_address(),
+            sender_private_key,
+            options,
+        )
+        .await
+    }
+
+    /// Approves or revokes `operator` to transfer all of the sender's tokens in the contract
+    pub async fn erc1155_set_approval_for_all(
+        &self,
+        contract: Address,
+        operator: Address,
+        approved: bool,
+        sender_private_key: &EthPrivateKey,
+        options: Vec<SendTxOption>,
+    ) -> Result<TxHash, Web3Error> {
+        let payload = encode_call(
+            "setApprovalForAll(address,bool)",
+            &[operator.into(), Token::Bool(approved)],
+        )?;
+        self.send_transaction(
+            contract,
+            payload,
+            u256!(0),
+            sender_private_key.to_address(),
+            sender_private_key,
+            options,
+        )
+        .await
+    }
+}
+
+#[test]
+fn test_transfer_topics() {
+    use clarity::abi::derive_signature;
+    assert_eq!(
+        derive_signature("TransferSingle(address,address,address,uint256,uint256)").unwrap(),
+        TRANSFER_SINGLE_TOPIC
+    );
+    assert_eq!(
+        derive_signature("TransferBatch(address,address,address,uint256[],uint256[])").unwrap(),
+        TRANSFER_BATCH_TOPIC
+    );
+}
+
+#[test]
+fn test_balance_of_batch_encoding() {
+    use crate::multicall::read_usize;
+    use clarity::utils::hex_str_to_bytes;
+    let a: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let b: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let payload =
+        encode_balance_of_batch(&[a, b], &[Uint256::from_u64(1), Uint256::from_u64(2)]).unwrap();
+    let expected = hex_str_to_bytes(concat!(
+        "4e1273f4",
+        "0000000000000000000000000000000000000000000000000000000000000040",
+        "00000000000000000000000000000000000000000000000000000000000000a0",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+        "0000000000000000000000001111111111111111111111111111111111111111",
+        "0000000000000000000000002222222222222222222222222222222222222222",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        "0000000000000000000000000000000000000000000000000000000000000002",
+    ))
+    .unwrap();
+    assert_eq!(payload, expected);
+
+    // empty arrays are still two offsets and two zero lengths
+    let empty = encode_balance_of_batch(&[], &[]).unwrap();
+    assert_eq!(empty.len(), 4 + 4 * 32);
+    assert_eq!(read_usize(&empty[4..], 32), Some(96));
+}
+
+#[test]
+fn test_safe_batch_transfer_encoding() {
+    use crate::event_utils::address_to_event;
+    use crate::multicall::read_usize;
+    let from: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let to: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let transfers = [
+        (Uint256::from_u64(7), Uint256::from_u64(100)),
+        (Uint256::from_u64(8), Uint256::from_u64(200)),
+        (Uint256::from_u64(9), Uint256::from_u64(300)),
+    ];
+    let payload = encode_safe_batch_transfer_from(from, to, &transfers, &[0xab; 33]).unwrap();
+    assert_eq!(payload[0..4], [0x2e, 0xb2, 0xc2, 0xd6]);
+    let args = &payload[4..];
+    assert_eq!(args[0..32], address_to_event(from));
+    assert_eq!(args[32..64], address_to_event(to));
+    // five head words, then two arrays of three and the bytes padded to two words
+    assert_eq!(read_usize(args, 64), Some(160));
+    assert_eq!(read_usize(args, 96), Some(160 + 4 * 32));
+    assert_eq!(read_usize(args, 128), Some(160 + 8 * 32));
+    assert_eq!(args.len(), 160 + 8 * 32 + 3 * 32);
+    assert_eq!(read_usize(args, 160 + 8 * 32), Some(33));
+    assert_eq!(args[160 + 9 * 32..160 + 9 * 32 + 33], [0xab; 33]);
+    assert!(args[160 + 9 * 32 + 33..].iter().all(|b| *b == 0));
+
+    let ids: Vec<Uint256> = transfers.iter().map(|t| t.0).collect();
+    let values: Vec<Uint256> = transfers.iter().map(|t| t.1).collect();
+    assert_eq!(decode_uint_array(args, 64).unwrap(), ids);
+    assert_eq!(decode_uint_array(args, 96).unwrap(), values);
+}
+
+#[test]
+fn test_decode_uint_array_bounds() {
+    use crate::hash::H256;
+    use crate::multicall::word;
+
+    let mut data = word(32).to_vec();
+    data.extend_from_slice(&word(2));
+    data.extend_from_slice(&H256::from(Uint256::max_value()).0);
+    data.extend_from_slice(&word(5));
+    assert_eq!(
+        decode_uint_array(&data, 0).unwrap(),
+        vec![Uint256::max_value(), Uint256::from_u64(5)]
+    );
+    // truncated arrays and absurd lengths or offsets are rejected without allocating
+    assert!(decode_uint_array(&data[..data.len() - 1], 0).is_none());
+    let mut huge = data.clone();
+    huge[32..64].copy_from_slice(&H256::from(Uint256::max_value()).0);
+    assert!(decode_uint_array(&huge, 0).is_none());
+    huge[32..64].copy_from_slice(&word(usize::MAX / 16));
+    assert!(decode_uint_array(&huge, 0).is_none());
+    assert!(decode_uint_array(&data, 64).is_none());
+}
+
+#[test]
+fn test_decode_transfer_events() {
+    use crate::event_utils::address_to_event;
+    use crate::hash::H256;
+    use crate::types::Data;
+
+    let contract: Address = "0x00000000000000000000000000000000000000aa"
+        .parse()
+        .unwrap();
+    let operator: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let from: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let to: Address = "0x3333333333333333333333333333333333333333"
+        .parse()
+        .unwrap();
+    let log = |topic0: [u8; 32], data: Vec<u8>| Log {
+        address: contract,
+        topics: vec![
+            Data(topic0.to_vec()),
+            Data(address_to_event(operator).to_vec()),
+            Data(address_to_event(from).to_vec()),
+            Data(address_to_event(to).to_vec()),
+        ],
+        data: Data(data),
+        block_number: Some(Uint256::from_u64(12)),
+        ..Default::default()
+    };
+
+    let mut single = H256::from(Uint256::from_u64(7)).0.to_vec();
+    single.extend_from_slice(&H256::from(Uint256::from_u64(100)).0);
+
+    let ids: Vec<Uint256> = (1..=3).map(Uint256::from_u64).collect();
+    let values: Vec<Uint256> = (1..=3).map(|v| Uint256::from_u64(v * 1000)).collect();
+    // the event data is encoded like the arguments of a call
+    let arrays = |ids: &[Uint256], values: &[Uint256]| {
+        encode_call(
+            "TransferBatch(uint256[],uint256[])",
+            &[uint_array(ids), uint_array(values)],
+        )
+        .unwrap()[4..]
+            .to_vec()
+    };
+    let batch = arrays(&ids, &values);
+
+    let mut other = log([0x55; 32], single.clone());
+    other.topics.truncate(3);
+    let logs = vec![
+        log(TRANSFER_SINGLE_TOPIC, single.clone()),
+        other,
+        log(TRANSFER_BATCH_TOPIC, batch.clone()),
+        // mismatched array lengths
+        log(TRANSFER_BATCH_TOPIC, arrays(&ids, &values[..2])),
+        // truncated data
+        log(TRANSFER_SINGLE_TOPIC, single[..40].to_vec()),
+    ];
+
+    let transfers = decode_erc1155_transfers(&logs, None);
+    assert_eq!(
+        transfers,
+        vec![
+            Erc1155Transfer {
+                contract,
+                operator,
+                from,
+                to,
+                ids: vec![Uint256::from_u64(7)],
+                values: vec![Uint256::from_u64(100)],
+                batch: false,
+                block_number: Some(Uint256::from_u64(12)),
+                transaction_hash: None,
+            },
+            Erc1155Transfer {
+                contract,
+                operator,
+                from,
+                to,
+                ids,
+                values,
+                batch: true,
+                block_number: Some(Uint256::from_u64(12)),
+                transaction_hash: None,
+            },
+        ]
+    );
+    assert!(decode_erc1155_transfers(&logs, Some(operator)).is_empty());
+}
+
+#[test]
+fn test_substitute_uri_id() {
+    assert_eq!(
+        substitute_uri_id("https://token-cdn-domain/{id}.json", Uint256::from_u64(314592)),
+        "https://token-cdn-domain/000000000000000000000000000000000000000000000000000000000004cce0.json"
+    );
+    assert_eq!(
+        substitute_uri_id("ipfs://static", Uint256::from_u64(1)),
+        "ipfs://static"
+    );
+}
+
+#[tokio::test]
+async fn test_erc1155_calls() {
+    use crate::jsonrpc::mock::MockNode;
+    use crate::multicall::read_usize;
+    use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    let owner_a: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let owner_b: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let balances: HashMap<(Address, Uint256), Uint256> = [
+        ((owner_a, Uint256::from_u64(1)), Uint256::from_u64(10)),
+        ((owner_b, Uint256::from_u64(1)), Uint256::from_u64(20)),
+        ((owner_b, Uint256::from_u64(2)), Uint256::max_value()),
+    ]
+    .into_iter()
+    .collect();
+
+    // a contract that decodes the calldata itself and answers from `balances`
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_call" => {
+            let data = hex_str_to_bytes(params[0]["data"].as_str().unwrap()).unwrap();
+            let (selector, args) = data.split_at(4);
+            let output = match selector {
+                [0x4e, 0x12, 0x73, 0xf4] => {
+                    let owners_at = read_usize(args, 0).unwrap();
+                    let ids = decode_uint_array(args, 32).unwrap();
+                    let owners = (0..ids.len())
+                        .map(|i| topic_address(&args[owners_at + 32 + i * 32..][..32]).unwrap());
+                    let result: Vec<Uint256> = owners
+                        .zip(ids.iter())
+                        .map(|(owner, id)| balances.get(&(owner, *id)).copied().unwrap_or_default())
+                        .collect();
+                    encode_call("balanceOfBatch(uint256[])", &[uint_array(&result)]).unwrap()[4..]
+                        .to_vec()
+                }
+                [0x0e, 0x89, 0x34, 0x1c] => {
+                    let uri = b"https://example.com/{id}.json".to_vec();
+                    encode_call("uri(string)", &[Token::UnboundedBytes(uri)]).unwrap()[4..].to_vec()
+                }
+                _ => return Err((3, "execution reverted".to_string())),
+            };
+            Ok(json!(format!("0x{}", bytes_to_hex_str(&output))))
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let contract: Address = "0x00000000000000000000000000000000000000aa"
+        .parse()
+        .unwrap();
+
+    let result = web3
+        .erc1155_balance_of_batch(
+            contract,
+            &[owner_a, owner_b, owner_b, owner_a],
+            &[
+                Uint256::from_u64(1),
+                Uint256::from_u64(1),
+                Uint256::from_u64(2),
+                Uint256::from_u64(2),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            Uint256::from_u64(10),
+            Uint256::from_u64(20),
+            Uint256::max_value(),
+            Uint256::from_u64(0)
+        ]
+    );
+    assert!(web3
+        .erc1155_balance_of_batch(contract, &[owner_a], &[])
+        .await
+        .is_err());
+
+    assert_eq!(
+        web3.erc1155_uri(contract, Uint256::from_u64(255))
+            .await
+            .unwrap(),
+        format!("https://example.com/{:064x}.json", 255)
+    );
+    assert!(web3
+        .erc1155_balance_of(contract, owner_a, Uint256::from_u64(1))
+        .await
+        .is_err());
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_erc1155_transfers_fork() {
+    use clarity::PrivateKey;
+    use std::time::Duration;
+
+    // run with a local hardhat or anvil mainnet fork, takes tokens from whoever
+    // recently received some so that no contract has to be deployed
+    let web3 = Web3::new("http://localhost:8545", Duration::from_secs(30));
+    let secret: PrivateKey = "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
+        .parse()
+        .unwrap();
+    let us = secret.to_address();
+    let timeout = Duration::from_secs(30);
+    let guard = web3.snapshot_guard().await.unwrap();
+
+    let end = web3.eth_block_number().await.unwrap();
+    let logs = web3
+        .check_for_events(
+            end.checked_sub(u256!(100)).unwrap(),
+            Some(end),
+            vec![],
+            vec!["TransferSingle(address,address,address,uint256,uint256)"],
+        )
+        .await
+        .unwrap();
+    let mut holder = None;
+    for transfer in decode_erc1155_transfers(&logs, None) {
+        if transfer.to == Address::default() || transfer.to == us {
+            continue;
+        }
+        let (contract, owner, id) = (transfer.contract, transfer.to, transfer.ids[0]);
+        let balance = web3.erc1155_balance_of(contract, owner, id).await.unwrap();
+        if balance >= u256!(2) {
+            holder = Some((contract, owner, id, balance));
+            break;
+        }
+    }
+    let (contract, owner, id, balance) = holder.expect("no recent ERC-1155 holder found");
+
+    web3.set_balance(us, u256!(10000000000000000000))
+        .await
+        .unwrap();
+    web3.set_balance(owner, u256!(10000000000000000000))
+        .await
+        .unwrap();
+    web3.impersonate_account(owner).await.unwrap();
+    let approve = encode_call(
+        "setApprovalForAll(address,bool)",
+        &[us.into(), Token::Bool(true)],
+    )
+    .unwrap();
+    let hash = web3
+        .send_transaction_impersonated(owner, contract, approve, u256!(0))
+        .await
+        .unwrap();
+    web3.wait_for_transaction(hash, timeout, None)
+        .await
+        .unwrap();
+
+    let hash = web3
+        .erc1155_safe_transfer_from(contract, owner, us, id, u256!(1), vec![], &secret, vec![])
+        .await
+        .unwrap();
+    web3.wait_for_transaction(hash, timeout, None)
+        .await
+        .unwrap();
+    let hash = web3
+        .erc1155_safe_batch_transfer_from(
+            contract,
+            owner,
+            us,
+            &[(id, u256!(1))],
+            vec![],
+            &secret,
+            vec![],
+        )
+        .await
+        .unwrap();
+    web3.wait_for_transaction(hash, timeout, None)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        web3.erc1155_balance_of_batch(contract, &[us, owner], &[id, id])
+            .await
+            .unwrap(),
+        vec![u256!(2), balance.checked_sub(u256!(2)).unwrap()]
+    );
+    let receipt = web3
+        .eth_get_transaction_receipt(hash)
+        .await
+        .unwrap()
+        .unwrap();
+    let transfers = decode_erc1155_transfers(&receipt.logs, Some(contract));
+    assert_eq!(transfers.len(), 1);
+    assert!(transfers[0].batch);
+    assert_eq!(transfers[0].operator, us);
+    assert_eq!((transfers[0].from, transfers[0].to), (owner, us));
+    assert_eq!(transfers[0].ids, vec![id]);
+    assert_eq!(transfers[0].values, vec![u256!(1)]);
+    assert_eq!(transfers[0].transaction_hash, Some(hash));
+
+    web3.stop_impersonating_account(owner).await.unwrap();
+    guard.revert().await.unwrap();
+}
@@ -0,0 +1,155 @@
+//! This module contains utility functions for probing contracts with ERC-165
+//! `supportsInterface` calls and detecting which token standard they implement
+use crate::jsonrpc::error::Web3Error;
+use crate::{client::Web3, types::TransactionRequest};
+use clarity::abi::encode_call;
+use clarity::{Address, Uint256};
+
+/// The ERC-165 spec requires `supportsInterface` to use less than 30,000 gas,
+/// so probes are sent with this as the gas limit
+pub const ERC165_GAS_LIMIT: u64 = 30_000;
+
+/// The selector of `supportsInterface(bytes4)`, which is also the ERC-165 interface id
+pub const ERC165_INTERFACE_ID: [u8; 4] = [0x01, 0xff, 0xc9, 0xa7];
+/// An interface id that every ERC-165 compliant contract must report as unsupported
+pub const INVALID_INTERFACE_ID: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+/// ERC-721 interface id
+pub const ERC721_INTERFACE_ID: [u8; 4] = [0x80, 0xac, 0x58, 0xcd];
+/// ERC-1155 interface id
+pub const ERC1155_INTERFACE_ID: [u8; 4] = [0xd9, 0xb6, 0x7a, 0x26];
+
+/// The token standard a contract was detected to implement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenStandard {
+    Erc20,
+    Erc721,
+    Erc1155,
+    Unknown,
+}
+
+/// Builds the calldata for `supportsInterface(bytes4)`, the bytes4 argument
+/// is left aligned and right padded with zeros to a full 32 byte word
+fn supports_interface_payload(interface_id: [u8; 4]) -> Vec<u8> {
+    let mut payload = ERC165_INTERFACE_ID.to_vec();
+    payload.extend_from_slice(&interface_id);
+    payload.extend_from_slice(&[0u8; 28]);
+    payload
+}
+
+/// Interprets the return data of a `supportsInterface` call, anything other
+/// than an abi encoded `true` is treated as `false`
+fn decode_supports_interface(output: &[u8]) -> bool {
+    match output.get(0..32) {
+        Some(word) => word[..31].iter().all(|b| *b == 0) && word[31] == 1,
+        None => false,
+    }
+}
+
+impl Web3 {
+    /// Calls `supportsInterface(interface_id)` on the given contract with the 30,000 gas
+    /// ceiling from the ERC-165 spec. A revert or an empty return is treated as `false`
+    /// per the spec, errors unrelated to the contract itself are returned.
+    pub async fn supports_interface(
+        &self,
+        contract: Address,
+        interface_id: [u8; 4],
+    ) -> Result<bool, Web3Error> {
+        let transaction = TransactionRequest::builder()
+            .to(contract)
+            .gas(Uint256::from_u64(ERC165_GAS_LIMIT))
+            .data(supports_interface_payload(interface_id))
+            .build()?;
+
+        match self.eth_call(transaction).await {
+            Ok(output) => Ok(decode_supports_interface(&output)),
+            // reverts are reported by the node as a JSON-RPC error response
+            Err(Web3Error::JsonRpcError { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks if a contract implements ERC-165 using the detection procedure from the spec,
+    /// the contract must return `true` for the ERC-165 interface id and `false` for 0xffffffff
+    pub async fn supports_erc165(&self, contract: Address) -> Result<bool, Web3Error> {
+        Ok(self
+            .supports_interface(contract, ERC165_INTERFACE_ID)
+            .await?
+            && !self
+                .supports_interface(contract, INVALID_INTERFACE_ID)
+                .await?)
+    }
+
+    /// Detects the token standard implemented by a contract. ERC-721 and ERC-1155 are detected
+    /// with ERC-165 probes, ERC-20 has no ERC-165 interface id so a `decimals()` call is used
+    /// instead, which technically means ERC-20 tokens without the optional decimals field will
+    /// be reported as `Unknown`
+    pub async fn detect_token_standard(
+        &self,
+        contract: Address,
+    ) -> Result<TokenStandard, Web3Error> {
+        if self.supports_erc165(contract).await? {
+            if self
+                .supports_interface(contract, ERC1155_INTERFACE_ID)
+                .await?
+            {
+                return Ok(TokenStandard::Erc1155);
+            }
+            if self
+                .supports_interface(contract, ERC721_INTERFACE_ID)
+                .await?
+            {
+                return Ok(TokenStandard::Erc721);
+            }
+        }
+
+        let transaction = TransactionRequest::call(contract, encode_call("decimals()", &[])?);
+        match self.eth_call(transaction).await {
+            Ok(output) if output.len() >= 32 => Ok(TokenStandard::Erc20),
+            Ok(_) | Err(Web3Error::JsonRpcError { .. }) => Ok(TokenStandard::Unknown),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[test]
+fn test_supports_interface_payload() {
+    let payload = supports_interface_payload(ERC721_INTERFACE_ID);
+    assert_eq!(payload.len(), 36);
+    assert_eq!(payload[0..4], [0x01, 0xff, 0xc9, 0xa7]);
+    assert_eq!(payload[4..8], [0x80, 0xac, 0x58, 0xcd]);
+    assert!(payload[8..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn test_decode_supports_interface() {
+    let mut word = [0u8; 32];
+    assert!(!decode_supports_interface(&word));
+    word[31] = 1;
+    assert!(decode_supports_interface(&word));
+    assert!(!decode_supports_interface(&[]));
+    word[0] = 1;
+    assert!(!decode_supports_interface(&word));
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_detect_token_standard() {
+    use std::time::Duration;
+    let web3 = Web3::new("https://eth.althea.net", Duration::from_secs(5));
+    // BAYC
+    let erc721 = "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f13D"
+        .parse()
+        .unwrap();
+    let dai = "0x6B175474E89094C44Da98b954EedeAC495271d0F"
+        .parse()
+        .unwrap();
+
+    assert_eq!(
+        web3.detect_token_standard(erc721).await.unwrap(),
+        TokenStandard::Erc721
+    );
+    assert_eq!(
+        web3.detect_token_standard(dai).await.unwrap(),
+        TokenStandard::Erc20
+    );
+}
@@ -0,0 +1,389 @@
+//! Reconstructs the balance of every holder of an ERC-20 token at a past block by replaying
+//! its Transfer events, for governance snapshots and airdrops
+use crate::client::Web3;
+use crate::event_utils::{ParamKind, ScanDirection};
+use crate::jsonrpc::error::Web3Error;
+use crate::types::{Log, TransactionRequest};
+use clarity::abi::{derive_signature, encode_call, Token};
+use clarity::{Address, Uint256};
+use std::collections::HashMap;
+
+/// How many of the largest computed balances `erc20_snapshot` compares against `balanceOf`
+pub const SNAPSHOT_SAMPLE_SIZE: usize = 20;
+
+/// Reported to the progress callback of `erc20_snapshot` after each block range is replayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotProgress {
+    /// every block up to and including this one has been replayed
+    pub scanned_to: Uint256,
+    /// the block the snapshot is taken at
+    pub end_block: Uint256,
+    /// Transfer events replayed so far
+    pub transfers: u64,
+    /// addresses with a nonzero balance so far
+    pub holders: usize,
+}
+
+/// A holder whose balance computed from Transfer events differs from what `balanceOf` returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalanceMismatch {
+    pub holder: Address,
+    pub computed: Uint256,
+    pub actual: Uint256,
+}
+
+/// The balances of every holder of a token at a block, returned by `Web3::erc20_snapshot`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Erc20Snapshot {
+    pub token: Address,
+    pub block: Uint256,
+    /// every address with a nonzero balance, the zero address is never included
+    pub balances: HashMap<Address, Uint256>,
+    /// the amount minted minus the amount burned, as transfers from and to the zero address
+    pub total_supply: Uint256,
+    /// what `totalSupply()` returned at the block, if the call succeeded
+    pub reported_total_supply: Option<Uint256>,
+    /// the number of Transfer events replayed
+    pub transfers: u64,
+    /// the holders whose computed balance was compared against `balanceOf`
+    pub checked: Vec<Address>,
+    /// checked holders whose balance did not match, which happens for tokens that change
+    /// balances without emitting Transfer events such as fee-on-transfer and rebasing tokens
+    pub mismatches: Vec<BalanceMismatch>,
+}
+
+impl Erc20Snapshot {
+    /// True if every checked balance and the total supply matched the token's own view
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+            && self
+                .reported_total_supply
+                .map_or(true, |supply| supply == self.total_supply)
+    }
+
+    /// The holders sorted from the largest balance to the smallest, ties by address
+    pub fn holders_by_balance(&self) -> Vec<(Address, Uint256)> {
+        let mut holders: Vec<(Address, Uint256)> =
+            self.balances.iter().map(|(a, b)| (*a, *b)).collect();
+        holders.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        holders
+    }
+
+    /// Moves `value` from `from` to `to`, transfers from the zero address are mints and
+    /// transfers to it are burns. Balances saturate rather than wrap, if a token lets them go
+    /// below zero the sample check against `balanceOf` will report the difference.
+    fn apply_transfer(&mut self, from: Address, to: Address, value: Uint256) {
+        let zero = Address::default();
+        self.transfers += 1;
+        if from == zero {
+            self.total_supply = self
+                .total_supply
+                .checked_add(value)
+                .unwrap_or(Uint256::max_value());
+        } else {
+            let balance = self.balances.remove(&from).unwrap_or_default();
+            let balance = balance.checked_sub(value).unwrap_or_default();
+            if balance != Uint256::default() {
+                self.balances.insert(from, balance);
+            }
+        }
+        if to == zero {
+            self.total_supply = self.total_supply.checked_sub(value).unwrap_or_default();
+        } else if value != Uint256::default() {
+            let balance = self.balances.entry(to).or_default();
+            *balance = balance.checked_add(value).unwrap_or(Uint256::max_value());
+        }
+    }
+}
+
+/// Decodes the from, to and value of an ERC-20 Transfer log, ERC-721 transfers share the
+/// signature but have a fourth topic and are skipped
+fn decode_transfer(log: &Log) -> Option<(Address, Address, Uint256)> {
+    if log.topics.len() != 3 || log.is_removed() {
+        return None;
+    }
+    let address = |topic: &[u8]| match ParamKind::Address.decode_data(topic, 0)? {
+        Token::Address(address) => Some(address),
+        _ => None,
+    };
+    let value = match ParamKind::Uint.decode_data(&log.data, 0)? {
+        Token::Uint(value) => value,
+        _ => return None,
+    };
+    Some((address(&log.topics[1])?, address(&log.topics[2])?, value))
+}
+
+impl Web3 {
+    /// Computes the balance of every holder of `token` at `at_block` by replaying all of its
+    /// Transfer events from `deployment_block`, which must be at or before the block the token
+    /// was deployed in. The largest `SNAPSHOT_SAMPLE_SIZE` balances and the total supply are
+    /// then compared against the token at `at_block`, which needs an archive node for blocks
+    /// that are not recent, any differences are reported in the result rather than as errors.
+    ///
+    /// Logs are requested `MAX_LOG_RANGE` blocks at a time, ranges a provider refuses for
+    /// returning too many logs are halved and retried down to a single block, any other error
+    /// ends the snapshot. Only the running balances are kept in memory, `progress` is called
+    /// after each range so that long replays of popular tokens can report how far along they
+    /// are.
+    pub async fn erc20_snapshot<F: FnMut(SnapshotProgress)>(
+        &self,
+        token: Address,
+        at_block: Uint256,
+        deployment_block: Uint256,
+        mut progress: F,
+    ) -> Result<Erc20Snapshot, Web3Error> {
+        let transfer = derive_signature("Transfer(address,address,uint256)")?;
+        let mut snapshot = Erc20Snapshot {
+            token,
+            block: at_block,
+            ..Default::default()
+        };
+
+        self.scan_logs(
+            deployment_block,
+            at_block,
+            vec![token],
+            vec![vec![transfer]],
+            ScanDirection::Forward,
+            |_, end, logs| {
+                for (from, to, value) in logs.iter().filter_map(decode_transfer) {
+                    snapshot.apply_transfer(from, to, value);
+                }
+                progress(SnapshotProgress {
+                    scanned_to: end,
+                    end_block: at_block,
+                    transfers: snapshot.transfers,
+                    holders: snapshot.balances.len(),
+                });
+                true
+            },
+        )
+        .await?;
+
+        let payload = encode_call("totalSupply()", &[])?;
+        snapshot.reported_total_supply = match self.erc20_call_at(token, payload, at_block).await {
+            Ok(supply) => Some(supply),
+            Err(e) => {
+                warn!("Could not get totalSupply for snapshot {:?}", e);
+                None
+            }
+        };
+        for (holder, computed) in snapshot
+            .holders_by_balance()
+            .into_iter()
+            .take(SNAPSHOT_SAMPLE_SIZE)
+        {
+            let payload = encode_call("balanceOf(address)", &[holder.into()])?;
+            let actual = match self.erc20_call_at(token, payload, at_block).await {
+                Ok(actual) => actual,
+                Err(e) => {
+                    warn!("Could not check snapshot balance of {} {:?}", holder, e);
+                    break;
+                }
+            };
+            snapshot.checked.push(holder);
+            if actual != computed {
+                snapshot.mismatches.push(BalanceMismatch {
+                    holder,
+                    computed,
+                    actual,
+                });
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Makes a call that returns a single uint256 at `block`
+    async fn erc20_call_at(
+        &self,
+        token: Address,
+        payload: Vec<u8>,
+        block: Uint256,
+    ) -> Result<Uint256, Web3Error> {
+        let output = self
+            .eth_call_at_height(TransactionRequest::call(token, payload), block)
+            .await?;
+        match output.get(0..32).and_then(Uint256::from_bytes_be) {
+            Some(value) => Ok(value),
+            None => Err(Web3Error::ContractCallError(
+                "Bad response from ERC20 call".to_string(),
+            )),
+        }
+    }
+}
+
+#[test]
+fn test_apply_transfer() {
+    let zero = Address::default();
+    let a: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let b: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let n = Uint256::from_u64;
+    let mut snapshot = Erc20Snapshot::default();
+
+    snapshot.apply_transfer(zero, a, n(1000));
+    snapshot.apply_transfer(a, b, n(300));
+    // zero value transfers do not create holders
+    snapshot.apply_transfer(a, zero, n(0));
+    snapshot.apply_transfer(b, zero, n(100));
+    assert_eq!(snapshot.total_supply, n(900));
+    assert_eq!(snapshot.transfers, 4);
+    assert_eq!(
+        snapshot.holders_by_balance(),
+        vec![(a, n(700)), (b, n(200))]
+    );
+
+    // emptied balances are removed rather than kept as zero
+    snapshot.apply_transfer(b, a, n(200));
+    assert_eq!(snapshot.balances.len(), 1);
+    assert!(!snapshot.balances.contains_key(&zero));
+
+    // sending more than the computed balance saturates instead of wrapping
+    snapshot.apply_transfer(b, a, n(5));
+    assert_eq!(snapshot.balances.get(&a), Some(&n(905)));
+    assert!(!snapshot.balances.contains_key(&b));
+}
+
+#[tokio::test]
+async fn test_erc20_snapshot() {
+    use crate::event_utils::address_to_event;
+    use crate::jsonrpc::mock::MockNode;
+    use clarity::utils::bytes_to_hex_str;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    let token: Address = "0x00000000000000000000000000000000000000aa"
+        .parse()
+        .unwrap();
+    let a: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let b: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let topic = |bytes: &[u8]| format!("0x{}", bytes_to_hex_str(bytes));
+    let transfer = derive_signature("Transfer(address,address,uint256)").unwrap();
+    let log = |block: u64, from: Address, to: Address, value: u64| {
+        json!({
+            "address": token.to_string(),
+            "topics": [
+                topic(&transfer),
+                topic(&address_to_event(from)),
+                topic(&address_to_event(to)),
+            ],
+            "data": format!("0x{:064x}", value),
+            "blockNumber": format!("{:#x}", block),
+        })
+    };
+    let mut nft = log(1600, a, b, 1);
+    nft["topics"]
+        .as_array_mut()
+        .unwrap()
+        .push(json!(topic(&[0; 32])));
+    let logs = vec![
+        log(5, Address::default(), a, 1000),
+        log(1500, a, b, 300),
+        nft,
+        log(2100, b, Address::default(), 100),
+    ];
+    let hex = |v: &Value| u64::from_str_radix(v.as_str().unwrap().trim_start_matches("0x"), 16);
+
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_blockNumber" => Ok(json!("0x1000")),
+        "eth_getLogs" => {
+            let from = hex(&params[0]["fromBlock"]).unwrap();
+            let to = hex(&params[0]["toBlock"]).unwrap();
+            // too many results around block 1500 unless the range is small
+            if from <= 1500 && 1500 <= to && to - from >= 500 {
+                return Err((-32005, "query returned more than 10000 results".to_string()));
+            }
+            let in_range: Vec<Value> = logs
+                .iter()
+                .filter(|log| (from..=to).contains(&hex(&log["blockNumber"]).unwrap()))
+                .cloned()
+                .collect();
+            Ok(json!(in_range))
+        }
+        "eth_call" => {
+            let data = params[0]["data"].as_str().unwrap();
+            let value = match &data[..10] {
+                "0x18160ddd" => 900,
+                // b paid a fee on the transfer it received
+                "0x70a08231" if data.ends_with("2222") => 190,
+                "0x70a08231" => 700,
+                _ => return Err((3, "execution reverted".to_string())),
+            };
+            Ok(json!(format!("0x{:064x}", value)))
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let mut reports = Vec::new();
+    let snapshot = web3
+        .erc20_snapshot(token, Uint256::from_u64(2500), Uint256::from_u64(0), |p| {
+            reports.push(p)
+        })
+        .await
+        .unwrap();
+
+    let n = Uint256::from_u64;
+    assert_eq!(
+        snapshot.holders_by_balance(),
+        vec![(a, n(700)), (b, n(200))]
+    );
+    assert_eq!(snapshot.total_supply, n(900));
+    assert_eq!(snapshot.reported_total_supply, Some(n(900)));
+    assert_eq!(snapshot.transfers, 3);
+    assert_eq!(snapshot.checked, vec![a, b]);
+    assert_eq!(
+        snapshot.mismatches,
+        vec![BalanceMismatch {
+            holder: b,
+            computed: n(200),
+            actual: n(190)
+        }]
+    );
+    assert!(!snapshot.is_consistent());
+
+    // the range over block 1500 was halved and the range grew again afterwards
+    let ranges: Vec<(u64, u64)> = reports
+        .iter()
+        .map(|p| (p.scanned_to.to_string().parse().unwrap(), p.transfers))
+        .collect();
+    assert_eq!(ranges, vec![(999, 1), (1499, 1), (1999, 2), (2500, 3)]);
+    assert!(reports.iter().all(|p| p.end_block == n(2500)));
+}
+
+#[tokio::test]
+async fn test_erc20_snapshot_node_error() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    // errors other than the log limit are returned instead of being retried on smaller ranges
+    let node = MockNode::start(|method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_blockNumber" => Ok(json!("0x1000")),
+        "eth_getLogs" => Err((-32000, "header not found".to_string())),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let token: Address = "0x3333333333333333333333333333333333333333"
+        .parse()
+        .unwrap();
+
+    let mut reports = Vec::new();
+    let res = web3
+        .erc20_snapshot(token, Uint256::from_u64(2500), Uint256::from_u64(0), |p| {
+            reports.push(p)
+        })
+        .await;
+    assert!(res.is_err());
+    assert!(reports.is_empty());
+    assert_eq!(node.calls("eth_getLogs"), 1);
+}
@@ -1,13 +1,19 @@
 //! This module contains utility functions for interacting with ERC20 tokens and contracts
+use crate::client::{wait_for_balance, Web3};
+use crate::hash::TxHash;
 use crate::jsonrpc::error::Web3Error;
-use crate::{client::Web3, types::SendTxOption};
-use clarity::{abi::encode_call, PrivateKey as EthPrivateKey};
+use crate::signer::TransactionSigner;
+use crate::time::timeout as future_timeout;
+use crate::types::{SendTxOption, TransactionRequest};
+use clarity::abi::{encode_call, Token};
 use clarity::{u256, Address, Uint256};
 use std::time::Duration;
-use tokio::time::timeout as future_timeout;
 
 pub static ERC20_GAS_LIMIT: u128 = 100_000;
 
+/// The most balances requested in a single multicall by the batch balance getters
+pub const BALANCE_BATCH_SIZE: usize = 500;
+
 impl Web3 {
     /// Checks if any given contract is approved to spend money from any given erc20 contract
     /// using any given address. What exactly this does can be hard to grok, essentially when
@@ -55,26 +61,18 @@ impl Web3 {
     pub async fn approve_erc20_transfers(
         &self,
         erc20: Address,
-        eth_private_key: &EthPrivateKey,
+        signer: &dyn TransactionSigner,
         target_contract: Address,
         timeout: Option<Duration>,
         options: Vec<SendTxOption>,
-    ) -> Result<Uint256, Web3Error> {
-        let own_address = eth_private_key.to_address();
+    ) -> Result<TxHash, Web3Error> {
         let payload = encode_call(
             "approve(address,uint256)",
             &[target_contract.into(), Uint256::max_value().into()],
         )?;
 
         let txid = self
-            .send_transaction(
-                erc20,
-                payload,
-                u256!(0),
-                own_address,
-                eth_private_key,
-                options,
-            )
+            .send_transaction_with_signer(erc20, payload, u256!(0), signer, options)
             .await?;
 
         // wait for transaction to enter the chain if the user has requested it
@@ -99,12 +97,10 @@ impl Web3 {
         amount: Uint256,
         recipient: Address,
         erc20: Address,
-        sender_private_key: EthPrivateKey,
+        sender: &dyn TransactionSigner,
         wait_timeout: Option<Duration>,
         options: Vec<SendTxOption>,
-    ) -> Result<Uint256, Web3Error> {
-        let sender_address = sender_private_key.to_address();
-
+    ) -> Result<TxHash, Web3Error> {
         // if the user sets a gas limit we should honor it, if they don't we
         // should add the default
         let mut has_gas_limit = false;
@@ -120,15 +116,14 @@ impl Web3 {
         }
 
         let tx_hash = self
-            .send_transaction(
+            .send_transaction_with_signer(
                 erc20,
                 encode_call(
                     "transfer(address,uint256)",
                     &[recipient.into(), amount.into()],
                 )?,
                 u256!(0),
-                sender_address,
-                &sender_private_key,
+                sender,
                 options,
             )
             .await?;
@@ -161,6 +156,93 @@ impl Web3 {
         .unwrap())
     }
 
+    /// Gets the balance of `owner` in `erc20` at the end of `block`, fails with
+    /// `StateUnavailable` if the node is not an archive node and has pruned that state
+    pub async fn erc20_balance_at_block(
+        &self,
+        erc20: Address,
+        owner: Address,
+        block: Uint256,
+    ) -> Result<Uint256, Web3Error> {
+        let payload = encode_call("balanceOf(address)", &[owner.into()])?;
+        let balance = self
+            .eth_call_at_height(TransactionRequest::call(erc20, payload), block)
+            .await?;
+        match balance.get(0..32).and_then(Uint256::from_bytes_be) {
+            Some(balance) => Ok(balance),
+            None => Err(Web3Error::ContractCallError(
+                "Bad response from ERC20 balance".to_string(),
+            )),
+        }
+    }
+
+    /// Gets the balance of `owner` in each of `tokens` with as few requests as possible using
+    /// multicall, in the same order as `tokens`. Balances that can not be read, because the
+    /// token reverts or is not a contract, are None rather than failing the whole batch.
+    pub async fn get_erc20_balances(
+        &self,
+        tokens: &[Address],
+        owner: Address,
+    ) -> Result<Vec<Option<Uint256>>, Web3Error> {
+        let args = [Token::from(owner)];
+        let calls: Vec<(Address, &str, &[Token])> = tokens
+            .iter()
+            .map(|token| (*token, "balanceOf(address)", &args[..]))
+            .collect();
+        self.batch_balance_calls(&calls).await
+    }
+
+    /// Gets the balance of each of `owners` in `erc20`, see `get_erc20_balances`
+    pub async fn get_erc20_balances_for_owners(
+        &self,
+        erc20: Address,
+        owners: &[Address],
+    ) -> Result<Vec<Option<Uint256>>, Web3Error> {
+        let args: Vec<[Token; 1]> = owners.iter().map(|owner| [(*owner).into()]).collect();
+        let calls: Vec<(Address, &str, &[Token])> = args
+            .iter()
+            .map(|args| (erc20, "balanceOf(address)", &args[..]))
+            .collect();
+        self.batch_balance_calls(&calls).await
+    }
+
+    async fn batch_balance_calls(
+        &self,
+        calls: &[(Address, &str, &[Token])],
+    ) -> Result<Vec<Option<Uint256>>, Web3Error> {
+        let mut res = Vec::new();
+        for chunk in calls.chunks(BALANCE_BATCH_SIZE) {
+            for result in self.multicall(chunk).await? {
+                let balance = match result {
+                    Ok(data) => data.get(0..32).and_then(Uint256::from_bytes_be),
+                    Err(e) => {
+                        trace!("Failed to get balance {:?}", e);
+                        None
+                    }
+                };
+                res.push(balance);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Waits until the balance of `target_address` in `erc20` is at least `minimum`, checking
+    /// every `poll_interval`, and returns the balance that was seen. Fails with
+    /// `BalanceNotReached` once `timeout` has passed, transient errors are retried.
+    pub async fn wait_for_erc20_balance(
+        &self,
+        erc20: Address,
+        target_address: Address,
+        minimum: Uint256,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<Uint256, Web3Error> {
+        wait_for_balance(minimum, timeout, poll_interval, || {
+            self.get_erc20_balance(erc20, target_address)
+        })
+        .await
+    }
+
     pub async fn get_erc20_name(
         &self,
         erc20: Address,
@@ -171,7 +253,7 @@ impl Web3 {
             .simulate_transaction(erc20, u256!(0), payload, caller_address, None)
             .await?;
 
-        match String::from_utf8(name) {
+        match String::from_utf8(name.0) {
             Ok(mut val) => {
                 // the value returned is actually in Ethereum ABI encoded format
                 // stripping control characters is an easy way to strip off the encoding
@@ -195,7 +277,7 @@ impl Web3 {
             .simulate_transaction(erc20, u256!(0), payload, caller_address, None)
             .await?;
 
-        match String::from_utf8(symbol) {
+        match String::from_utf8(symbol.0) {
             Ok(mut val) => {
                 // the value returned is actually in Ethereum ABI encoded format
                 // stripping control characters is an easy way to strip off the encoding
@@ -252,6 +334,107 @@ impl Web3 {
     }
 }
 
+#[tokio::test]
+async fn test_get_erc20_balances_round_trips() {
+    use crate::jsonrpc::mock::MockNode;
+    use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
+    use serde_json::json;
+
+    // answers aggregate3 with the index of each call as its balance, except that every
+    // tenth token reverts
+    let node = MockNode::start(|method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_call" => {
+            let data = hex_str_to_bytes(params[0]["data"].as_str().unwrap()).unwrap();
+            let calls = Uint256::from_bytes_be(&data[36..68]).unwrap();
+            let calls: usize = calls.to_string().parse().unwrap();
+            let word = |n: usize| format!("{:064x}", n);
+            let mut res = word(32) + &word(calls);
+            for i in 0..calls {
+                res += &word(calls * 32 + i * 128);
+            }
+            for i in 0..calls {
+                res += &word((i % 10 != 0) as usize);
+                res += &word(64);
+                res += &word(32);
+                res += &word(i);
+            }
+            Ok(json!(format!("0x{}", res)))
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let owner: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let tokens: Vec<Address> = (0..50u8)
+        .map(|i| {
+            format!("0x{}", bytes_to_hex_str(&[i + 1; 20]))
+                .parse()
+                .unwrap()
+        })
+        .collect();
+
+    let balances = web3.get_erc20_balances(&tokens, owner).await.unwrap();
+    assert_eq!(balances.len(), 50);
+    assert_eq!(balances[0], None);
+    assert_eq!(balances[7], Some(u256!(7)));
+    assert_eq!(balances[49], Some(u256!(49)));
+    // 50 balances in a single eth_call
+    assert_eq!(node.calls("eth_call"), 1);
+
+    let balances = web3
+        .get_erc20_balances_for_owners(tokens[0], &tokens[..3])
+        .await
+        .unwrap();
+    assert_eq!(balances, vec![None, Some(u256!(1)), Some(u256!(2))]);
+    assert_eq!(node.calls("eth_call"), 2);
+}
+
+#[tokio::test]
+async fn test_erc20_balance_at_block() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let start = |pruned: bool| {
+        MockNode::start(move |method, _| match method {
+            "eth_blockNumber" => Ok(json!("0x100")),
+            "eth_syncing" => Ok(json!(false)),
+            "eth_call" if pruned => Err((-32000, "missing trie node 0d3f (path )".to_string())),
+            "eth_call" => Ok(json!(format!("0x{:064x}", 5))),
+            _ => Err((-32601, "Method not found".to_string())),
+        })
+    };
+    let token: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let owner: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+
+    let archive = start(false);
+    let web3 = Web3::new(&archive.url, Duration::from_secs(5));
+    assert_eq!(
+        web3.erc20_balance_at_block(token, owner, u256!(16))
+            .await
+            .unwrap(),
+        u256!(5)
+    );
+
+    let pruned = start(true);
+    let web3 = Web3::new(&pruned.url, Duration::from_secs(5));
+    assert!(matches!(
+        web3.erc20_balance_at_block(token, owner, u256!(16)).await,
+        Err(Web3Error::StateUnavailable { block }) if block == u256!(16)
+    ));
+    // blocks the node has not seen yet are a different problem
+    assert!(matches!(
+        web3.erc20_balance_at_block(token, owner, u256!(0x200))
+            .await,
+        Err(Web3Error::BadInput(_))
+    ));
+}
+
 // #[tokio::test]
 // async fn test_erc20_metadata() {
 //     let web3 = Web3::new("https://eth.althea.net", Duration::from_secs(5));
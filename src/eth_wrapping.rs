@@ -1,27 +1,28 @@
 use crate::amm::WETH_CONTRACT_ADDRESS;
+use crate::hash::TxHash;
+use crate::signer::TransactionSigner;
+use crate::time::timeout as future_timeout;
 use crate::{client::Web3, jsonrpc::error::Web3Error};
 use clarity::abi::Token;
-use clarity::{abi::encode_call, PrivateKey, Uint256};
+use clarity::{abi::encode_call, Uint256};
 use clarity::{u256, Address};
 use std::time::Duration;
-use tokio::time::timeout as future_timeout;
 
 // Performs wrapping and unwrapping of eth, along with balance checking
 impl Web3 {
     pub async fn wrap_eth(
         &self,
         amount: Uint256,
-        secret: PrivateKey,
+        signer: &dyn TransactionSigner,
         weth_address: Option<Address>,
         wait_timeout: Option<Duration>,
-    ) -> Result<Uint256, Web3Error> {
-        let own_address = secret.to_address();
+    ) -> Result<TxHash, Web3Error> {
         let sig = "deposit()";
         let tokens = [];
         let payload = encode_call(sig, &tokens).unwrap();
         let weth_address = weth_address.unwrap_or(*WETH_CONTRACT_ADDRESS);
         let txid = self
-            .send_transaction(weth_address, payload, amount, own_address, &secret, vec![])
+            .send_transaction_with_signer(weth_address, payload, amount, signer, vec![])
             .await?;
 
         if let Some(timeout) = wait_timeout {
@@ -33,24 +34,16 @@ impl Web3 {
     pub async fn unwrap_eth(
         &self,
         amount: Uint256,
-        secret: PrivateKey,
+        signer: &dyn TransactionSigner,
         weth_address: Option<Address>,
         wait_timeout: Option<Duration>,
-    ) -> Result<Uint256, Web3Error> {
-        let own_address = secret.to_address();
+    ) -> Result<TxHash, Web3Error> {
         let sig = "withdraw(uint256)";
         let tokens = [Token::Uint(amount)];
         let payload = encode_call(sig, &tokens).unwrap();
         let weth_address = weth_address.unwrap_or(*WETH_CONTRACT_ADDRESS);
         let txid = self
-            .send_transaction(
-                weth_address,
-                payload,
-                u256!(0),
-                own_address,
-                &secret,
-                vec![],
-            )
+            .send_transaction_with_signer(weth_address, payload, u256!(0), signer, vec![])
             .await?;
 
         if let Some(timeout) = wait_timeout {
@@ -1,13 +1,20 @@
 //! This module contains functions for managing Ethereum events
-use crate::{client::Web3, types::NewFilter};
+use crate::hash::BlockHash;
+use crate::hex::{data_to_hex, quantity_to_hex};
+use crate::keccak::{canonical_arg, canonical_signature, keccak256, split_args};
+use crate::log_watcher::MAX_LOG_RANGE;
+use crate::time::sleep as delay_for;
+use crate::time::Instant;
+use crate::{client::Web3, filters::InstalledFilter, types::NewFilter};
 use crate::{jsonrpc::error::Web3Error, types::Log};
 use clarity::{
     abi::{derive_signature, SerializedToken, Token},
     utils::bytes_to_hex_str,
 };
 use clarity::{Address, Uint256};
-use std::time::{Duration, Instant};
-use tokio::time::sleep as delay_for;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
 
 /// takes an address and spits out an event, There's some argument to
 /// not use [u8; 32] for event definitions because of how much trouble
@@ -22,17 +29,393 @@ pub fn address_to_event(address: Address) -> [u8; 32] {
     }
 }
 
+/// Returns the topic an indexed event argument with the value `token` is logged as. Value
+/// types such as addresses, integers and `bytesN` are padded to a 32 byte word, while
+/// strings, `bytes`, arrays and structs are hashed: the topic of an indexed `string` is the
+/// keccak256 of its UTF-8 bytes and the topic of an array or struct is the keccak256 of its
+/// elements each padded to 32 bytes, without offsets or lengths.
+///
+/// Hashed values can only be matched, there is no way to recover the original string or
+/// array from a log. Filter on them with `indexed_topics_for` and compare decoded topics
+/// against this.
+///
+/// See more: https://docs.soliditylang.org/en/latest/abi-spec.html#encoding-of-indexed-event-parameters
+pub fn indexed_topic_for(token: &Token) -> [u8; 32] {
+    match token {
+        Token::String(s) => keccak256(s.as_bytes()),
+        Token::UnboundedBytes(bytes) => keccak256(bytes),
+        Token::Dynamic(_) | Token::Struct(_) => {
+            let mut encoded = Vec::new();
+            in_place_encoding(token, &mut encoded);
+            keccak256(&encoded)
+        }
+        _ => match token.serialize() {
+            SerializedToken::Static(word) => word,
+            SerializedToken::Dynamic(data) => keccak256(&data),
+        },
+    }
+}
+
+/// Appends the encoding of a value nested in an indexed array or struct, where strings and
+/// bytes are right padded to a multiple of 32 bytes rather than hashed
+fn in_place_encoding(token: &Token, out: &mut Vec<u8>) {
+    match token {
+        Token::String(s) => pad_to_words(s.as_bytes(), out),
+        Token::UnboundedBytes(bytes) => pad_to_words(bytes, out),
+        Token::Dynamic(tokens) | Token::Struct(tokens) => {
+            for token in tokens {
+                in_place_encoding(token, out);
+            }
+        }
+        _ => out.extend_from_slice(&indexed_topic_for(token)),
+    }
+}
+
+fn pad_to_words(bytes: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(bytes);
+    out.resize(out.len() + (32 - bytes.len() % 32) % 32, 0);
+}
+
+/// Builds the `indexed_topics` of `Web3::check_for_events_filtered` from the values allowed
+/// for each indexed argument, with `None` matching anything. For example the logs of
+/// "Registered(string indexed name, address owner)" for the name "alice" are found with
+/// `indexed_topics_for([Some(vec![Token::String("alice".into())]), None, None])`.
+pub fn indexed_topics_for(values: [Option<Vec<Token>>; 3]) -> [Option<Vec<[u8; 32]>>; 3] {
+    values.map(|values| values.map(|tokens| tokens.iter().map(indexed_topic_for).collect()))
+}
+
+/// The types of event arguments that `decode_events` can decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ParamKind {
+    Address,
+    Bool,
+    /// uintN, and intN which is returned as the raw two's complement word
+    Uint,
+    /// bytesN
+    FixedBytes(usize),
+    Bytes,
+    String,
+}
+
+impl ParamKind {
+    pub(crate) fn parse(ty: &str) -> Option<ParamKind> {
+        match ty {
+            "address" => Some(ParamKind::Address),
+            "bool" => Some(ParamKind::Bool),
+            "string" => Some(ParamKind::String),
+            "bytes" => Some(ParamKind::Bytes),
+            _ if ty.starts_with("uint") || ty.starts_with("int") => {
+                let bits = ty.trim_start_matches("uint").trim_start_matches("int");
+                if bits.is_empty() {
+                    return Some(ParamKind::Uint);
+                }
+                match bits.parse::<usize>() {
+                    Ok(bits) if bits % 8 == 0 && (8..=256).contains(&bits) => Some(ParamKind::Uint),
+                    _ => None,
+                }
+            }
+            _ if ty.starts_with("bytes") => match ty["bytes".len()..].parse::<usize>() {
+                Ok(len) if (1..=32).contains(&len) => Some(ParamKind::FixedBytes(len)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Decodes a single 32 byte word, indexed dynamic values are only available as their hash,
+    /// see `indexed_topic_for`
+    fn decode_word(self, word: &[u8]) -> Option<Token> {
+        match self {
+            ParamKind::Address => {
+                let address = data_to_hex(&word[12..32]);
+                Some(Token::Address(address.parse().ok()?))
+            }
+            ParamKind::Bool => Some(Token::Bool(word[31] != 0)),
+            ParamKind::Uint => Some(Token::Uint(Uint256::from_bytes_be(&word[0..32])?)),
+            ParamKind::FixedBytes(len) => Some(Token::Bytes(word[0..len].to_vec())),
+            ParamKind::Bytes | ParamKind::String => Some(Token::Bytes(word[0..32].to_vec())),
+        }
+    }
+
+    /// Decodes a non indexed value from the data section of a log, or from abi encoded
+    /// return data, where `head` is the offset of its 32 byte head word
+    pub(crate) fn decode_data(self, data: &[u8], head: usize) -> Option<Token> {
+        let word = data.get(head..head + 32)?;
+        match self {
+            ParamKind::Bytes | ParamKind::String => {
+                let offset = usize_from_word(word)?;
+                let len = usize_from_word(data.get(offset..offset.checked_add(32)?)?)?;
+                let start = offset + 32;
+                let bytes = data.get(start..start.checked_add(len)?)?.to_vec();
+                if self == ParamKind::String {
+                    Some(Token::String(String::from_utf8(bytes).ok()?))
+                } else {
+                    Some(Token::UnboundedBytes(bytes))
+                }
+            }
+            _ => self.decode_word(word),
+        }
+    }
+}
+
+fn usize_from_word(word: &[u8]) -> Option<usize> {
+    Uint256::from_bytes_be(word)?.to_string().parse().ok()
+}
+
+/// Decodes a `uint256[]` from abi encoded data, where `head` is the offset of the word
+/// holding the offset of the array
+pub(crate) fn decode_uint_array(data: &[u8], head: usize) -> Option<Vec<Uint256>> {
+    let offset = usize_from_word(data.get(head..head.checked_add(32)?)?)?;
+    let len = usize_from_word(data.get(offset..offset.checked_add(32)?)?)?;
+    let start = offset.checked_add(32)?;
+    // check the whole array is present before allocating for it
+    let words = data.get(start..start.checked_add(len.checked_mul(32)?)?)?;
+    words
+        .chunks(32)
+        .map(Uint256::from_bytes_be)
+        .collect::<Option<Vec<Uint256>>>()
+}
+
+/// A parsed event signature for `decode_events`
+struct EventSpec {
+    /// the canonical signature, such as "Transfer(address,address,uint256)"
+    signature: String,
+    /// None for anonymous events, which have no signature topic
+    topic0: Option<[u8; 32]>,
+    params: Vec<ParamKind>,
+    /// which params are indexed, if the signature marked them explicitly
+    indexed: Option<Vec<bool>>,
+}
+
+impl EventSpec {
+    fn parse(signature: &str) -> Result<EventSpec, Web3Error> {
+        let bad = || Web3Error::BadInput(format!("Can not decode event {}", signature));
+        let (anonymous, signature) = match signature.trim().strip_prefix("anonymous ") {
+            Some(rest) => (true, rest.trim()),
+            None => (false, signature.trim()),
+        };
+        let (_, args) = signature.split_once('(').ok_or_else(bad)?;
+        let args = args.strip_suffix(')').ok_or_else(bad)?;
+
+        let mut params = Vec::new();
+        let mut indexed = Vec::new();
+        for arg in split_args(args).into_iter().map(|a| a.trim()) {
+            if arg.is_empty() {
+                continue;
+            }
+            let ty = canonical_arg(arg).ok_or_else(bad)?;
+            let is_indexed = arg.split_whitespace().any(|w| w == "indexed");
+            let kind = match ParamKind::parse(&ty) {
+                Some(kind) => kind,
+                // indexed arrays and structs are hashed like strings, and can be decoded as such
+                None if is_indexed && (ty.ends_with(']') || ty.starts_with('(')) => {
+                    ParamKind::Bytes
+                }
+                None => return Err(bad()),
+            };
+            params.push(kind);
+            indexed.push(is_indexed);
+        }
+        let signature = canonical_signature(signature)?;
+        let topic0 = if anonymous {
+            None
+        } else {
+            Some(derive_signature(&signature)?)
+        };
+        Ok(EventSpec {
+            signature,
+            topic0,
+            params,
+            indexed: if indexed.iter().any(|i| *i) {
+                Some(indexed)
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Decodes a log that matches this event, returning None if its layout does not fit
+    fn decode(&self, log: &Log) -> Option<Vec<Token>> {
+        let mut topics = log.topics.iter();
+        if self.topic0.is_some() {
+            topics.next();
+        }
+        // without explicit markers the leading params are assumed to be the indexed ones
+        let indexed = match &self.indexed {
+            Some(indexed) => indexed.clone(),
+            None => (0..self.params.len()).map(|i| i < topics.len()).collect(),
+        };
+        if indexed.iter().filter(|i| **i).count() != topics.len() {
+            return None;
+        }
+
+        let mut tokens = Vec::new();
+        let mut head = 0;
+        for (kind, indexed) in self.params.iter().zip(indexed) {
+            if indexed {
+                let topic = topics.next()?;
+                tokens.push(kind.decode_word(topic.0.get(0..32)?)?);
+            } else {
+                tokens.push(kind.decode_data(&log.data.0, head)?);
+                head += 32;
+            }
+        }
+        Some(tokens)
+    }
+}
+
+/// Returns true if `decode_events` can decode the event with this signature, events with
+/// arrays or structs that are not indexed can not be
+pub(crate) fn is_decodable_event(signature: &str) -> bool {
+    EventSpec::parse(signature).is_ok()
+}
+
+/// Decodes the logs that match any of the given event signatures, returning the canonical
+/// signature of each matched event along with its arguments in declaration order. Logs from
+/// other contracts than `contract`, if it is provided, and with unknown signatures are skipped.
+///
+/// Arguments can be marked `indexed` as in Solidity, "Transfer(address indexed from, address
+/// indexed to, uint256 value)", otherwise the leading arguments are assumed to be the indexed
+/// ones. Indexed strings, bytes, arrays and structs are only available as their hash, returned as
+/// `Token::Bytes`, compare it with `indexed_topic_for` of the expected value to match them.
+/// Anonymous events have no signature topic, prefix them with "anonymous " and they will match
+/// any log from `contract` with the right number of topics that no other signature matched.
+pub fn decode_events(
+    logs: &[Log],
+    contract: Option<Address>,
+    signatures: &[&str],
+) -> Result<Vec<(String, Vec<Token>)>, Web3Error> {
+    let mut specs = Vec::new();
+    for signature in signatures {
+        specs.push(EventSpec::parse(signature)?);
+    }
+
+    let mut res = Vec::new();
+    for log in logs {
+        if contract.map_or(false, |c| c != log.address) {
+            continue;
+        }
+        let topic0 = log.topics.first().map(|t| t.0.as_slice());
+        let named = specs
+            .iter()
+            .filter(|spec| spec.topic0.is_some() && spec.topic0.as_ref().map(|t| &t[..]) == topic0)
+            .find_map(|spec| spec.decode(log).map(|tokens| (spec, tokens)));
+        let matched = named.or_else(|| {
+            specs
+                .iter()
+                .filter(|spec| spec.topic0.is_none())
+                .find_map(|spec| spec.decode(log).map(|tokens| (spec, tokens)))
+        });
+        match matched {
+            Some((spec, tokens)) => res.push((spec.signature.clone(), tokens)),
+            None => trace!(
+                "Skipping log with unknown signature {:?}",
+                log.topics.first()
+            ),
+        }
+    }
+    Ok(res)
+}
+
+/// Sorts logs into the order they were emitted, see `LogPosition`, and removes duplicates
+/// of the same log in the same block as returned by overlapping or retried queries. Logs
+/// without a block hash or log index are never considered duplicates.
+pub fn normalize_logs(mut logs: Vec<Log>) -> Vec<Log> {
+    logs.sort_by_key(|log| log.position());
+    let mut seen = HashSet::new();
+    logs.retain(|log| match (log.block_hash, log.log_index) {
+        (Some(hash), Some(index)) => seen.insert((hash, index)),
+        _ => true,
+    });
+    logs
+}
+
+/// The default interval between polls when waiting for an event
+pub const EVENT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
 fn bytes_to_data(s: &[u8]) -> String {
     let mut val = "0x".to_string();
     val.push_str(&bytes_to_hex_str(s));
     val
 }
 
+/// Builds a filter for a single event, `topics` are the filters for the indexed
+/// arguments of the event starting from topic1, see `topics_filter`
+fn event_filter(
+    contract_address: Vec<Address>,
+    event: &str,
+    topics: Vec<Vec<[u8; 32]>>,
+) -> Result<NewFilter, Web3Error> {
+    let mut positions = vec![vec![derive_signature(&canonical_signature(event)?)?]];
+    positions.extend(topics);
+    Ok(NewFilter {
+        address: contract_address,
+        from_block: None,
+        to_block: None,
+        topics: Some(topics_filter(positions)),
+        block_hash: None,
+    })
+}
+
+/// Builds the topic positions for a list of event signatures and optional filters on their indexed
+/// arguments, the signatures are alternatives for topic0 and trailing wildcards are dropped
+fn event_topics(
+    events: &[&str],
+    indexed_topics: [Option<Vec<[u8; 32]>>; 3],
+) -> Result<Vec<Vec<[u8; 32]>>, Web3Error> {
+    let mut sigs = Vec::new();
+    for event in events {
+        sigs.push(derive_signature(&canonical_signature(event)?)?);
+    }
+    let mut topics = vec![sigs];
+    for (i, topic) in indexed_topics.into_iter().enumerate() {
+        match topic {
+            Some(values) if values.is_empty() => {
+                return Err(Web3Error::BadInput(format!(
+                    "Filter for topic{} has no values and would match nothing, use None to match anything",
+                    i + 1
+                )))
+            }
+            Some(values) => topics.push(values),
+            None => topics.push(Vec::new()),
+        }
+    }
+    while topics.len() > 1 && topics[topics.len() - 1].is_empty() {
+        topics.pop();
+    }
+    Ok(topics)
+}
+
+/// Builds the topics of a filter. Each entry of the outer vec is a topic position, which are
+/// ANDed together, while the values within a position are ORed. An empty position matches
+/// any value, so `vec![vec![sig1, sig2]]` matches logs with either signature as topic0.
+fn topics_filter(topics: Vec<Vec<[u8; 32]>>) -> Vec<Option<Vec<Option<String>>>> {
+    topics
+        .into_iter()
+        .map(|position| {
+            if position.is_empty() {
+                None
+            } else {
+                Some(position.iter().map(|t| Some(bytes_to_data(t))).collect())
+            }
+        })
+        .collect()
+}
+
+/// The direction `Web3::scan_logs` walks its block range in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ScanDirection {
+    /// from the start of the range, for replaying events in order
+    Forward,
+    /// from the end of the range, for finding the most recent events first
+    Backward,
+}
+
 impl Web3 {
-    /// Waits for a single event but instead of creating a filter and checking
-    /// for changes this function waits for the provided wait time before
-    /// checking if the event has occurred. This function will wait for at
-    // least 'wait_time' before progressing, regardless of the outcome.
+    /// Waits for a single event by polling eth_getLogs every `EVENT_POLL_INTERVAL` instead of
+    /// creating a filter, returning as soon as a log passing `local_filter` is found or an
+    /// `EventNotFound` error once `wait_time` has passed. Only logs from blocks at or after the
+    /// block height when this function was called are considered.
     pub async fn wait_for_event_alt<F: Fn(Log) -> bool + 'static>(
         &self,
         wait_time: Duration,
@@ -41,28 +424,63 @@ impl Web3 {
         topics: Vec<Vec<[u8; 32]>>,
         local_filter: F,
     ) -> Result<Log, Web3Error> {
-        let sig = derive_signature(event)?;
-        let mut final_topics = vec![Some(vec![Some(bytes_to_data(&sig))])];
-        for topic in topics {
-            let mut parts = Vec::new();
-            for item in topic {
-                parts.push(Some(bytes_to_data(&item)))
+        self.wait_for_event_alt_with_interval(
+            wait_time,
+            self.poll_interval_or(EVENT_POLL_INTERVAL),
+            contract_address,
+            event,
+            topics,
+            local_filter,
+        )
+        .await
+    }
+
+    /// `wait_for_event_alt` with a configurable interval between eth_getLogs polls
+    pub async fn wait_for_event_alt_with_interval<F: Fn(Log) -> bool + 'static>(
+        &self,
+        wait_time: Duration,
+        poll_interval: Duration,
+        contract_address: Vec<Address>,
+        event: &str,
+        topics: Vec<Vec<[u8; 32]>>,
+        local_filter: F,
+    ) -> Result<Log, Web3Error> {
+        let start = Instant::now();
+        let mut new_filter = event_filter(contract_address, event, topics)?;
+        // pin the start of the range so that every poll sees the same logs plus any new ones
+        let start_block = self.eth_block_number().await?;
+        new_filter.from_block = Some(quantity_to_hex(start_block));
+
+        loop {
+            let logs = self.eth_get_logs(new_filter.clone()).await?;
+            for log in logs {
+                if local_filter(log.clone()) {
+                    return Ok(log);
+                }
+            }
+
+            let elapsed = Instant::now() - start;
+            if elapsed >= wait_time {
+                return Err(Web3Error::EventNotFound(event.to_string()));
             }
-            final_topics.push(Some(parts));
+            delay_for(min(poll_interval, wait_time - elapsed)).await;
         }
+    }
 
-        let new_filter = NewFilter {
-            address: contract_address,
-            from_block: None,
-            to_block: None,
-            topics: Some(final_topics),
-        };
+    /// Waits for the full `wait_time` and then checks once for a matching event, this was
+    /// the behavior of `wait_for_event_alt` before it was changed to poll
+    pub async fn check_for_event_after_delay<F: Fn(Log) -> bool + 'static>(
+        &self,
+        wait_time: Duration,
+        contract_address: Vec<Address>,
+        event: &str,
+        topics: Vec<Vec<[u8; 32]>>,
+        local_filter: F,
+    ) -> Result<Log, Web3Error> {
+        let new_filter = event_filter(contract_address, event, topics)?;
 
         delay_for(wait_time).await;
-        let logs = match self.eth_get_logs(new_filter.clone()).await {
-            Ok(logs) => logs,
-            Err(e) => return Err(e),
-        };
+        let logs = self.eth_get_logs(new_filter).await?;
 
         for log in logs {
             if local_filter(log.clone()) {
@@ -74,8 +492,8 @@ impl Web3 {
 
     /// Sets up an event filter, waits for a single event to happen, then removes the filter. Includes a
     /// local filter. If a captured event does not pass this filter, it is ignored. This differs from
-    /// wait_for_event_alt in that it will check for filter changes every second and potentially exit
-    /// earlier than the wait_for time provided by the user.
+    /// wait_for_event_alt in that it installs a filter on the node and checks it for changes every
+    /// second rather than querying the logs of a block range.
     pub async fn wait_for_event<F: Fn(Log) -> bool + 'static>(
         &self,
         wait_for: Duration,
@@ -84,56 +502,139 @@ impl Web3 {
         topics: Vec<Vec<[u8; 32]>>,
         local_filter: F,
     ) -> Result<Log, Web3Error> {
-        let sig = derive_signature(event)?;
-        let mut final_topics = vec![Some(vec![Some(bytes_to_data(&sig))])];
-        for topic in topics {
-            let mut parts = Vec::new();
-            for item in topic {
-                parts.push(Some(bytes_to_data(&item)))
-            }
-            final_topics.push(Some(parts));
-        }
+        self.wait_for_event_with_interval(
+            wait_for,
+            self.poll_interval_or(Duration::from_secs(1)),
+            contract_address,
+            event,
+            topics,
+            local_filter,
+        )
+        .await
+    }
 
-        let new_filter = NewFilter {
-            address: contract_address,
-            from_block: None,
-            to_block: None,
-            topics: Some(final_topics),
-        };
+    /// `wait_for_event` with a configurable interval between checks of the filter. Returns as soon
+    /// as a matching log is found, the filter is removed from the node whether or not this succeeds.
+    pub async fn wait_for_event_with_interval<F: Fn(Log) -> bool + 'static>(
+        &self,
+        wait_for: Duration,
+        poll_interval: Duration,
+        contract_address: Vec<Address>,
+        event: &str,
+        topics: Vec<Vec<[u8; 32]>>,
+        local_filter: F,
+    ) -> Result<Log, Web3Error> {
+        self.wait_for_event_with_options(
+            wait_for,
+            poll_interval,
+            false,
+            contract_address,
+            event,
+            topics,
+            local_filter,
+        )
+        .await
+    }
 
-        let filter_id = match self.eth_new_filter(new_filter).await {
-            Ok(f) => f,
-            Err(e) => return Err(e),
-        };
+    /// `wait_for_event_with_interval` that can also return logs removed by a reorg when
+    /// `include_removed` is set, which are otherwise skipped. Check `Log::is_removed` in
+    /// `local_filter` to tell them apart.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "web30.wait_for_event", skip_all, fields(event = %event))
+    )]
+    pub async fn wait_for_event_with_options<F: Fn(Log) -> bool + 'static>(
+        &self,
+        wait_for: Duration,
+        poll_interval: Duration,
+        include_removed: bool,
+        contract_address: Vec<Address>,
+        event: &str,
+        topics: Vec<Vec<[u8; 32]>>,
+        local_filter: F,
+    ) -> Result<Log, Web3Error> {
+        let new_filter = event_filter(contract_address, event, topics)?;
+        // recorded so that logs can be recovered if the filter has to be recreated
+        let installed_at = self.eth_synced_block_number().await?;
+        // if this future is cancelled the filter is uninstalled when it is dropped
+        let mut filter = self
+            .new_log_filter(new_filter)
+            .await?
+            .with_removed_logs(include_removed);
+
+        let result = self
+            .poll_filter_for_event(
+                &mut filter,
+                installed_at,
+                wait_for,
+                poll_interval,
+                event,
+                &local_filter,
+            )
+            .await;
+
+        let filter_id = filter.id();
+        match (result, filter.uninstall().await) {
+            (Ok(log), Ok(_)) => Ok(log),
+            (Ok(_), Err(e)) => Err(Web3Error::CouldNotRemoveFilter(format!("{}", e))),
+            (Err(e), Ok(_)) => Err(e),
+            (Err(e), Err(uninstall_error)) => {
+                warn!(
+                    "Failed to remove filter {:#x} after error {:?}",
+                    filter_id, uninstall_error
+                );
+                Err(e)
+            }
+        }
+    }
 
+    /// Checks an installed filter every `poll_interval` until a log passing `local_filter` is
+    /// found or `wait_for` has passed, the caller is responsible for removing the filter. If the
+    /// node no longer knows the filter it is reinstalled and any logs since `installed_at` are
+    /// fetched with eth_getLogs so that nothing is missed in between.
+    async fn poll_filter_for_event<F: Fn(Log) -> bool>(
+        &self,
+        filter: &mut InstalledFilter,
+        installed_at: Uint256,
+        wait_for: Duration,
+        poll_interval: Duration,
+        event: &str,
+        local_filter: &F,
+    ) -> Result<Log, Web3Error> {
         let start = Instant::now();
-        let mut found_log = None;
-        while Instant::now() - start < wait_for {
-            delay_for(Duration::from_secs(1)).await;
-            let logs = match self.eth_get_filter_changes(filter_id).await {
-                Ok(changes) => changes,
+        loop {
+            let elapsed = Instant::now() - start;
+            if elapsed >= wait_for {
+                return Err(Web3Error::EventNotFound(event.to_string()));
+            }
+            delay_for(min(poll_interval, wait_for - elapsed)).await;
+
+            let logs = match filter.poll_changes().await {
+                Ok(logs) => logs,
+                Err(e) if e.is_filter_not_found() => {
+                    warn!(
+                        "Filter {:#x} was dropped by the node, recreating it. Your provider may be load balanced or expiring filters",
+                        filter.id()
+                    );
+                    filter.reinstall().await?;
+                    let mut gap = filter.filter().clone();
+                    gap.from_block = Some(quantity_to_hex(installed_at));
+                    self.eth_get_logs(gap).await?
+                }
                 Err(e) => return Err(e),
             };
             for log in logs {
                 if local_filter(log.clone()) {
-                    found_log = Some(log);
-                    break;
+                    return Ok(log);
                 }
             }
         }
-
-        if let Err(e) = self.eth_uninstall_filter(filter_id).await {
-            return Err(Web3Error::CouldNotRemoveFilter(format!("{}", e)));
-        }
-
-        match found_log {
-            Some(log) => Ok(log),
-            None => Err(Web3Error::EventNotFound(event.to_string())),
-        }
     }
 
     /// Checks for multiple events as defined by their signature strings over a block range. If no ending block is provided
-    /// the latest finalized block will be used. This function will not wait for events to occur.
+    /// the latest finalized block will be used. This function will not wait for events to occur. The logs are sorted
+    /// by block number, transaction index and log index and deduplicated, see `normalize_logs`.
     pub async fn check_for_events(
         &self,
         start_block: Uint256,
@@ -141,34 +642,243 @@ impl Web3 {
         contract_address: Vec<Address>,
         events: Vec<&str>,
     ) -> Result<Vec<Log>, Web3Error> {
-        // Build a filter with specified topics
-        let from_block = Some(format!("{:#x}", start_block));
-        let to_block;
-        if let Some(end_block) = end_block {
-            to_block = Some(format!("{:#x}", end_block));
-        } else {
-            let latest_block = self.eth_finalized_block_number().await?;
-            to_block = Some(format!("{:#x}", latest_block));
+        self.check_for_events_filtered(
+            start_block,
+            end_block,
+            contract_address,
+            events,
+            [None, None, None],
+        )
+        .await
+    }
+
+    /// Like `check_for_events` but also filters on the indexed arguments of the events, `indexed_topics`
+    /// are the alternatives allowed for topic1, topic2 and topic3 with `None` matching anything. Use
+    /// `address_to_event` to build address topics, for example to only get the Transfers to an address
+    /// pass `[None, Some(vec![address_to_event(address)]), None]`, or `indexed_topics_for` to build
+    /// them from any values including strings and arrays, which are logged as their hash. When
+    /// multiple events are given the topic filters apply to all of them, so they should have
+    /// compatible indexed arguments. Signatures may include parameter names and `indexed`.
+    /// The logs are in the same order as for `check_for_events`.
+    pub async fn check_for_events_filtered(
+        &self,
+        start_block: Uint256,
+        end_block: Option<Uint256>,
+        contract_address: Vec<Address>,
+        events: Vec<&str>,
+        indexed_topics: [Option<Vec<[u8; 32]>>; 3],
+    ) -> Result<Vec<Log>, Web3Error> {
+        let topics = event_topics(&events, indexed_topics)?;
+        self.check_for_arbitrary_events(start_block, end_block, contract_address, topics)
+            .await
+    }
+
+    /// Like `check_for_events` but for many contracts at once, returning the logs of each
+    /// contract separately, in the order they were emitted. Every contract has an entry even
+    /// if it emitted nothing. All contracts are queried with a single filter, if the provider
+    /// refuses it for returning too many logs the contracts are split in halves first, and
+    /// only a single contract's query has its block range halved, so that each result set
+    /// still covers whole contracts.
+    pub async fn check_for_events_grouped(
+        &self,
+        start_block: Uint256,
+        end_block: Option<Uint256>,
+        contracts: Vec<Address>,
+        events: Vec<&str>,
+    ) -> Result<HashMap<Address, Vec<Log>>, Web3Error> {
+        let topics = event_topics(&events, [None, None, None])?;
+        let mut grouped: HashMap<Address, Vec<Log>> =
+            contracts.iter().map(|c| (*c, Vec::new())).collect();
+        // a filter without addresses would match every contract
+        if contracts.is_empty() {
+            return Ok(grouped);
         }
+        let end_block = match end_block {
+            Some(end_block) => end_block,
+            None => self.eth_finalized_block_number().await?,
+        };
 
-        let mut final_topics = Vec::new();
-        for event in events {
-            let sig = derive_signature(event)?;
-            final_topics.push(Some(vec![Some(bytes_to_data(&sig))]));
+        let mut logs = Vec::new();
+        let mut queries = VecDeque::from([(contracts, start_block, end_block)]);
+        while let Some((contracts, start, end)) = queries.pop_front() {
+            // a single contract has its block range halved by the chunked scanner instead
+            if contracts.len() == 1 {
+                self.scan_logs(
+                    start,
+                    end,
+                    contracts,
+                    topics.clone(),
+                    ScanDirection::Forward,
+                    |_, _, found| {
+                        logs.extend(found);
+                        true
+                    },
+                )
+                .await?;
+                continue;
+            }
+            let e = match self
+                .check_for_arbitrary_events(start, Some(end), contracts.clone(), topics.clone())
+                .await
+            {
+                Ok(found) => {
+                    logs.extend(found);
+                    continue;
+                }
+                Err(e) if e.is_log_limit_exceeded() => e,
+                Err(e) => return Err(e),
+            };
+            debug!(
+                "Too many logs for {} contracts, splitting them {:?}",
+                contracts.len(),
+                e
+            );
+            let (left, right) = contracts.split_at(contracts.len() / 2);
+            queries.push_back((left.to_vec(), start, end));
+            queries.push_back((right.to_vec(), start, end));
         }
 
-        let new_filter = NewFilter {
-            address: contract_address,
+        for log in normalize_logs(logs) {
+            if let Some(contract_logs) = grouped.get_mut(&log.address) {
+                contract_logs.push(log);
+            }
+        }
+        Ok(grouped)
+    }
+
+    /// Finds the most recent log of `event` from `from_block` to `to_block` inclusive, answering
+    /// whether an event has already happened. The range defaults to the whole chain, from block
+    /// 0 to the latest block, since nodes given no range only search the latest block.
+    /// `indexed_topics` filter on topic1 to topic3 as for `check_for_events_filtered`. Returns
+    /// None if no log matches.
+    ///
+    /// The whole range is requested at once first, if the provider limits the range or the
+    /// number of results it is scanned backwards `MAX_LOG_RANGE` blocks at a time, halving
+    /// ranges that still fail, so that the search stops at the most recent match. Any other
+    /// error is returned as is.
+    pub async fn check_for_event(
+        &self,
+        from_block: Option<Uint256>,
+        to_block: Option<Uint256>,
+        contract_address: Vec<Address>,
+        event: &str,
+        indexed_topics: [Option<Vec<[u8; 32]>>; 3],
+    ) -> Result<Option<Log>, Web3Error> {
+        let topics = event_topics(&[event], indexed_topics)?;
+        let from_block = from_block.unwrap_or_default();
+        let to_block = match to_block {
+            Some(to_block) => to_block,
+            None => self.eth_block_number().await?,
+        };
+        if from_block > to_block {
+            return Ok(None);
+        }
+        // the latest by position rather than by the order of the response
+        let latest = |logs: Vec<Log>| logs.into_iter().max_by_key(|log| log.position());
+
+        match self
+            .check_for_arbitrary_events(
+                from_block,
+                Some(to_block),
+                contract_address.clone(),
+                topics.clone(),
+            )
+            .await
+        {
+            Ok(logs) => return Ok(latest(logs)),
+            Err(e) if e.is_log_limit_exceeded() => debug!(
+                "Too many logs for {} to {}, scanning in ranges {:?}",
+                from_block, to_block, e
+            ),
+            Err(e) => return Err(e),
+        }
+
+        let mut found = None;
+        self.scan_logs(
             from_block,
             to_block,
-            topics: Some(final_topics),
-        };
+            contract_address,
+            topics,
+            ScanDirection::Backward,
+            |_, _, logs| {
+                found = latest(logs);
+                found.is_none()
+            },
+        )
+        .await?;
+        Ok(found)
+    }
 
-        self.eth_get_logs(new_filter).await
+    /// The chunked log scanner shared by the scans over long block ranges. Requests the logs
+    /// matching `contracts` and `topics` from `start` to `end` inclusive in ranges of at most
+    /// `MAX_LOG_RANGE` blocks, walking the range in `direction`. A range the provider refuses
+    /// for returning too many logs is halved and retried down to a single block, and ranges
+    /// grow back afterwards, any other error is returned as is. `on_range` is given each range
+    /// with its logs and returns false to stop the scan early.
+    pub(crate) async fn scan_logs<F>(
+        &self,
+        start: Uint256,
+        end: Uint256,
+        contracts: Vec<Address>,
+        topics: Vec<Vec<[u8; 32]>>,
+        direction: ScanDirection,
+        mut on_range: F,
+    ) -> Result<(), Web3Error>
+    where
+        F: FnMut(Uint256, Uint256, Vec<Log>) -> bool,
+    {
+        let one = Uint256::from_u64(1);
+        // the blocks from `low` to `high` have not been scanned yet
+        let (mut low, mut high) = (start, end);
+        let mut range = MAX_LOG_RANGE;
+        while low <= high {
+            let span = Uint256::from_u64(range - 1);
+            let (from, to) = match direction {
+                ScanDirection::Forward => (low, min(high, low.checked_add(span).unwrap_or(high))),
+                ScanDirection::Backward => {
+                    (max(low, high.checked_sub(span).unwrap_or_default()), high)
+                }
+            };
+            match self
+                .check_for_arbitrary_events(from, Some(to), contracts.clone(), topics.clone())
+                .await
+            {
+                Ok(logs) => {
+                    if !on_range(from, to, logs) {
+                        return Ok(());
+                    }
+                }
+                Err(e) if from < to && e.is_log_limit_exceeded() => {
+                    debug!(
+                        "Too many logs for {} to {}, halving range {:?}",
+                        from, to, e
+                    );
+                    // halve the range that failed, which may be shorter than `range` at the end
+                    let len = to.checked_sub(from).unwrap_or_default().resize_to_u128() + 1;
+                    range = (min(range as u128, len) / 2) as u64;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            range = min(range * 2, MAX_LOG_RANGE);
+            match direction {
+                ScanDirection::Forward => match to.checked_add(one) {
+                    Some(next) => low = next,
+                    None => return Ok(()),
+                },
+                ScanDirection::Backward => match from.checked_sub(one) {
+                    Some(next) => high = next,
+                    None => return Ok(()),
+                },
+            }
+        }
+        Ok(())
     }
 
     /// Checks for multiple events as defined by arbitrary user input over a block range. If no ending block is provided
-    /// the latest finalized block will be used. This function will not wait for events to occur
+    /// the latest finalized block will be used. This function will not wait for events to occur. Each entry of `topics`
+    /// is a topic position and the values within it are alternatives, so `vec![vec![sig1, sig2]]` matches either event.
+    /// The logs are sorted and deduplicated with `normalize_logs`, providers do not all return them in order.
     pub async fn check_for_arbitrary_events(
         &self,
         start_block: Uint256,
@@ -177,31 +887,853 @@ impl Web3 {
         topics: Vec<Vec<[u8; 32]>>,
     ) -> Result<Vec<Log>, Web3Error> {
         // Build a filter with specified topics
-        let from_block = Some(format!("{:#x}", start_block));
+        let from_block = Some(quantity_to_hex(start_block));
         let to_block;
         if let Some(end_block) = end_block {
-            to_block = Some(format!("{:#x}", end_block));
+            to_block = Some(quantity_to_hex(end_block));
         } else {
             let latest_block = self.eth_finalized_block_number().await?;
-            to_block = Some(format!("{:#x}", latest_block));
-        }
-
-        let mut final_topics = Vec::new();
-        for topic in topics {
-            let mut parts = Vec::new();
-            for item in topic {
-                parts.push(Some(bytes_to_data(&item)))
-            }
-            final_topics.push(Some(parts));
+            to_block = Some(quantity_to_hex(latest_block));
         }
 
         let new_filter = NewFilter {
             address: contract_address,
             from_block,
             to_block,
-            topics: Some(final_topics),
+            topics: Some(topics_filter(topics)),
+            block_hash: None,
         };
 
-        self.eth_get_logs(new_filter).await
+        Ok(normalize_logs(self.eth_get_logs(new_filter).await?))
     }
+
+    /// Gets the given events emitted by any of `contract_address` in the block with hash
+    /// `block_hash`. Unlike a block range this always returns the logs of that exact block,
+    /// or an error if the node does not know it, which makes it useful for handling reorgs.
+    /// The logs are in emission order without duplicates, see `normalize_logs`.
+    pub async fn get_logs_in_block(
+        &self,
+        block_hash: impl Into<BlockHash>,
+        contract_address: Vec<Address>,
+        events: Vec<&str>,
+    ) -> Result<Vec<Log>, Web3Error> {
+        let topics = event_topics(&events, [None, None, None])?;
+        let new_filter = NewFilter {
+            address: contract_address,
+            from_block: None,
+            to_block: None,
+            topics: Some(topics_filter(topics)),
+            block_hash: Some(block_hash.into().to_string()),
+        };
+
+        Ok(normalize_logs(self.eth_get_logs(new_filter).await?))
+    }
+}
+
+#[test]
+fn test_topics_filter() {
+    let sig1 = derive_signature("Deposit(address,uint256)").unwrap();
+    let sig2 = derive_signature("Withdrawal(address,uint256)").unwrap();
+    let filter = topics_filter(vec![vec![sig1, sig2]]);
+    assert_eq!(filter.len(), 1);
+    assert_eq!(
+        filter[0],
+        Some(vec![Some(bytes_to_data(&sig1)), Some(bytes_to_data(&sig2))])
+    );
+
+    // empty positions are wildcards
+    let filter = topics_filter(vec![vec![sig1], vec![], vec![sig2]]);
+    assert_eq!(filter[1], None);
+    assert_eq!(filter[2], Some(vec![Some(bytes_to_data(&sig2))]));
+}
+
+#[test]
+fn test_event_topics() {
+    let transfer = derive_signature("Transfer(address,address,uint256)").unwrap();
+    let to = address_to_event(
+        "0x1111111111111111111111111111111111111111"
+            .parse()
+            .unwrap(),
+    );
+
+    let topics = event_topics(&["Transfer(address,address,uint256)"], [None, None, None]).unwrap();
+    assert_eq!(topics, vec![vec![transfer]]);
+
+    // the recipient is topic2, topic1 becomes a wildcard and topic3 is dropped
+    let topics = event_topics(
+        &["Transfer(address,address,uint256)"],
+        [None, Some(vec![to]), None],
+    )
+    .unwrap();
+    assert_eq!(topics, vec![vec![transfer], vec![], vec![to]]);
+    let filter = topics_filter(topics);
+    assert_eq!(filter[1], None);
+    assert_eq!(filter[2], Some(vec![Some(bytes_to_data(&to))]));
+
+    assert!(event_topics(
+        &["Transfer(address,address,uint256)"],
+        [Some(vec![]), None, None]
+    )
+    .is_err());
+}
+
+#[test]
+fn test_decode_events() {
+    use crate::types::Data;
+    use clarity::u256;
+
+    let token: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let other: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let from = address_to_event(other);
+    let to = address_to_event(token);
+    let value = Token::Uint(u256!(100)).serialize();
+    let value = match value {
+        SerializedToken::Static(v) => v.to_vec(),
+        SerializedToken::Dynamic(_) => unreachable!(),
+    };
+    let transfer = Log {
+        address: token,
+        data: Data(value.clone()),
+        topics: vec![
+            Data(
+                derive_signature("Transfer(address,address,uint256)")
+                    .unwrap()
+                    .to_vec(),
+            ),
+            Data(from.to_vec()),
+            Data(to.to_vec()),
+        ],
+        ..Default::default()
+    };
+    let unknown = Log {
+        topics: vec![Data(
+            derive_signature("Approval(address,address,uint256)")
+                .unwrap()
+                .to_vec(),
+        )],
+        ..transfer.clone()
+    };
+    let elsewhere = Log {
+        address: other,
+        ..transfer.clone()
+    };
+    let logs = vec![transfer, unknown, elsewhere];
+
+    let events = decode_events(
+        &logs,
+        Some(token),
+        &["Transfer(address indexed from, address indexed to, uint256 value)"],
+    )
+    .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, "Transfer(address,address,uint256)");
+    assert!(matches!(&events[0].1[0], Token::Address(a) if *a == other));
+    assert!(matches!(&events[0].1[1], Token::Address(a) if *a == token));
+    assert!(matches!(&events[0].1[2], Token::Uint(v) if *v == u256!(100)));
+
+    // without indexed markers the leading arguments are taken from the topics
+    let events = decode_events(&logs, None, &["Transfer(address,address,uint256)"]).unwrap();
+    assert_eq!(events.len(), 2);
+
+    // anonymous events match any log from the contract with the right layout
+    let anonymous = Log {
+        address: token,
+        data: Data(value),
+        topics: vec![Data(from.to_vec())],
+        ..Default::default()
+    };
+    let events = decode_events(
+        &[anonymous],
+        Some(token),
+        &["anonymous Paid(address indexed, uint256)"],
+    )
+    .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, "Paid(address,uint256)");
+    assert!(matches!(&events[0].1[0], Token::Address(a) if *a == other));
+
+    assert!(decode_events(&logs, None, &["Transfer(address,tuple)"]).is_err());
+}
+
+#[test]
+fn test_indexed_topic_for() {
+    use crate::hash::H256;
+    use clarity::u256;
+    use clarity::utils::hex_str_to_bytes;
+
+    let hash = |s: &str| hex_str_to_bytes(s).unwrap();
+    let owner: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+
+    // value types are their abi encoded word
+    assert_eq!(
+        indexed_topic_for(&Token::Address(owner)),
+        address_to_event(owner)
+    );
+    assert_eq!(
+        indexed_topic_for(&Token::Uint(u256!(5))),
+        H256::from(u256!(5)).0
+    );
+    let mut bytes4 = [0u8; 32];
+    bytes4[..4].copy_from_slice(&[1, 2, 3, 4]);
+    assert_eq!(indexed_topic_for(&Token::Bytes(vec![1, 2, 3, 4])), bytes4);
+
+    // strings and bytes are the hash of their contents
+    assert_eq!(
+        indexed_topic_for(&Token::String("alice".into())).to_vec(),
+        hash("0x9c0257114eb9399a2985f8e75dad7600c5d89fe3824ffa99ec1c3eb8bf3b0501")
+    );
+    assert_eq!(
+        indexed_topic_for(&Token::UnboundedBytes(vec![1, 2, 3])).to_vec(),
+        hash("0xf1885eda54b7a053318cd41e2093220dab15d65381b1157a3633a83bfd5c9239")
+    );
+
+    // arrays and structs are the hash of their padded elements without lengths
+    assert_eq!(
+        indexed_topic_for(&Token::Dynamic(vec![
+            Token::Uint(u256!(1)),
+            Token::Uint(u256!(2))
+        ]))
+        .to_vec(),
+        hash("0xe90b7bceb6e7df5418fb78d8ee546e97c83a08bbccc01a0644d599ccd2a7c2e0")
+    );
+    assert_eq!(
+        indexed_topic_for(&Token::Dynamic(vec![
+            Token::String("a".into()),
+            Token::String("bc".into())
+        ]))
+        .to_vec(),
+        hash("0xc67bd33d6cde3ae6fb96523422d6f7251674afefdeec3f634f52284c86af11b8")
+    );
+    assert_eq!(
+        indexed_topic_for(&Token::Struct(vec![
+            Token::Uint(u256!(7)),
+            Token::String("hello".into())
+        ]))
+        .to_vec(),
+        hash("0x2919104b27111a00427dc5719d616be7497d87822aa864c58817a818e17032b4")
+    );
+}
+
+#[tokio::test]
+async fn test_indexed_string_events() {
+    use crate::jsonrpc::mock::MockNode;
+    use crate::types::Data;
+    use clarity::utils::hex_str_to_bytes;
+
+    let registry: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let owner: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let event = "Registered(string indexed name, address owner)";
+    let registered =
+        hex_str_to_bytes("0x50f74ca45caac8020b8d891bd13ea5a2d79564986ee6a839f0d914896388322d")
+            .unwrap();
+    let alice = indexed_topic_for(&Token::String("alice".into()));
+
+    // as emitted by a registry contract for register("alice") from owner
+    let log = serde_json::json!({
+        "address": "0x1111111111111111111111111111111111111111",
+        "blockNumber": "0x10",
+        "logIndex": "0x0",
+        "data": data_to_hex(&address_to_event(owner)),
+        "topics": [data_to_hex(&registered), data_to_hex(&alice)],
+    });
+    let node = MockNode::start(move |method, _| match method {
+        "eth_getLogs" => Ok(serde_json::json!([log.clone()])),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let logs = web3
+        .check_for_events_filtered(
+            Uint256::from_u64(1),
+            Some(Uint256::from_u64(0x20)),
+            vec![registry],
+            vec![event],
+            indexed_topics_for([Some(vec![Token::String("alice".into())]), None, None]),
+        )
+        .await
+        .unwrap();
+    let params = &node.params("eth_getLogs")[0][0];
+    assert_eq!(
+        params["topics"],
+        serde_json::json!([[data_to_hex(&registered)], [data_to_hex(&alice)]])
+    );
+
+    let events = decode_events(&logs, Some(registry), &[event]).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, "Registered(string,address)");
+    // the name can only be matched against its hash
+    assert!(matches!(&events[0].1[0], Token::Bytes(b) if b[..] == alice[..]));
+    assert!(matches!(&events[0].1[1], Token::Address(a) if *a == owner));
+    assert_eq!(logs[0].topics[1], Data(alice.to_vec()));
+
+    // indexed arrays decode to their hash too, but can not be decoded from the data
+    assert!(EventSpec::parse("Batch(uint256[] indexed ids, address owner)").is_ok());
+    assert!(EventSpec::parse("Batch(uint256[] ids, address owner)").is_err());
+    assert!(!is_decodable_event("Batch(uint256[] ids, address owner)"));
+}
+
+#[test]
+fn test_normalize_logs() {
+    use crate::types::LogPosition;
+
+    // logs in two blocks, where block 3 was also seen on a fork with a different hash
+    let mut logs = Vec::new();
+    for (block, hash) in [(2, 20), (3, 30), (3, 31)] {
+        for tx in 0..3u64 {
+            for i in 0..2u64 {
+                logs.push(Log {
+                    block_number: Some(Uint256::from_u64(block)),
+                    block_hash: Some(Uint256::from_u64(hash).into()),
+                    transaction_index: Some(Uint256::from_u64(tx)),
+                    log_index: Some(Uint256::from_u64(tx * 2 + i)),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    let expected = normalize_logs(logs.clone());
+    assert_eq!(expected.len(), logs.len());
+    assert!(expected
+        .windows(2)
+        .all(|w| w[0].position() <= w[1].position()));
+
+    // shuffled inputs with duplicates always normalize to the same logs
+    let mut state = 0x2545f4914f6cdd1du64;
+    let mut random = |n: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as usize % n
+    };
+    for _ in 0..200 {
+        let mut input = logs.clone();
+        for _ in 0..random(10) {
+            let duplicate = input[random(input.len())].clone();
+            input.push(duplicate);
+        }
+        for i in (1..input.len()).rev() {
+            input.swap(i, random(i + 1));
+        }
+        let output = normalize_logs(input);
+        let positions: Vec<LogPosition> = output.iter().map(|log| log.position()).collect();
+        let mut sorted = positions.clone();
+        sorted.sort();
+        assert_eq!(positions, sorted);
+        assert_eq!(output.len(), expected.len());
+        for log in &expected {
+            assert!(output.contains(log));
+        }
+    }
+
+    // logs without a hash are never dropped
+    let pending = Log::default();
+    assert_eq!(normalize_logs(vec![pending.clone(), pending]).len(), 2);
+}
+
+#[cfg(test)]
+fn mock_log() -> serde_json::Value {
+    serde_json::json!({
+        "address": "0x1111111111111111111111111111111111111111",
+        "blockNumber": "0x10",
+        "logIndex": "0x0",
+        "transactionHash": "0x8b9ef028f99016cd3cb8d4168df7491a0bf44f08b678d37f63ab61e782c500ab",
+        "data": "0x",
+        "topics": [bytes_to_data(&derive_signature("Deposit(address,uint256)").unwrap())],
+    })
+}
+
+#[tokio::test]
+async fn test_wait_for_event_returns_promptly() {
+    use crate::jsonrpc::mock::MockNode;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_blockNumber" => Ok(serde_json::json!("0x10")),
+        "eth_newFilter" => Ok(serde_json::json!("0x1")),
+        "eth_getFilterChanges" => Ok(serde_json::json!([mock_log()])),
+        "eth_uninstallFilter" => Ok(serde_json::json!(true)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let start = Instant::now();
+    let log = web3
+        .wait_for_event_with_interval(
+            Duration::from_secs(60),
+            Duration::from_millis(10),
+            vec![],
+            "Deposit(address,uint256)",
+            vec![],
+            |_| true,
+        )
+        .await
+        .unwrap();
+    assert!(Instant::now() - start < Duration::from_secs(5));
+    assert_eq!(log.block_number, Some(Uint256::from_u64(16)));
+    assert_eq!(node.calls("eth_getFilterChanges"), 1);
+    assert_eq!(node.calls("eth_uninstallFilter"), 1);
+}
+
+#[tokio::test]
+async fn test_wait_for_event_removes_filter_on_error() {
+    use crate::jsonrpc::mock::MockNode;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_blockNumber" => Ok(serde_json::json!("0x10")),
+        "eth_newFilter" => Ok(serde_json::json!("0x1")),
+        "eth_getFilterChanges" => Err((-32603, "internal error".to_string())),
+        "eth_uninstallFilter" => Ok(serde_json::json!(true)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let res = web3
+        .wait_for_event_with_interval(
+            Duration::from_secs(60),
+            Duration::from_millis(10),
+            vec![],
+            "Deposit(address,uint256)",
+            vec![],
+            |_| true,
+        )
+        .await;
+    assert!(matches!(
+        res,
+        Err(Web3Error::JsonRpcError { code: -32603, .. })
+    ));
+    assert_eq!(node.calls("eth_uninstallFilter"), 1);
+}
+
+#[tokio::test]
+async fn test_wait_for_event_recreates_missing_filter() {
+    use crate::jsonrpc::mock::MockNode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let changes_calls = AtomicUsize::new(0);
+    let node = MockNode::start(move |method, _| match method {
+        "eth_blockNumber" => Ok(serde_json::json!("0x10")),
+        "eth_newFilter" => Ok(serde_json::json!("0x1")),
+        // the first check finds the filter missing, afterwards there are no changes
+        "eth_getFilterChanges" => match changes_calls.fetch_add(1, Ordering::SeqCst) {
+            0 => Err((-32000, "filter not found".to_string())),
+            _ => Ok(serde_json::json!([])),
+        },
+        "eth_getLogs" => Ok(serde_json::json!([mock_log()])),
+        "eth_uninstallFilter" => Ok(serde_json::json!(true)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let log = web3
+        .wait_for_event_with_interval(
+            Duration::from_secs(60),
+            Duration::from_millis(10),
+            vec![],
+            "Deposit(address,uint256)",
+            vec![],
+            |_| true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(log.block_number, Some(Uint256::from_u64(16)));
+    assert_eq!(node.calls("eth_newFilter"), 2);
+    // the gap is fetched from the height the original filter was installed at
+    assert_eq!(node.params("eth_getLogs")[0][0]["fromBlock"], "0x10");
+    assert_eq!(node.calls("eth_uninstallFilter"), 1);
+}
+
+#[tokio::test]
+async fn test_wait_for_event_cancellation_removes_filter() {
+    use crate::jsonrpc::mock::MockNode;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_blockNumber" => Ok(serde_json::json!("0x10")),
+        "eth_newFilter" => Ok(serde_json::json!("0x1")),
+        "eth_getFilterChanges" => Ok(serde_json::json!([])),
+        "eth_uninstallFilter" => Ok(serde_json::json!(true)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let res = tokio::time::timeout(
+        Duration::from_millis(200),
+        web3.wait_for_event_with_interval(
+            Duration::from_secs(60),
+            Duration::from_millis(10),
+            vec![],
+            "Deposit(address,uint256)",
+            vec![],
+            |_| true,
+        ),
+    )
+    .await;
+    assert!(res.is_err());
+    delay_for(Duration::from_millis(500)).await;
+    assert_eq!(node.calls("eth_uninstallFilter"), 1);
+}
+
+#[tokio::test]
+async fn test_get_logs_in_block() {
+    use crate::jsonrpc::mock::MockNode;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_getLogs" => Ok(serde_json::json!([mock_log()])),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let contract = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+
+    let logs = web3
+        .get_logs_in_block(
+            Uint256::from_u64(0xabcd),
+            vec![contract],
+            vec!["Deposit(address,uint256)"],
+        )
+        .await
+        .unwrap();
+    assert_eq!(logs.len(), 1);
+    let params = &node.params("eth_getLogs")[0][0];
+    assert_eq!(
+        params["blockHash"],
+        "0x000000000000000000000000000000000000000000000000000000000000abcd"
+    );
+    assert!(params.get("fromBlock").is_none());
+    assert!(params.get("toBlock").is_none());
+
+    // a block hash can not be combined with a range
+    let filter = NewFilter {
+        from_block: Some("0x1".to_string()),
+        block_hash: Some(format!("{:#066x}", Uint256::from_u64(0xabcd))),
+        ..Default::default()
+    };
+    assert!(web3.eth_get_logs(filter).await.is_err());
+    assert_eq!(node.calls("eth_getLogs"), 1);
+}
+
+#[tokio::test]
+async fn test_check_for_event() {
+    use crate::hex::word_to_hex;
+    use crate::jsonrpc::mock::MockNode;
+    use clarity::u256;
+    use serde_json::{json, Value};
+
+    let depositor: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let deposit = |block: u64, index: u64| {
+        json!({
+            "address": "0x2222222222222222222222222222222222222222",
+            "blockNumber": quantity_to_hex(Uint256::from_u64(block)),
+            "blockHash": word_to_hex(Uint256::from_u64(block)),
+            "logIndex": quantity_to_hex(Uint256::from_u64(index)),
+            "transactionIndex": "0x0",
+            "data": word_to_hex(u256!(5)),
+            "topics": [
+                data_to_hex(&derive_signature("Deposit(address,uint256)").unwrap()),
+                data_to_hex(&address_to_event(depositor)),
+            ]
+        })
+    };
+    let logs = vec![deposit(40, 1), deposit(120, 7), deposit(120, 3)];
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_blockNumber" => Ok(json!("0x9c4")),
+        "eth_getLogs" => {
+            let block = |key: &str| {
+                let hex = params[0][key].as_str().unwrap().trim_start_matches("0x");
+                u64::from_str_radix(hex, 16).unwrap()
+            };
+            let (from, to) = (block("fromBlock"), block("toBlock"));
+            // like most providers, ranges over 1000 blocks are refused
+            if to - from >= 1000 {
+                return Err((-32005, "block range is too large".to_string()));
+            }
+            if from == 7 {
+                return Err((-32000, "header not found".to_string()));
+            }
+            let found: Vec<Value> = logs
+                .iter()
+                .filter(|log| (from..=to).contains(&block_of(log)))
+                .cloned()
+                .collect();
+            Ok(json!(found))
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    fn block_of(log: &Value) -> u64 {
+        let hex = log["blockNumber"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches("0x");
+        u64::from_str_radix(hex, 16).unwrap()
+    }
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let event = "Deposit(address indexed depositor, uint256 amount)";
+
+    // the whole chain to the latest block 2500 is searched, backwards from the end once the
+    // full range is refused, and the latest log wins whatever the response order
+    let log = web3
+        .check_for_event(None, None, vec![], event, [None, None, None])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(log.block_number, Some(u256!(120)));
+    assert_eq!(log.log_index, Some(u256!(7)));
+    let ranges: Vec<(Value, Value)> = node
+        .params("eth_getLogs")
+        .iter()
+        .map(|p| (p[0]["fromBlock"].clone(), p[0]["toBlock"].clone()))
+        .collect();
+    assert_eq!(
+        ranges,
+        vec![
+            (json!("0x0"), json!("0x9c4")),
+            (json!("0x5dd"), json!("0x9c4")),
+            (json!("0x1f5"), json!("0x5dc")),
+            (json!("0x0"), json!("0x1f4")),
+        ]
+    );
+
+    // all four topics are filtered on
+    let log = web3
+        .check_for_event(
+            Some(u256!(10)),
+            Some(u256!(100)),
+            vec![],
+            event,
+            [Some(vec![address_to_event(depositor)]), None, None],
+        )
+        .await
+        .unwrap();
+    assert_eq!(log.unwrap().block_number, Some(u256!(40)));
+    let topics = &node.params("eth_getLogs")[4][0]["topics"];
+    assert_eq!(
+        topics[1],
+        json!([data_to_hex(&address_to_event(depositor))])
+    );
+
+    assert!(web3
+        .check_for_event(Some(u256!(121)), None, vec![], event, [None, None, None])
+        .await
+        .unwrap()
+        .is_none());
+    assert!(web3
+        .check_for_event(
+            Some(u256!(50)),
+            Some(u256!(40)),
+            vec![],
+            event,
+            [None, None, None]
+        )
+        .await
+        .unwrap()
+        .is_none());
+
+    // errors other than a refused range are returned instead of scanning in ranges
+    let calls = node.calls("eth_getLogs");
+    let err = web3
+        .check_for_event(
+            Some(u256!(7)),
+            Some(u256!(9)),
+            vec![],
+            event,
+            [None, None, None],
+        )
+        .await
+        .unwrap_err();
+    assert!(!err.is_log_limit_exceeded());
+    assert_eq!(node.calls("eth_getLogs"), calls + 1);
+}
+
+#[tokio::test]
+async fn test_check_for_events_grouped() {
+    use crate::hex::word_to_hex;
+    use crate::jsonrpc::mock::MockNode;
+    use clarity::u256;
+    use serde_json::{json, Value};
+
+    let contract = |n: u8| -> Address { data_to_hex(&[n; 20]).parse().unwrap() };
+    let (a, b, c, d, broken) = (
+        contract(1),
+        contract(2),
+        contract(3),
+        contract(4),
+        contract(5),
+    );
+    let deposit = |address: Address, block: u64, index: u64| {
+        json!({
+            "address": address.to_string(),
+            "blockNumber": quantity_to_hex(Uint256::from_u64(block)),
+            "blockHash": word_to_hex(Uint256::from_u64(block)),
+            "logIndex": quantity_to_hex(Uint256::from_u64(index)),
+            "transactionIndex": "0x0",
+            "data": word_to_hex(u256!(5)),
+            "topics": [data_to_hex(&derive_signature("Deposit(address,uint256)").unwrap())]
+        })
+    };
+    // returned newest first, as some providers do
+    let logs = vec![
+        deposit(a, 40, 0),
+        deposit(a, 30, 0),
+        deposit(a, 20, 0),
+        deposit(a, 15, 1),
+        deposit(b, 15, 0),
+        deposit(a, 10, 0),
+        deposit(d, 5, 1),
+        deposit(d, 5, 0),
+    ];
+    fn number(value: &Value) -> u64 {
+        u64::from_str_radix(value.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+    }
+    let node = MockNode::start(move |method, params| match method {
+        "eth_getLogs" => {
+            let filter = &params[0];
+            let addresses: Vec<Address> = filter["address"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|a| a.as_str().unwrap().parse().unwrap())
+                .collect();
+            if addresses.contains(&broken) {
+                return Err((-32603, "internal error".to_string()));
+            }
+            let range = number(&filter["fromBlock"])..=number(&filter["toBlock"]);
+            let found: Vec<Value> = logs
+                .iter()
+                .filter(|log| {
+                    addresses.contains(&log["address"].as_str().unwrap().parse().unwrap())
+                        && range.contains(&number(&log["blockNumber"]))
+                })
+                .cloned()
+                .collect();
+            if found.len() > 3 {
+                return Err((-32005, "query returned more than 3 results".to_string()));
+            }
+            Ok(json!(found))
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let events = vec!["Deposit(address,uint256)"];
+
+    let grouped = web3
+        .check_for_events_grouped(u256!(0), Some(u256!(100)), vec![a, b, c, d], events.clone())
+        .await
+        .unwrap();
+    assert_eq!(grouped.len(), 4);
+    let blocks = |contract: &Address| -> Vec<(u64, u64)> {
+        grouped[contract]
+            .iter()
+            .map(|log| {
+                let position = log.position();
+                (
+                    position.block_number.unwrap().to_string().parse().unwrap(),
+                    position.log_index.unwrap().to_string().parse().unwrap(),
+                )
+            })
+            .collect()
+    };
+    assert_eq!(
+        blocks(&a),
+        vec![(10, 0), (15, 1), (20, 0), (30, 0), (40, 0)]
+    );
+    assert_eq!(blocks(&b), vec![(15, 0)]);
+    assert!(grouped[&c].is_empty());
+    assert_eq!(blocks(&d), vec![(5, 0), (5, 1)]);
+    // the contracts are split until only a, which is over the limit on its own, is scanned
+    // in ranges that are halved until they are accepted and grow again afterwards
+    let queries: Vec<(usize, u64, u64)> = node
+        .params("eth_getLogs")
+        .iter()
+        .map(|p| {
+            let filter = &p[0];
+            (
+                filter["address"].as_array().unwrap().len(),
+                number(&filter["fromBlock"]),
+                number(&filter["toBlock"]),
+            )
+        })
+        .collect();
+    assert_eq!(
+        queries,
+        vec![
+            (4, 0, 100),
+            (2, 0, 100),
+            (2, 0, 100),
+            (1, 0, 100),
+            (1, 0, 49),
+            (1, 0, 24),
+            (1, 25, 74),
+            (1, 75, 100),
+            (1, 0, 100),
+        ]
+    );
+
+    // other errors are not split
+    let res = web3
+        .check_for_events_grouped(u256!(0), Some(u256!(100)), vec![b, broken], events.clone())
+        .await;
+    assert!(matches!(
+        res,
+        Err(Web3Error::JsonRpcError { code: -32603, .. })
+    ));
+    assert_eq!(node.calls("eth_getLogs"), 10);
+
+    // no contracts is no query, rather than one for every contract
+    let grouped = web3
+        .check_for_events_grouped(u256!(0), Some(u256!(100)), vec![], events)
+        .await
+        .unwrap();
+    assert!(grouped.is_empty());
+    assert_eq!(node.calls("eth_getLogs"), 10);
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_check_for_multiple_events() {
+    use crate::amm::WETH_CONTRACT_ADDRESS;
+    use clarity::{u256, PrivateKey};
+
+    // run with a local hardhat or anvil mainnet fork
+    let web3 = Web3::new("http://localhost:8545", Duration::from_secs(30));
+    let secret: PrivateKey = "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
+        .parse()
+        .unwrap();
+    let amount = u256!(1000000000000000000);
+    web3.set_balance(secret.to_address(), u256!(10000000000000000000))
+        .await
+        .unwrap();
+
+    let start = web3.eth_block_number().await.unwrap();
+    let timeout = Some(Duration::from_secs(30));
+    web3.wrap_eth(amount, &secret, None, timeout).await.unwrap();
+    web3.unwrap_eth(amount, &secret, None, timeout)
+        .await
+        .unwrap();
+    let end = web3.eth_block_number().await.unwrap();
+
+    let logs = web3
+        .check_for_events(
+            start,
+            Some(end),
+            vec![*WETH_CONTRACT_ADDRESS],
+            vec!["Deposit(address,uint256)", "Withdrawal(address,uint256)"],
+        )
+        .await
+        .unwrap();
+    let deposit = derive_signature("Deposit(address,uint256)").unwrap();
+    let withdrawal = derive_signature("Withdrawal(address,uint256)").unwrap();
+    assert!(logs.iter().any(|l| l.topics[0].0 == deposit));
+    assert!(logs.iter().any(|l| l.topics[0].0 == withdrawal));
 }
@@ -0,0 +1,169 @@
+//! Server side filters installed with eth_newFilter, wrapped so that they are removed from
+//! the node even if the future using them is cancelled
+use crate::{
+    client::Web3,
+    event_utils::normalize_logs,
+    jsonrpc::error::Web3Error,
+    types::{Log, NewFilter},
+};
+use clarity::Uint256;
+
+/// A log filter installed on the node, created with `Web3::new_log_filter`. Call `uninstall()`
+/// when finished with it, dropping an installed filter spawns a best effort uninstall on the
+/// current runtime so that filters are not leaked by cancelled futures.
+///
+/// Logs that the node reports as removed by a reorg are skipped unless `with_removed_logs`
+/// is used, in which case consumers are responsible for checking `Log::is_removed`.
+pub struct InstalledFilter {
+    web3: Web3,
+    filter: NewFilter,
+    id: Uint256,
+    installed: bool,
+    include_removed: bool,
+}
+
+impl InstalledFilter {
+    /// The id of the filter on the node, this changes if the filter is reinstalled
+    pub fn id(&self) -> Uint256 {
+        self.id
+    }
+
+    /// The filter that was installed
+    pub fn filter(&self) -> &NewFilter {
+        &self.filter
+    }
+
+    /// Sets whether logs removed by a reorg are returned by `poll_changes`
+    pub fn with_removed_logs(mut self, include_removed: bool) -> Self {
+        self.include_removed = include_removed;
+        self
+    }
+
+    /// Returns the logs matching the filter since the last poll. Without removed logs these
+    /// are sorted and deduplicated with `normalize_logs`, with removed logs they are left in
+    /// the order the node returned them since a removal must be seen before its replacement.
+    pub async fn poll_changes(&mut self) -> Result<Vec<Log>, Web3Error> {
+        let mut logs = self.web3.eth_get_filter_changes(self.id).await?;
+        if self.include_removed {
+            return Ok(logs);
+        }
+        logs.retain(|log| !log.is_removed());
+        Ok(normalize_logs(logs))
+    }
+
+    /// Installs the filter again under a new id, for use when the node has dropped it.
+    /// Logs emitted while the filter was missing are not returned by later polls.
+    pub async fn reinstall(&mut self) -> Result<(), Web3Error> {
+        self.id = self.web3.eth_new_filter(self.filter.clone()).await?;
+        Ok(())
+    }
+
+    /// Removes the filter from the node, returning false if the node did not know the filter
+    pub async fn uninstall(mut self) -> Result<bool, Web3Error> {
+        self.installed = false;
+        self.web3.eth_uninstall_filter(self.id).await
+    }
+}
+
+impl Drop for InstalledFilter {
+    fn drop(&mut self) {
+        if !self.installed {
+            return;
+        }
+        // spawning onto a runtime that is shutting down drops the task rather than panicking
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let web3 = self.web3.clone();
+                let id = self.id;
+                handle.spawn(async move {
+                    if let Err(e) = web3.eth_uninstall_filter(id).await {
+                        warn!("Failed to uninstall filter {:#x} on drop {:?}", id, e);
+                    }
+                });
+            }
+            Err(_) => warn!(
+                "InstalledFilter {:#x} dropped outside of a runtime, not uninstalling",
+                self.id
+            ),
+        }
+    }
+}
+
+impl Web3 {
+    /// Installs a log filter on the node, see `InstalledFilter`
+    pub async fn new_log_filter(&self, filter: NewFilter) -> Result<InstalledFilter, Web3Error> {
+        let id = self.eth_new_filter(filter.clone()).await?;
+        Ok(InstalledFilter {
+            web3: self.clone(),
+            filter,
+            id,
+            installed: true,
+            include_removed: false,
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_filter_uninstalled_on_drop() {
+    use crate::jsonrpc::mock::MockNode;
+    use std::time::Duration;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_newFilter" => Ok(serde_json::json!("0x1")),
+        "eth_uninstallFilter" => Ok(serde_json::json!(true)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let filter = web3.new_log_filter(NewFilter::default()).await.unwrap();
+    assert!(filter.uninstall().await.unwrap());
+    assert_eq!(node.calls("eth_uninstallFilter"), 1);
+
+    let filter = web3.new_log_filter(NewFilter::default()).await.unwrap();
+    drop(filter);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(node.calls("eth_uninstallFilter"), 2);
+}
+
+#[tokio::test]
+async fn test_removed_logs_skipped() {
+    use crate::jsonrpc::mock::MockNode;
+    use std::time::Duration;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_newFilter" => Ok(serde_json::json!("0x1")),
+        "eth_uninstallFilter" => Ok(serde_json::json!(true)),
+        "eth_getFilterChanges" => Ok(serde_json::json!([
+            {
+                "address": "0x1111111111111111111111111111111111111111",
+                "data": "0x",
+                "topics": [],
+                "logIndex": "0x0",
+                "removed": true,
+            },
+            {
+                "address": "0x1111111111111111111111111111111111111111",
+                "data": "0x",
+                "topics": [],
+                "logIndex": "0x1",
+                "removed": false,
+            },
+        ])),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let mut filter = web3.new_log_filter(NewFilter::default()).await.unwrap();
+    let logs = filter.poll_changes().await.unwrap();
+    assert_eq!(logs.len(), 1);
+    assert!(!logs[0].is_removed());
+    filter.uninstall().await.unwrap();
+
+    let mut filter = web3
+        .new_log_filter(NewFilter::default())
+        .await
+        .unwrap()
+        .with_removed_logs(true);
+    assert_eq!(filter.poll_changes().await.unwrap().len(), 2);
+    filter.uninstall().await.unwrap();
+}
@@ -0,0 +1,306 @@
+//! Pluggable strategies for choosing the gas price of transactions sent with
+//! `Web3::send_transaction`, see `SendTxOption::GasStrategy`
+use crate::jsonrpc::error::Web3Error;
+use crate::{client::Web3, types::BlockId};
+use clarity::Uint256;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The fees a strategy suggests paying per unit of gas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasFees {
+    /// a single gas price, as used before the london hardfork and on chains without EIP-1559
+    Legacy { price: Uint256 },
+    /// an EIP-1559 fee cap and the priority fee paid to the block producer within it
+    Eip1559 {
+        max_fee: Uint256,
+        priority_fee: Uint256,
+    },
+}
+
+impl GasFees {
+    /// The most that could be paid per unit of gas with these fees
+    pub fn max_fee(&self) -> Uint256 {
+        match self {
+            GasFees::Legacy { price } => *price,
+            GasFees::Eip1559 { max_fee, .. } => *max_fee,
+        }
+    }
+}
+
+/// The future returned by `GasPriceStrategy::fetch`
+pub type GasFeesFuture<'a> = Pin<Box<dyn Future<Output = Result<GasFees, Web3Error>> + Send + 'a>>;
+
+/// A way of choosing gas fees, implement this to use custom pricing with `send_transaction`
+pub trait GasPriceStrategy: Debug + Send + Sync {
+    /// Suggests the fees to use for a transaction sent now
+    fn fetch<'a>(&'a self, web3: &'a Web3) -> GasFeesFuture<'a>;
+}
+
+/// Uses the gas price suggested by the node, the default behavior of `send_transaction`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeSuggested;
+
+impl GasPriceStrategy for NodeSuggested {
+    fn fetch<'a>(&'a self, web3: &'a Web3) -> GasFeesFuture<'a> {
+        Box::pin(async move {
+            Ok(GasFees::Legacy {
+                price: web3.eth_gas_price().await?,
+            })
+        })
+    }
+}
+
+/// Uses the gas price suggested by the node multiplied by a factor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaledNodeSuggested(pub f64);
+
+impl GasPriceStrategy for ScaledNodeSuggested {
+    fn fetch<'a>(&'a self, web3: &'a Web3) -> GasFeesFuture<'a> {
+        Box::pin(async move {
+            let price = web3.eth_gas_price().await?;
+            Ok(GasFees::Legacy {
+                price: scale(price, self.0)?,
+            })
+        })
+    }
+}
+
+/// Uses eth_feeHistory to pay the priority fee that was paid at `percentile` of the gas
+/// used in each of the last `lookback` blocks, averaged over those blocks. The fee cap
+/// is twice the base fee of the next block plus the priority fee, which allows the base
+/// fee to keep rising for several full blocks before the transaction is priced out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeHistoryPercentile {
+    pub percentile: f64,
+    pub lookback: u64,
+}
+
+impl GasPriceStrategy for FeeHistoryPercentile {
+    fn fetch<'a>(&'a self, web3: &'a Web3) -> GasFeesFuture<'a> {
+        Box::pin(async move {
+            let history = web3
+                .eth_fee_history(self.lookback, BlockId::Latest, vec![self.percentile])
+                .await?;
+            // the base fee of the block after the newest one is included at the end
+            let base_fee = match history.base_fee_per_gas.last() {
+                Some(base_fee) => *base_fee,
+                None => {
+                    return Err(Web3Error::BadResponse(
+                        "eth_feeHistory returned no base fees".to_string(),
+                    ))
+                }
+            };
+            let rewards: Vec<Uint256> = history
+                .reward
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|block| block.first().copied())
+                .collect();
+            let mut priority_fee = Uint256::from_u64(0);
+            for reward in rewards.iter() {
+                priority_fee = priority_fee.checked_add(*reward).ok_or_else(overflow)?;
+            }
+            if !rewards.is_empty() {
+                priority_fee = priority_fee
+                    .divide(Uint256::from_u64(rewards.len() as u64))
+                    .ok_or_else(overflow)?
+                    .0;
+            }
+            let max_fee = base_fee
+                .checked_mul(Uint256::from_u64(2))
+                .and_then(|fee| fee.checked_add(priority_fee))
+                .ok_or_else(overflow)?;
+            Ok(GasFees::Eip1559 {
+                max_fee,
+                priority_fee,
+            })
+        })
+    }
+}
+
+/// Always uses the same fees, for chains with a fixed gas price
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixed(pub GasFees);
+
+impl GasPriceStrategy for Fixed {
+    fn fetch<'a>(&'a self, _web3: &'a Web3) -> GasFeesFuture<'a> {
+        Box::pin(async move { Ok(self.0) })
+    }
+}
+
+/// Wraps another strategy and returns `Web3Error::GasPriceTooHigh` instead of fees whose
+/// maximum is above `cap`, so that unattended senders do not pay for fee spikes
+#[derive(Debug, Clone)]
+pub struct Capped {
+    pub strategy: GasStrategy,
+    pub cap: Uint256,
+}
+
+impl GasPriceStrategy for Capped {
+    fn fetch<'a>(&'a self, web3: &'a Web3) -> GasFeesFuture<'a> {
+        Box::pin(async move {
+            let fees = self.strategy.0.fetch(web3).await?;
+            if fees.max_fee() > self.cap {
+                return Err(Web3Error::GasPriceTooHigh {
+                    suggested: fees.max_fee(),
+                    cap: self.cap,
+                });
+            }
+            Ok(fees)
+        })
+    }
+}
+
+/// A shared handle to a strategy, for use in `SendTxOption::GasStrategy`
+#[derive(Clone)]
+pub struct GasStrategy(pub Arc<dyn GasPriceStrategy>);
+
+impl GasStrategy {
+    pub fn new<S: GasPriceStrategy + 'static>(strategy: S) -> Self {
+        GasStrategy(Arc::new(strategy))
+    }
+}
+
+impl Debug for GasStrategy {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.0.fmt(f)
+    }
+}
+
+impl PartialEq for GasStrategy {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// The lowest possible blob base fee, in wei per unit of blob gas
+pub const MIN_BASE_FEE_PER_BLOB_GAS: u64 = 1;
+/// The blob base fee update fraction from the dencun hardfork until prague
+pub const BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN: u64 = 3338477;
+/// The blob base fee update fraction since the prague hardfork raised the blob target
+pub const BLOB_BASE_FEE_UPDATE_FRACTION_PRAGUE: u64 = 5007716;
+
+/// Computes the blob base fee from the `excess_blob_gas` of a block header with the
+/// integer approximation of `MIN_BASE_FEE_PER_BLOB_GAS * e^(excess / update_fraction)`
+/// from EIP-4844. The update fraction is set per hardfork, see the
+/// `BLOB_BASE_FEE_UPDATE_FRACTION_` constants, prefer `Web3::eth_blob_base_fee` where the
+/// node supports it. Returns None if the fee does not fit in a u128.
+pub fn blob_base_fee(excess_blob_gas: Uint256, update_fraction: u64) -> Option<Uint256> {
+    if excess_blob_gas.sig_bits() > 128 || update_fraction == 0 {
+        return None;
+    }
+    let numerator = excess_blob_gas.resize_to_u128();
+    let denominator = update_fraction as u128;
+    let mut output: u128 = 0;
+    let mut accumulator = (MIN_BASE_FEE_PER_BLOB_GAS as u128).checked_mul(denominator)?;
+    let mut i: u128 = 1;
+    while accumulator > 0 {
+        output = output.checked_add(accumulator)?;
+        accumulator = accumulator.checked_mul(numerator)? / denominator.checked_mul(i)?;
+        i += 1;
+    }
+    Some(Uint256::from_u128(output / denominator))
+}
+
+fn overflow() -> Web3Error {
+    Web3Error::BadResponse("Gas fee overflows a Uint256".to_string())
+}
+
+/// Multiplies a price by a factor, this is lossy but any price that fits in a u128 is
+/// far higher than could ever be paid
+fn scale(price: Uint256, factor: f64) -> Result<Uint256, Web3Error> {
+    if price.sig_bits() > 128 {
+        return Err(Web3Error::BadInput(
+            "the gas price is higher than should be possible".to_owned(),
+        ));
+    }
+    Ok(Uint256::from_u128(
+        (price.resize_to_u128() as f64 * factor) as u128,
+    ))
+}
+
+#[tokio::test]
+async fn test_fee_history_percentile() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_feeHistory" => Ok(json!({
+            "oldestBlock": "0x10",
+            "baseFeePerGas": ["0x64", "0x6e", "0x78"],
+            "gasUsedRatio": [0.5, 0.9],
+            "reward": [["0xa"], ["0x14"]],
+        })),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let strategy = FeeHistoryPercentile {
+        percentile: 50.0,
+        lookback: 2,
+    };
+    let fees = strategy.fetch(&web3).await.unwrap();
+    assert_eq!(
+        fees,
+        GasFees::Eip1559 {
+            max_fee: Uint256::from_u64(2 * 120 + 15),
+            priority_fee: Uint256::from_u64(15),
+        }
+    );
+    assert_eq!(
+        node.params("eth_feeHistory")[0],
+        json!(["0x2", "latest", [50.0]])
+    );
+
+    let capped = Capped {
+        strategy: GasStrategy::new(strategy),
+        cap: Uint256::from_u64(200),
+    };
+    match capped.fetch(&web3).await {
+        Err(Web3Error::GasPriceTooHigh { suggested, cap }) => {
+            assert_eq!(suggested, Uint256::from_u64(255));
+            assert_eq!(cap, Uint256::from_u64(200));
+        }
+        res => panic!("Expected GasPriceTooHigh, got {:?}", res),
+    }
+
+    let fixed = Fixed(GasFees::Legacy {
+        price: Uint256::from_u64(7),
+    });
+    assert_eq!(
+        fixed.fetch(&web3).await.unwrap().max_fee(),
+        Uint256::from_u64(7)
+    );
+}
+
+#[test]
+fn test_blob_base_fee() {
+    let fee = |excess: u64, fraction: u64| {
+        blob_base_fee(Uint256::from_u64(excess), fraction).map(|fee| fee.resize_to_u128())
+    };
+    assert_eq!(fee(0, BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN), Some(1));
+    assert_eq!(fee(3338477, BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN), Some(2));
+    assert_eq!(
+        fee(10_000_000, BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN),
+        Some(19)
+    );
+    assert_eq!(
+        fee(10_000_000, BLOB_BASE_FEE_UPDATE_FRACTION_PRAGUE),
+        Some(7)
+    );
+    assert_eq!(
+        fee(100_000_000, BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN),
+        Some(10203769476395)
+    );
+    assert_eq!(
+        fee(100_000_000, BLOB_BASE_FEE_UPDATE_FRACTION_PRAGUE),
+        Some(470442149)
+    );
+    // e^(u64::MAX / 3338477) is far beyond a u128
+    assert_eq!(fee(u64::MAX, BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN), None);
+    assert_eq!(fee(1, 0), None);
+}
@@ -0,0 +1,152 @@
+//! A fixed 32 byte hash, so that transaction and block hashes keep their leading zero bytes
+//! however they were obtained
+//!
+//! Migrating from 0.18, where hashes were `Uint256`: every method that sends a transaction
+//! (`send_transaction`, `eth_send_transaction`, `eth_send_raw_transaction`, `broadcast` and
+//! the helpers built on them) returns a `TxHash`, and the hash fields of `Block`,
+//! `ConciseBlock`, `TransactionResponse`, `TransactionReceipt`, `Log` and the trace types are
+//! `TxHash` or `BlockHash`. Methods taking a hash accept anything that converts into one, so
+//! stored `Uint256` hashes can still be passed in. Where a `Uint256` is still needed
+//! `Uint256::from(hash)` converts it losslessly, and `H256::from(uint)` converts back. The
+//! serialized form is the same 0x prefixed hex, but always 64 digits.
+use crate::hex::{hex_to_fixed_bytes, HexMode};
+use crate::types::Data;
+use clarity::abi::{SerializedToken, Token};
+use clarity::utils::bytes_to_hex_str;
+use clarity::Uint256;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+/// A 32 byte hash, serialized as a 0x prefixed string of exactly 64 hex digits
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct H256(pub [u8; 32]);
+
+/// The hash of a transaction
+pub type TxHash = H256;
+/// The hash of a block header
+pub type BlockHash = H256;
+
+impl H256 {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Returns None unless `bytes` is exactly 32 bytes long
+    pub fn from_slice(bytes: &[u8]) -> Option<H256> {
+        Some(H256(bytes.try_into().ok()?))
+    }
+}
+
+impl Display for H256 {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "0x{}", bytes_to_hex_str(&self.0))
+    }
+}
+
+impl Debug for H256 {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        Display::fmt(self, f)
+    }
+}
+
+impl FromStr for H256 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        hex_to_fixed_bytes::<32>(s, HexMode::Strict).map(H256)
+    }
+}
+
+impl Serialize for H256 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for H256 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl From<[u8; 32]> for H256 {
+    fn from(bytes: [u8; 32]) -> Self {
+        H256(bytes)
+    }
+}
+
+impl From<H256> for [u8; 32] {
+    fn from(hash: H256) -> Self {
+        hash.0
+    }
+}
+
+impl From<Uint256> for H256 {
+    fn from(n: Uint256) -> Self {
+        match Token::Uint(n).serialize() {
+            SerializedToken::Static(word) => H256(word),
+            SerializedToken::Dynamic(_) => unreachable!(),
+        }
+    }
+}
+
+impl From<H256> for Uint256 {
+    fn from(hash: H256) -> Self {
+        Uint256::from_bytes_be(&hash.0).expect("32 bytes fit in a Uint256")
+    }
+}
+
+impl TryFrom<&Data> for H256 {
+    type Error = ();
+
+    fn try_from(data: &Data) -> Result<Self, Self::Error> {
+        H256::from_slice(data).ok_or(())
+    }
+}
+
+impl From<H256> for Data {
+    fn from(hash: H256) -> Self {
+        Data(hash.0.to_vec())
+    }
+}
+
+#[test]
+fn test_h256_leading_zeros() {
+    // a transaction hash whose high byte is zero
+    let hex = "0x00a4f7e6c0d2b3c1e5f60718293a4b5c6d7e8f9011223344556677889900aabb";
+    let hash: H256 = hex.parse().unwrap();
+    assert_eq!(hash.0[0], 0);
+    assert_eq!(hash.to_string(), hex);
+    assert_eq!(format!("{:?}", hash), hex);
+    assert_eq!(serde_json::to_value(hash).unwrap(), serde_json::json!(hex));
+    assert_eq!(
+        serde_json::from_value::<H256>(serde_json::json!(hex)).unwrap(),
+        hash
+    );
+
+    // the Uint256 representation drops the leading zeros, converting back restores them
+    let n: Uint256 = hash.into();
+    assert_eq!(format!("{:#x}", n).len(), 64);
+    assert_eq!(H256::from(n), hash);
+    assert_eq!(
+        H256::from(Uint256::from_u64(1)).to_string(),
+        format!("0x{:064x}", 1)
+    );
+    let bytes: [u8; 32] = hash.into();
+    assert_eq!(H256::from(bytes), hash);
+    assert_eq!(H256::try_from(&Data::from(hash)), Ok(hash));
+
+    // truncated or overlong hashes are rejected instead of padded
+    assert!(hex[..64].parse::<H256>().is_err());
+    assert!(format!("{}00", hex).parse::<H256>().is_err());
+    assert!(hex.trim_start_matches("0x").parse::<H256>().is_err());
+    assert!(H256::try_from(&Data(vec![0; 31])).is_err());
+}
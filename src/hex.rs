@@ -0,0 +1,176 @@
+//! Hex encoding of JSON-RPC values following the rules of EIP-1474. Quantities are minimal
+//! 0x prefixed hex without leading zeros ("0x0", "0x5208"), while unformatted data such as
+//! calldata, hashes and addresses is 0x prefixed with two digits for every byte, keeping
+//! leading zeros. Encode request params with these so that every method follows the same
+//! rules.
+//!
+//! See more: https://eips.ethereum.org/EIPS/eip-1474
+use crate::hash::H256;
+use crate::quantity::parse_quantity;
+use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
+use clarity::Uint256;
+
+/// How strictly the parsers here follow EIP-1474
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HexMode {
+    /// only the exact encodings of the spec are accepted
+    Strict,
+    /// also accepts what providers are known to send, leading zeros and decimal strings
+    /// for quantities, a missing prefix and odd or short lengths for data
+    Lossy,
+}
+
+/// Encodes a quantity as minimal hex, zero is "0x0"
+pub fn quantity_to_hex(n: Uint256) -> String {
+    n.to_hex_string()
+}
+
+/// Encodes unformatted data with two digits per byte, empty data is "0x"
+pub fn data_to_hex(data: &[u8]) -> String {
+    format!("0x{}", bytes_to_hex_str(data))
+}
+
+/// Encodes fixed size data such as an address or a hash, always `2 + 2 * N` characters long
+pub fn fixed_bytes_to_hex<const N: usize>(bytes: &[u8; N]) -> String {
+    data_to_hex(bytes)
+}
+
+/// Encodes a `Uint256` as 32 bytes of unformatted data, as for hashes and storage values
+pub fn word_to_hex(n: Uint256) -> String {
+    fixed_bytes_to_hex(&H256::from(n).0)
+}
+
+/// Parses a quantity, in strict mode the value must be minimal hex with the 0x prefix
+pub fn hex_to_quantity(s: &str, mode: HexMode) -> Result<Uint256, String> {
+    if mode == HexMode::Lossy {
+        return parse_quantity(s);
+    }
+    let digits = match s.strip_prefix("0x") {
+        Some(digits) => digits,
+        None => return Err(format!("{:?} is missing the 0x prefix", s)),
+    };
+    if digits.is_empty() {
+        return Err(format!("{:?} is not a quantity", s));
+    }
+    if digits.len() > 1 && digits.starts_with('0') {
+        return Err(format!("{:?} has leading zeros", s));
+    }
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("{:?} contains the non hex character {:?}", s, c));
+    }
+    parse_quantity(s)
+}
+
+/// Parses unformatted data, in strict mode it must have the 0x prefix and an even number of
+/// digits
+pub fn hex_to_data(s: &str, mode: HexMode) -> Result<Vec<u8>, String> {
+    let digits = match (s.strip_prefix("0x"), mode) {
+        (Some(digits), _) => digits,
+        (None, HexMode::Lossy) => s,
+        (None, HexMode::Strict) => return Err(format!("{:?} is missing the 0x prefix", s)),
+    };
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("{:?} contains the non hex character {:?}", s, c));
+    }
+    let padded;
+    let digits = match (digits.len() % 2, mode) {
+        (0, _) => digits,
+        (_, HexMode::Lossy) => {
+            padded = format!("0{}", digits);
+            &padded
+        }
+        (_, HexMode::Strict) => return Err(format!("{:?} has an odd number of hex digits", s)),
+    };
+    if digits.is_empty() {
+        return Ok(Vec::new());
+    }
+    hex_str_to_bytes(digits).map_err(|_| format!("{:?} is not hex", s))
+}
+
+/// Parses exactly `N` bytes of unformatted data, in lossy mode shorter values are left
+/// padded with zeros
+pub fn hex_to_fixed_bytes<const N: usize>(s: &str, mode: HexMode) -> Result<[u8; N], String> {
+    let data = hex_to_data(s, mode)?;
+    if data.len() > N || (mode == HexMode::Strict && data.len() != N) {
+        return Err(format!("{:?} is {} bytes, expected {}", s, data.len(), N));
+    }
+    let mut bytes = [0u8; N];
+    bytes[N - data.len()..].copy_from_slice(&data);
+    Ok(bytes)
+}
+
+#[test]
+fn test_spec_encodings() {
+    // the examples of EIP-1474
+    assert_eq!(quantity_to_hex(Uint256::from_u64(0)), "0x0");
+    assert_eq!(quantity_to_hex(Uint256::from_u64(1)), "0x1");
+    assert_eq!(quantity_to_hex(Uint256::from_u64(65)), "0x41");
+    assert_eq!(quantity_to_hex(Uint256::from_u64(1024)), "0x400");
+    assert_eq!(
+        quantity_to_hex(Uint256::max_value()),
+        format!("0x{}", "f".repeat(64))
+    );
+    assert_eq!(data_to_hex(&[]), "0x");
+    assert_eq!(data_to_hex(&[0x41]), "0x41");
+    assert_eq!(data_to_hex(&[0x00, 0x41]), "0x0041");
+    assert_eq!(data_to_hex(&[0x00, 0x00, 0x0a]), "0x00000a");
+    assert_eq!(fixed_bytes_to_hex(&[0u8; 4]), "0x00000000");
+    assert_eq!(
+        word_to_hex(Uint256::from_u64(1)),
+        format!("0x{}1", "0".repeat(63))
+    );
+    assert_eq!(word_to_hex(Uint256::from_u64(1)).len(), 66);
+}
+
+#[test]
+fn test_strict_parsing() {
+    use HexMode::Strict;
+
+    assert_eq!(hex_to_quantity("0x0", Strict), Ok(Uint256::from_u64(0)));
+    assert_eq!(
+        hex_to_quantity("0x400", Strict),
+        Ok(Uint256::from_u64(1024))
+    );
+    assert_eq!(hex_to_quantity("0xAb", Strict), Ok(Uint256::from_u64(0xab)));
+    for bad in ["0x", "0x0400", "0x00", "ff", "400", "0xg", "0X1", ""] {
+        assert!(hex_to_quantity(bad, Strict).is_err(), "{}", bad);
+    }
+
+    assert_eq!(hex_to_data("0x", Strict), Ok(Vec::new()));
+    assert_eq!(hex_to_data("0x0041", Strict), Ok(vec![0x00, 0x41]));
+    for bad in ["0xf0f0f", "004200", "0xzz", ""] {
+        assert!(hex_to_data(bad, Strict).is_err(), "{}", bad);
+    }
+
+    assert_eq!(hex_to_fixed_bytes::<2>("0x0041", Strict), Ok([0x00, 0x41]));
+    assert!(hex_to_fixed_bytes::<2>("0x41", Strict).is_err());
+    assert!(hex_to_fixed_bytes::<2>("0x000041", Strict).is_err());
+
+    // whatever is encoded parses back
+    let n = Uint256::from_u128(0x0102_0304_0506_0708_090a);
+    assert_eq!(hex_to_quantity(&quantity_to_hex(n), Strict), Ok(n));
+    assert_eq!(
+        hex_to_fixed_bytes::<32>(&word_to_hex(n), Strict),
+        Ok(H256::from(n).0)
+    );
+}
+
+#[test]
+fn test_lossy_parsing() {
+    use HexMode::Lossy;
+
+    assert_eq!(
+        hex_to_quantity("0x0400", Lossy),
+        Ok(Uint256::from_u64(1024))
+    );
+    assert_eq!(hex_to_quantity("1024", Lossy), Ok(Uint256::from_u64(1024)));
+    assert!(hex_to_quantity("0x", Lossy).is_err());
+
+    assert_eq!(hex_to_data("", Lossy), Ok(Vec::new()));
+    assert_eq!(hex_to_data("0041", Lossy), Ok(vec![0x00, 0x41]));
+    assert_eq!(hex_to_data("0xf0f0f", Lossy), Ok(vec![0x0f, 0x0f, 0x0f]));
+    assert!(hex_to_data("0xzz", Lossy).is_err());
+
+    assert_eq!(hex_to_fixed_bytes::<2>("0x41", Lossy), Ok([0x00, 0x41]));
+    assert!(hex_to_fixed_bytes::<2>("0x000041", Lossy).is_err());
+}
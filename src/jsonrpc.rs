@@ -1,4 +1,10 @@
 pub mod client;
 pub mod error;
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod rate_limit;
 pub mod request;
 pub mod response;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tls;
+pub(crate) mod transport;
@@ -1,75 +1,308 @@
+use crate::balanced::Balancer;
 use crate::jsonrpc::error::Web3Error;
+use crate::jsonrpc::rate_limit::{
+    detect_rate_limit, RateLimitMatcher, RateLimiter, MAX_RATE_LIMIT_RETRY_WAIT,
+    RATE_LIMIT_MATCHERS,
+};
 use crate::jsonrpc::request::Request as JsonRpcRequest;
-use crate::jsonrpc::response::Response as JsonResponse;
-use crate::mem::get_buffer_size;
-use hyper::body::{Bytes, HttpBody};
-use hyper::client::HttpConnector;
-use hyper::{header, Body, Client, Method, Request};
-use hyper_tls::HttpsConnector;
+use crate::jsonrpc::response::{Response as JsonResponse, ResponseData};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::jsonrpc::tls::TlsConfig;
+use crate::jsonrpc::transport::{default_transport, HttpResponse, Transport};
+use crate::metrics::{MetricsSink, Outcome};
+use crate::time::{sleep, Instant};
+use hyper::body::Bytes;
+use hyper::header::{self, HeaderValue};
+use hyper::HeaderMap;
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use serde_json::Value;
+use std::collections::HashMap;
 use std::str;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
-use tokio::time;
+use tokio::sync::{OnceCell, Semaphore};
+
+/// The maximum length of the response body included in deserialization errors
+const BODY_SNIPPET_LEN: usize = 1024;
+
+/// The maximum length of the serialized params included in deserialization errors
+const PARAMS_SUMMARY_LEN: usize = 256;
+
+/// Returns the first `len` bytes of `bytes` as a string for use in error messages
+fn snippet(bytes: &[u8], len: usize) -> String {
+    let snippet = String::from_utf8_lossy(&bytes[..bytes.len().min(len)]);
+    if bytes.len() > len {
+        format!("{}...", snippet)
+    } else {
+        snippet.to_string()
+    }
+}
+
+/// Returns the start of a response body as a string for use in error messages
+fn body_snippet(body: &[u8]) -> String {
+    snippet(body, BODY_SNIPPET_LEN)
+}
+
+/// Builds the error for a response body to `method` request `id` that could not be parsed
+fn deserialization_error(
+    method: &str,
+    id: u64,
+    params: &Value,
+    body: &[u8],
+    error: serde_json::Error,
+) -> Web3Error {
+    Web3Error::DeserializationError {
+        method: method.to_string(),
+        params_summary: snippet(params.to_string().as_bytes(), PARAMS_SUMMARY_LEN),
+        body_snippet: body_snippet(body),
+        serde_error: error.to_string(),
+        request_id: id,
+    }
+}
+
+/// Read only methods for which concurrent identical requests are coalesced into a single
+/// HTTP request, and which are retried after timeouts and network failures. Methods with
+/// side effects such as eth_sendRawTransaction or the filter methods must never be added here.
+const COALESCED_METHODS: &[&str] = &[
+    "eth_blockNumber",
+    "eth_call",
+    "eth_chainId",
+    "eth_estimateGas",
+    "eth_feeHistory",
+    "eth_gasPrice",
+    "eth_getBalance",
+    "eth_getBlockByHash",
+    "eth_getBlockByNumber",
+    "eth_getCode",
+    "eth_getLogs",
+    "eth_getStorageAt",
+    "eth_getTransactionByHash",
+    "eth_getTransactionCount",
+    "eth_getTransactionReceipt",
+    "eth_maxPriorityFeePerGas",
+    "eth_syncing",
+    "net_version",
+    "web3_clientVersion",
+];
+
+/// Computes headers from the serialized body of each request, for endpoints that
+/// authenticate requests by a signature over the body
+pub type BodyHook = Arc<dyn Fn(&[u8]) -> HeaderMap + Send + Sync>;
+
+/// The response to a coalesced request, shared by every caller that was waiting on it
+type SharedResponse = Result<(u64, Bytes), Arc<Web3Error>>;
+
+/// Copies the error of a coalesced request for each caller. `Web3Error` is not `Clone`, but
+/// sending a request only fails with the variants copied here, so that `is_retriable` and
+/// metrics see the same error for every caller.
+fn copy_shared_error(error: &Web3Error) -> Web3Error {
+    match error {
+        Web3Error::TransportError(e) => Web3Error::TransportError(e.clone()),
+        Web3Error::Timeout => Web3Error::Timeout,
+        Web3Error::RateLimited { retry_after } => Web3Error::RateLimited {
+            retry_after: *retry_after,
+        },
+        Web3Error::BadResponse(message) => Web3Error::BadResponse(message.clone()),
+        e => Web3Error::BadResponse(e.to_string()),
+    }
+}
+
+/// The User-Agent sent with every request unless it is replaced with
+/// `HttpClient::with_user_agent`, so that node operators can tell what is calling them
+pub const DEFAULT_USER_AGENT: &str = concat!("web30/", env!("CARGO_PKG_VERSION"));
+
+/// The header carrying the JSON-RPC id of a request when `HttpClient::with_request_id_header`
+/// is enabled
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
 
 pub struct HttpClient {
-    id_counter: Arc<Mutex<RefCell<u64>>>,
+    /// shared by every clone of a `Web3` so that ids are unique per connection
+    id_counter: AtomicU64,
     url: String,
-    client: Client<HttpsConnector<HttpConnector>>,
+    transport: Arc<dyn Transport>,
+    /// coalesced requests currently awaiting a response, keyed by method and params
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<SharedResponse>>>>,
+    metrics: RwLock<Option<Arc<dyn MetricsSink>>>,
+    /// extra headers sent with every request, such as API keys
+    headers: HeaderMap,
+    user_agent: String,
+    /// whether the JSON-RPC id of each request is also sent as `REQUEST_ID_HEADER`
+    request_id_header: bool,
+    /// adds headers computed from the body of each request
+    body_hook: Option<BodyHook>,
+    /// how many times requests that failed at the network level are retried
+    retries: u32,
+    /// limits the number of requests in flight at once if set
+    concurrency_limit: Option<Semaphore>,
+    /// limits the rate of requests if set, possibly shared with other clients
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// recognize responses to rate limited requests
+    rate_limit_matchers: Vec<RateLimitMatcher>,
+    /// spreads requests over several urls instead of always using `url`
+    balancer: Option<Arc<Balancer>>,
+    /// the host part of `url`, recorded on request spans
+    #[cfg(feature = "tracing")]
+    host: String,
 }
 
 impl HttpClient {
     pub fn new(url: &str) -> Self {
-        let https = HttpsConnector::new();
-
         Self {
-            id_counter: Arc::new(Mutex::new(RefCell::new(0u64))),
+            id_counter: AtomicU64::new(0),
             url: url.into(),
-            client: Client::builder().build(https),
+            transport: default_transport(),
+            in_flight: Mutex::new(HashMap::new()),
+            metrics: RwLock::new(None),
+            headers: HeaderMap::new(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            request_id_header: false,
+            body_hook: None,
+            retries: 0,
+            concurrency_limit: None,
+            rate_limiter: None,
+            rate_limit_matchers: RATE_LIMIT_MATCHERS.to_vec(),
+            balancer: None,
+            #[cfg(feature = "tracing")]
+            host: url
+                .parse::<hyper::Uri>()
+                .ok()
+                .and_then(|uri| uri.host().map(str::to_string))
+                .unwrap_or_default(),
         }
     }
 
-    fn next_id(&self) -> u64 {
-        let counter = self.id_counter.clone();
-        let counter = counter.lock().expect("id error");
-        let mut value = counter.borrow_mut();
-        *value += 1;
-        *value
+    /// Authenticates servers according to `tls` instead of only trusting the public
+    /// certificate authorities
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_tls(mut self, tls: &TlsConfig) -> Self {
+        self.transport = Arc::new(crate::jsonrpc::transport::HyperTransport::new(tls));
+        self
     }
 
-    async fn aggregate_bytes(&self, request: Request<Body>) -> Result<Bytes, Web3Error> {
-        let request_size_limit = get_buffer_size();
-        let res = self.client.request(request).await?;
+    /// Sends requests through `transport`, for testing without a network
+    #[cfg(test)]
+    pub(crate) fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
 
-        trace!("response headers {:?}", res.headers());
-        trace!("using buffer size of {}", request_size_limit);
+    /// Sends `headers` with every request
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
 
-        let response_size = res.size_hint().lower() as usize;
+    /// Replaces the `DEFAULT_USER_AGENT`, a User-Agent set with `with_headers` takes precedence
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
 
-        if response_size > request_size_limit {
-            return Err(Web3Error::BadResponse(format!(
-                "Size Limit {} and Response size {} Web3 Error",
-                request_size_limit, response_size
-            )));
+    /// Appends an identifier of the application, such as "my-indexer/1.2", to the User-Agent
+    /// so that node operators can tell apart the applications built on web30
+    pub fn with_app_identifier(mut self, app: &str) -> Self {
+        self.user_agent = format!("{} {}", self.user_agent, app);
+        self
+    }
+
+    /// Sends the JSON-RPC id of each request as the `REQUEST_ID_HEADER` header, so that
+    /// requests can be correlated between the logs of the client and the node
+    pub fn with_request_id_header(mut self, enabled: bool) -> Self {
+        self.request_id_header = enabled;
+        self
+    }
+
+    /// The headers identifying this client that are sent with the request with JSON-RPC id
+    /// `id`, followed by those set with `with_headers`. Every transport must send these.
+    fn request_headers(&self, id: u64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if !self.headers.contains_key(header::USER_AGENT) {
+            match HeaderValue::from_str(&self.user_agent) {
+                Ok(user_agent) => {
+                    headers.insert(header::USER_AGENT, user_agent);
+                }
+                Err(_) => warn!("Invalid User-Agent {:?} not sent", self.user_agent),
+            }
         }
+        if self.request_id_header {
+            headers.insert(REQUEST_ID_HEADER, HeaderValue::from(id));
+        }
+        headers.extend(self.headers.iter().map(|(k, v)| (k.clone(), v.clone())));
+        headers
+    }
 
-        hyper::body::to_bytes(res.into_body())
-            .await
-            .map_err(Into::into)
+    /// Adds the headers returned by `hook` for the serialized body to every request, after the
+    /// headers set with `with_headers`
+    pub fn with_body_hook(mut self, hook: BodyHook) -> Self {
+        self.body_hook = Some(hook);
+        self
     }
 
-    pub async fn request_method<T: Serialize, R: 'static>(
+    /// Retries requests that time out or fail at the network level up to `retries` times.
+    /// Only read only methods are retried after such failures, since the node may already
+    /// have acted on a transaction or filter request whose response was lost, while rate
+    /// limited requests of any method are retried.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Limits the number of requests in flight at once, further requests wait their turn,
+    /// zero leaves the number unlimited. The wait is not part of the request timeout, which
+    /// only covers the HTTP exchange.
+    pub fn with_max_concurrent_requests(mut self, max: usize) -> Self {
+        self.concurrency_limit = match max {
+            0 => None,
+            max => Some(Semaphore::new(max)),
+        };
+        self
+    }
+
+    /// Waits for `limiter` before sending each request, including retries. The wait is not
+    /// part of the request timeout, which only covers the HTTP exchange.
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// The rate limiter set with `with_rate_limiter`, for logging its utilization
+    pub fn rate_limiter(&self) -> Option<&Arc<RateLimiter>> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Recognizes rate limited responses with `matchers` instead of `RATE_LIMIT_MATCHERS`,
+    /// an empty list leaves them to be parsed as ordinary responses
+    pub fn with_rate_limit_matchers(mut self, matchers: Vec<RateLimitMatcher>) -> Self {
+        self.rate_limit_matchers = matchers;
+        self
+    }
+
+    /// Sends requests to the backends of `balancer` instead of the url of this client
+    pub(crate) fn with_balancer(mut self, balancer: Arc<Balancer>) -> Self {
+        self.balancer = Some(balancer);
+        self
+    }
+
+    /// Sets the sink that observes every request made through this client
+    pub fn set_metrics_sink(&self, sink: Option<Arc<dyn MetricsSink>>) {
+        *self.metrics.write().expect("metrics sink poisoned") = sink;
+    }
+
+    fn next_id(&self) -> u64 {
+        self.id_counter.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Sends the request and returns its id along with the raw response body
+    async fn send_request(
         &self,
         method: &str,
-        params: T,
+        params: &Value,
         timeout: Duration,
-    ) -> Result<R, Web3Error>
-    where
-        for<'de> R: Deserialize<'de>,
-        R: std::fmt::Debug,
-    {
-        let json_payload = JsonRpcRequest::new(self.next_id(), method, params);
+    ) -> Result<(u64, Bytes), Web3Error> {
+        let id = self.next_id();
+        trace!("sending request {} id {}", method, id);
+        let json_payload = JsonRpcRequest::new(id, method, params);
         let payload = serde_json::to_vec(&json_payload)?;
 
         #[cfg(feature = "debug_requests")]
@@ -77,49 +310,615 @@ impl HttpClient {
             println!("{}", String::from_utf8(payload.clone()).unwrap());
         }
 
-        let req = Request::builder()
-            .method(Method::POST)
-            .header(header::CONTENT_TYPE, "application/json")
-            .uri(&self.url)
-            .body(payload.into())
-            .expect("Expected json body");
+        let backend = self
+            .balancer
+            .as_ref()
+            .map(|balancer| (balancer, balancer.select(method, params)));
+        let url = match backend {
+            Some((balancer, backend)) => balancer.url(backend),
+            None => &self.url,
+        };
+        let hook_headers = self.body_hook.as_ref().map(|hook| hook(&payload));
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        headers.extend(self.request_headers(id));
+        if let Some(hook_headers) = hook_headers {
+            headers.extend(hook_headers);
+        }
+
+        // waiting for the limits is left out of the timeout, the backend is not at fault for it
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire(method).await;
+        }
+        let _permit = match &self.concurrency_limit {
+            Some(limit) => Some(limit.acquire().await.expect("semaphore closed")),
+            None => None,
+        };
 
         // race between the Timeout and the Request - with slight bias towards the request itself
-        let result: Result<Bytes, Web3Error> = tokio::select! {
+        let result: Result<HttpResponse, Web3Error> = tokio::select! {
             biased;
 
-            bytes = self.aggregate_bytes(req) => Ok(bytes?),
-            _ = time::sleep(timeout) => Err(Web3Error::BadResponse("Request Timed Out".into()))
+            response = self.transport.post(url, headers, payload) => Ok(response?),
+            _ = sleep(timeout) => Err(Web3Error::Timeout)
         };
-
-        let response: JsonResponse<R> = serde_json::from_slice(&result?)?;
-        #[cfg(feature = "debug_responses")]
-        {
-            println!("{:?}", response);
-        }
-        #[cfg(not(feature = "debug_responses"))]
-        {
-            // we have this separate in case we only want responses and not all the other traces
-            trace!("got web3 response {:?}", response);
+        let result = result.and_then(|(status, retry_after, bytes)| {
+            match detect_rate_limit(&self.rate_limit_matchers, status, retry_after, &bytes) {
+                Some(retry_after) => Err(Web3Error::RateLimited { retry_after }),
+                None => Ok(bytes),
+            }
+        });
+        if let Some((balancer, backend)) = backend {
+            balancer.record(backend, method, params, &result);
         }
+        Ok((id, result?))
+    }
 
-        match response.data.into_result() {
-            Ok(result) => Ok(result),
-            Err(error) => {
-                #[cfg(feature = "debug_errors")]
+    /// Sends the request, retrying it with a backoff if it times out or fails at the network
+    /// level and is read only, or is rate limited, and retries are configured. Rate limited
+    /// requests wait for the backoff the provider suggested, unless it is longer than
+    /// `MAX_RATE_LIMIT_RETRY_WAIT`.
+    async fn send_with_retries(
+        &self,
+        method: &str,
+        params: &Value,
+        timeout: Duration,
+    ) -> Result<(u64, Bytes), Web3Error> {
+        let mut attempt = 0;
+        loop {
+            match self.send_request(method, params, timeout).await {
+                Err(Web3Error::TransportError(_)) | Err(Web3Error::Timeout)
+                    if attempt < self.retries && COALESCED_METHODS.contains(&method) =>
+                {
+                    attempt += 1;
+                    warn!(
+                        "Request {} failed, retry {} of {}",
+                        method, attempt, self.retries
+                    );
+                    sleep(Duration::from_millis(100 << attempt.min(6))).await;
+                }
+                Err(Web3Error::RateLimited { retry_after })
+                    if attempt < self.retries
+                        && retry_after.map_or(true, |d| d <= MAX_RATE_LIMIT_RETRY_WAIT) =>
                 {
-                    error!(
-                        "when using request payload:\n{}\n, got web3 error response {:?}",
-                        String::from_utf8(serde_json::to_vec(&json_payload)?).unwrap(),
-                        error
+                    attempt += 1;
+                    let wait =
+                        retry_after.unwrap_or_else(|| Duration::from_millis(100 << attempt.min(6)));
+                    warn!(
+                        "Request {} rate limited, retry {} of {} in {:?}",
+                        method, attempt, self.retries, wait
                     );
+                    sleep(wait).await;
                 }
-                Err(Web3Error::JsonRpcError {
-                    code: error.code,
-                    message: error.message,
-                    data: format!("{:?}", error.data),
-                })
+                res => return res,
             }
         }
     }
+
+    /// Sends the request unless an identical one is already in flight, in which case its
+    /// response is shared instead. Responses are only shared between overlapping requests,
+    /// once a response arrives the next identical request goes to the node again. Also
+    /// returns true if this call sent the request rather than sharing the response.
+    async fn coalesced_request(
+        &self,
+        method: &str,
+        params: &Value,
+        timeout: Duration,
+    ) -> (Result<(u64, Bytes), Web3Error>, bool) {
+        let key = format!("{}{}", method, params);
+        let cell = self
+            .in_flight
+            .lock()
+            .expect("in flight requests poisoned")
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let mut sent = false;
+        let response = cell
+            .get_or_init(|| async {
+                sent = true;
+                let response = self
+                    .send_with_retries(method, params, timeout)
+                    .await
+                    .map_err(Arc::new);
+                let mut in_flight = self.in_flight.lock().expect("in flight requests poisoned");
+                if in_flight.get(&key).map_or(false, |c| Arc::ptr_eq(c, &cell)) {
+                    in_flight.remove(&key);
+                }
+                response
+            })
+            .await;
+        let response = match response {
+            Ok((id, bytes)) => Ok((*id, bytes.clone())),
+            Err(e) => Err(copy_shared_error(e)),
+        };
+        (response, sent)
+    }
+
+    pub async fn request_method<T: Serialize, R: 'static>(
+        &self,
+        method: &str,
+        params: T,
+        timeout: Duration,
+    ) -> Result<R, Web3Error>
+    where
+        for<'de> R: Deserialize<'de>,
+        R: std::fmt::Debug,
+    {
+        let sink = self.metrics.read().expect("metrics sink poisoned").clone();
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "web30.rpc",
+            method,
+            host = %self.host,
+            request_id = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        #[cfg(not(feature = "tracing"))]
+        if sink.is_none() {
+            return self.request_method_inner(method, params, timeout).await.0;
+        }
+
+        let start = Instant::now();
+        let request = self.request_method_inner(method, params, timeout);
+        #[cfg(feature = "tracing")]
+        let (res, sent) = tracing::Instrument::instrument(request, span.clone()).await;
+        #[cfg(not(feature = "tracing"))]
+        let (res, sent) = request.await;
+        let elapsed = start.elapsed();
+
+        #[cfg(feature = "tracing")]
+        {
+            span.record("duration_ms", elapsed.as_millis() as u64);
+            if res.is_err() {
+                span.record("error", tracing::field::debug(Outcome::of(&res)));
+            }
+        }
+        // callers sharing the response of a coalesced request are not counted again, so
+        // that metrics match the requests the node received
+        if let Some(sink) = sink.filter(|_| sent) {
+            sink.observe(method, elapsed, Outcome::of(&res));
+        }
+        res
+    }
+
+    /// Sends the request, also returning true if it was sent rather than sharing the
+    /// response of a coalesced request
+    async fn request_method_inner<T: Serialize, R: 'static>(
+        &self,
+        method: &str,
+        params: T,
+        timeout: Duration,
+    ) -> (Result<R, Web3Error>, bool)
+    where
+        for<'de> R: Deserialize<'de>,
+        R: std::fmt::Debug,
+    {
+        let params = match serde_json::to_value(params) {
+            Ok(params) => params,
+            Err(e) => return (Err(e.into()), true),
+        };
+        // params can contain calldata so they are only recorded at trace level
+        #[cfg(feature = "tracing")]
+        tracing::trace!(params = %params, "web30.rpc params");
+        let (response, sent) = if COALESCED_METHODS.contains(&method) {
+            self.coalesced_request(method, &params, timeout).await
+        } else {
+            (self.send_with_retries(method, &params, timeout).await, true)
+        };
+        let res = response.and_then(|(id, bytes)| parse_response(method, &params, id, &bytes));
+        (res, sent)
+    }
+}
+
+/// Parses the response body to request `id`
+fn parse_response<R: 'static>(
+    method: &str,
+    params: &Value,
+    id: u64,
+    bytes: &[u8],
+) -> Result<R, Web3Error>
+where
+    for<'de> R: Deserialize<'de>,
+    R: std::fmt::Debug,
+{
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("request_id", id);
+    let response: JsonResponse<R> = match serde_json::from_slice(bytes) {
+        Ok(response) => response,
+        Err(e) => return Err(deserialization_error(method, id, params, bytes, e)),
+    };
+    #[cfg(feature = "debug_responses")]
+    {
+        println!("{:?}", response);
+    }
+    #[cfg(not(feature = "debug_responses"))]
+    {
+        // we have this separate in case we only want responses and not all the other traces
+        trace!("got web3 response {:?}", response);
+    }
+    check_response_id(
+        method,
+        id,
+        &response.id,
+        matches!(response.data, ResponseData::Error { .. }),
+    )?;
+
+    match response.data.into_result() {
+        Ok(result) => Ok(result),
+        Err(error) => {
+            #[cfg(feature = "debug_errors")]
+            {
+                error!(
+                    "when using request {} id {} with params:\n{}\n, got web3 error response {:?}",
+                    method, id, params, error
+                );
+            }
+            Err(Web3Error::JsonRpcError {
+                code: error.code,
+                message: error.message,
+                data: error.data,
+                request_id: Some(id),
+            })
+        }
+    }
+}
+
+/// Checks that the id of a response is the id of the request it is supposed to answer.
+/// Servers are allowed to answer with a null id when they could not parse the request.
+fn check_response_id(
+    method: &str,
+    request_id: u64,
+    response_id: &Value,
+    is_error: bool,
+) -> Result<(), Web3Error> {
+    let matches = match response_id {
+        Value::Number(n) => n.as_u64() == Some(request_id),
+        // some servers echo ids back as strings
+        Value::String(s) => s.parse() == Ok(request_id),
+        Value::Null => is_error,
+        _ => false,
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(Web3Error::ResponseIdMismatch {
+            method: method.to_string(),
+            request_id,
+            response_id: response_id.clone(),
+        })
+    }
+}
+
+#[test]
+fn test_body_snippet() {
+    assert_eq!(body_snippet(b"{}"), "{}");
+    let long = vec![b'a'; BODY_SNIPPET_LEN * 2];
+    assert_eq!(body_snippet(&long).len(), BODY_SNIPPET_LEN + 3);
+}
+
+#[tokio::test]
+async fn test_coalesced_requests() {
+    use crate::jsonrpc::mock::MockNode;
+    use clarity::Uint256;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_getBlockByNumber" => Ok(json!({"number": "0x10"})),
+        "eth_sendRawTransaction" => Ok(json!(format!("{:#066x}", 1))),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let client = Arc::new(HttpClient::new(&node.url));
+    let timeout = Duration::from_secs(5);
+
+    let handles: Vec<_> = (0..50)
+        .map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                client
+                    .request_method::<_, Value>("eth_getBlockByNumber", ("latest", false), timeout)
+                    .await
+            })
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(handle.await.unwrap().unwrap()["number"], json!("0x10"));
+    }
+    assert_eq!(node.calls("eth_getBlockByNumber"), 1);
+
+    // the response is not reused once it has arrived
+    client
+        .request_method::<_, Value>("eth_getBlockByNumber", ("latest", false), timeout)
+        .await
+        .unwrap();
+    assert_eq!(node.calls("eth_getBlockByNumber"), 2);
+
+    // methods with side effects are never coalesced
+    let handles: Vec<_> = (0..5)
+        .map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                client
+                    .request_method::<_, Uint256>("eth_sendRawTransaction", vec!["0x00"], timeout)
+                    .await
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.await.unwrap().unwrap();
+    }
+    assert_eq!(node.calls("eth_sendRawTransaction"), 5);
+}
+
+#[tokio::test]
+async fn test_retries_only_read_only_methods() {
+    use crate::jsonrpc::transport::TransportFuture;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Times out every request, counting them
+    struct TimingOut(AtomicUsize);
+
+    impl Transport for TimingOut {
+        fn post<'a>(&'a self, _: &'a str, _: HeaderMap, _: Vec<u8>) -> TransportFuture<'a> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Err(Web3Error::Timeout) })
+        }
+    }
+
+    let transport = Arc::new(TimingOut(AtomicUsize::new(0)));
+    let client = HttpClient::new("http://node.invalid")
+        .with_transport(transport.clone())
+        .with_retries(2);
+    let timeout = Duration::from_secs(5);
+
+    let res = client
+        .request_method::<_, Value>("eth_getBalance", Vec::<String>::new(), timeout)
+        .await;
+    assert!(matches!(res, Err(Web3Error::Timeout)));
+    assert_eq!(transport.0.load(Ordering::SeqCst), 3);
+
+    // the transaction may have reached the node, so sending it again is left to the caller
+    let res = client
+        .request_method::<_, Value>("eth_sendRawTransaction", vec!["0x00"], timeout)
+        .await;
+    assert!(matches!(res, Err(Web3Error::Timeout)));
+    assert_eq!(transport.0.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn test_coalesced_request_errors() {
+    let timeout = Duration::from_secs(5);
+    // nothing listens on port 1, so the connection is refused
+    let client = HttpClient::new("http://127.0.0.1:1");
+
+    // the second request waits on the first, and both see a network failure
+    let (first, second) = tokio::join!(
+        client.request_method::<_, Value>("eth_blockNumber", Vec::<String>::new(), timeout),
+        client.request_method::<_, Value>("eth_blockNumber", Vec::<String>::new(), timeout),
+    );
+    for res in [first, second] {
+        let e = res.unwrap_err();
+        assert!(matches!(e, Web3Error::TransportError(_)), "{:?}", e);
+        assert!(e.is_retriable());
+    }
+}
+
+#[test]
+fn test_check_response_id() {
+    use serde_json::json;
+
+    assert!(check_response_id("eth_chainId", 7, &json!(7), false).is_ok());
+    assert!(check_response_id("eth_chainId", 7, &json!("7"), false).is_ok());
+    assert!(check_response_id("eth_chainId", 7, &Value::Null, true).is_ok());
+    assert!(check_response_id("eth_chainId", 7, &Value::Null, false).is_err());
+    match check_response_id("eth_chainId", 7, &json!(8), false) {
+        Err(e @ Web3Error::ResponseIdMismatch { .. }) => {
+            assert_eq!(
+                e.to_string(),
+                "Web3 response to eth_chainId request id 7 had id 8"
+            );
+        }
+        res => panic!("Expected ResponseIdMismatch, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_unique_ids() {
+    let client = Arc::new(HttpClient::new("http://localhost:8545"));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let client = client.clone();
+            std::thread::spawn(move || (0..1000).map(|_| client.next_id()).collect::<Vec<_>>())
+        })
+        .collect();
+    let mut ids: Vec<u64> = handles
+        .into_iter()
+        .flat_map(|h| h.join().unwrap())
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), 4000);
+}
+
+#[test]
+fn test_deserialization_error_text() {
+    use serde_json::json;
+
+    let params = json!(["latest", false]);
+    let html = format!("<html><body>{}</body></html>", "a".repeat(2000));
+    let e = serde_json::from_str::<Value>(&html).unwrap_err();
+    let text =
+        deserialization_error("eth_getBlockByNumber", 1, &params, html.as_bytes(), e).to_string();
+    assert!(text.starts_with(
+        "Web3 eth_getBlockByNumber endpoint did not return JSON, is this a JSON-RPC URL? body <html><body>aaa"
+    ));
+    assert!(text.ends_with("a..."));
+
+    let body = r#"{"jsonrpc":"2.0","id":1,"result":{"number":"0x10"}}"#;
+    let e = serde_json::from_str::<JsonResponse<u64>>(body).unwrap_err();
+    let text =
+        deserialization_error("eth_blockNumber", 1, &json!([]), body.as_bytes(), e).to_string();
+    assert_eq!(
+        text,
+        format!(
+            "Web3 failed to parse response to eth_blockNumber id 1 with params [] ({}) body {}",
+            serde_json::from_str::<JsonResponse<u64>>(body).unwrap_err(),
+            body
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_deserialization_error_shape_mismatch() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!({"number": "0x10"})),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let client = HttpClient::new(&node.url);
+    let res = client
+        .request_method::<_, u64>("eth_blockNumber", vec!["latest"], Duration::from_secs(5))
+        .await;
+    match res {
+        Err(Web3Error::DeserializationError {
+            method,
+            params_summary,
+            body_snippet,
+            ..
+        }) => {
+            assert_eq!(method, "eth_blockNumber");
+            assert_eq!(params_summary, r#"["latest"]"#);
+            assert!(body_snippet.contains(r#""number":"0x10""#));
+        }
+        res => panic!("Expected DeserializationError, got {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_error_request_ids() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!({"number": "0x10"})),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let client = HttpClient::new(&node.url);
+    let timeout = Duration::from_secs(5);
+
+    let res = client
+        .request_method::<_, u64>("eth_blockNumber", Vec::<String>::new(), timeout)
+        .await;
+    assert!(matches!(
+        res,
+        Err(Web3Error::DeserializationError { request_id: 1, .. })
+    ));
+    let e = client
+        .request_method::<_, u64>("eth_foo", Vec::<String>::new(), timeout)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        e,
+        Web3Error::JsonRpcError {
+            request_id: Some(2),
+            ..
+        }
+    ));
+    assert!(e.to_string().ends_with("request id 2"));
+}
+
+#[tokio::test]
+async fn test_rate_limited_responses() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::fs::read_to_string;
+    use std::sync::atomic::AtomicUsize;
+
+    let alchemy = read_to_string("test_files/rate_limit_alchemy.json").unwrap();
+    let ankr = read_to_string("test_files/rate_limit_ankr.json").unwrap();
+    let calls = AtomicUsize::new(0);
+    let node = MockNode::start_raw(move |method, _, id| {
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+        match (method, call) {
+            ("net_version", 0) => (429, vec![("retry-after", "0".to_string())], alchemy.clone()),
+            ("net_version", 1) => (
+                200,
+                Vec::new(),
+                json!({"jsonrpc": "2.0", "id": id, "error": {
+                    "code": -32005,
+                    "message": "project ID request rate exceeded",
+                    "data": {"rate": {"backoff_seconds": 0.05}},
+                }})
+                .to_string(),
+            ),
+            ("net_version", _) => (
+                200,
+                Vec::new(),
+                json!({"jsonrpc": "2.0", "id": id, "result": "1"}).to_string(),
+            ),
+            ("eth_chainId", _) => {
+                let mut body: Value = serde_json::from_str(&ankr).unwrap();
+                body["id"] = id.clone();
+                (200, Vec::new(), body.to_string())
+            }
+            _ => (
+                429,
+                vec![("retry-after", "120".to_string())],
+                "Too Many Requests".to_string(),
+            ),
+        }
+    });
+    let timeout = Duration::from_secs(5);
+
+    // retried after the suggested backoffs
+    let client = HttpClient::new(&node.url).with_retries(2);
+    let start = Instant::now();
+    let version: String = client
+        .request_method("net_version", Vec::<String>::new(), timeout)
+        .await
+        .unwrap();
+    assert_eq!(version, "1");
+    assert_eq!(node.calls("net_version"), 3);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    // without retries the backoff is surfaced
+    let client = HttpClient::new(&node.url);
+    let res = client
+        .request_method::<_, String>("eth_chainId", Vec::<String>::new(), timeout)
+        .await;
+    assert!(matches!(
+        res,
+        Err(Web3Error::RateLimited {
+            retry_after: Some(d)
+        }) if d == Duration::from_secs(10)
+    ));
+
+    // backoffs too long to wait for are surfaced even with retries
+    let client = HttpClient::new(&node.url).with_retries(2);
+    let res = client
+        .request_method::<_, String>("eth_getBalance", Vec::<String>::new(), timeout)
+        .await;
+    assert!(matches!(
+        res,
+        Err(Web3Error::RateLimited {
+            retry_after: Some(d)
+        }) if d == Duration::from_secs(120)
+    ));
+    assert_eq!(node.calls("eth_getBalance"), 1);
+
+    // with no matchers the response is parsed as usual
+    let client = HttpClient::new(&node.url).with_rate_limit_matchers(Vec::new());
+    let res = client
+        .request_method::<_, String>("eth_chainId", Vec::<String>::new(), timeout)
+        .await;
+    assert_eq!(
+        res.unwrap_err().json_rpc_code(),
+        Some(crate::jsonrpc::error::JsonRpcErrorCode::Other(-32090))
+    );
 }
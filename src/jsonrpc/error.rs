@@ -1,21 +1,49 @@
+use crate::hash::TxHash;
 use clarity::Error as ClarityError;
-use clarity::Uint256;
+use clarity::{Address, Uint256};
+use serde_json::Value;
 use std::error::Error;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
 use std::num::ParseIntError;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::error::Elapsed;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Web3Error {
     BadResponse(String),
-    RequestFailed(hyper::Error),
+    /// the HTTP request failed at the network level, shared so that every caller of a
+    /// coalesced request gets it
+    TransportError(Arc<hyper::Error>),
+    /// the request did not complete within the configured timeout
+    Timeout,
+    /// the response could not be parsed into the expected type
+    DeserializationError {
+        method: String,
+        /// the request params, truncated
+        params_summary: String,
+        /// the start of the response body
+        body_snippet: String,
+        serde_error: String,
+        /// the JSON-RPC id of the request, for finding it in provider logs
+        request_id: u64,
+    },
+    /// the id of the response does not match the id of the request it answers
+    ResponseIdMismatch {
+        method: String,
+        request_id: u64,
+        response_id: Value,
+    },
+    /// the node returned a JSON-RPC error object
     JsonRpcError {
         code: i64,
         message: String,
-        data: String,
+        data: Option<Value>,
+        /// the JSON-RPC id of the request, None for errors that were not returned by a node
+        request_id: Option<u64>,
     },
     InsufficientGas {
         balance: Uint256,
@@ -27,11 +55,244 @@ pub enum Web3Error {
     CouldNotRemoveFilter(String),
     ClarityError(ClarityError),
     ContractCallError(String),
-    TransactionTimeout,
+    /// the transaction was not mined in time, with its hash if known
+    TransactionTimeout(Option<TxHash>),
+    /// another transaction with the same nonce was mined, so this one never will be
+    TransactionReplaced {
+        hash: TxHash,
+        nonce: Uint256,
+    },
     NoBlockProduced {
         time: Duration,
     },
     SyncingNode(String),
+    /// the node does not implement or has not enabled the given method
+    UnsupportedMethod(String),
+    /// the suggested fee per gas is above the configured cap, the transaction was not sent
+    GasPriceTooHigh {
+        suggested: Uint256,
+        cap: Uint256,
+    },
+    /// the sender can not pay for the value and gas of a transaction, it was not sent
+    InsufficientBalance {
+        have: Uint256,
+        need: Uint256,
+    },
+    /// a balance did not reach `minimum` in time, with the last balance that was seen
+    BalanceNotReached {
+        minimum: Uint256,
+        last_seen: Option<Uint256>,
+    },
+    /// a price feed answer was last updated longer ago than the caller allows
+    StalePrice {
+        feed: Address,
+        age: Duration,
+    },
+    /// the MAC of a keystore does not match its ciphertext, usually the password is wrong
+    KeystoreMacMismatch,
+    /// the provider rejected the request because of a rate limit, with the backoff it
+    /// suggested if any, see `rate_limit::RATE_LIMIT_MATCHERS`
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// the node no longer has the state of `block`, it is pruned and a query this far back
+    /// needs an archive node
+    StateUnavailable {
+        block: Uint256,
+    },
+}
+
+/// JSON-RPC error code for a method that does not exist or is not available
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// JSON-RPC error code used by Infura and others when a request is rate limited
+pub const LIMIT_EXCEEDED: i64 = -32005;
+
+/// The meaning of a JSON-RPC error code, as defined by the JSON-RPC 2.0 spec and EIP-1474
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JsonRpcErrorCode {
+    /// -32700, the request was not valid JSON
+    ParseError,
+    /// -32600
+    InvalidRequest,
+    /// -32601
+    MethodNotFound,
+    /// -32602
+    InvalidParams,
+    /// -32603
+    InternalError,
+    /// -32000, the catch all that geth uses for most transaction errors, which are only
+    /// distinguished by their message
+    ServerError,
+    /// -32005 or an HTTP style 429
+    LimitExceeded,
+    /// 3, a call or gas estimate reverted, with the revert data in the error data
+    ExecutionReverted,
+    Other(i64),
+}
+
+impl JsonRpcErrorCode {
+    pub fn from_code(code: i64) -> JsonRpcErrorCode {
+        match code {
+            -32700 => JsonRpcErrorCode::ParseError,
+            -32600 => JsonRpcErrorCode::InvalidRequest,
+            METHOD_NOT_FOUND => JsonRpcErrorCode::MethodNotFound,
+            -32602 => JsonRpcErrorCode::InvalidParams,
+            -32603 => JsonRpcErrorCode::InternalError,
+            -32000 => JsonRpcErrorCode::ServerError,
+            LIMIT_EXCEEDED | 429 => JsonRpcErrorCode::LimitExceeded,
+            3 => JsonRpcErrorCode::ExecutionReverted,
+            code => JsonRpcErrorCode::Other(code),
+        }
+    }
+}
+
+impl Web3Error {
+    /// Returns the kind of JSON-RPC error the node responded with, if this is one
+    pub fn json_rpc_code(&self) -> Option<JsonRpcErrorCode> {
+        match self {
+            Web3Error::JsonRpcError { code, .. } => Some(JsonRpcErrorCode::from_code(*code)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this is a JSON-RPC error whose message contains any of `patterns`,
+    /// which must be lowercase. Nodes only distinguish transaction errors by their message.
+    fn message_contains(&self, patterns: &[&str]) -> bool {
+        match self {
+            Web3Error::JsonRpcError { message, .. } => {
+                let message = message.to_lowercase();
+                patterns.iter().any(|p| message.contains(p))
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if a transaction was rejected because its nonce is already used or too
+    /// far ahead, in which case the nonce should be refetched rather than the send retried
+    pub fn is_nonce_error(&self) -> bool {
+        self.message_contains(&["nonce too low", "nonce too high", "invalid nonce"])
+    }
+
+    /// Returns true if the node already has this exact transaction in its pool, so that it
+    /// was effectively sent successfully
+    pub fn is_already_known(&self) -> bool {
+        self.message_contains(&["already known", "known transaction", "already imported"])
+    }
+
+    /// Returns true if the sender can not pay for the gas and value of a transaction
+    pub fn is_insufficient_funds(&self) -> bool {
+        self.message_contains(&["insufficient funds"])
+    }
+
+    /// Returns true if a transaction replacing another with the same nonce did not raise
+    /// the fees by enough for the node to accept it
+    pub fn is_replacement_underpriced(&self) -> bool {
+        self.message_contains(&["replacement transaction underpriced"])
+    }
+
+    /// Returns true if the request was rejected because of a provider rate limit
+    pub fn is_rate_limited(&self) -> bool {
+        match self {
+            Web3Error::RateLimited { .. } => true,
+            Web3Error::JsonRpcError { code, .. } => {
+                JsonRpcErrorCode::from_code(*code) == JsonRpcErrorCode::LimitExceeded
+                    || self.message_contains(&["rate limit", "too many requests"])
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the node could not answer because the state of the requested block was
+    /// pruned, the "missing trie node" class of errors returned by non archive nodes
+    pub fn is_state_unavailable(&self) -> bool {
+        match self {
+            Web3Error::StateUnavailable { .. } => true,
+            // geth and older erigon, geth when it can not regenerate the state, reth and
+            // erigon ("state at block #N is pruned") and besu
+            _ => self.message_contains(&[
+                "missing trie node",
+                "required historical state unavailable",
+                "is pruned",
+                "world state unavailable",
+            ]),
+        }
+    }
+
+    /// Converts a pruned state error for a query at `block` into `StateUnavailable`, leaving
+    /// other errors untouched
+    pub fn map_state_unavailable(self, block: Uint256) -> Web3Error {
+        match self {
+            e if e.is_state_unavailable() => Web3Error::StateUnavailable { block },
+            e => e,
+        }
+    }
+
+    /// Returns true if an eth_getLogs query was refused for covering too many blocks or
+    /// returning too many logs, in which case a smaller query may succeed
+    pub fn is_log_limit_exceeded(&self) -> bool {
+        self.message_contains(&[
+            "query returned more than",
+            "response size exceeded",
+            "response size should not",
+            "block range",
+            "range is too large",
+            "range too large",
+            "too many results",
+            "too many logs",
+            "logs are limited",
+            "is limited to",
+        ])
+    }
+
+    /// Returns true if the node reported that a filter id does not exist, which happens when
+    /// filters expire from inactivity or a load balancer routes to a node without the filter
+    pub fn is_filter_not_found(&self) -> bool {
+        match self {
+            Web3Error::JsonRpcError { code, message, .. } => {
+                *code == -32000 && message.to_lowercase().contains("filter not found")
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the node rejected a block tag such as "finalized" or "safe" because it
+    /// does not know it, as opposed to failing or rate limiting the request
+    pub fn is_unsupported_block_tag(&self) -> bool {
+        match self {
+            Web3Error::JsonRpcError { code, .. } if !self.is_rate_limited() => {
+                matches!(
+                    JsonRpcErrorCode::from_code(*code),
+                    JsonRpcErrorCode::InvalidParams | JsonRpcErrorCode::MethodNotFound
+                ) || self.message_contains(&["unknown block", "invalid block tag"])
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true for errors that are likely to succeed if the same request is
+    /// tried again later, such as network failures, timeouts and rate limits
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            Web3Error::TransportError(_)
+            | Web3Error::Timeout
+            | Web3Error::SyncingNode(_)
+            | Web3Error::RateLimited { .. } => true,
+            Web3Error::JsonRpcError { .. } => self.is_rate_limited(),
+            _ => false,
+        }
+    }
+
+    /// Converts a "method not found" response for `method` into `UnsupportedMethod`,
+    /// leaving other errors untouched
+    pub fn map_unsupported(self, method: &str) -> Web3Error {
+        match self {
+            Web3Error::JsonRpcError { code, .. } if code == METHOD_NOT_FOUND => {
+                Web3Error::UnsupportedMethod(method.to_string())
+            }
+            e => e,
+        }
+    }
 }
 
 impl From<ParseIntError> for Web3Error {
@@ -48,13 +309,13 @@ impl From<ClarityError> for Web3Error {
 
 impl From<Elapsed> for Web3Error {
     fn from(_error: Elapsed) -> Self {
-        Web3Error::TransactionTimeout
+        Web3Error::TransactionTimeout(None)
     }
 }
 
 impl From<hyper::Error> for Web3Error {
     fn from(error: hyper::Error) -> Self {
-        Web3Error::RequestFailed(error)
+        Web3Error::TransportError(Arc::new(error))
     }
 }
 
@@ -69,12 +330,53 @@ impl Display for Web3Error {
         match self {
             Web3Error::BadResponse(val) => write!(f, "Web3 bad response {}", val),
             Web3Error::BadInput(val) => write!(f, "Web3 bad input {}", val),
-            Web3Error::RequestFailed(val) => {
+            Web3Error::TransportError(val) => {
                 write!(f, "Web3 Request Failed on the Network {}", val)
             }
+            Web3Error::Timeout => write!(f, "Web3 Request Timed Out"),
+            // a misconfigured URL usually serves an HTML page
+            Web3Error::DeserializationError {
+                method,
+                body_snippet,
+                ..
+            } if body_snippet.trim_start().starts_with('<') => write!(
+                f,
+                "Web3 {} endpoint did not return JSON, is this a JSON-RPC URL? body {}",
+                method, body_snippet
+            ),
+            Web3Error::DeserializationError {
+                method,
+                params_summary,
+                body_snippet,
+                serde_error,
+                request_id,
+            } => write!(
+                f,
+                "Web3 failed to parse response to {} id {} with params {} ({}) body {}",
+                method, request_id, params_summary, serde_error, body_snippet
+            ),
+            Web3Error::ResponseIdMismatch {
+                method,
+                request_id,
+                response_id,
+            } => write!(
+                f,
+                "Web3 response to {} request id {} had id {}",
+                method, request_id, response_id
+            ),
             Web3Error::EventNotFound(val) => write!(f, "Web3 Failed to find event {}", val),
             Web3Error::ClarityError(val) => write!(f, "ClarityError {}", val),
-            Web3Error::TransactionTimeout => write!(f, "Transaction did not enter chain in time"),
+            Web3Error::TransactionTimeout(None) => {
+                write!(f, "Transaction did not enter chain in time")
+            }
+            Web3Error::TransactionTimeout(Some(hash)) => {
+                write!(f, "Transaction {} did not enter chain in time", hash)
+            }
+            Web3Error::TransactionReplaced { hash, nonce } => write!(
+                f,
+                "Transaction {} was replaced by another transaction with nonce {}",
+                hash, nonce
+            ),
             Web3Error::NoBlockProduced { time } => {
                 write!(
                     f,
@@ -100,16 +402,220 @@ impl Display for Web3Error {
                 code,
                 message,
                 data,
+                request_id: None,
             } => write!(
                 f,
                 "Web3 Response error code {} message {} data {:?}",
                 code, message, data
             ),
+            Web3Error::JsonRpcError {
+                code,
+                message,
+                data,
+                request_id: Some(request_id),
+            } => write!(
+                f,
+                "Web3 Response error code {} message {} data {:?} request id {}",
+                code, message, data, request_id
+            ),
             Web3Error::SyncingNode(val) => {
                 write!(f, "Web3 Node is syncing {}", val)
             }
+            Web3Error::UnsupportedMethod(val) => {
+                write!(f, "Web3 Node does not support {}", val)
+            }
+            Web3Error::GasPriceTooHigh { suggested, cap } => write!(
+                f,
+                "Suggested fee per gas {} is above the cap of {}, transaction not sent",
+                suggested, cap
+            ),
+            Web3Error::InsufficientBalance { have, need } => write!(
+                f,
+                "Balance of {} wei can not pay for the {} wei this transaction requires",
+                have, need
+            ),
+            Web3Error::BalanceNotReached { minimum, last_seen } => write!(
+                f,
+                "Balance did not reach {} in time, last seen {:?}",
+                minimum, last_seen
+            ),
+            Web3Error::StalePrice { feed, age } => write!(
+                f,
+                "Price feed {} was last updated {} seconds ago",
+                feed,
+                age.as_secs()
+            ),
+            Web3Error::KeystoreMacMismatch => {
+                write!(f, "Keystore MAC mismatch, the password is probably wrong")
+            }
+            Web3Error::RateLimited { retry_after: None } => write!(f, "Web3 Request rate limited"),
+            Web3Error::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "Web3 Request rate limited, retry after {:?}",
+                retry_after
+            ),
+            Web3Error::StateUnavailable { block } => write!(
+                f,
+                "Web3 Node does not have the state of block {}, an archive node is required",
+                block
+            ),
         }
     }
 }
 
 impl Error for Web3Error {}
+
+#[test]
+fn test_error_predicates() {
+    let rate_limited = Web3Error::JsonRpcError {
+        code: LIMIT_EXCEEDED,
+        message: "request limit reached".to_string(),
+        data: None,
+        request_id: None,
+    };
+    assert!(rate_limited.is_rate_limited());
+    assert!(rate_limited.is_retriable());
+
+    let too_many = Web3Error::JsonRpcError {
+        code: -32000,
+        message: "Too Many Requests".to_string(),
+        data: None,
+        request_id: None,
+    };
+    assert!(too_many.is_rate_limited());
+    let backoff = Web3Error::RateLimited {
+        retry_after: Some(Duration::from_secs(30)),
+    };
+    assert!(backoff.is_rate_limited());
+    assert!(backoff.is_retriable());
+    assert_eq!(backoff.json_rpc_code(), None);
+
+    let reverted = Web3Error::JsonRpcError {
+        code: 3,
+        message: "execution reverted".to_string(),
+        data: Some(serde_json::json!("0x08c379a0")),
+        request_id: None,
+    };
+    assert!(!reverted.is_rate_limited());
+    assert!(!reverted.is_retriable());
+
+    assert!(Web3Error::Timeout.is_retriable());
+    assert!(Web3Error::JsonRpcError {
+        code: -32000,
+        message: "filter not found".to_string(),
+        data: None,
+        request_id: None,
+    }
+    .is_filter_not_found());
+    assert!(!Web3Error::BadInput("bad".to_string()).is_retriable());
+
+    // geth, geth, reth and besu respectively
+    for message in [
+        "missing trie node 4c2a1e5f6b0c9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8a7b6c5d4e (path )",
+        "required historical state unavailable (reexec=128)",
+        "state at block #1000 is pruned",
+        "World state unavailable for block 0x3e8",
+    ] {
+        let pruned = Web3Error::JsonRpcError {
+            code: -32000,
+            message: message.to_string(),
+            data: None,
+            request_id: None,
+        };
+        assert!(pruned.is_state_unavailable(), "{}", message);
+        assert!(matches!(
+            pruned.map_state_unavailable(Uint256::from_u64(1000)),
+            Web3Error::StateUnavailable { block } if block == Uint256::from_u64(1000)
+        ));
+    }
+    assert!(!reverted.is_state_unavailable());
+    // revert reasons are free text and must not be mistaken for pruning
+    for message in [
+        "execution reverted: archive period over",
+        "execution reverted: historical state snapshot not found",
+    ] {
+        let reverted = Web3Error::JsonRpcError {
+            code: 3,
+            message: message.to_string(),
+            data: None,
+            request_id: None,
+        };
+        assert!(!reverted.is_state_unavailable(), "{}", message);
+    }
+
+    // infura, alchemy, bsc and ankr respectively
+    for message in [
+        "query returned more than 10000 results",
+        "Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range",
+        "exceed maximum block range: 5000",
+        "eth_getLogs is limited to a 10,000 range",
+    ] {
+        let limited = Web3Error::JsonRpcError {
+            code: -32005,
+            message: message.to_string(),
+            data: None,
+            request_id: None,
+        };
+        assert!(limited.is_log_limit_exceeded(), "{}", message);
+    }
+    assert!(!reverted.is_log_limit_exceeded());
+    assert!(!Web3Error::Timeout.is_log_limit_exceeded());
+    assert!(matches!(
+        Web3Error::Timeout.map_state_unavailable(Uint256::from_u64(1)),
+        Web3Error::Timeout
+    ));
+    assert!(!Web3Error::DeserializationError {
+        method: "eth_blockNumber".to_string(),
+        params_summary: "[]".to_string(),
+        body_snippet: "<html>".to_string(),
+        serde_error: "expected value at line 1 column 1".to_string(),
+        request_id: 1,
+    }
+    .is_retriable());
+}
+
+#[test]
+fn test_json_rpc_error_codes() {
+    let error = |code, message: &str| Web3Error::JsonRpcError {
+        code,
+        message: message.to_string(),
+        data: None,
+        request_id: None,
+    };
+
+    assert_eq!(
+        error(-32601, "the method eth_foo does not exist").json_rpc_code(),
+        Some(JsonRpcErrorCode::MethodNotFound)
+    );
+    assert_eq!(
+        error(-32602, "invalid argument 0").json_rpc_code(),
+        Some(JsonRpcErrorCode::InvalidParams)
+    );
+    assert_eq!(
+        error(429, "").json_rpc_code(),
+        Some(JsonRpcErrorCode::LimitExceeded)
+    );
+    assert_eq!(
+        error(-31999, "").json_rpc_code(),
+        Some(JsonRpcErrorCode::Other(-31999))
+    );
+    assert_eq!(Web3Error::Timeout.json_rpc_code(), None);
+
+    assert!(error(-32000, "nonce too low").is_nonce_error());
+    assert!(error(-32000, "already known").is_already_known());
+    assert!(error(
+        -32010,
+        "Transaction with the same hash was already imported."
+    )
+    .is_already_known());
+    assert!(error(
+        -32000,
+        "insufficient funds for gas * price + value: address 0x01 have 0 want 1"
+    )
+    .is_insufficient_funds());
+    assert!(error(-32000, "replacement transaction underpriced").is_replacement_underpriced());
+    assert!(!error(-32000, "replacement transaction underpriced").is_nonce_error());
+    assert!(!Web3Error::BadInput("nonce too low".to_string()).is_nonce_error());
+}
@@ -0,0 +1,237 @@
+//! A minimal JSON-RPC node for tests, serving responses from a handler function over
+//! a real HTTP server on localhost so that requests go through the normal client. On wasm32,
+//! where tests can not listen on a socket, requests are passed to the node in process.
+use hyper::body::Bytes;
+use hyper::HeaderMap;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(target_arch = "wasm32")]
+use crate::jsonrpc::error::Web3Error;
+#[cfg(target_arch = "wasm32")]
+use crate::jsonrpc::transport::{parse_retry_after, Transport, TransportFuture};
+
+/// The result of handling a request, either a result value or a JSON-RPC error code and message
+pub type MockResult = Result<Value, (i64, String)>;
+
+/// The HTTP status, headers and body of a response, for responses that are not well formed
+/// JSON-RPC such as those of rate limiting proxies
+pub type RawResponse = (u16, Vec<(&'static str, String)>, String);
+
+/// Records a request with its headers and body and answers it
+type Respond = Arc<dyn Fn(HeaderMap, Bytes) -> RawResponse + Send + Sync>;
+
+/// A running mock node, the server runs until the test runtime shuts down
+pub struct MockNode {
+    pub url: String,
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+    /// the headers and body of every request, in the same order as `requests`
+    raw_requests: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+}
+
+impl MockNode {
+    /// Starts a mock node that answers every request with `handler(method, params)`
+    pub fn start<F>(handler: F) -> MockNode
+    where
+        F: Fn(&str, &Value) -> MockResult + Send + Sync + 'static,
+    {
+        MockNode::start_with_latency(Duration::ZERO, handler)
+    }
+
+    /// The same as `start` but every response is delayed by `latency`, like a distant provider
+    pub fn start_with_latency<F>(latency: Duration, handler: F) -> MockNode
+    where
+        F: Fn(&str, &Value) -> MockResult + Send + Sync + 'static,
+    {
+        MockNode::serve(latency, move |method, params, id| {
+            let response = match handler(method, params) {
+                Ok(result) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": result,
+                }),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message },
+                }),
+            };
+            (200, Vec::new(), response.to_string())
+        })
+    }
+
+    /// Starts a mock node that answers every request with `handler(method, params, id)`
+    pub fn start_raw<F>(handler: F) -> MockNode
+    where
+        F: Fn(&str, &Value, &Value) -> RawResponse + Send + Sync + 'static,
+    {
+        MockNode::serve(Duration::ZERO, handler)
+    }
+
+    fn serve<F>(latency: Duration, handler: F) -> MockNode
+    where
+        F: Fn(&str, &Value, &Value) -> RawResponse + Send + Sync + 'static,
+    {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let raw_requests = Arc::new(Mutex::new(Vec::new()));
+        let respond: Respond = {
+            let requests = requests.clone();
+            let raw_requests = raw_requests.clone();
+            Arc::new(move |headers, body| {
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let method = request["method"].as_str().unwrap().to_string();
+                let params = request["params"].clone();
+                let response = handler(&method, &params, &request["id"]);
+                // both are pushed under the requests lock to keep them in order
+                let mut requests = requests.lock().unwrap();
+                raw_requests.lock().unwrap().push((headers, body));
+                requests.push((method, params));
+                response
+            })
+        };
+        MockNode {
+            url: listen(latency, respond),
+            requests,
+            raw_requests,
+        }
+    }
+
+    /// Returns the number of requests received for `method`
+    pub fn calls(&self, method: &str) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(m, _)| m == method)
+            .count()
+    }
+
+    /// Returns the headers and raw body of every request received for `method`, in order
+    pub fn raw_requests(&self, method: &str) -> Vec<(HeaderMap, Bytes)> {
+        let requests = self.requests.lock().unwrap();
+        let raw_requests = self.raw_requests.lock().unwrap();
+        requests
+            .iter()
+            .zip(raw_requests.iter())
+            .filter(|((m, _), _)| m == method)
+            .map(|(_, raw)| raw.clone())
+            .collect()
+    }
+
+    /// Returns the params of every request received for `method`, in order
+    pub fn params(&self, method: &str) -> Vec<Value> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(m, _)| m == method)
+            .map(|(_, params)| params.clone())
+            .collect()
+    }
+}
+
+/// Serves `respond` on a free localhost port and returns its url
+#[cfg(not(target_arch = "wasm32"))]
+fn listen(latency: Duration, respond: Respond) -> String {
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use std::convert::Infallible;
+
+    let make_service = make_service_fn(move |_| {
+        let respond = respond.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let respond = respond.clone();
+                async move {
+                    let headers = req.headers().clone();
+                    let body = hyper::body::to_bytes(req.into_body()).await?;
+                    tokio::time::sleep(latency).await;
+                    let (status, response_headers, response_body) = respond(headers, body);
+                    let mut response = Response::builder().status(status);
+                    for (name, value) in response_headers {
+                        response = response.header(name, value);
+                    }
+                    Ok::<_, hyper::Error>(response.body(Body::from(response_body)).unwrap())
+                }
+            }))
+        }
+    });
+
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+    url
+}
+
+/// The nodes started by the tests, indexed by the path of their url
+#[cfg(target_arch = "wasm32")]
+static NODES: Mutex<Vec<(Duration, Respond)>> = Mutex::new(Vec::new());
+
+/// Registers `respond` for `MockTransport` and returns its url
+#[cfg(target_arch = "wasm32")]
+fn listen(latency: Duration, respond: Respond) -> String {
+    let mut nodes = NODES.lock().unwrap();
+    nodes.push((latency, respond));
+    format!("http://mock.invalid/{}", nodes.len() - 1)
+}
+
+/// The transport of every client in wasm32 tests, passes requests to the `MockNode` of the url
+#[cfg(target_arch = "wasm32")]
+pub(crate) struct MockTransport;
+
+#[cfg(target_arch = "wasm32")]
+impl Transport for MockTransport {
+    fn post<'a>(&'a self, url: &'a str, headers: HeaderMap, body: Vec<u8>) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let node = url
+                .strip_prefix("http://mock.invalid/")
+                .and_then(|index| index.parse::<usize>().ok())
+                .and_then(|index| NODES.lock().unwrap().get(index).cloned());
+            let (latency, respond) = match node {
+                Some(node) => node,
+                None => return Err(Web3Error::BadResponse(format!("no mock node at {}", url))),
+            };
+            crate::time::sleep(latency).await;
+            let (status, response_headers, response_body) = respond(headers, body.into());
+            let retry_after = response_headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))
+                .map(|(_, value)| value.as_str());
+            Ok((status, parse_retry_after(retry_after), response_body.into()))
+        })
+    }
+}
+
+/// Mainnet block 11568854 with its full transactions, for handlers that serve blocks
+pub fn geth_block() -> Value {
+    let file = std::fs::read_to_string("test_files/complete_geth_eth_block.json").unwrap();
+    serde_json::from_str(&file).unwrap()
+}
+
+/// `geth_block` renumbered to `number` and without transactions, for tests that only need
+/// the chain to have blocks
+pub fn empty_block(number: u64) -> Value {
+    let mut block = geth_block();
+    block["number"] = json!(format!("{:#x}", number));
+    block["transactions"] = json!([]);
+    block
+}
+
+#[tokio::test]
+async fn test_mock_node() {
+    use crate::client::Web3;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!("0x10")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    assert_eq!(
+        web3.eth_synced_block_number().await.unwrap(),
+        clarity::u256!(16)
+    );
+    assert!(web3.net_version().await.is_err());
+    assert_eq!(node.calls("eth_blockNumber"), 1);
+    assert_eq!(node.calls("net_version"), 1);
+}
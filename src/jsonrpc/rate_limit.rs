@@ -0,0 +1,516 @@
+//! A token bucket rate limiter for keeping request rates within the limits of hosted
+//! providers, shared by every request made through a client and optionally several clients,
+//! and recognition of the responses providers send when a limit is exceeded anyway
+use crate::time::sleep;
+use crate::time::Instant;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The request rate of `RateLimiter::public_endpoint`, a conservative limit that most free
+/// public endpoints and entry level paid plans accept
+pub const PUBLIC_ENDPOINT_RPS: u32 = 25;
+
+/// Approximate compute unit costs of common methods from Alchemy's pricing, used by
+/// `RateLimiter::alchemy`. Methods not listed cost `ALCHEMY_DEFAULT_COMPUTE_UNITS`.
+pub const ALCHEMY_COMPUTE_UNITS: &[(&str, u32)] = &[
+    ("eth_chainId", 0),
+    ("net_version", 0),
+    ("eth_syncing", 0),
+    ("eth_blockNumber", 10),
+    ("eth_feeHistory", 10),
+    ("eth_maxPriorityFeePerGas", 10),
+    ("eth_getTransactionReceipt", 15),
+    ("eth_getBlockByNumber", 16),
+    ("eth_getBlockByHash", 16),
+    ("eth_getTransactionByHash", 17),
+    ("eth_getStorageAt", 17),
+    ("eth_gasPrice", 19),
+    ("eth_getBalance", 19),
+    ("eth_getCode", 19),
+    ("eth_call", 26),
+    ("eth_getTransactionCount", 26),
+    ("eth_getLogs", 75),
+    ("eth_estimateGas", 87),
+    ("eth_sendRawTransaction", 250),
+    ("debug_traceTransaction", 309),
+];
+
+/// The cost of methods missing from `ALCHEMY_COMPUTE_UNITS`
+pub const ALCHEMY_DEFAULT_COMPUTE_UNITS: u32 = 26;
+
+/// A snapshot of how much of a `RateLimiter`'s budget is in use, for logging
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitUtilization {
+    /// tokens that can be spent right now without waiting, negative when requests are
+    /// waiting for tokens that have already been promised to them
+    pub available: f64,
+    /// the most tokens the bucket holds, the burst size
+    pub capacity: f64,
+    /// requests currently waiting for their turn
+    pub waiting: usize,
+}
+
+impl RateLimitUtilization {
+    /// The fraction of the burst capacity in use, above 1.0 when requests are queued
+    pub fn fraction(&self) -> f64 {
+        1.0 - self.available / self.capacity
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// may go below zero, the debt is what waiting requests have reserved
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Counts a request as waiting for as long as it is alive, so that a request whose future is
+/// dropped while sleeping, for example by a timeout, is not counted forever
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl<'a> WaitingGuard<'a> {
+    fn new(waiting: &'a AtomicUsize) -> Self {
+        waiting.fetch_add(1, Ordering::Relaxed);
+        WaitingGuard(waiting)
+    }
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A token bucket that refills at a fixed rate up to a burst capacity. Every request spends
+/// tokens according to the weight of its method, by default one, so the limit can be set in
+/// requests per second or in a provider's compute units. When the bucket is empty requests
+/// wait their turn in the order they arrived instead of failing.
+///
+/// Wrap it in an `Arc` and pass it to `Web3Builder::rate_limiter`, the same limiter can be
+/// given to several clients that share an API key.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// tokens added per second
+    rate: f64,
+    burst: f64,
+    weights: HashMap<String, u32>,
+    default_weight: u32,
+    bucket: Mutex<Bucket>,
+    waiting: AtomicUsize,
+}
+
+impl RateLimiter {
+    /// Allows `rate` tokens per second on average and up to `burst` at once, the bucket
+    /// starts full
+    pub fn new(rate: f64, burst: f64) -> RateLimiter {
+        assert!(rate > 0.0 && burst > 0.0, "rate limits must be positive");
+        RateLimiter {
+            rate,
+            burst,
+            weights: HashMap::new(),
+            default_weight: 1,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                refilled_at: Instant::now(),
+            }),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Allows `rps` requests per second with bursts of up to one second's worth
+    pub fn requests_per_second(rps: u32) -> RateLimiter {
+        RateLimiter::new(rps as f64, rps as f64)
+    }
+
+    /// `PUBLIC_ENDPOINT_RPS` requests per second
+    pub fn public_endpoint() -> RateLimiter {
+        RateLimiter::requests_per_second(PUBLIC_ENDPOINT_RPS)
+    }
+
+    /// A budget of `compute_units_per_second` with methods weighted by
+    /// `ALCHEMY_COMPUTE_UNITS`, for Alchemy and providers with a similar pricing model
+    pub fn alchemy(compute_units_per_second: u32) -> RateLimiter {
+        let mut limiter = RateLimiter::new(
+            compute_units_per_second as f64,
+            compute_units_per_second as f64,
+        )
+        .with_default_weight(ALCHEMY_DEFAULT_COMPUTE_UNITS);
+        for (method, weight) in ALCHEMY_COMPUTE_UNITS {
+            limiter = limiter.with_method_weight(method, *weight);
+        }
+        limiter
+    }
+
+    /// Sets how many tokens a request for `method` spends, zero exempts it from the limit
+    pub fn with_method_weight(mut self, method: &str, weight: u32) -> Self {
+        self.weights.insert(method.to_string(), weight);
+        self
+    }
+
+    /// Sets how many tokens requests for methods without their own weight spend
+    pub fn with_default_weight(mut self, weight: u32) -> Self {
+        self.default_weight = weight;
+        self
+    }
+
+    /// The tokens a request for `method` spends
+    pub fn weight(&self, method: &str) -> u32 {
+        self.weights
+            .get(method)
+            .copied()
+            .unwrap_or(self.default_weight)
+    }
+
+    /// Adds the tokens accumulated since the last refill
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.refilled_at = now;
+    }
+
+    /// Reserves the tokens for a request and returns how long to wait before sending it.
+    /// Reservations are made in arrival order, so later requests wait behind earlier ones.
+    fn reserve(&self, weight: u32) -> Duration {
+        let mut bucket = self.bucket.lock().expect("rate limiter poisoned");
+        self.refill(&mut bucket);
+        bucket.tokens -= weight as f64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.rate)
+        }
+    }
+
+    /// Waits until a request for `method` may be sent
+    pub async fn acquire(&self, method: &str) {
+        let weight = self.weight(method);
+        if weight == 0 {
+            return;
+        }
+        let wait = self.reserve(weight);
+        if wait > Duration::ZERO {
+            trace!("Rate limiting {} for {:?}", method, wait);
+            let _waiting = WaitingGuard::new(&self.waiting);
+            sleep(wait).await;
+        }
+    }
+
+    /// How much of the budget is currently in use
+    pub fn utilization(&self) -> RateLimitUtilization {
+        let mut bucket = self.bucket.lock().expect("rate limiter poisoned");
+        self.refill(&mut bucket);
+        RateLimitUtilization {
+            available: bucket.tokens,
+            capacity: self.burst,
+            waiting: self.waiting.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Rate limited requests are only retried automatically if the provider asks for a backoff
+/// of at most this long, longer ones are returned as `Web3Error::RateLimited` so that the
+/// caller can schedule the work instead of blocking on it
+pub const MAX_RATE_LIMIT_RETRY_WAIT: Duration = Duration::from_secs(30);
+
+/// Successful responses can be very large and are not worth parsing twice, rate limit
+/// responses are small or come with an HTTP 429 status
+const MAX_RATE_LIMIT_BODY: usize = 4096;
+
+/// Recognizes the rate limit responses of a provider, see `RATE_LIMIT_MATCHERS`
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitMatcher {
+    /// the provider whose responses this recognizes, for logging
+    pub provider: &'static str,
+    /// given the HTTP status and the body, or null if it is not JSON, returns `None` if the
+    /// response is not a rate limit and otherwise the backoff it suggests, if any
+    pub detect: fn(status: u16, body: &Value) -> Option<Option<Duration>>,
+}
+
+/// The matchers an `HttpClient` uses by default, the first that recognizes a response wins.
+/// Replace them with `HttpClient::with_rate_limit_matchers` to support other providers.
+pub const RATE_LIMIT_MATCHERS: &[RateLimitMatcher] = &[
+    RateLimitMatcher {
+        provider: "infura",
+        detect: detect_infura,
+    },
+    RateLimitMatcher {
+        provider: "ankr",
+        detect: detect_ankr,
+    },
+    RateLimitMatcher {
+        provider: "alchemy",
+        detect: detect_alchemy,
+    },
+    RateLimitMatcher {
+        provider: "http",
+        detect: detect_http,
+    },
+];
+
+/// Reads a number of seconds that may be encoded as a number or a string
+fn seconds(value: &Value) -> Option<Duration> {
+    let seconds = match value {
+        Value::Number(n) => n.as_f64()?,
+        Value::String(s) => s.trim().parse().ok()?,
+        _ => return None,
+    };
+    // the value comes from the provider, so one too large for a Duration is ignored
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Reads a Go style duration such as "10s" or "500ms" following "retry in" in a message
+fn retry_in_message(message: &str) -> Option<Duration> {
+    let message = message.to_lowercase();
+    let rest = &message[message.find("retry in ")? + "retry in ".len()..];
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(rest.len());
+    let amount: f64 = rest[..digits].parse().ok()?;
+    let scale = match &rest[digits..] {
+        unit if unit.starts_with("ms") => 0.001,
+        unit if unit.starts_with('s') => 1.0,
+        unit if unit.starts_with('m') => 60.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(amount * scale).ok()
+}
+
+/// -32005 with the allowed and current rates, and a backoff, in the error data
+fn detect_infura(_status: u16, body: &Value) -> Option<Option<Duration>> {
+    let error = &body["error"];
+    if error["code"] != -32005 {
+        return None;
+    }
+    let data = &error["data"];
+    let backoff =
+        seconds(&data["rate"]["backoff_seconds"]).or_else(|| seconds(&data["backoff_seconds"]));
+    // -32005 is also used for queries that return too many results
+    let message = error["message"].as_str().unwrap_or_default().to_lowercase();
+    if backoff.is_some() || message.contains("rate") {
+        Some(backoff)
+    } else {
+        None
+    }
+}
+
+/// -32090 with the backoff only given in the message
+fn detect_ankr(_status: u16, body: &Value) -> Option<Option<Duration>> {
+    let error = &body["error"];
+    if error["code"] != -32090 {
+        return None;
+    }
+    Some(retry_in_message(
+        error["message"].as_str().unwrap_or_default(),
+    ))
+}
+
+/// An HTTP 429 with a JSON-RPC error code of 429, the backoff is usually in the Retry-After
+/// header but may be in the error data
+fn detect_alchemy(_status: u16, body: &Value) -> Option<Option<Duration>> {
+    let error = &body["error"];
+    if error["code"] != 429 {
+        return None;
+    }
+    let data = &error["data"];
+    Some(seconds(&data["retryAfter"]).or_else(|| seconds(&data["retry_after"])))
+}
+
+/// Any other HTTP 429, such as those from reverse proxies with a plain text body
+fn detect_http(status: u16, _body: &Value) -> Option<Option<Duration>> {
+    if status == 429 {
+        Some(None)
+    } else {
+        None
+    }
+}
+
+/// Returns `Some` with the suggested backoff if any of `matchers` recognizes the response as a
+/// rate limit, falling back to the Retry-After header when the body has no backoff
+pub(crate) fn detect_rate_limit(
+    matchers: &[RateLimitMatcher],
+    status: u16,
+    retry_after_header: Option<Duration>,
+    body: &[u8],
+) -> Option<Option<Duration>> {
+    if status != 429 && body.len() > MAX_RATE_LIMIT_BODY {
+        return None;
+    }
+    let body = serde_json::from_slice(body).unwrap_or(Value::Null);
+    matchers.iter().find_map(|matcher| {
+        let backoff = (matcher.detect)(status, &body)?;
+        trace!(
+            "{} rate limit response, backoff {:?}",
+            matcher.provider,
+            backoff
+        );
+        Some(backoff.or(retry_after_header))
+    })
+}
+
+#[tokio::test]
+async fn test_rate_limiter_burst_and_rate() {
+    let limiter = RateLimiter::new(100.0, 5.0);
+    let start = Instant::now();
+    for _ in 0..5 {
+        limiter.acquire("eth_blockNumber").await;
+    }
+    // the burst goes through at once
+    assert!(start.elapsed() < Duration::from_millis(40));
+    assert!(limiter.utilization().fraction() > 0.9);
+
+    for _ in 0..5 {
+        limiter.acquire("eth_blockNumber").await;
+    }
+    // the next five are spaced out at 100 per second
+    assert!(start.elapsed() >= Duration::from_millis(45));
+}
+
+#[tokio::test]
+async fn test_rate_limiter_weights() {
+    let limiter = RateLimiter::alchemy(100);
+    assert_eq!(limiter.weight("eth_getLogs"), 75);
+    assert_eq!(limiter.weight("eth_chainId"), 0);
+    assert_eq!(
+        limiter.weight("eth_someNewMethod"),
+        ALCHEMY_DEFAULT_COMPUTE_UNITS
+    );
+
+    let start = Instant::now();
+    limiter.acquire("eth_getLogs").await;
+    // free methods never wait
+    for _ in 0..100 {
+        limiter.acquire("eth_chainId").await;
+    }
+    assert!(start.elapsed() < Duration::from_millis(40));
+    // 25 units left, the second eth_getLogs owes 50 units or half a second
+    limiter.acquire("eth_getLogs").await;
+    assert!(start.elapsed() >= Duration::from_millis(450));
+}
+
+#[tokio::test]
+async fn test_rate_limiter_shared() {
+    use std::sync::Arc;
+
+    let limiter = Arc::new(RateLimiter::new(200.0, 10.0));
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..3)
+        .map(|_| {
+            let limiter = limiter.clone();
+            tokio::spawn(async move {
+                for _ in 0..10 {
+                    limiter.acquire("eth_call").await;
+                }
+            })
+        })
+        .collect();
+
+    // let the tasks queue up behind the burst
+    sleep(Duration::from_millis(20)).await;
+    let utilization = limiter.utilization();
+    assert!(utilization.available < 0.0);
+    assert!(utilization.waiting > 0);
+    assert!(utilization.fraction() > 1.0);
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+    // 30 requests with a burst of 10 need another 20 tokens at 200 per second
+    assert!(start.elapsed() >= Duration::from_millis(95));
+    assert_eq!(limiter.utilization().waiting, 0);
+}
+
+#[tokio::test]
+async fn test_rate_limiter_cancelled_wait() {
+    let limiter = RateLimiter::new(10.0, 1.0);
+    limiter.acquire("eth_call").await;
+    // the second request would wait 100ms but gives up first
+    let result = tokio::time::timeout(Duration::from_millis(10), limiter.acquire("eth_call")).await;
+    assert!(result.is_err());
+    assert_eq!(limiter.utilization().waiting, 0);
+}
+
+#[test]
+fn test_absurd_backoff() {
+    use serde_json::json;
+
+    assert_eq!(seconds(&json!(1.5)), Some(Duration::from_millis(1500)));
+    assert_eq!(seconds(&json!(1e30)), None);
+    assert_eq!(seconds(&json!("1e30")), None);
+    assert_eq!(seconds(&json!(-1)), None);
+    assert_eq!(
+        retry_in_message("Too many requests, retry in 10s"),
+        Some(Duration::from_secs(10))
+    );
+    assert_eq!(
+        retry_in_message(&format!("Too many requests, retry in {}s", "9".repeat(30))),
+        None
+    );
+}
+
+#[test]
+fn test_detect_rate_limit() {
+    use std::fs::read_to_string;
+
+    let fixture = |name: &str| read_to_string(format!("test_files/{}", name)).unwrap();
+    let detect = |status, header, body: &str| {
+        detect_rate_limit(RATE_LIMIT_MATCHERS, status, header, body.as_bytes())
+    };
+
+    let alchemy = fixture("rate_limit_alchemy.json");
+    assert_eq!(detect(429, None, &alchemy), Some(None));
+    assert_eq!(
+        detect(429, Some(Duration::from_secs(2)), &alchemy),
+        Some(Some(Duration::from_secs(2)))
+    );
+    let infura = fixture("rate_limit_infura.json");
+    assert_eq!(
+        detect(200, None, &infura),
+        Some(Some(Duration::from_secs(30)))
+    );
+    // the body takes precedence over the header
+    assert_eq!(
+        detect(200, Some(Duration::from_secs(1)), &infura),
+        Some(Some(Duration::from_secs(30)))
+    );
+    let ankr = fixture("rate_limit_ankr.json");
+    assert_eq!(
+        detect(200, None, &ankr),
+        Some(Some(Duration::from_secs(10)))
+    );
+
+    // proxies answer with plain text
+    assert_eq!(detect(429, None, "Too Many Requests"), Some(None));
+    // -32005 is also the error for too many results
+    assert_eq!(
+        detect(
+            200,
+            None,
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,"message":"query returned more than 10000 results"}}"#
+        ),
+        None
+    );
+    assert_eq!(
+        detect(200, None, r#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#),
+        None
+    );
+    assert_eq!(detect(200, None, &alchemy), Some(None));
+    // matchers can be replaced to ignore a provider
+    assert_eq!(
+        detect_rate_limit(&RATE_LIMIT_MATCHERS[..1], 200, None, ankr.as_bytes()),
+        None
+    );
+
+    assert_eq!(
+        retry_in_message("retry in 500ms"),
+        Some(Duration::from_millis(500))
+    );
+    assert_eq!(
+        retry_in_message("Retry in 2m"),
+        Some(Duration::from_secs(120))
+    );
+    assert_eq!(retry_in_message("try again later"), None);
+}
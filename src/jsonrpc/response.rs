@@ -1,3 +1,4 @@
+use serde::{Deserialize, Deserializer};
 use serde_json::Value;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -9,9 +10,25 @@ pub struct JsonRpcError<E> {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
+#[serde(bound(deserialize = "R: Deserialize<'de>, E: Deserialize<'de>"))]
 pub enum ResponseData<R, E> {
-    Error { error: JsonRpcError<E> },
-    Success { result: R },
+    Error {
+        error: JsonRpcError<E>,
+    },
+    Success {
+        // without this serde treats a missing result as null when R is an Option, which
+        // would turn a malformed response into a legitimate "not found"
+        #[serde(deserialize_with = "present")]
+        result: R,
+    },
+}
+
+fn present<'de, D, R>(deserializer: D) -> Result<R, D::Error>
+where
+    D: Deserializer<'de>,
+    R: Deserialize<'de>,
+{
+    R::deserialize(deserializer)
 }
 
 impl<R, E> ResponseData<R, E> {
@@ -48,3 +65,14 @@ fn test_error() {
     assert_eq!(err.code, -32601);
     assert_eq!(err.message, "Method not found");
 }
+
+#[test]
+fn test_null_result() {
+    let response: Response<Option<u64>> =
+        serde_json::from_str(r#"{"jsonrpc": "2.0", "result": null, "id": 1}"#).unwrap();
+    assert_eq!(response.data.into_result().unwrap(), None);
+    // a response with neither a result nor an error is malformed, not a null result
+    assert!(
+        serde_json::from_str::<Response<Option<u64>>>(r#"{"jsonrpc": "2.0", "id": 1}"#).is_err()
+    );
+}
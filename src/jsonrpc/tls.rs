@@ -0,0 +1,293 @@
+//! TLS settings of the HTTP transport. The backend is chosen at compile time, `native-tls`
+//! (the default) uses the platform library which is openssl on Linux, while `rustls` is pure
+//! Rust with the Mozilla root certificates built in and links nothing, which suits static
+//! musl builds. When both features are enabled rustls is used.
+use crate::jsonrpc::error::Web3Error;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hyper::client::HttpConnector;
+use std::str;
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("web30 requires a TLS backend, enable either the native-tls or the rustls feature");
+
+/// The connector of the TLS backend web30 was built with
+#[cfg(feature = "rustls")]
+pub type HttpsConnector = hyper_rustls::HttpsConnector<HttpConnector>;
+/// The connector of the TLS backend web30 was built with
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+pub type HttpsConnector = hyper_tls::HttpsConnector<HttpConnector>;
+
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+/// Decodes every certificate of a PEM bundle to DER, other PEM blocks such as keys are
+/// ignored
+fn pem_certificates(pem: &[u8]) -> Result<Vec<Vec<u8>>, Web3Error> {
+    let bad = |reason: &str| Web3Error::BadInput(format!("Invalid PEM bundle, {}", reason));
+    let mut rest = str::from_utf8(pem).map_err(|_| bad("not UTF-8"))?;
+    let mut certificates = Vec::new();
+    while let Some(start) = rest.find(PEM_BEGIN) {
+        let body = &rest[start + PEM_BEGIN.len()..];
+        let end = body
+            .find(PEM_END)
+            .ok_or_else(|| bad("unterminated certificate"))?;
+        let base64: String = body[..end].chars().filter(|c| !c.is_whitespace()).collect();
+        let der = STANDARD
+            .decode(base64)
+            .map_err(|e| bad(&format!("bad base64 {}", e)))?;
+        certificates.push(der);
+        rest = &body[end + PEM_END.len()..];
+    }
+    if certificates.is_empty() {
+        return Err(bad("no certificates found"));
+    }
+    Ok(certificates)
+}
+
+/// Returns true if the SHA-256 of the DER encoded `certificate` is one of `pins`
+#[cfg(feature = "rustls")]
+fn pin_matches(pins: &[[u8; 32]], certificate: &[u8]) -> bool {
+    use sha2::{Digest, Sha256};
+
+    let fingerprint: [u8; 32] = Sha256::digest(certificate).into();
+    pins.contains(&fingerprint)
+}
+
+/// How servers are authenticated, see `HttpClient::with_tls` and `Web3Builder::tls`. The
+/// default trusts the usual public certificate authorities.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// DER encoded certificates trusted in addition to the public authorities
+    extra_roots: Vec<Vec<u8>>,
+    /// SHA-256 fingerprints of the DER encoded server certificates that are accepted
+    #[cfg(feature = "rustls")]
+    pins: Vec<[u8; 32]>,
+    accept_invalid_certs: bool,
+}
+
+impl TlsConfig {
+    pub fn new() -> TlsConfig {
+        TlsConfig::default()
+    }
+
+    /// Trusts the certificates of a PEM bundle, such as the CA of an internal network that
+    /// hosts private nodes, in addition to the public authorities
+    pub fn with_ca_pem(mut self, pem: &[u8]) -> Result<Self, Web3Error> {
+        for der in pem_certificates(pem)? {
+            #[cfg(feature = "rustls")]
+            rustls::RootCertStore::empty()
+                .add(&rustls::Certificate(der.clone()))
+                .map_err(|e| Web3Error::BadInput(format!("Invalid CA certificate {}", e)))?;
+            #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+            hyper_tls::native_tls::Certificate::from_der(&der)
+                .map_err(|e| Web3Error::BadInput(format!("Invalid CA certificate {}", e)))?;
+            self.extra_roots.push(der);
+        }
+        Ok(self)
+    }
+
+    /// Only accepts servers presenting a certificate with one of the pinned SHA-256
+    /// fingerprints, as printed by `openssl x509 -noout -fingerprint -sha256`. The certificate
+    /// must still be valid and trusted, use `with_ca_pem` for ones issued by an internal CA.
+    /// Only available with rustls, native-tls has no way to inspect the certificate.
+    #[cfg(feature = "rustls")]
+    pub fn with_pinned_certificate(mut self, sha256_fingerprint: [u8; 32]) -> Self {
+        self.pins.push(sha256_fingerprint);
+        self
+    }
+
+    /// Accepts any certificate for any host name, including expired and self signed ones,
+    /// which makes TLS no protection at all against an attacker on the network. Only for
+    /// local dev chains with throwaway certificates, never use this with real funds.
+    pub fn danger_accept_invalid_certs_for_dev_chains_only(mut self) -> Self {
+        self.accept_invalid_certs = true;
+        self
+    }
+
+    /// Builds the connector for these settings, which also accepts plain http urls
+    #[cfg(feature = "rustls")]
+    pub(crate) fn connector(&self) -> HttpsConnector {
+        use rustls::{OwnedTrustAnchor, RootCertStore};
+        use std::sync::Arc;
+
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+        for der in &self.extra_roots {
+            roots
+                .add(&rustls::Certificate(der.clone()))
+                .expect("validated by with_ca_pem");
+        }
+
+        let builder = rustls::ClientConfig::builder().with_safe_defaults();
+        let config = if self.accept_invalid_certs {
+            builder
+                .with_custom_certificate_verifier(Arc::new(verifiers::AcceptAny))
+                .with_no_client_auth()
+        } else if !self.pins.is_empty() {
+            builder
+                .with_custom_certificate_verifier(Arc::new(verifiers::Pinned {
+                    inner: rustls::client::WebPkiVerifier::new(roots, None),
+                    pins: self.pins.clone(),
+                }))
+                .with_no_client_auth()
+        } else {
+            builder.with_root_certificates(roots).with_no_client_auth()
+        };
+        hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(config)
+            .https_or_http()
+            .enable_http1()
+            .build()
+    }
+
+    /// Builds the connector for these settings, which also accepts plain http urls
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    pub(crate) fn connector(&self) -> HttpsConnector {
+        use hyper_tls::native_tls::{Certificate, TlsConnector};
+
+        let mut builder = TlsConnector::builder();
+        for der in &self.extra_roots {
+            builder.add_root_certificate(
+                Certificate::from_der(der).expect("validated by with_ca_pem"),
+            );
+        }
+        builder.danger_accept_invalid_certs(self.accept_invalid_certs);
+        // hyper_tls::HttpsConnector::new panics the same way if the platform library fails
+        let tls = builder.build().expect("Failed to initialize native TLS");
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        hyper_tls::HttpsConnector::from((http, tls.into()))
+    }
+}
+
+#[cfg(feature = "rustls")]
+mod verifiers {
+    use super::pin_matches;
+    use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+    use rustls::{Certificate, Error, ServerName};
+    use std::time::SystemTime;
+
+    /// Accepts every certificate, see `TlsConfig::danger_accept_invalid_certs_for_dev_chains_only`
+    pub(super) struct AcceptAny;
+
+    impl ServerCertVerifier for AcceptAny {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: SystemTime,
+        ) -> Result<ServerCertVerified, Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    /// Verifies certificates as usual and then also requires them to be pinned
+    pub(super) struct Pinned {
+        pub(super) inner: WebPkiVerifier,
+        pub(super) pins: Vec<[u8; 32]>,
+    }
+
+    impl ServerCertVerifier for Pinned {
+        fn verify_server_cert(
+            &self,
+            end_entity: &Certificate,
+            intermediates: &[Certificate],
+            server_name: &ServerName,
+            scts: &mut dyn Iterator<Item = &[u8]>,
+            ocsp_response: &[u8],
+            now: SystemTime,
+        ) -> Result<ServerCertVerified, Error> {
+            if !pin_matches(&self.pins, &end_entity.0) {
+                return Err(Error::General(
+                    "Server certificate does not match any pinned certificate".to_string(),
+                ));
+            }
+            self.inner.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )
+        }
+    }
+}
+
+#[test]
+fn test_pem_certificates() {
+    let bundle = std::fs::read("test_files/internal_ca_bundle.pem").unwrap();
+    let certificates = pem_certificates(&bundle).unwrap();
+    assert_eq!(certificates.len(), 2);
+    // DER certificates are a SEQUENCE with a two byte length
+    assert!(certificates.iter().all(|der| der[0..2] == [0x30, 0x82]));
+
+    let config = TlsConfig::new().with_ca_pem(&bundle).unwrap();
+    assert_eq!(config.extra_roots, certificates);
+
+    assert!(pem_certificates(b"").is_err());
+    assert!(pem_certificates(b"-----BEGIN CERTIFICATE-----\nMIIB").is_err());
+    assert!(
+        pem_certificates(b"-----BEGIN CERTIFICATE-----\n!!\n-----END CERTIFICATE-----").is_err()
+    );
+    assert!(TlsConfig::new()
+        .with_ca_pem(b"-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----")
+        .is_err());
+}
+
+#[cfg(feature = "rustls")]
+#[test]
+fn test_pin_matches() {
+    use clarity::utils::hex_str_to_bytes;
+
+    let bundle = std::fs::read("test_files/internal_ca_bundle.pem").unwrap();
+    let ca = &pem_certificates(&bundle).unwrap()[0];
+    // from openssl x509 -noout -fingerprint -sha256
+    let fingerprint: [u8; 32] =
+        hex_str_to_bytes("0xf9bad9b9c8f4d41572fdc2455cb1984430b3d3192b38b6dbd3e21f2cbdf0bab8")
+            .unwrap()
+            .try_into()
+            .unwrap();
+    assert!(pin_matches(&[[0; 32], fingerprint], ca));
+    assert!(!pin_matches(&[[0; 32]], ca));
+    assert!(!pin_matches(&[], ca));
+}
+
+#[tokio::test]
+async fn test_tls_client_construction() {
+    use crate::client::Web3;
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let node = MockNode::start(|method, _| match method {
+        "net_version" => Ok(json!("1")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let bundle = std::fs::read("test_files/internal_ca_bundle.pem").unwrap();
+    #[allow(unused_mut)]
+    let mut configs = vec![
+        TlsConfig::new(),
+        TlsConfig::new().with_ca_pem(&bundle).unwrap(),
+        TlsConfig::new().danger_accept_invalid_certs_for_dev_chains_only(),
+    ];
+    #[cfg(feature = "rustls")]
+    configs.push(TlsConfig::new().with_pinned_certificate([7; 32]));
+    let count = configs.len();
+    // whichever backend is compiled in, every configuration builds and still speaks http
+    for config in configs {
+        let web3 = Web3::builder(&node.url).tls(config).build();
+        assert_eq!(web3.net_version().await.unwrap(), 1);
+    }
+    assert_eq!(node.calls("net_version"), count);
+}
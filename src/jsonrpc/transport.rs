@@ -0,0 +1,248 @@
+//! Sending the HTTP POST of a JSON-RPC request. Native targets use hyper with the TLS
+//! backend web30 was built with, while on wasm32 in the browser requests go through the
+//! fetch API of the window or worker, which also takes care of TLS.
+use crate::jsonrpc::error::Web3Error;
+use hyper::body::Bytes;
+use hyper::HeaderMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::jsonrpc::tls::{HttpsConnector, TlsConfig};
+
+/// The status, Retry-After header in seconds and body of an HTTP response
+pub(crate) type HttpResponse = (u16, Option<Duration>, Bytes);
+
+/// The response future of a `Transport`
+pub(crate) type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<HttpResponse, Web3Error>> + Send + 'a>>;
+
+/// Sends a serialized JSON-RPC request to a url
+pub(crate) trait Transport: Send + Sync {
+    fn post<'a>(&'a self, url: &'a str, headers: HeaderMap, body: Vec<u8>) -> TransportFuture<'a>;
+}
+
+/// Parses a Retry-After header, the HTTP date form is rare enough among JSON-RPC providers
+/// to be ignored
+pub(crate) fn parse_retry_after(value: Option<&str>) -> Option<Duration> {
+    value
+        .and_then(|v| v.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// The transport of a new `HttpClient`
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(HyperTransport::new(&TlsConfig::default()))
+}
+
+/// The transport of a new `HttpClient`
+#[cfg(all(target_arch = "wasm32", not(test)))]
+pub(crate) fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(FetchTransport)
+}
+
+/// Tests run under node, which has no window to fetch with, and talk to mock nodes anyway
+#[cfg(all(target_arch = "wasm32", test))]
+pub(crate) fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(crate::jsonrpc::mock::MockTransport)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct HyperTransport {
+    client: hyper::Client<HttpsConnector>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HyperTransport {
+    pub(crate) fn new(tls: &TlsConfig) -> Self {
+        HyperTransport {
+            client: hyper::Client::builder().build(tls.connector()),
+        }
+    }
+
+    async fn aggregate_bytes(
+        &self,
+        request: hyper::Request<hyper::Body>,
+    ) -> Result<HttpResponse, Web3Error> {
+        use crate::mem::get_buffer_size;
+        use hyper::body::HttpBody;
+        use hyper::header;
+
+        let request_size_limit = get_buffer_size();
+        let res = self.client.request(request).await?;
+        let status = res.status().as_u16();
+        let retry_after = parse_retry_after(
+            res.headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok()),
+        );
+
+        trace!("response headers {:?}", res.headers());
+        trace!("using buffer size of {}", request_size_limit);
+
+        let response_size = res.size_hint().lower() as usize;
+
+        if response_size > request_size_limit {
+            return Err(Web3Error::BadResponse(format!(
+                "Size Limit {} and Response size {} Web3 Error",
+                request_size_limit, response_size
+            )));
+        }
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await?;
+        Ok((status, retry_after, bytes))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for HyperTransport {
+    fn post<'a>(&'a self, url: &'a str, headers: HeaderMap, body: Vec<u8>) -> TransportFuture<'a> {
+        let mut request = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri(url)
+            .body(body.into())
+            .expect("Expected json body");
+        *request.headers_mut() = headers;
+        Box::pin(self.aggregate_bytes(request))
+    }
+}
+
+/// Sends requests with the fetch API of the browser
+#[cfg(target_arch = "wasm32")]
+#[cfg_attr(test, allow(dead_code))]
+pub(crate) struct FetchTransport;
+
+#[cfg(target_arch = "wasm32")]
+impl Transport for FetchTransport {
+    fn post<'a>(&'a self, url: &'a str, headers: HeaderMap, body: Vec<u8>) -> TransportFuture<'a> {
+        // browser futures are not Send, but wasm32 in the browser has only the one thread
+        Box::pin(send_wrapper::SendWrapper::new(fetch(url, headers, body)))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn fetch(url: &str, headers: HeaderMap, body: Vec<u8>) -> Result<HttpResponse, Web3Error> {
+    use js_sys::{JsString, Uint8Array};
+    use wasm_bindgen::{JsCast, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{Request, RequestInit, Response, Window, WorkerGlobalScope};
+
+    // the browser reports network failures, CORS rejections and blocked mixed content
+    // alike as a TypeError without further details
+    let failed = |e: JsValue| {
+        let message = e
+            .dyn_ref::<js_sys::Error>()
+            .map(|e| String::from(e.message()))
+            .or_else(|| e.dyn_ref::<JsString>().map(String::from))
+            .unwrap_or_else(|| format!("{:?}", e));
+        Web3Error::BadResponse(format!("fetch of {} failed: {}", url, message))
+    };
+
+    let init = RequestInit::new();
+    init.set_method("POST");
+    init.set_body(&Uint8Array::from(body.as_slice()));
+    let request = Request::new_with_str_and_init(url, &init).map_err(failed)?;
+    for (name, value) in headers.iter() {
+        // browsers silently refuse some headers such as User-Agent, which they set themselves
+        if let Ok(value) = value.to_str() {
+            request
+                .headers()
+                .set(name.as_str(), value)
+                .map_err(failed)?;
+        }
+    }
+
+    let global = js_sys::global();
+    let promise = match global.dyn_ref::<Window>() {
+        Some(window) => window.fetch_with_request(&request),
+        None => global
+            .unchecked_into::<WorkerGlobalScope>()
+            .fetch_with_request(&request),
+    };
+    let response: Response = JsFuture::from(promise)
+        .await
+        .map_err(failed)?
+        .dyn_into()
+        .map_err(failed)?;
+    let status = response.status();
+    let retry_after = parse_retry_after(
+        response
+            .headers()
+            .get("retry-after")
+            .ok()
+            .flatten()
+            .as_deref(),
+    );
+    let buffer = JsFuture::from(response.array_buffer().map_err(failed)?)
+        .await
+        .map_err(failed)?;
+    Ok((
+        status,
+        retry_after,
+        Uint8Array::new(&buffer).to_vec().into(),
+    ))
+}
+
+#[test]
+fn test_parse_retry_after() {
+    assert_eq!(parse_retry_after(Some("7")), Some(Duration::from_secs(7)));
+    assert_eq!(parse_retry_after(Some(" 0 ")), Some(Duration::from_secs(0)));
+    assert_eq!(
+        parse_retry_after(Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+        None
+    );
+    assert_eq!(parse_retry_after(None), None);
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+async fn test_mocked_transport() {
+    use crate::client::Web3;
+    use crate::jsonrpc::client::HttpClient;
+    use clarity::Uint256;
+    use hyper::header::CONTENT_TYPE;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+
+    /// Answers every request with a canned result, as the browser transport is swapped in
+    struct Canned {
+        requests: Mutex<Vec<(String, HeaderMap, Value)>>,
+    }
+
+    impl Transport for Canned {
+        fn post<'a>(
+            &'a self,
+            url: &'a str,
+            headers: HeaderMap,
+            body: Vec<u8>,
+        ) -> TransportFuture<'a> {
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let response = json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x2a"});
+            self.requests
+                .lock()
+                .unwrap()
+                .push((url.to_string(), headers, request));
+            Box::pin(async move { Ok((200, None, serde_json::to_vec(&response).unwrap().into())) })
+        }
+    }
+
+    let transport = Arc::new(Canned {
+        requests: Mutex::new(Vec::new()),
+    });
+    let client = HttpClient::new("http://node.invalid").with_transport(transport.clone());
+    let web3 = Web3::with_client("http://node.invalid", client, Duration::from_secs(5));
+    assert_eq!(
+        web3.eth_block_number().await.unwrap(),
+        Uint256::from_u64(42)
+    );
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    let (url, headers, request) = &requests[0];
+    assert_eq!(url, "http://node.invalid");
+    assert_eq!(headers[CONTENT_TYPE], "application/json");
+    assert_eq!(request["method"], "eth_blockNumber");
+}
@@ -0,0 +1,157 @@
+//! Keccak256 hashing and checks for the signature strings that get hashed into event
+//! topics and method ids, where a stray space or parameter name silently changes the hash
+use crate::jsonrpc::error::Web3Error;
+use clarity::abi::derive_signature;
+use sha3::{Digest, Keccak256};
+
+/// The keccak256 hash of `data`, as used throughout Ethereum. This is the original Keccak
+/// padding and not the standardized SHA3-256.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Splits the arguments of a signature on the commas that are not inside a tuple
+pub(crate) fn split_args(args: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                res.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    res.push(&args[start..]);
+    res
+}
+
+/// Canonicalizes a single type, `uint` and `int` are aliases for their 256 bit versions
+fn canonical_type(ty: &str) -> Option<String> {
+    if let Some(inner) = ty.strip_prefix('(') {
+        let end = inner.rfind(')')?;
+        let args: Option<Vec<String>> = split_args(&inner[..end])
+            .into_iter()
+            .map(canonical_arg)
+            .collect();
+        return Some(format!("({}){}", args?.join(","), &inner[end + 1..]));
+    }
+    let (base, suffix) = match ty.find('[') {
+        Some(i) => ty.split_at(i),
+        None => (ty, ""),
+    };
+    let base = match base {
+        "uint" => "uint256",
+        "int" => "int256",
+        base => base,
+    };
+    Some(format!("{}{}", base, suffix))
+}
+
+/// Canonicalizes an argument such as "address indexed from" to just its type
+pub(crate) fn canonical_arg(arg: &str) -> Option<String> {
+    let arg = arg.trim();
+    let ty = if arg.starts_with('(') {
+        // the type of a tuple runs to its closing parenthesis and any array suffix
+        let mut depth = 0;
+        let mut end = arg.len();
+        for (i, c) in arg.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                c if depth == 0 && c.is_whitespace() => {
+                    end = i;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        &arg[..end]
+    } else {
+        arg.split_whitespace().next()?
+    };
+    canonical_type(ty)
+}
+
+/// Returns the canonical form of a Solidity style event or function signature, with
+/// parameter names, `indexed` keywords and whitespace removed and type aliases expanded, so
+/// "Transfer(address indexed from, address indexed to, uint value)" becomes
+/// "Transfer(address,address,uint256)"
+pub fn canonical_signature(signature: &str) -> Result<String, Web3Error> {
+    let bad = || Web3Error::BadInput(format!("{:?} is not a valid signature", signature));
+    let signature = signature.trim();
+    let (name, args) = signature.split_once('(').ok_or_else(bad)?;
+    let args = args.strip_suffix(')').ok_or_else(bad)?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(bad());
+    }
+    let args = if args.trim().is_empty() {
+        Vec::new()
+    } else {
+        split_args(args)
+            .into_iter()
+            .map(canonical_arg)
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(bad)?
+    };
+    Ok(format!("{}({})", name, args.join(",")))
+}
+
+/// Checks that `signature` is already canonical so that `derive_signature(signature)` gives
+/// the topic the event is actually emitted with, returning that topic. Otherwise the error
+/// includes the canonical signature that should have been used, for example spaces after
+/// commas, parameter names and `uint` instead of `uint256` all produce the wrong topic.
+pub fn check_event_signature(signature: &str) -> Result<[u8; 32], Web3Error> {
+    let canonical = canonical_signature(signature)?;
+    let expected = keccak256(canonical.as_bytes());
+    let derived = derive_signature(signature)?;
+    if derived != expected {
+        return Err(Web3Error::BadInput(format!(
+            "Event signature {:?} does not hash to the topic of {:?}, use the canonical form",
+            signature, canonical
+        )));
+    }
+    Ok(expected)
+}
+
+#[test]
+fn test_keccak256() {
+    use clarity::utils::bytes_to_hex_str;
+
+    assert_eq!(
+        bytes_to_hex_str(&keccak256(b"")),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+    assert_eq!(
+        keccak256(b"Transfer(address,address,uint256)"),
+        derive_signature("Transfer(address,address,uint256)").unwrap()
+    );
+}
+
+#[test]
+fn test_check_event_signature() {
+    assert_eq!(
+        canonical_signature("Transfer(address indexed from, address indexed to, uint value)")
+            .unwrap(),
+        "Transfer(address,address,uint256)"
+    );
+    assert_eq!(
+        canonical_signature("Swap((address token, uint[] amounts) params, int[2] deltas)").unwrap(),
+        "Swap((address,uint256[]),int256[2])"
+    );
+    assert_eq!(canonical_signature("Ping()").unwrap(), "Ping()");
+    assert!(canonical_signature("Transfer").is_err());
+
+    assert!(check_event_signature("Transfer(address,address,uint256)").is_ok());
+    match check_event_signature("Transfer(address, address, uint)") {
+        Err(Web3Error::BadInput(msg)) => {
+            assert!(msg.contains("Transfer(address,address,uint256)"))
+        }
+        res => panic!("Expected BadInput, got {:?}", res),
+    }
+}
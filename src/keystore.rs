@@ -0,0 +1,308 @@
+//! Loading and saving private keys in the Web3 Secret Storage format, the encrypted
+//! keystore JSON files used by geth and most wallets
+//!
+//! See more: https://ethereum.org/en/developers/docs/data-structures-and-encoding/web3-secret-storage/
+use crate::hex::data_to_hex;
+use crate::jsonrpc::error::Web3Error;
+use aes::Aes128;
+use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
+use clarity::PrivateKey;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+/// Scrypt cost parameters for `save_keystore`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScryptParams {
+    /// log2 of the CPU and memory cost `n`
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl ScryptParams {
+    /// The parameters geth uses by default, taking around a second and 256MB to decrypt
+    pub const STANDARD: ScryptParams = ScryptParams {
+        log_n: 18,
+        r: 8,
+        p: 1,
+    };
+    /// The parameters geth uses with `--lightkdf`, for constrained devices
+    pub const LIGHT: ScryptParams = ScryptParams {
+        log_n: 12,
+        r: 8,
+        p: 6,
+    };
+}
+
+impl Default for ScryptParams {
+    fn default() -> Self {
+        ScryptParams::STANDARD
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Keystore {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    crypto: KeystoreCrypto,
+    id: String,
+    version: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KeystoreCrypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: String,
+    kdf: String,
+    kdfparams: KdfParams,
+    mac: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CipherParams {
+    iv: String,
+}
+
+/// The parameters of either KDF, scrypt uses n, r and p while pbkdf2 uses c and prf
+#[derive(Debug, Serialize, Deserialize)]
+struct KdfParams {
+    dklen: usize,
+    salt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    r: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    c: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prf: Option<String>,
+}
+
+fn bad(msg: &str) -> Web3Error {
+    Web3Error::BadInput(format!("Invalid keystore: {}", msg))
+}
+
+fn parse_hex(hex: &str, field: &str) -> Result<Vec<u8>, Web3Error> {
+    hex_str_to_bytes(hex).map_err(|_| bad(&format!("{} is not hex", field)))
+}
+
+/// The MAC is the keccak256 of the second 16 bytes of the derived key and the ciphertext
+fn keystore_mac(derived_key: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let mut hasher = Keccak256::new();
+    hasher.update(&derived_key[16..32]);
+    hasher.update(ciphertext);
+    hasher.finalize().to_vec()
+}
+
+fn derive_key(password: &str, params: &KdfParams, kdf: &str) -> Result<Vec<u8>, Web3Error> {
+    if params.dklen < 32 {
+        return Err(bad("dklen is less than 32"));
+    }
+    let salt = parse_hex(&params.salt, "salt")?;
+    let mut derived_key = vec![0u8; params.dklen];
+    match kdf {
+        "scrypt" => {
+            let (n, r, p) = match (params.n, params.r, params.p) {
+                (Some(n), Some(r), Some(p)) if n.is_power_of_two() && n > 1 => (n, r, p),
+                _ => return Err(bad("scrypt needs n as a power of two, r and p")),
+            };
+            let params = scrypt::Params::new(n.trailing_zeros() as u8, r, p, params.dklen)
+                .map_err(|e| bad(&format!("scrypt params {}", e)))?;
+            scrypt::scrypt(password.as_bytes(), &salt, &params, &mut derived_key)
+                .map_err(|e| bad(&format!("scrypt output {}", e)))?;
+        }
+        "pbkdf2" => {
+            let c = match (params.c, params.prf.as_deref()) {
+                (Some(c), Some("hmac-sha256")) => c,
+                _ => return Err(bad("pbkdf2 needs c and the hmac-sha256 prf")),
+            };
+            pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, c, &mut derived_key);
+        }
+        kdf => return Err(bad(&format!("unsupported kdf {}", kdf))),
+    }
+    Ok(derived_key)
+}
+
+/// Decrypts a private key from keystore JSON, supporting the scrypt and pbkdf2 KDFs. A wrong
+/// password returns `Web3Error::KeystoreMacMismatch` rather than a different key.
+pub fn load_keystore(json: &str, password: &str) -> Result<PrivateKey, Web3Error> {
+    let keystore: Keystore = match serde_json::from_str(json) {
+        Ok(keystore) => keystore,
+        Err(e) => return Err(bad(&e.to_string())),
+    };
+    if keystore.version != 3 {
+        return Err(bad(&format!("unsupported version {}", keystore.version)));
+    }
+    let crypto = keystore.crypto;
+    if crypto.cipher != "aes-128-ctr" {
+        return Err(bad(&format!("unsupported cipher {}", crypto.cipher)));
+    }
+
+    let derived_key = derive_key(password, &crypto.kdfparams, &crypto.kdf)?;
+    let mut ciphertext = parse_hex(&crypto.ciphertext, "ciphertext")?;
+    if keystore_mac(&derived_key, &ciphertext) != parse_hex(&crypto.mac, "mac")? {
+        return Err(Web3Error::KeystoreMacMismatch);
+    }
+
+    let iv = parse_hex(&crypto.cipherparams.iv, "iv")?;
+    let mut cipher = Aes128Ctr::new_from_slices(&derived_key[..16], &iv)
+        .map_err(|_| bad("iv is not 16 bytes"))?;
+    cipher.apply_keystream(&mut ciphertext);
+    if ciphertext.len() != 32 {
+        return Err(bad("key is not 32 bytes"));
+    }
+    data_to_hex(&ciphertext)
+        .parse()
+        .map_err(|_| bad("key is not a valid secp256k1 key"))
+}
+
+/// Encrypts a private key into keystore JSON using scrypt with `params` and a random
+/// salt and iv, see `ScryptParams::STANDARD`
+pub fn save_keystore(
+    key: &PrivateKey,
+    password: &str,
+    params: ScryptParams,
+) -> Result<String, Web3Error> {
+    let salt: [u8; 32] = rand::random();
+    let iv: [u8; 16] = rand::random();
+    let kdfparams = KdfParams {
+        dklen: 32,
+        salt: bytes_to_hex_str(&salt),
+        n: Some(1u64 << params.log_n),
+        r: Some(params.r),
+        p: Some(params.p),
+        c: None,
+        prf: None,
+    };
+    let derived_key = derive_key(password, &kdfparams, "scrypt")?;
+
+    let mut ciphertext = key.to_bytes().to_vec();
+    let mut cipher = Aes128Ctr::new_from_slices(&derived_key[..16], &iv)
+        .map_err(|_| bad("iv is not 16 bytes"))?;
+    cipher.apply_keystream(&mut ciphertext);
+    let mac = keystore_mac(&derived_key, &ciphertext);
+
+    // a random version 4 uuid
+    let mut id: [u8; 16] = rand::random();
+    id[6] = (id[6] & 0x0f) | 0x40;
+    id[8] = (id[8] & 0x3f) | 0x80;
+    let id = bytes_to_hex_str(&id);
+    let keystore = Keystore {
+        address: Some(
+            key.to_address()
+                .to_string()
+                .to_lowercase()
+                .trim_start_matches("0x")
+                .to_string(),
+        ),
+        crypto: KeystoreCrypto {
+            cipher: "aes-128-ctr".to_string(),
+            cipherparams: CipherParams {
+                iv: bytes_to_hex_str(&iv),
+            },
+            ciphertext: bytes_to_hex_str(&ciphertext),
+            kdf: "scrypt".to_string(),
+            kdfparams,
+            mac: bytes_to_hex_str(&mac),
+        },
+        id: format!(
+            "{}-{}-{}-{}-{}",
+            &id[0..8],
+            &id[8..12],
+            &id[12..16],
+            &id[16..20],
+            &id[20..32]
+        ),
+        version: 3,
+    };
+    Ok(serde_json::to_string(&keystore)?)
+}
+
+#[cfg(test)]
+const TEST_KEY: &str = "0x7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+#[test]
+fn test_keystore_pbkdf2_vector() {
+    // from the Web3 Secret Storage definition
+    let json = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": {"iv": "6087dab2f9fdbbfaddc31a909735c1e6"},
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+    let key = load_keystore(json, "testpassword").unwrap();
+    assert_eq!(
+        key.to_address(),
+        TEST_KEY.parse::<PrivateKey>().unwrap().to_address()
+    );
+    assert!(matches!(
+        load_keystore(json, "wrongpassword"),
+        Err(Web3Error::KeystoreMacMismatch)
+    ));
+}
+
+#[test]
+fn test_keystore_scrypt_vector() {
+    // from the Web3 Secret Storage definition
+    let json = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": {"iv": "83dbcc02d8ccb40e466191a123791e0e"},
+            "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 262144,
+                "p": 8,
+                "r": 1,
+                "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+            },
+            "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+    let key = load_keystore(json, "testpassword").unwrap();
+    assert_eq!(
+        key.to_address(),
+        TEST_KEY.parse::<PrivateKey>().unwrap().to_address()
+    );
+}
+
+#[test]
+fn test_keystore_round_trip() {
+    let key: PrivateKey = TEST_KEY.parse().unwrap();
+    // cheap parameters to keep the test fast
+    let params = ScryptParams {
+        log_n: 4,
+        r: 8,
+        p: 1,
+    };
+    let json = save_keystore(&key, "hunter2", params).unwrap();
+    assert_eq!(
+        load_keystore(&json, "hunter2").unwrap().to_address(),
+        key.to_address()
+    );
+    assert!(matches!(
+        load_keystore(&json, "hunter3"),
+        Err(Web3Error::KeystoreMacMismatch)
+    ));
+    assert!(load_keystore("{}", "hunter2").is_err());
+}
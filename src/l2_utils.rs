@@ -0,0 +1,142 @@
+//! Fee estimation for layer 2 rollups, where the cost of a transaction includes a fee
+//! for publishing its data to layer 1 on top of the normal execution fee
+use crate::jsonrpc::error::Web3Error;
+use crate::{client::Web3, types::TransactionRequest};
+use clarity::abi::{encode_call, Token};
+use clarity::{Address, Transaction, Uint256};
+
+lazy_static! {
+    /// The GasPriceOracle predeploy, present at the same address on every OP stack chain
+    pub static ref GAS_PRICE_ORACLE_ADDRESS: Address =
+        Address::parse_and_validate("0x420000000000000000000000000000000000000F").unwrap();
+}
+
+/// Chain ids of OP stack chains, whose transactions pay an L1 data fee that is not
+/// included in the gas used. Optimism, Base, Zora, Mode and their testnets.
+pub const OP_STACK_CHAIN_IDS: [u64; 7] = [10, 8453, 7777777, 34443, 11155420, 84532, 999999999];
+
+/// Returns true if `chain_id` is a known OP stack chain
+pub fn is_op_stack_chain(chain_id: Uint256) -> bool {
+    OP_STACK_CHAIN_IDS
+        .iter()
+        .any(|id| Uint256::from_u64(*id) == chain_id)
+}
+
+impl Web3 {
+    /// Estimates the total fee in wei of sending `tx`, including the L1 data fee on OP stack
+    /// chains which is computed by the GasPriceOracle predeploy. On other chains, including
+    /// Arbitrum which already accounts for L1 costs in its gas estimates, this is the gas
+    /// limit multiplied by the gas price. Any of the gas, gas price and nonce fields of `tx`
+    /// that are not set are filled in from the node.
+    pub async fn estimate_total_l2_fee(
+        &self,
+        tx: TransactionRequest,
+    ) -> Result<Uint256, Web3Error> {
+        let gas_limit = match tx.gas {
+            Some(gas) => gas.0,
+            None => self.eth_estimate_gas(tx.clone()).await?,
+        };
+        let gas_price = match tx.gas_price {
+            Some(price) => price.0,
+            None => self.eth_gas_price().await?,
+        };
+        let execution_fee = match gas_limit.checked_mul(gas_price) {
+            Some(fee) => fee,
+            None => return Err(Web3Error::BadInput("Fee overflows a Uint256".to_string())),
+        };
+
+        let chain_id = self.eth_chainid().await?;
+        if !chain_id.map_or(false, is_op_stack_chain) {
+            return Ok(execution_fee);
+        }
+
+        let nonce = match (tx.nonce, tx.from) {
+            (Some(nonce), _) => nonce.0,
+            (None, Some(from)) => self.eth_get_transaction_count(from).await?,
+            (None, None) => Uint256::from_u64(0),
+        };
+        // the oracle expects the unsigned transaction and adds the signature overhead itself
+        let transaction = Transaction {
+            to: tx.to,
+            nonce,
+            gas_price,
+            gas_limit,
+            value: tx.value.map(|v| v.0).unwrap_or_default(),
+            data: tx.data.map(|d| d.0).unwrap_or_default(),
+            signature: None,
+        };
+        let l1_fee = self.get_l1_fee(transaction).await?;
+        match execution_fee.checked_add(l1_fee) {
+            Some(fee) => Ok(fee),
+            None => Err(Web3Error::BadInput("Fee overflows a Uint256".to_string())),
+        }
+    }
+
+    /// Gets the L1 data fee in wei for `transaction` from the GasPriceOracle predeploy,
+    /// only available on OP stack chains
+    async fn get_l1_fee(&self, transaction: Transaction) -> Result<Uint256, Web3Error> {
+        let bytes = match transaction.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(Web3Error::BadInput(format!("{:?}", e))),
+        };
+        let payload = encode_call("getL1Fee(bytes)", &[Token::UnboundedBytes(bytes)])?;
+        let res = self
+            .eth_call(TransactionRequest::call(*GAS_PRICE_ORACLE_ADDRESS, payload))
+            .await?;
+        match res.0.get(0..32).and_then(Uint256::from_bytes_be) {
+            Some(fee) => Ok(fee),
+            None => Err(Web3Error::ContractCallError(
+                "Bad response from GasPriceOracle getL1Fee".to_string(),
+            )),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_estimate_total_l2_fee() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let chain_id = Arc::new(AtomicU64::new(8453));
+    let node_chain_id = chain_id.clone();
+    let node = MockNode::start(move |method, _| match method {
+        "eth_chainId" => Ok(json!(format!(
+            "{:#x}",
+            node_chain_id.load(Ordering::SeqCst)
+        ))),
+        "eth_syncing" => Ok(json!(false)),
+        // 1000 wei of L1 fee
+        "eth_call" => Ok(json!(
+            "0x00000000000000000000000000000000000000000000000000000000000003e8"
+        )),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let tx = TransactionRequest::builder()
+        .to("0x3535353535353535353535353535353535353535"
+            .parse()
+            .unwrap())
+        .gas(Uint256::from_u64(21000))
+        .gas_price(Uint256::from_u64(10))
+        .value(Uint256::from_u64(1))
+        .nonce(Uint256::from_u64(0))
+        .build()
+        .unwrap();
+
+    let fee = web3.estimate_total_l2_fee(tx.clone()).await.unwrap();
+    assert_eq!(fee, Uint256::from_u64(21000 * 10 + 1000));
+    let call = &node.params("eth_call")[0][0];
+    assert_eq!(
+        call["to"].as_str().unwrap().to_lowercase(),
+        "0x420000000000000000000000000000000000000f"
+    );
+
+    // on other chains only the execution fee is paid
+    chain_id.store(1, Ordering::SeqCst);
+    let fee = web3.estimate_total_l2_fee(tx).await.unwrap();
+    assert_eq!(fee, Uint256::from_u64(21000 * 10));
+    assert_eq!(node.calls("eth_call"), 1);
+}
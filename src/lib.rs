@@ -8,13 +8,101 @@ extern crate log;
 #[macro_use]
 extern crate lazy_static;
 
+mod address_activity;
+mod address_watcher;
 pub mod amm;
+mod balanced;
+mod block_range;
+mod block_stream;
+mod block_time;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod bloom;
+mod cache;
+pub mod chainlink;
+pub mod checksum;
 pub mod client;
+pub mod contract;
+pub mod create_address;
+mod debug_utils;
+pub mod dex_utils;
+pub mod eip712;
+#[cfg(feature = "engine")]
+pub mod engine_api;
+pub mod erc1155_utils;
+pub mod erc165_utils;
+mod erc20_snapshot;
 mod erc20_utils;
 pub mod eth_wrapping;
 mod event_utils;
+mod filters;
+mod gas_price;
+mod hash;
+pub mod hex;
 pub mod jsonrpc;
+mod keccak;
+#[cfg(feature = "keystore")]
+pub mod keystore;
+pub mod l2_utils;
+mod log_watcher;
+#[cfg(not(target_arch = "wasm32"))]
 mod mem;
+mod metrics;
+pub mod multicall;
+mod offline_tx;
+pub mod personal_sign;
+mod private_tx;
+pub mod proxy_utils;
+pub mod quantity;
+pub mod raw_transaction;
+mod revert_utils;
+mod signer;
+mod testing_utils;
+mod time;
+mod trace_utils;
+mod tx_summary;
+mod txpool_utils;
 pub mod types;
+pub mod units;
+pub mod user_operation;
 
-pub use event_utils::address_to_event;
+pub use address_activity::{AddressActivity, GasReport, TransactionGas};
+pub use address_watcher::{AddressChange, AddressWatcher};
+pub use balanced::{BackendStats, BalancedClient, MAX_CONSECUTIVE_FAILURES, UNHEALTHY_BACKOFF};
+pub use block_range::{BlockRangeStream, BLOCK_FETCH_RETRIES};
+pub use block_stream::{BlockStream, BlockStreamItem, MAX_REORG_DEPTH};
+pub use block_time::{BLOCK_TIME_CACHE_TTL, BLOCK_TIME_SAMPLE_BLOCKS};
+pub use bloom::{Bloom, BLOOM_BYTES};
+pub use cache::CacheConfig;
+pub use debug_utils::find_revert_in_trace;
+pub use erc20_snapshot::{BalanceMismatch, Erc20Snapshot, SnapshotProgress, SNAPSHOT_SAMPLE_SIZE};
+pub use event_utils::{
+    address_to_event, decode_events, indexed_topic_for, indexed_topics_for, normalize_logs,
+    EVENT_POLL_INTERVAL,
+};
+pub use filters::InstalledFilter;
+pub use gas_price::{
+    blob_base_fee, BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN, BLOB_BASE_FEE_UPDATE_FRACTION_PRAGUE,
+    MIN_BASE_FEE_PER_BLOB_GAS,
+};
+pub use gas_price::{Capped, FeeHistoryPercentile, Fixed, NodeSuggested, ScaledNodeSuggested};
+pub use gas_price::{GasFees, GasFeesFuture, GasPriceStrategy, GasStrategy};
+pub use hash::{BlockHash, TxHash, H256};
+pub use keccak::{canonical_signature, check_event_signature, keccak256};
+pub use log_watcher::{
+    LogCheckpoint, LogWatcher, BLOOM_PREFILTER_MAX_RANGE, LOG_WATCHER_POLL_INTERVAL, MAX_LOG_RANGE,
+};
+pub use metrics::LATENCY_BUCKETS;
+pub use metrics::{MethodMetrics, MetricsCollector, MetricsSink, MetricsSnapshot, Outcome};
+pub use offline_tx::{build_transaction, TransactionFees, TransactionParams, UnsignedTx};
+pub use private_tx::{
+    flashbots_body_hook, flashbots_signature, PrivacyPreferences, PrivateTxPreferences,
+};
+pub use private_tx::{BundleOptions, BundleSimulation, BundleStats, BundleTxResult};
+pub use private_tx::{DEFAULT_PRIVATE_TX_MAX_BLOCKS, FLASHBOTS_SIGNATURE_HEADER};
+pub use revert_utils::{decode_revert_data, RevertOutcome, RevertReason};
+pub use revert_utils::{ERROR_SELECTOR, PANIC_SELECTOR};
+pub use signer::{NodeSigner, SignedBytesFuture, TransactionSigner};
+pub use testing_utils::SnapshotGuard;
+pub use tx_summary::{decode_function_call, DecodedCall, DecodedEvent, TxStatus, TxSummary};
+pub use tx_summary::{KNOWN_EVENTS, KNOWN_FUNCTIONS};
@@ -0,0 +1,410 @@
+//! A resumable watcher for contract events that only emits logs once they are
+//! sufficiently confirmed, intended for daemons that process events continuously
+use crate::hash::TxHash;
+use crate::time::sleep as delay_for;
+use crate::types::{BlockId, Log};
+use crate::{client::Web3, jsonrpc::error::Web3Error};
+use clarity::abi::derive_signature;
+use clarity::{u256, Address, Uint256};
+use std::cmp::min;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// The maximum number of blocks requested in a single eth_getLogs call
+pub const MAX_LOG_RANGE: u64 = 1000;
+
+/// The default interval at which a `LogWatcher` polls for new blocks
+pub const LOG_WATCHER_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The largest range a `LogWatcher` with the bloom prefilter checks block headers for,
+/// larger ranges take fewer requests with a single eth_getLogs call
+pub const BLOOM_PREFILTER_MAX_RANGE: u64 = 16;
+
+/// Identifies a log independently of which scan returned it
+type LogKey = (Option<TxHash>, Option<String>);
+
+fn log_key(log: &Log) -> LogKey {
+    (log.transaction_hash, log.log_index.map(|i| i.to_string()))
+}
+
+/// Where a `LogWatcher` has got to, persist this and pass it to `LogWatcher::with_checkpoint`
+/// to resume after a restart without returning any log twice
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LogCheckpoint {
+    /// every log in earlier blocks has been returned
+    pub block: Uint256,
+    /// the index of the last log returned from `block`, None if none of its logs were
+    pub log_index: Option<Uint256>,
+}
+
+/// Yields the logs matching a set of contracts and events in chain order, sorted by
+/// `LogPosition` without duplicates, created with `Web3::log_watcher`. Only blocks at least
+/// `confirmations` deep are scanned, the stream never terminates and errors from the node
+/// are logged and retried after a delay.
+pub struct LogWatcher {
+    web3: Web3,
+    contracts: Vec<Address>,
+    topics: Vec<[u8; 32]>,
+    confirmations: Uint256,
+    poll_interval: Duration,
+    bloom_prefilter: bool,
+    /// the next block that has not been scanned yet
+    next_block: Uint256,
+    queue: VecDeque<Log>,
+    /// logs that have been queued, with the block they are in, used to drop duplicates
+    seen: HashMap<LogKey, Uint256>,
+    /// the block and log index of the last log returned by `next`
+    returned: Option<(Uint256, Uint256)>,
+    /// logs at or before this block and log index were returned before a restart
+    resume_after: Option<(Uint256, Uint256)>,
+}
+
+impl LogWatcher {
+    /// Sets how often the node is polled for new blocks, by default the poll interval of the
+    /// `Web3` or `LOG_WATCHER_POLL_INTERVAL`
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Checks the logs bloom of each block header before requesting the logs of up to
+    /// `BLOOM_PREFILTER_MAX_RANGE` blocks, skipping the eth_getLogs call when no block can
+    /// contain a watched event. This saves requests to providers that charge more for
+    /// eth_getLogs than for headers when watching rare events near the head of the chain.
+    pub fn with_bloom_prefilter(mut self, enabled: bool) -> Self {
+        self.bloom_prefilter = enabled;
+        self
+    }
+
+    /// Resumes from a checkpoint taken with `checkpoint` before a restart, scanning from its
+    /// block but skipping the logs of that block that were already returned
+    pub fn with_checkpoint(mut self, checkpoint: LogCheckpoint) -> Self {
+        self.next_block = checkpoint.block;
+        self.resume_after = checkpoint.log_index.map(|index| (checkpoint.block, index));
+        self
+    }
+
+    /// Returns a block number such that every log in earlier blocks has been returned by
+    /// `next`. Some but not all logs of the block itself may have been returned, use
+    /// `checkpoint` to resume after a restart without returning them again.
+    pub fn current_block(&self) -> Uint256 {
+        match self.queue.front().and_then(|log| log.block_number) {
+            Some(block) => block,
+            None => self.next_block,
+        }
+    }
+
+    /// Returns the position up to which logs have been returned by `next`, persist this and
+    /// pass it to `with_checkpoint` to resume after a restart
+    pub fn checkpoint(&self) -> LogCheckpoint {
+        let block = self.current_block();
+        let log_index = match self.returned.or(self.resume_after) {
+            Some((returned_block, index)) if returned_block == block => Some(index),
+            _ => None,
+        };
+        LogCheckpoint { block, log_index }
+    }
+
+    /// Returns true if the log was returned before the restart this watcher resumed from
+    fn returned_before_resume(&self, log: &Log) -> bool {
+        match (self.resume_after, log.block_number, log.log_index) {
+            (Some(checkpoint), Some(block), Some(index)) => (block, index) <= checkpoint,
+            _ => false,
+        }
+    }
+
+    /// Waits for and returns the next log
+    pub async fn next(&mut self) -> Log {
+        loop {
+            if let Some(log) = self.queue.pop_front() {
+                if let (Some(block), Some(index)) = (log.block_number, log.log_index) {
+                    self.returned = Some((block, index));
+                }
+                return log;
+            }
+            if let Err(e) = self.poll().await {
+                warn!("Log watcher failed to get logs {:?}", e);
+            }
+            if self.queue.is_empty() {
+                delay_for(self.poll_interval).await;
+            }
+        }
+    }
+
+    /// Scans the next confirmed block range, in batches of at most `MAX_LOG_RANGE` blocks
+    async fn poll(&mut self) -> Result<(), Web3Error> {
+        let head = self.web3.eth_block_number().await?;
+        let confirmed = match head.checked_sub(self.confirmations) {
+            Some(confirmed) => confirmed,
+            None => return Ok(()),
+        };
+        while self.next_block <= confirmed {
+            let end = min(
+                confirmed,
+                self.next_block
+                    .checked_add(Uint256::from_u64(MAX_LOG_RANGE - 1))
+                    .unwrap(),
+            );
+            if self.bloom_prefilter && !self.range_may_contain_logs(self.next_block, end).await? {
+                self.next_block = end.checked_add(u256!(1)).unwrap();
+                continue;
+            }
+            // all event signatures go in a single topic0 list so that any of them match
+            let topics = if self.topics.is_empty() {
+                Vec::new()
+            } else {
+                vec![self.topics.clone()]
+            };
+            let logs = self
+                .web3
+                .check_for_arbitrary_events(
+                    self.next_block,
+                    Some(end),
+                    self.contracts.clone(),
+                    topics,
+                )
+                .await?;
+
+            let checkpoint = self.current_block();
+            self.seen.retain(|_, block| *block >= checkpoint);
+            for log in logs {
+                if self.returned_before_resume(&log) {
+                    continue;
+                }
+                let block = log.block_number.unwrap_or(self.next_block);
+                if self.seen.insert(log_key(&log), block).is_none() {
+                    self.queue.push_back(log);
+                }
+            }
+            self.next_block = end.checked_add(u256!(1)).unwrap();
+        }
+        Ok(())
+    }
+
+    /// Returns false if the logs blooms prove that no block from `start` to `end` contains a
+    /// watched event, ranges larger than `BLOOM_PREFILTER_MAX_RANGE` are not checked
+    async fn range_may_contain_logs(
+        &self,
+        start: Uint256,
+        end: Uint256,
+    ) -> Result<bool, Web3Error> {
+        let len = end.checked_sub(start).unwrap_or_default();
+        if len >= Uint256::from_u64(BLOOM_PREFILTER_MAX_RANGE) {
+            return Ok(true);
+        }
+        let mut number = start;
+        while number <= end {
+            let header = self
+                .web3
+                .eth_get_concise_block_by_id(BlockId::Number(number))
+                .await?;
+            // a missing header or malformed bloom can not prove anything
+            match header.and_then(|header| header.bloom()) {
+                Some(bloom) if !bloom.may_contain(&self.contracts, &self.topics) => {}
+                _ => return Ok(true),
+            }
+            number = number.checked_add(u256!(1)).unwrap();
+        }
+        Ok(false)
+    }
+}
+
+impl Web3 {
+    /// Creates a `LogWatcher` for the given events emitted by any of `contracts`, starting at
+    /// `start_block` and only returning logs from blocks that are at least `confirmations`
+    /// blocks behind the head of the chain
+    pub fn log_watcher(
+        &self,
+        contracts: Vec<Address>,
+        events: Vec<&str>,
+        start_block: Uint256,
+        confirmations: u64,
+    ) -> Result<LogWatcher, Web3Error> {
+        let mut topics = Vec::new();
+        for event in events {
+            topics.push(derive_signature(event)?);
+        }
+        Ok(LogWatcher {
+            web3: self.clone(),
+            contracts,
+            topics,
+            confirmations: Uint256::from_u64(confirmations),
+            poll_interval: self.poll_interval_or(LOG_WATCHER_POLL_INTERVAL),
+            bloom_prefilter: false,
+            next_block: start_block,
+            queue: VecDeque::new(),
+            seen: HashMap::new(),
+            returned: None,
+            resume_after: None,
+        })
+    }
+}
+
+#[test]
+fn test_log_key() {
+    let mut log = Log {
+        transaction_hash: Some(u256!(1).into()),
+        log_index: Some(u256!(3)),
+        ..Default::default()
+    };
+    let key = log_key(&log);
+    log.block_number = Some(u256!(100));
+    assert_eq!(log_key(&log), key);
+    log.log_index = Some(u256!(4));
+    assert_ne!(log_key(&log), key);
+}
+
+#[tokio::test]
+async fn test_log_watcher_bloom_prefilter() {
+    use crate::bloom::Bloom;
+    use crate::event_utils::address_to_event;
+    use crate::jsonrpc::mock::{empty_block, MockNode};
+    use clarity::utils::bytes_to_hex_str;
+    use serde_json::json;
+
+    let contract: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let transfer = derive_signature("Transfer(address,address,uint256)").unwrap();
+    let mut bloom = Bloom::default();
+    bloom.accrue(&address_to_event(contract)[12..]);
+    bloom.accrue(&transfer);
+    let bloom = format!("0x{}", bytes_to_hex_str(&bloom.0));
+
+    let mut block = empty_block(0);
+    block["logsBloom"] = json!(format!("0x{}", "00".repeat(256)));
+    let node = MockNode::start(move |method, params| match method {
+        "eth_blockNumber" => Ok(json!("0x6e")),
+        // only block 105 has the event
+        "eth_getBlockByNumber" => {
+            let mut block = block.clone();
+            block["number"] = params[0].clone();
+            if params[0] == json!("0x69") {
+                block["logsBloom"] = json!(bloom.clone());
+            }
+            Ok(block)
+        }
+        "eth_getLogs" => Ok(json!([])),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let mut watcher = web3
+        .log_watcher(
+            vec![contract],
+            vec!["Transfer(address,address,uint256)"],
+            u256!(100),
+            0,
+        )
+        .unwrap()
+        .with_bloom_prefilter(true);
+
+    // blocks 100 to 104 can not contain the event
+    assert!(!watcher
+        .range_may_contain_logs(u256!(100), u256!(104))
+        .await
+        .unwrap());
+    assert!(watcher
+        .range_may_contain_logs(u256!(100), u256!(105))
+        .await
+        .unwrap());
+    // too large to check
+    assert!(watcher
+        .range_may_contain_logs(u256!(0), u256!(100))
+        .await
+        .unwrap());
+
+    watcher.poll().await.unwrap();
+    assert_eq!(node.calls("eth_getLogs"), 1);
+    assert_eq!(watcher.next_block, u256!(111));
+
+    // a poll of only empty blocks skips eth_getLogs
+    watcher.next_block = u256!(106);
+    watcher.poll().await.unwrap();
+    assert_eq!(node.calls("eth_getLogs"), 1);
+    assert_eq!(watcher.next_block, u256!(111));
+}
+
+#[tokio::test]
+async fn test_log_watcher_checkpoint() {
+    use crate::hex::word_to_hex;
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let log = |block: u64, index: u64| {
+        json!({
+            "address": "0x1111111111111111111111111111111111111111",
+            "blockNumber": format!("{:#x}", block),
+            "transactionHash": word_to_hex(Uint256::from_u64(block * 10 + index)),
+            "logIndex": format!("{:#x}", index),
+            "transactionIndex": "0x0",
+            "data": "0x",
+            "topics": []
+        })
+    };
+    let logs = json!([log(100, 0), log(100, 1), log(100, 2), log(105, 0)]);
+    let node = MockNode::start(move |method, _| match method {
+        "eth_blockNumber" => Ok(json!("0x6e")),
+        "eth_getLogs" => Ok(logs.clone()),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::builder(&node.url)
+        .poll_interval(Duration::from_millis(50))
+        .build();
+    let watcher = || web3.log_watcher(vec![], vec![], u256!(100), 0).unwrap();
+    let position = |log: Log| (log.block_number.unwrap(), log.log_index.unwrap());
+    assert_eq!(watcher().poll_interval, Duration::from_millis(50));
+
+    let mut first = watcher();
+    assert_eq!(first.checkpoint().block, u256!(100));
+    assert_eq!(first.checkpoint().log_index, None);
+    assert_eq!(position(first.next().await), (u256!(100), u256!(0)));
+    assert_eq!(position(first.next().await), (u256!(100), u256!(1)));
+    let checkpoint = first.checkpoint();
+    assert_eq!(
+        checkpoint,
+        LogCheckpoint {
+            block: u256!(100),
+            log_index: Some(u256!(1)),
+        }
+    );
+
+    // after a restart the logs of block 100 that were returned are skipped
+    let mut resumed = watcher().with_checkpoint(checkpoint);
+    assert_eq!(resumed.checkpoint(), checkpoint);
+    assert_eq!(position(resumed.next().await), (u256!(100), u256!(2)));
+    assert_eq!(position(resumed.next().await), (u256!(105), u256!(0)));
+    assert_eq!(
+        resumed.checkpoint(),
+        LogCheckpoint {
+            block: u256!(111),
+            log_index: None,
+        }
+    );
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_log_watcher() {
+    let web3 = Web3::new("https://eth.althea.net", Duration::from_secs(30));
+    // USDC transfers are frequent enough to show up in any range
+    let usdc = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+        .parse()
+        .unwrap();
+    let start = web3.eth_block_number().await.unwrap();
+    let start = start.checked_sub(u256!(20)).unwrap();
+    let mut watcher = web3
+        .log_watcher(
+            vec![usdc],
+            vec!["Transfer(address,address,uint256)"],
+            start,
+            6,
+        )
+        .unwrap();
+
+    let first = watcher.next().await;
+    let second = watcher.next().await;
+    assert!(first.block_number.unwrap() >= start);
+    assert!(first.block_number <= second.block_number);
+    assert_ne!(log_key(&first), log_key(&second));
+    assert!(watcher.current_block() >= first.block_number.unwrap());
+}
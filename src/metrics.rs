@@ -0,0 +1,181 @@
+//! Hooks for observing the JSON-RPC requests a `Web3` makes, see `Web3::set_metrics_sink`,
+//! along with `MetricsCollector` which keeps per method counters in memory for export
+use crate::jsonrpc::error::Web3Error;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// How a JSON-RPC request ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Success,
+    /// the node responded with a JSON-RPC error object, or rejected a rate limited request or
+    /// a query of pruned state
+    RpcError,
+    /// the request failed at the network level
+    TransportError,
+    Timeout,
+    /// the response could not be parsed
+    BadResponse,
+}
+
+impl Outcome {
+    pub(crate) fn of<T>(result: &Result<T, Web3Error>) -> Outcome {
+        match result {
+            Ok(_) => Outcome::Success,
+            Err(Web3Error::JsonRpcError { .. })
+            | Err(Web3Error::RateLimited { .. })
+            | Err(Web3Error::StateUnavailable { .. }) => Outcome::RpcError,
+            Err(Web3Error::TransportError(_)) => Outcome::TransportError,
+            Err(Web3Error::Timeout) => Outcome::Timeout,
+            Err(_) => Outcome::BadResponse,
+        }
+    }
+}
+
+/// Receives an observation for every JSON-RPC request made, implement this to export
+/// request counts and latencies to a metrics system
+pub trait MetricsSink: Debug + Send + Sync {
+    fn observe(&self, method: &str, duration: Duration, outcome: Outcome);
+}
+
+/// The upper bounds of the latency histogram buckets, requests slower than the last bound
+/// are counted in a final overflow bucket
+pub const LATENCY_BUCKETS: [Duration; 10] = [
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+#[derive(Debug, Default)]
+struct MethodCounters {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    timeouts: AtomicU64,
+    total_micros: AtomicU64,
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+}
+
+/// The metrics of a single method at the time of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MethodMetrics {
+    pub requests: u64,
+    /// requests that did not succeed, including timeouts
+    pub errors: u64,
+    pub timeouts: u64,
+    pub total_latency: Duration,
+    /// the number of requests at or below each bound of `LATENCY_BUCKETS`, not cumulative,
+    /// with the requests above the last bound in the final entry
+    pub latency_buckets: Vec<u64>,
+}
+
+/// A point in time copy of everything a `MetricsCollector` has observed, keyed by method
+pub type MetricsSnapshot = HashMap<String, MethodMetrics>;
+
+/// A `MetricsSink` that counts requests, errors and latencies per method using atomics
+#[derive(Debug, Default)]
+pub struct MetricsCollector {
+    methods: RwLock<HashMap<String, Arc<MethodCounters>>>,
+}
+
+impl MetricsCollector {
+    pub fn new() -> MetricsCollector {
+        MetricsCollector::default()
+    }
+
+    fn counters(&self, method: &str) -> Arc<MethodCounters> {
+        if let Some(counters) = self.methods.read().expect("metrics poisoned").get(method) {
+            return counters.clone();
+        }
+        self.methods
+            .write()
+            .expect("metrics poisoned")
+            .entry(method.to_string())
+            .or_default()
+            .clone()
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.methods
+            .read()
+            .expect("metrics poisoned")
+            .iter()
+            .map(|(method, c)| {
+                (
+                    method.clone(),
+                    MethodMetrics {
+                        requests: c.requests.load(Ordering::Relaxed),
+                        errors: c.errors.load(Ordering::Relaxed),
+                        timeouts: c.timeouts.load(Ordering::Relaxed),
+                        total_latency: Duration::from_micros(
+                            c.total_micros.load(Ordering::Relaxed),
+                        ),
+                        latency_buckets: c
+                            .buckets
+                            .iter()
+                            .map(|b| b.load(Ordering::Relaxed))
+                            .collect(),
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+impl MetricsSink for MetricsCollector {
+    fn observe(&self, method: &str, duration: Duration, outcome: Outcome) {
+        let counters = self.counters(method);
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        if outcome != Outcome::Success {
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if outcome == Outcome::Timeout {
+            counters.timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+        counters
+            .total_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| duration <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        counters.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn test_metrics_collector() {
+    let collector = MetricsCollector::new();
+    collector.observe(
+        "eth_blockNumber",
+        Duration::from_millis(3),
+        Outcome::Success,
+    );
+    collector.observe(
+        "eth_blockNumber",
+        Duration::from_millis(30),
+        Outcome::Success,
+    );
+    collector.observe("eth_call", Duration::from_secs(20), Outcome::Timeout);
+
+    let snapshot = collector.snapshot();
+    let block_number = &snapshot["eth_blockNumber"];
+    assert_eq!(block_number.requests, 2);
+    assert_eq!(block_number.errors, 0);
+    assert_eq!(block_number.total_latency, Duration::from_millis(33));
+    assert_eq!(block_number.latency_buckets[0], 1);
+    assert_eq!(block_number.latency_buckets[3], 1);
+
+    let call = &snapshot["eth_call"];
+    assert_eq!((call.requests, call.errors, call.timeouts), (1, 1, 1));
+    assert_eq!(call.latency_buckets[LATENCY_BUCKETS.len()], 1);
+}
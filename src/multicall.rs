@@ -0,0 +1,271 @@
+//! Batches many contract reads into a single eth_call using the Multicall3 contract
+//!
+//! See more: https://github.com/mds1/multicall
+use crate::event_utils::address_to_event;
+use crate::jsonrpc::error::Web3Error;
+use crate::revert_utils::revert_data_from_error;
+use crate::{client::Web3, types::TransactionRequest};
+use clarity::abi::{derive_method_id, encode_call, Token};
+use clarity::{Address, Uint256};
+
+lazy_static! {
+    /// The canonical Multicall3 deployment, at the same address on most chains
+    pub static ref MULTICALL3_ADDRESS: Address =
+        Address::parse_and_validate("0xcA11bde05977b3631167028862bE2a173976CA11").unwrap();
+}
+
+/// Why a single call in a multicall did not return data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MulticallFailure {
+    /// the call reverted, with the revert data if any, see `decode_revert_data`
+    Reverted(Vec<u8>),
+    /// the call could not be made, only when falling back to individual calls
+    RequestFailed(String),
+}
+
+/// The result of each call in a multicall, in the order the calls were given
+pub type MulticallResults = Vec<Result<Vec<u8>, MulticallFailure>>;
+
+pub(crate) fn word(n: usize) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&(n as u64).to_be_bytes());
+    word
+}
+
+pub(crate) fn read_usize(data: &[u8], offset: usize) -> Option<usize> {
+    let word = data.get(offset..offset.checked_add(32)?)?;
+    Uint256::from_bytes_be(word)?.to_string().parse().ok()
+}
+
+/// Encodes `aggregate3((address,bool,bytes)[])` with every call allowed to fail
+fn encode_aggregate3(calls: &[(Address, Vec<u8>)]) -> Result<Vec<u8>, Web3Error> {
+    let mut tuples = Vec::new();
+    for (target, data) in calls {
+        let mut tuple = Vec::new();
+        tuple.extend_from_slice(&address_to_event(*target));
+        tuple.extend_from_slice(&word(1));
+        // the calldata follows the three head words of the tuple
+        tuple.extend_from_slice(&word(96));
+        tuple.extend_from_slice(&word(data.len()));
+        tuple.extend_from_slice(data);
+        tuple.resize(tuple.len() + (32 - data.len() % 32) % 32, 0);
+        tuples.push(tuple);
+    }
+
+    let mut res = derive_method_id("aggregate3((address,bool,bytes)[])")?.to_vec();
+    res.extend_from_slice(&word(32));
+    res.extend_from_slice(&word(calls.len()));
+    // offsets of the tuples are relative to the word after the array length
+    let mut offset = calls.len() * 32;
+    for tuple in tuples.iter() {
+        res.extend_from_slice(&word(offset));
+        offset += tuple.len();
+    }
+    for tuple in tuples {
+        res.extend(tuple);
+    }
+    Ok(res)
+}
+
+/// Decodes the `(bool,bytes)[]` returned by aggregate3
+fn decode_aggregate3(data: &[u8]) -> Option<MulticallResults> {
+    let array = read_usize(data, 0)?;
+    let len = read_usize(data, array)?;
+    // every offset comes from the node, so none of the additions may overflow
+    let start = array.checked_add(32)?;
+    let mut res = Vec::new();
+    for i in 0..len {
+        let tuple = start.checked_add(read_usize(data, start.checked_add(i.checked_mul(32)?)?)?)?;
+        let success = read_usize(data, tuple)? != 0;
+        let bytes = tuple.checked_add(read_usize(data, tuple.checked_add(32)?)?)?;
+        let bytes_len = read_usize(data, bytes)?;
+        let bytes = bytes.checked_add(32)?;
+        let bytes = data.get(bytes..bytes.checked_add(bytes_len)?)?;
+        res.push(if success {
+            Ok(bytes.to_vec())
+        } else {
+            Err(MulticallFailure::Reverted(bytes.to_vec()))
+        });
+    }
+    Some(res)
+}
+
+impl Web3 {
+    /// Makes many read only contract calls in a single eth_call through the canonical
+    /// Multicall3 contract, see `multicall_at`
+    pub async fn multicall(
+        &self,
+        calls: &[(Address, &str, &[Token])],
+    ) -> Result<MulticallResults, Web3Error> {
+        self.multicall_at(*MULTICALL3_ADDRESS, calls).await
+    }
+
+    /// Makes many read only contract calls, given as the contract, function signature and
+    /// arguments, in a single eth_call through the Multicall3 contract at `multicall_address`.
+    /// The result of each call is returned in order, a reverted call does not fail the others.
+    /// If there is no contract at `multicall_address` the calls are made individually instead.
+    pub async fn multicall_at(
+        &self,
+        multicall_address: Address,
+        calls: &[(Address, &str, &[Token])],
+    ) -> Result<MulticallResults, Web3Error> {
+        let mut encoded = Vec::new();
+        for (target, sig, tokens) in calls {
+            encoded.push((*target, encode_call(sig, tokens)?));
+        }
+        if encoded.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let payload = encode_aggregate3(&encoded)?;
+        let res = self
+            .eth_call(TransactionRequest::call(multicall_address, payload))
+            .await?;
+        // calling an address without code succeeds with no return data
+        if res.0.is_empty() {
+            warn!(
+                "No multicall contract at {}, making {} calls individually",
+                multicall_address,
+                encoded.len()
+            );
+            return self.individual_calls(encoded).await;
+        }
+        match decode_aggregate3(&res.0) {
+            Some(results) if results.len() == encoded.len() => Ok(results),
+            _ => Err(Web3Error::ContractCallError(
+                "Bad response from multicall aggregate3".to_string(),
+            )),
+        }
+    }
+
+    /// Makes each call with its own eth_call, concurrently
+    async fn individual_calls(
+        &self,
+        calls: Vec<(Address, Vec<u8>)>,
+    ) -> Result<MulticallResults, Web3Error> {
+        let mut handles = Vec::new();
+        for (target, data) in calls {
+            let web3 = self.clone();
+            handles.push(tokio::spawn(async move {
+                web3.eth_call(TransactionRequest::call(target, data)).await
+            }));
+        }
+
+        let mut res = Vec::new();
+        for handle in handles {
+            let result = match handle.await {
+                Ok(result) => result,
+                Err(e) => return Err(Web3Error::BadResponse(format!("{}", e))),
+            };
+            res.push(match result {
+                Ok(data) => Ok(data.0),
+                Err(Web3Error::JsonRpcError { message, data, .. }) => {
+                    match data.as_ref().and_then(revert_data_from_error) {
+                        Some(data) => Err(MulticallFailure::Reverted(data)),
+                        None if message.contains("revert") => {
+                            Err(MulticallFailure::Reverted(Vec::new()))
+                        }
+                        None => Err(MulticallFailure::RequestFailed(message)),
+                    }
+                }
+                Err(e) => Err(MulticallFailure::RequestFailed(format!("{}", e))),
+            });
+        }
+        Ok(res)
+    }
+}
+
+#[test]
+fn test_aggregate3_encoding() {
+    use clarity::utils::hex_str_to_bytes;
+
+    let target: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let encoded = encode_aggregate3(&[(target, vec![0x12, 0x34, 0x56, 0x78])]).unwrap();
+    let expected = hex_str_to_bytes(
+        "0x82ad56cb\
+         0000000000000000000000000000000000000000000000000000000000000020\
+         0000000000000000000000000000000000000000000000000000000000000001\
+         0000000000000000000000000000000000000000000000000000000000000020\
+         0000000000000000000000001111111111111111111111111111111111111111\
+         0000000000000000000000000000000000000000000000000000000000000001\
+         0000000000000000000000000000000000000000000000000000000000000060\
+         0000000000000000000000000000000000000000000000000000000000000004\
+         1234567800000000000000000000000000000000000000000000000000000000",
+    )
+    .unwrap();
+    assert_eq!(encoded, expected);
+
+    // [(true, 0x2a), (false, "")]
+    let response = hex_str_to_bytes(
+        "0x0000000000000000000000000000000000000000000000000000000000000020\
+         0000000000000000000000000000000000000000000000000000000000000002\
+         0000000000000000000000000000000000000000000000000000000000000040\
+         00000000000000000000000000000000000000000000000000000000000000c0\
+         0000000000000000000000000000000000000000000000000000000000000001\
+         0000000000000000000000000000000000000000000000000000000000000040\
+         0000000000000000000000000000000000000000000000000000000000000020\
+         000000000000000000000000000000000000000000000000000000000000002a\
+         0000000000000000000000000000000000000000000000000000000000000000\
+         0000000000000000000000000000000000000000000000000000000000000040\
+         0000000000000000000000000000000000000000000000000000000000000000",
+    )
+    .unwrap();
+    let mut value = vec![0u8; 32];
+    value[31] = 0x2a;
+    assert_eq!(
+        decode_aggregate3(&response).unwrap(),
+        vec![Ok(value), Err(MulticallFailure::Reverted(Vec::new()))]
+    );
+    assert!(decode_aggregate3(&response[..100]).is_none());
+
+    // offsets that overflow when added to their base are rejected, not a panic
+    for at in [64, 128 + 32] {
+        let mut hostile = response.clone();
+        hostile[at + 24..at + 32].copy_from_slice(&[0xff; 8]);
+        assert!(decode_aggregate3(&hostile).is_none());
+    }
+}
+
+#[tokio::test]
+async fn test_multicall_fallback() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let node = MockNode::start(|method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_call" => match params[0]["to"].as_str().unwrap() {
+            // no contract at the multicall address
+            to if to.to_lowercase() == "0xca11bde05977b3631167028862be2a173976ca11" => {
+                Ok(json!("0x"))
+            }
+            to if to.starts_with("0x1111") => Ok(json!(
+                "0x000000000000000000000000000000000000000000000000000000000000002a"
+            )),
+            _ => Err((3, "execution reverted".to_string())),
+        },
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let token: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let other: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let owner: Token = token.into();
+
+    let results = web3
+        .multicall(&[
+            (token, "balanceOf(address)", &[owner.clone()]),
+            (other, "balanceOf(address)", &[owner]),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].as_ref().unwrap()[31], 0x2a);
+    assert_eq!(results[1], Err(MulticallFailure::Reverted(Vec::new())));
+    assert_eq!(node.calls("eth_call"), 3);
+}
@@ -0,0 +1,353 @@
+//! Construction and encoding of transactions without a node, for air-gapped setups where a
+//! transaction is signed on one machine, or by an HSM or hardware wallet, and broadcast from
+//! another with `Web3::broadcast`. This is also how transactions signed with a `PrivateKey`
+//! are encoded when sent through `Web3`.
+//!
+//! See more: https://eips.ethereum.org/EIPS/eip-155 and https://eips.ethereum.org/EIPS/eip-1559
+use crate::event_utils::address_to_event;
+use crate::hash::H256;
+use crate::jsonrpc::error::Web3Error;
+use crate::raw_transaction::{rlp_encode_bytes, rlp_encode_list, strip_zeros};
+use crate::types::{AccessListItem, SignedTx};
+use clarity::{Address, PrivateKey, Signature, Transaction, Uint256};
+use sha3::{Digest, Keccak256};
+
+/// The EIP-2718 type byte of EIP-1559 transactions
+const EIP1559_TX_TYPE: u8 = 2;
+
+/// How a transaction pays for gas, which also determines its format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionFees {
+    /// a legacy transaction with EIP-155 replay protection
+    Legacy { gas_price: Uint256 },
+    /// an EIP-1559 transaction
+    Eip1559 {
+        max_fee_per_gas: Uint256,
+        max_priority_fee_per_gas: Uint256,
+    },
+}
+
+/// Everything that goes into a transaction, see `build_transaction`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionParams {
+    pub chain_id: u64,
+    pub nonce: Uint256,
+    pub fees: TransactionFees,
+    pub gas_limit: Uint256,
+    /// None creates a contract with `data` as the init code
+    pub to: Option<Address>,
+    pub value: Uint256,
+    pub data: Vec<u8>,
+    /// only EIP-1559 transactions can have an access list
+    pub access_list: Vec<AccessListItem>,
+}
+
+impl TransactionParams {
+    /// The parameters of a clarity legacy transaction, which uses the zero address for
+    /// contract creation
+    pub fn from_legacy(transaction: Transaction, chain_id: u64) -> TransactionParams {
+        TransactionParams {
+            chain_id,
+            nonce: transaction.nonce,
+            fees: TransactionFees::Legacy {
+                gas_price: transaction.gas_price,
+            },
+            gas_limit: transaction.gas_limit,
+            to: if transaction.to == Address::default() {
+                None
+            } else {
+                Some(transaction.to)
+            },
+            value: transaction.value,
+            data: transaction.data,
+            access_list: Vec::new(),
+        }
+    }
+}
+
+/// A transaction that is ready to be signed, created with `build_transaction`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsignedTx {
+    params: TransactionParams,
+}
+
+/// Checks that `params` describe a valid transaction, without any network access
+pub fn build_transaction(params: TransactionParams) -> Result<UnsignedTx, Web3Error> {
+    match params.fees {
+        TransactionFees::Legacy { .. } if !params.access_list.is_empty() => {
+            return Err(Web3Error::BadInput(
+                "Legacy transactions can not have an access list".to_string(),
+            ))
+        }
+        TransactionFees::Eip1559 {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        } if max_priority_fee_per_gas > max_fee_per_gas => {
+            return Err(Web3Error::BadInput(format!(
+                "Max priority fee per gas {} is above the max fee per gas {}",
+                max_priority_fee_per_gas, max_fee_per_gas
+            )))
+        }
+        _ => {}
+    }
+    Ok(UnsignedTx { params })
+}
+
+fn encode_uint(value: Uint256) -> Vec<u8> {
+    rlp_encode_bytes(&strip_zeros(&value))
+}
+
+fn encode_address(address: Address) -> Vec<u8> {
+    rlp_encode_bytes(&address_to_event(address)[12..])
+}
+
+fn encode_access_list(access_list: &[AccessListItem]) -> Vec<u8> {
+    let entries: Vec<Vec<u8>> = access_list
+        .iter()
+        .map(|item| {
+            let keys: Vec<Vec<u8>> = item
+                .storage_keys
+                .iter()
+                .map(|key| rlp_encode_bytes(&H256::from(*key).0))
+                .collect();
+            let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+            let address = encode_address(item.address);
+            rlp_encode_list(&[&address, &rlp_encode_list(&keys)])
+        })
+        .collect();
+    let entries: Vec<&[u8]> = entries.iter().map(Vec::as_slice).collect();
+    rlp_encode_list(&entries)
+}
+
+impl UnsignedTx {
+    pub fn params(&self) -> &TransactionParams {
+        &self.params
+    }
+
+    /// The encoded fields common to the signing payload and the signed transaction
+    fn fields(&self) -> Vec<Vec<u8>> {
+        let p = &self.params;
+        let to = match p.to {
+            Some(to) => encode_address(to),
+            None => rlp_encode_bytes(&[]),
+        };
+        let mut fields = match p.fees {
+            TransactionFees::Legacy { gas_price } => {
+                vec![encode_uint(p.nonce), encode_uint(gas_price)]
+            }
+            TransactionFees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => vec![
+                encode_uint(Uint256::from_u64(p.chain_id)),
+                encode_uint(p.nonce),
+                encode_uint(max_priority_fee_per_gas),
+                encode_uint(max_fee_per_gas),
+            ],
+        };
+        fields.extend([
+            encode_uint(p.gas_limit),
+            to,
+            encode_uint(p.value),
+            rlp_encode_bytes(&p.data),
+        ]);
+        if let TransactionFees::Eip1559 { .. } = p.fees {
+            fields.push(encode_access_list(&p.access_list));
+        }
+        fields
+    }
+
+    /// Encodes `fields` followed by `trailer` as a list, with the type byte of typed
+    /// transactions in front
+    fn encode(&self, fields: &[Vec<u8>], trailer: &[Vec<u8>]) -> Vec<u8> {
+        let items: Vec<&[u8]> = fields
+            .iter()
+            .chain(trailer.iter())
+            .map(Vec::as_slice)
+            .collect();
+        let list = rlp_encode_list(&items);
+        match self.params.fees {
+            TransactionFees::Legacy { .. } => list,
+            TransactionFees::Eip1559 { .. } => {
+                let mut payload = vec![EIP1559_TX_TYPE];
+                payload.extend(list);
+                payload
+            }
+        }
+    }
+
+    /// The bytes whose keccak256 is signed
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let trailer = match self.params.fees {
+            // EIP-155 signs the chain id with empty r and s
+            TransactionFees::Legacy { .. } => vec![
+                encode_uint(Uint256::from_u64(self.params.chain_id)),
+                rlp_encode_bytes(&[]),
+                rlp_encode_bytes(&[]),
+            ],
+            TransactionFees::Eip1559 { .. } => Vec::new(),
+        };
+        self.encode(&self.fields(), &trailer)
+    }
+
+    /// The hash to sign, produce the signature anywhere and attach it with `with_signature`
+    pub fn signing_hash(&self) -> [u8; 32] {
+        Keccak256::digest(self.signing_payload()).into()
+    }
+
+    /// Attaches a signature of `signing_hash`, with a v of 27 or 28 or the y parity 0 or 1.
+    /// The signature is not checked, recover the sender from the result with
+    /// `decode_raw_transaction` to be sure it was made by the right key.
+    pub fn with_signature(&self, signature: &Signature) -> Result<SignedTx, Web3Error> {
+        let bytes = signature.to_bytes();
+        let parity = match bytes[64] {
+            v @ (0 | 1) => v,
+            v @ (27 | 28) => v - 27,
+            v => return Err(Web3Error::BadInput(format!("Invalid signature v {}", v))),
+        };
+        let r = Uint256::from_bytes_be(&bytes[0..32]).unwrap();
+        let s = Uint256::from_bytes_be(&bytes[32..64]).unwrap();
+        let v = match self.params.fees {
+            TransactionFees::Legacy { .. } => Uint256::from_u64(self.params.chain_id)
+                .checked_mul(Uint256::from_u64(2))
+                .and_then(|v| v.checked_add(Uint256::from_u64(35 + parity as u64)))
+                .unwrap(),
+            TransactionFees::Eip1559 { .. } => Uint256::from_u64(parity as u64),
+        };
+        let raw = self.encode(
+            &self.fields(),
+            &[encode_uint(v), encode_uint(r), encode_uint(s)],
+        );
+        Ok(SignedTx::new(raw, self.params.nonce))
+    }
+
+    /// Signs with a key held in this process
+    pub fn sign(&self, key: &PrivateKey) -> Result<SignedTx, Web3Error> {
+        self.with_signature(&key.sign_hash(&self.signing_hash()))
+    }
+}
+
+#[cfg(test)]
+const TEST_KEY: &str = "0x0123456789012345678901234567890123456789012345678901234567890123";
+
+#[test]
+fn test_legacy_matches_clarity() {
+    let key: PrivateKey = TEST_KEY.parse().unwrap();
+    let transaction = Transaction {
+        to: "0x3535353535353535353535353535353535353535"
+            .parse()
+            .unwrap(),
+        nonce: Uint256::from_u64(9),
+        gas_price: Uint256::from_u64(20_000_000_000),
+        gas_limit: Uint256::from_u64(21000),
+        value: Uint256::from_u128(1_000_000_000_000_000_000),
+        data: vec![0xa9, 0x05, 0x9c, 0xbb],
+        signature: None,
+    };
+    let expected = transaction.clone().sign(&key, Some(1)).to_bytes().unwrap();
+
+    let unsigned = build_transaction(TransactionParams::from_legacy(transaction, 1)).unwrap();
+    let signed = unsigned.sign(&key).unwrap();
+    assert_eq!(signed.raw_bytes(), &expected[..]);
+    assert_eq!(signed.nonce, Uint256::from_u64(9));
+    assert_eq!(signed.hash(), H256(Keccak256::digest(&expected).into()));
+}
+
+#[test]
+fn test_eip1559_transaction() {
+    use crate::raw_transaction::decode_raw_transaction;
+    use clarity::utils::hex_str_to_bytes;
+
+    let key: PrivateKey = TEST_KEY.parse().unwrap();
+    let to: Address = "0x3535353535353535353535353535353535353535"
+        .parse()
+        .unwrap();
+    let params = TransactionParams {
+        chain_id: 1,
+        nonce: Uint256::from_u64(9),
+        fees: TransactionFees::Eip1559 {
+            max_fee_per_gas: Uint256::from_u64(100_000_000_000),
+            max_priority_fee_per_gas: Uint256::from_u64(2_000_000_000),
+        },
+        gas_limit: Uint256::from_u64(50000),
+        to: Some(to),
+        value: Uint256::from_u128(1_000_000_000_000_000_000),
+        data: vec![0xa9, 0x05, 0x9c, 0xbb],
+        access_list: vec![AccessListItem {
+            address: "0x1111111111111111111111111111111111111111"
+                .parse()
+                .unwrap(),
+            storage_keys: vec![Uint256::from_u64(5)],
+        }],
+    };
+    let unsigned = build_transaction(params).unwrap();
+    assert_eq!(unsigned.signing_payload()[0], 2);
+
+    // the signature is produced elsewhere, only the hash leaves this machine
+    let signature = key.sign_hash(&unsigned.signing_hash());
+    let signed = unsigned.with_signature(&signature).unwrap();
+    // the same transaction as in test_decode_typed_transactions
+    let expected = hex_str_to_bytes(
+        "0x02f8b00109847735940085174876e80082c35094353535353535353535353535353535353535353588\
+         0de0b6b3a764000084a9059cbbf838f7941111111111111111111111111111111111111111e1a00000\
+         00000000000000000000000000000000000000000000000000000000000501a06ca57aa1ffe2da8da6\
+         b2300b34b5f9466afdc03ed1f0836a08ac963308aa0442a00cf035e62bf014b7a2e53746972158225f\
+         d85ba83fde83a7c17b2549a469ed76",
+    )
+    .unwrap();
+    assert_eq!(signed.raw_bytes(), &expected[..]);
+
+    let decoded = decode_raw_transaction(signed.raw_bytes()).unwrap();
+    assert_eq!(decoded.from, key.to_address());
+    assert_eq!(decoded.hash, signed.hash());
+    assert_eq!(decoded.to, Some(to));
+}
+
+#[test]
+fn test_build_transaction_errors() {
+    let params = TransactionParams {
+        chain_id: 1,
+        nonce: Uint256::default(),
+        fees: TransactionFees::Eip1559 {
+            max_fee_per_gas: Uint256::from_u64(1),
+            max_priority_fee_per_gas: Uint256::from_u64(2),
+        },
+        gas_limit: Uint256::from_u64(21000),
+        to: None,
+        value: Uint256::default(),
+        data: vec![0x60, 0x80],
+        access_list: Vec::new(),
+    };
+    assert!(build_transaction(params.clone()).is_err());
+    let legacy = TransactionParams {
+        fees: TransactionFees::Legacy {
+            gas_price: Uint256::from_u64(1),
+        },
+        access_list: vec![AccessListItem {
+            address: Address::default(),
+            storage_keys: Vec::new(),
+        }],
+        ..params
+    };
+    assert!(build_transaction(legacy.clone()).is_err());
+
+    // contract creation with an empty to
+    let key: PrivateKey = TEST_KEY.parse().unwrap();
+    let creation = build_transaction(TransactionParams {
+        access_list: Vec::new(),
+        ..legacy
+    })
+    .unwrap();
+    let signed = creation.sign(&key).unwrap();
+    let decoded = crate::raw_transaction::decode_raw_transaction(signed.raw_bytes()).unwrap();
+    assert_eq!(decoded.to, None);
+    assert_eq!(decoded.chain_id, Some(Uint256::from_u64(1)));
+    assert_eq!(decoded.from, key.to_address());
+
+    let bad_v = Signature::new(
+        Uint256::from_u64(30),
+        Uint256::from_u64(1),
+        Uint256::from_u64(1),
+    );
+    assert!(creation.with_signature(&bad_v).is_err());
+}
@@ -0,0 +1,175 @@
+//! Signing and verifying messages in the `personal_sign` format used by wallets for
+//! authentication, where the message is prefixed so that it can never be a valid transaction
+//!
+//! See more: https://eips.ethereum.org/EIPS/eip-191
+use crate::hex::data_to_hex;
+use crate::{client::Web3, jsonrpc::error::Web3Error, types::Data};
+use clarity::{Address, PrivateKey, Signature, Uint256};
+use sha3::{Digest, Keccak256};
+
+/// Returns the hash that is signed for `message`, the keccak256 of
+/// `"\x19Ethereum Signed Message:\n" + len(message) + message`
+pub fn personal_message_hash(message: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(b"\x19Ethereum Signed Message:\n");
+    hasher.update(message.len().to_string().as_bytes());
+    hasher.update(message);
+    hasher.finalize().into()
+}
+
+/// Signs `message` as `personal_sign` would, returning the 65 byte `r || s || v`
+/// signature with a v of 27 or 28
+pub fn personal_sign_local(message: &[u8], key: &PrivateKey) -> [u8; 65] {
+    key.sign_hash(&personal_message_hash(message)).to_bytes()
+}
+
+/// Splits a 65 byte `r || s || v` signature with v as 0, 1, 27 or 28 or a 64 byte EIP-2098
+/// compact signature into its parts, normalizing v to 27 or 28
+fn split_signature(signature: &[u8]) -> Result<(Uint256, Uint256, u8), Web3Error> {
+    let word = |bytes: &[u8]| Uint256::from_bytes_be(bytes).unwrap_or_default();
+    match signature.len() {
+        65 => {
+            let v = match signature[64] {
+                0 | 1 => signature[64] + 27,
+                27 | 28 => signature[64],
+                v => return Err(Web3Error::BadInput(format!("Invalid signature v {}", v))),
+            };
+            Ok((word(&signature[0..32]), word(&signature[32..64]), v))
+        }
+        // EIP-2098, the top bit of s is the y parity
+        64 => {
+            let mut s = [0u8; 32];
+            s.copy_from_slice(&signature[32..64]);
+            let v = 27 + (s[0] >> 7);
+            s[0] &= 0x7f;
+            Ok((word(&signature[0..32]), word(&s), v))
+        }
+        len => Err(Web3Error::BadInput(format!(
+            "Signature is {} bytes, expected 64 or 65",
+            len
+        ))),
+    }
+}
+
+/// Recovers the address that signed `message` with `personal_sign`, accepting 65 byte
+/// signatures with v as 0/1 or 27/28 and 64 byte EIP-2098 compact signatures. Compare the
+/// result with the expected signer, an invalid signature will recover a random address.
+pub fn recover_personal_signature(message: &[u8], signature: &[u8]) -> Result<Address, Web3Error> {
+    let (r, s, v) = split_signature(signature)?;
+    let signature = Signature::new(Uint256::from_u64(v as u64), r, s);
+    Ok(signature.recover(&personal_message_hash(message))?)
+}
+
+impl Web3 {
+    /// Asks the node to sign `message` with `personal_sign` using the unlocked account
+    /// `address`, as supported by development nodes and clef
+    pub async fn personal_sign_remote(
+        &self,
+        address: Address,
+        message: &[u8],
+    ) -> Result<[u8; 65], Web3Error> {
+        let res: Data = self
+            .jsonrpc_client
+            .request_method(
+                "personal_sign",
+                (data_to_hex(message), address.to_string()),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("personal_sign"))?;
+        match <[u8; 65]>::try_from(res.0.as_slice()) {
+            Ok(signature) => Ok(signature),
+            Err(_) => Err(Web3Error::BadResponse(format!(
+                "personal_sign returned {} bytes, expected 65",
+                res.0.len()
+            ))),
+        }
+    }
+}
+
+#[test]
+fn test_personal_sign_vectors() {
+    use clarity::utils::hex_str_to_bytes;
+
+    let key: PrivateKey = "0x0123456789012345678901234567890123456789012345678901234567890123"
+        .parse()
+        .unwrap();
+    let signer: Address = "0x14791697260e4c9a71f18484c9f997b308e59325"
+        .parse()
+        .unwrap();
+    assert_eq!(key.to_address(), signer);
+    assert_eq!(
+        personal_message_hash(b"Hello World").to_vec(),
+        hex_str_to_bytes("0xa1de988600a42c4b4ab089b619297c17d53cffae5d5120d82d8a92d0bb3b78f2")
+            .unwrap()
+    );
+
+    // as produced by MetaMask for this key
+    let expected = hex_str_to_bytes(
+        "0xe0ed34fbbe927a58267ce2e8067a611c69869e20e731bc99187a8bc97058664c\
+         16de07f7660f06ce0985d1d8e063726783033fda59b307897f26a21392d62b3a1c",
+    )
+    .unwrap();
+    let signature = personal_sign_local(b"Hello World", &key);
+    assert_eq!(signature.to_vec(), expected);
+    assert_eq!(
+        recover_personal_signature(b"Hello World", &signature).unwrap(),
+        signer
+    );
+
+    // v as 0/1
+    let mut zero_v = signature;
+    zero_v[64] -= 27;
+    assert_eq!(
+        recover_personal_signature(b"Hello World", &zero_v).unwrap(),
+        signer
+    );
+
+    // EIP-2098 compact, v of 28 sets the top bit of s
+    let mut compact = signature[..64].to_vec();
+    if signature[64] == 28 {
+        compact[32] |= 0x80;
+    }
+    assert_eq!(
+        recover_personal_signature(b"Hello World", &compact).unwrap(),
+        signer
+    );
+
+    assert_ne!(
+        recover_personal_signature(b"Hello World!", &signature).unwrap(),
+        signer
+    );
+    assert!(recover_personal_signature(b"Hello World", &signature[..63]).is_err());
+    zero_v[64] = 5;
+    assert!(recover_personal_signature(b"Hello World", &zero_v).is_err());
+}
+
+#[tokio::test]
+async fn test_personal_sign_remote() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let key: PrivateKey = "0x0123456789012345678901234567890123456789012345678901234567890123"
+        .parse()
+        .unwrap();
+    let signature = data_to_hex(&personal_sign_local(b"login", &key));
+    let node = MockNode::start(move |method, _| match method {
+        "personal_sign" => Ok(json!(signature)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let signature = web3
+        .personal_sign_remote(key.to_address(), b"login")
+        .await
+        .unwrap();
+    assert_eq!(
+        recover_personal_signature(b"login", &signature).unwrap(),
+        key.to_address()
+    );
+    assert_eq!(
+        node.params("personal_sign")[0],
+        json!(["0x6c6f67696e", key.to_address().to_string()])
+    );
+}
@@ -0,0 +1,675 @@
+//! Private transaction submission through relays such as Flashbots Protect, which forward
+//! transactions to block builders without exposing them in the public mempool
+use crate::client::Web3;
+use crate::hash::TxHash;
+use crate::hex::{data_to_hex, word_to_hex};
+use crate::jsonrpc::client::BodyHook;
+use crate::jsonrpc::error::Web3Error;
+use crate::keccak::keccak256;
+use crate::personal_sign::personal_sign_local;
+use crate::time::sleep as delay_for;
+use crate::time::Instant;
+use crate::types::{BlockId, SendTxOption, SignedTx, TransactionReceipt, UnpaddedHex};
+use clarity::utils::bytes_to_hex_str;
+use clarity::{Address, PrivateKey, Uint256};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::HeaderMap;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The header Flashbots style relays authenticate requests with
+pub const FLASHBOTS_SIGNATURE_HEADER: &str = "x-flashbots-signature";
+
+/// How many blocks a relay keeps trying to include a private transaction for by default,
+/// the same as the default of Flashbots Protect
+pub const DEFAULT_PRIVATE_TX_MAX_BLOCKS: u64 = 25;
+
+/// Preferences passed to the relay along with a private transaction
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrivateTxPreferences {
+    /// share the transaction with every builder the relay knows for faster inclusion
+    pub fast: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub privacy: Option<PrivacyPreferences>,
+}
+
+/// Which parts of a private transaction the relay may share and with which builders
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrivacyPreferences {
+    /// the fields shared with searchers, such as "calldata", "logs" or "hash"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hints: Vec<String>,
+    /// the builders the transaction is sent to
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub builders: Vec<String>,
+}
+
+/// The params of eth_sendPrivateTransaction
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PrivateTransaction {
+    tx: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_block_number: Option<UnpaddedHex>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preferences: Option<PrivateTxPreferences>,
+}
+
+/// Computes the X-Flashbots-Signature header of a request body, the address of
+/// `signing_key` and its `personal_sign` signature of the hex encoded keccak256 of the body
+pub fn flashbots_signature(body: &[u8], signing_key: &PrivateKey) -> String {
+    let message = data_to_hex(&keccak256(body));
+    let signature = personal_sign_local(message.as_bytes(), signing_key);
+    format!(
+        "{}:0x{}",
+        signing_key.to_address(),
+        bytes_to_hex_str(&signature)
+    )
+}
+
+/// A body hook that signs every request with `signing_key` as Flashbots style relays
+/// require. The signing key only identifies the sender to the relay for reputation, it
+/// should not hold funds.
+pub fn flashbots_body_hook(signing_key: PrivateKey) -> BodyHook {
+    Arc::new(move |body: &[u8]| {
+        let mut headers = HeaderMap::new();
+        let value = HeaderValue::from_str(&flashbots_signature(body, &signing_key))
+            .expect("signature header is ascii");
+        headers.insert(HeaderName::from_static(FLASHBOTS_SIGNATURE_HEADER), value);
+        headers
+    })
+}
+
+impl Web3 {
+    /// Creates a client for a private transaction relay that signs its requests with
+    /// `signing_key`, use it with `eth_send_private_transaction`
+    pub fn private_relay(url: &str, signing_key: PrivateKey, timeout: Duration) -> Web3 {
+        Web3::builder(url)
+            .timeout(timeout)
+            .body_hook(flashbots_body_hook(signing_key))
+            .build()
+    }
+
+    /// Sends a signed transaction to the relay this client is connected to, which only
+    /// tries to include it up to and including `max_block_number`. Returns the hash.
+    pub async fn eth_send_private_transaction(
+        &self,
+        transaction: &SignedTx,
+        max_block_number: Option<Uint256>,
+        preferences: Option<PrivateTxPreferences>,
+    ) -> Result<TxHash, Web3Error> {
+        let params = PrivateTransaction {
+            tx: data_to_hex(&transaction.raw),
+            max_block_number: max_block_number.map(UnpaddedHex),
+            preferences,
+        };
+        self.jsonrpc_client
+            .request_method("eth_sendPrivateTransaction", vec![params], self.timeout)
+            .await
+    }
+
+    /// Sends a signed transaction to the relay this client is connected to with the relay's
+    /// default block limit, returning the hash
+    pub async fn eth_send_private_raw_transaction(
+        &self,
+        transaction: &SignedTx,
+        preferences: Option<PrivateTxPreferences>,
+    ) -> Result<TxHash, Web3Error> {
+        let raw = data_to_hex(&transaction.raw);
+        match preferences {
+            Some(preferences) => {
+                self.jsonrpc_client
+                    .request_method(
+                        "eth_sendPrivateRawTransaction",
+                        (raw, preferences),
+                        self.timeout,
+                    )
+                    .await
+            }
+            None => {
+                self.jsonrpc_client
+                    .request_method("eth_sendPrivateRawTransaction", vec![raw], self.timeout)
+                    .await
+            }
+        }
+    }
+
+    /// Builds and signs a transaction with this client's node exactly as `send_transaction`
+    /// would, but broadcasts it only through the private relay at `relay_url` so that it can
+    /// not be front run from the public mempool. The relay gives up after
+    /// `DEFAULT_PRIVATE_TX_MAX_BLOCKS` blocks and the transaction is never sent publicly,
+    /// wait for it with `wait_for_private_transaction`.
+    pub async fn send_transaction_private(
+        &self,
+        to_address: Address,
+        data: Vec<u8>,
+        value: Uint256,
+        secret: &PrivateKey,
+        relay_url: &str,
+        signing_key: &PrivateKey,
+    ) -> Result<SignedTx, Web3Error> {
+        let transaction = self
+            .sign_transaction(to_address, data, value, secret, Vec::new())
+            .await?;
+        let max_block_number = self
+            .eth_block_number()
+            .await?
+            .checked_add(Uint256::from_u64(DEFAULT_PRIVATE_TX_MAX_BLOCKS))
+            .ok_or_else(|| Web3Error::BadResponse("Block number overflow".to_string()))?;
+        let relay = Web3::private_relay(relay_url, *signing_key, self.timeout);
+        let hash = relay
+            .eth_send_private_transaction(&transaction, Some(max_block_number), None)
+            .await?;
+        if hash != transaction.hash {
+            return Err(Web3Error::BadResponse(format!(
+                "Relay returned txid {} for transaction {}",
+                hash, transaction.hash
+            )));
+        }
+        Ok(transaction)
+    }
+
+    /// Waits up to `timeout` for a privately sent transaction to be mined, returning its
+    /// receipt. The transaction stays private unless `public_fallback_after` is set, then it
+    /// is broadcast through this client's node once that many blocks have passed without it
+    /// being included, giving up the protection against front running.
+    pub async fn wait_for_private_transaction(
+        &self,
+        transaction: &SignedTx,
+        public_fallback_after: Option<u64>,
+        timeout: Duration,
+    ) -> Result<TransactionReceipt, Web3Error> {
+        let start = Instant::now();
+        let start_block = self.eth_block_number().await?;
+        let mut fallback_block = public_fallback_after
+            .and_then(|blocks| start_block.checked_add(Uint256::from_u64(blocks)));
+        loop {
+            if let Some(receipt) = self.eth_get_transaction_receipt(transaction.hash).await? {
+                return Ok(receipt);
+            }
+            if let Some(block) = fallback_block {
+                if self.eth_block_number().await? >= block {
+                    warn!(
+                        "Private transaction {} was not included, broadcasting it publicly",
+                        transaction.hash
+                    );
+                    self.broadcast(transaction).await?;
+                    fallback_block = None;
+                }
+            }
+            if Instant::now() - start > timeout {
+                return Err(Web3Error::TransactionTimeout(Some(transaction.hash)));
+            }
+            delay_for(self.poll_interval_or(Duration::from_secs(1))).await;
+        }
+    }
+}
+
+/// Optional conditions of a bundle sent with `send_bundle`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BundleOptions {
+    /// the bundle is only valid in blocks with a timestamp at or after this
+    pub min_timestamp: Option<u64>,
+    /// the bundle is only valid in blocks with a timestamp at or before this
+    pub max_timestamp: Option<u64>,
+    /// hashes of transactions in the bundle that may revert without invalidating it
+    pub reverting_tx_hashes: Vec<TxHash>,
+}
+
+/// The params of eth_sendBundle
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Bundle {
+    txs: Vec<String>,
+    block_number: UnpaddedHex,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_timestamp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_timestamp: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reverting_tx_hashes: Vec<TxHash>,
+}
+
+/// The params of eth_callBundle
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CallBundle {
+    txs: Vec<String>,
+    block_number: UnpaddedHex,
+    state_block_number: BlockId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BundleHash {
+    bundle_hash: Uint256,
+}
+
+/// Relays return amounts as decimal strings in bundle simulations
+fn dec_or_hex<'de, D>(deserializer: D) -> Result<Uint256, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    Uint256::from_dec_or_hex_str_restricted(&s).map_err(serde::de::Error::custom)
+}
+
+/// The result of simulating a bundle with `call_bundle`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleSimulation {
+    pub bundle_hash: Uint256,
+    /// the effective gas price of the whole bundle, including payments to the coinbase
+    #[serde(deserialize_with = "dec_or_hex")]
+    pub bundle_gas_price: Uint256,
+    /// how much the balance of the block builder changed
+    #[serde(deserialize_with = "dec_or_hex")]
+    pub coinbase_diff: Uint256,
+    pub total_gas_used: u64,
+    pub state_block_number: u64,
+    pub results: Vec<BundleTxResult>,
+}
+
+/// The simulated outcome of a single transaction of a bundle
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleTxResult {
+    pub tx_hash: TxHash,
+    pub from_address: Address,
+    #[serde(default)]
+    pub to_address: Option<Address>,
+    pub gas_used: u64,
+    #[serde(deserialize_with = "dec_or_hex")]
+    pub coinbase_diff: Uint256,
+    /// set if the transaction failed, along with `revert` if it reverted with data
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub revert: Option<String>,
+}
+
+impl BundleTxResult {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// How far a bundle got at the relay, as returned by `flashbots_get_bundle_stats`
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleStats {
+    #[serde(default)]
+    pub is_simulated: bool,
+    #[serde(default)]
+    pub is_high_priority: bool,
+    /// RFC 3339 times of the simulation and of receiving the bundle
+    #[serde(default)]
+    pub simulated_at: Option<String>,
+    #[serde(default)]
+    pub received_at: Option<String>,
+    /// fields not known to this struct, such as the builders that considered the bundle
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+fn raw_txs(signed_txs: Vec<Vec<u8>>) -> Vec<String> {
+    signed_txs.iter().map(|tx| data_to_hex(tx)).collect()
+}
+
+impl Web3 {
+    /// Signs a transaction for each `(to, data, value)` of `calls` from the same key with
+    /// sequential nonces starting at the next nonce of the sender, for use in a bundle. Gas
+    /// is estimated against the current state without the earlier transactions, so pass
+    /// `SendTxOption::GasLimit` in `options` when later calls depend on earlier ones.
+    pub async fn sign_bundle_transactions(
+        &self,
+        calls: Vec<(Address, Vec<u8>, Uint256)>,
+        secret: &PrivateKey,
+        options: Vec<SendTxOption>,
+    ) -> Result<Vec<SignedTx>, Web3Error> {
+        let mut nonce = self.eth_get_transaction_count(secret.to_address()).await?;
+        let mut res = Vec::new();
+        for (to, data, value) in calls {
+            let mut options = options.clone();
+            options.push(SendTxOption::Nonce(nonce));
+            res.push(
+                self.sign_transaction(to, data, value, secret, options)
+                    .await?,
+            );
+            nonce = nonce
+                .checked_add(Uint256::from_u64(1))
+                .ok_or_else(|| Web3Error::BadInput("Nonce overflow".to_string()))?;
+        }
+        Ok(res)
+    }
+
+    /// Sends a bundle of signed transactions to the block builder or relay this client is
+    /// connected to, which includes all of them in order in `target_block` or none of them.
+    /// Returns the bundle hash. Relays require a signed request, create the client with
+    /// `Web3::private_relay`.
+    pub async fn send_bundle(
+        &self,
+        signed_txs: Vec<Vec<u8>>,
+        target_block: Uint256,
+        options: BundleOptions,
+    ) -> Result<Uint256, Web3Error> {
+        let bundle = Bundle {
+            txs: raw_txs(signed_txs),
+            block_number: UnpaddedHex(target_block),
+            min_timestamp: options.min_timestamp,
+            max_timestamp: options.max_timestamp,
+            reverting_tx_hashes: options.reverting_tx_hashes,
+        };
+        let res: BundleHash = self
+            .jsonrpc_client
+            .request_method("eth_sendBundle", vec![bundle], self.timeout)
+            .await?;
+        Ok(res.bundle_hash)
+    }
+
+    /// Simulates a bundle as if it was included in `target_block` on top of the state after
+    /// `state_block`, optionally with the given block timestamp
+    pub async fn call_bundle(
+        &self,
+        signed_txs: Vec<Vec<u8>>,
+        target_block: Uint256,
+        state_block: BlockId,
+        timestamp: Option<u64>,
+    ) -> Result<BundleSimulation, Web3Error> {
+        let bundle = CallBundle {
+            txs: raw_txs(signed_txs),
+            block_number: UnpaddedHex(target_block),
+            state_block_number: state_block,
+            timestamp,
+        };
+        self.jsonrpc_client
+            .request_method("eth_callBundle", vec![bundle], self.timeout)
+            .await
+    }
+
+    /// Gets what the relay did with a bundle sent for `target_block`
+    pub async fn flashbots_get_bundle_stats(
+        &self,
+        bundle_hash: Uint256,
+        target_block: Uint256,
+    ) -> Result<BundleStats, Web3Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Params {
+            bundle_hash: String,
+            block_number: UnpaddedHex,
+        }
+        let params = Params {
+            bundle_hash: word_to_hex(bundle_hash),
+            block_number: UnpaddedHex(target_block),
+        };
+        self.jsonrpc_client
+            .request_method("flashbots_getBundleStats", vec![params], self.timeout)
+            .await
+    }
+}
+
+#[test]
+fn test_flashbots_signature() {
+    use crate::personal_sign::recover_personal_signature;
+    use clarity::utils::hex_str_to_bytes;
+
+    let key: PrivateKey = "0x8888888888888888888888888888888888888888888888888888888888888888"
+        .parse()
+        .unwrap();
+    let body = br#"{"jsonrpc":"2.0","method":"eth_sendPrivateTransaction","params":[],"id":1}"#;
+    let header = flashbots_signature(body, &key);
+    let (address, signature) = header.split_once(':').unwrap();
+    assert_eq!(address.parse::<Address>().unwrap(), key.to_address());
+
+    // the signed message is the hex string of the hash, not the hash bytes
+    let message = format!("0x{}", bytes_to_hex_str(&keccak256(body)));
+    let signature = hex_str_to_bytes(signature).unwrap();
+    assert_eq!(
+        recover_personal_signature(message.as_bytes(), &signature).unwrap(),
+        key.to_address()
+    );
+    assert_ne!(
+        recover_personal_signature(&keccak256(body), &signature).unwrap(),
+        key.to_address()
+    );
+}
+
+#[tokio::test]
+async fn test_send_private_transaction() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let relay = MockNode::start(|method, params| match method {
+        "eth_sendPrivateTransaction" => {
+            let raw = params[0]["tx"].as_str().unwrap();
+            let hash = keccak256(&clarity::utils::hex_str_to_bytes(raw).unwrap());
+            Ok(json!(format!("0x{}", bytes_to_hex_str(&hash))))
+        }
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let signing_key: PrivateKey =
+        "0x8888888888888888888888888888888888888888888888888888888888888888"
+            .parse()
+            .unwrap();
+    let web3 = Web3::private_relay(&relay.url, signing_key, Duration::from_secs(5));
+    let transaction = SignedTx::new(vec![0x02, 0xf8, 0x01], Uint256::from_u64(0));
+
+    let preferences = PrivateTxPreferences {
+        fast: true,
+        privacy: Some(PrivacyPreferences {
+            hints: vec!["hash".to_string()],
+            builders: Vec::new(),
+        }),
+    };
+    let hash = web3
+        .eth_send_private_transaction(
+            &transaction,
+            Some(Uint256::from_u64(100)),
+            Some(preferences),
+        )
+        .await
+        .unwrap();
+    assert_eq!(hash, transaction.hash);
+    assert_eq!(
+        relay.params("eth_sendPrivateTransaction")[0],
+        json!([{
+            "tx": "0x02f801",
+            "maxBlockNumber": "0x64",
+            "preferences": {"fast": true, "privacy": {"hints": ["hash"]}}
+        }])
+    );
+
+    // every request carries the signature of its exact body
+    let (headers, body) = relay.raw_requests("eth_sendPrivateTransaction").remove(0);
+    assert_eq!(
+        headers[FLASHBOTS_SIGNATURE_HEADER].to_str().unwrap(),
+        flashbots_signature(&body, &signing_key)
+    );
+}
+
+#[tokio::test]
+async fn test_wait_for_private_transaction_fallback() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let transaction = SignedTx::new(vec![0x02, 0xf8, 0x01], Uint256::from_u64(0));
+    let hash = json!(transaction.hash);
+    let block = AtomicU64::new(100);
+    let node = MockNode::start(move |method, _| match method {
+        // a block passes on every poll
+        "eth_blockNumber" => Ok(json!(format!(
+            "{:#x}",
+            block.fetch_add(1, Ordering::Relaxed)
+        ))),
+        "eth_getTransactionReceipt" => Ok(Value::Null),
+        "eth_sendRawTransaction" => Ok(hash.clone()),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let mut web3 = Web3::new(&node.url, Duration::from_secs(5));
+    web3.set_poll_interval(Some(Duration::from_millis(10)));
+
+    // without the opt in the transaction is never broadcast publicly
+    let res = web3
+        .wait_for_private_transaction(&transaction, None, Duration::from_millis(200))
+        .await;
+    assert!(matches!(res, Err(Web3Error::TransactionTimeout(_))));
+    assert_eq!(node.calls("eth_sendRawTransaction"), 0);
+
+    let res = web3
+        .wait_for_private_transaction(&transaction, Some(3), Duration::from_millis(200))
+        .await;
+    assert!(matches!(res, Err(Web3Error::TransactionTimeout(_))));
+    assert_eq!(node.calls("eth_sendRawTransaction"), 1);
+}
+
+#[tokio::test]
+async fn test_send_bundle() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let relay = MockNode::start(|method, _| match method {
+        "eth_sendBundle" => Ok(json!({"bundleHash": format!("{:#066x}", 0xb0)})),
+        "eth_callBundle" => Ok(json!({
+            "bundleGasPrice": "476190476193",
+            "bundleHash": format!("{:#066x}", 0xb0),
+            "coinbaseDiff": "20000000000126000",
+            "ethSentToCoinbase": "20000000000000000",
+            "gasFees": "126000",
+            "results": [{
+                "coinbaseDiff": "10000000000063000",
+                "ethSentToCoinbase": "10000000000000000",
+                "fromAddress": "0x02a727155aef8609c9f7f2179b2a1f560b39f5a0",
+                "gasFees": "63000",
+                "gasPrice": "476190476193",
+                "gasUsed": 21000,
+                "toAddress": "0x73625f59cadc5009cb458b751b3e7b6b48c06f2c",
+                "txHash": format!("{:#066x}", 1),
+                "value": "0x"
+            }, {
+                "coinbaseDiff": "10000000000063000",
+                "ethSentToCoinbase": "10000000000000000",
+                "fromAddress": "0x02a727155aef8609c9f7f2179b2a1f560b39f5a0",
+                "gasFees": "63000",
+                "gasPrice": "476190476193",
+                "gasUsed": 21000,
+                "toAddress": "0x73625f59cadc5009cb458b751b3e7b6b48c06f2c",
+                "txHash": format!("{:#066x}", 2),
+                "error": "execution reverted",
+                "revert": "0x"
+            }],
+            "stateBlockNumber": 5221585,
+            "totalGasUsed": 42000
+        })),
+        "flashbots_getBundleStats" => Ok(json!({
+            "isHighPriority": true,
+            "isSimulated": true,
+            "simulatedAt": "2021-08-06T21:36:06.317Z",
+            "receivedAt": "2021-08-06T21:36:06.250Z",
+            "consideredByBuildersAt": []
+        })),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let signing_key: PrivateKey =
+        "0x8888888888888888888888888888888888888888888888888888888888888888"
+            .parse()
+            .unwrap();
+    let web3 = Web3::private_relay(&relay.url, signing_key, Duration::from_secs(5));
+    let txs = vec![vec![0x02, 0x01], vec![0x02, 0x02]];
+
+    let options = BundleOptions {
+        max_timestamp: Some(1_700_000_000),
+        reverting_tx_hashes: vec![Uint256::from_u64(2).into()],
+        ..Default::default()
+    };
+    let hash = web3
+        .send_bundle(txs.clone(), Uint256::from_u64(0x4fa9d2), options)
+        .await
+        .unwrap();
+    assert_eq!(hash, Uint256::from_u64(0xb0));
+    assert_eq!(
+        relay.params("eth_sendBundle")[0],
+        json!([{
+            "txs": ["0x0201", "0x0202"],
+            "blockNumber": "0x4fa9d2",
+            "maxTimestamp": 1_700_000_000,
+            "revertingTxHashes": [format!("{:#066x}", 2)]
+        }])
+    );
+    let (headers, body) = relay.raw_requests("eth_sendBundle").remove(0);
+    assert_eq!(
+        headers[FLASHBOTS_SIGNATURE_HEADER].to_str().unwrap(),
+        flashbots_signature(&body, &signing_key)
+    );
+
+    let simulation = web3
+        .call_bundle(txs, Uint256::from_u64(0x4fa9d2), BlockId::Latest, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        simulation.coinbase_diff,
+        Uint256::from_u64(20_000_000_000_126_000)
+    );
+    assert!(simulation.results[0].is_success());
+    assert!(!simulation.results[1].is_success());
+    assert_eq!(
+        relay.params("eth_callBundle")[0][0]["stateBlockNumber"],
+        json!("latest")
+    );
+
+    let stats = web3
+        .flashbots_get_bundle_stats(hash, Uint256::from_u64(0x4fa9d2))
+        .await
+        .unwrap();
+    assert!(stats.is_simulated && stats.is_high_priority);
+    assert!(stats.extra.contains_key("consideredByBuildersAt"));
+}
+
+#[tokio::test]
+async fn test_sign_bundle_transactions() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+
+    let node = MockNode::start(|method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_getBalance" => Ok(json!("0xde0b6b3a7640000")),
+        "eth_getTransactionCount" => Ok(json!("0x7")),
+        "net_version" => Ok(json!("1")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let key: PrivateKey = "0x8888888888888888888888888888888888888888888888888888888888888888"
+        .parse()
+        .unwrap();
+    let to: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+
+    let txs = web3
+        .sign_bundle_transactions(
+            vec![(to, Vec::new(), Uint256::from_u64(1)); 3],
+            &key,
+            vec![
+                SendTxOption::GasPrice(Uint256::from_u64(1)),
+                SendTxOption::GasLimit(Uint256::from_u64(21_000)),
+            ],
+        )
+        .await
+        .unwrap();
+    let nonces: Vec<Uint256> = txs.iter().map(|tx| tx.nonce).collect();
+    assert_eq!(
+        nonces,
+        vec![
+            Uint256::from_u64(7),
+            Uint256::from_u64(8),
+            Uint256::from_u64(9)
+        ]
+    );
+    assert_eq!(node.calls("eth_sendRawTransaction"), 0);
+}
@@ -0,0 +1,227 @@
+//! Finding the implementation contract behind upgradeable and minimal proxies, whose
+//! ABI is the one that should be used to call them and decode their events
+//!
+//! See more: https://eips.ethereum.org/EIPS/eip-1967 and https://eips.ethereum.org/EIPS/eip-1167
+use crate::client::Web3;
+use crate::hex::data_to_hex;
+use crate::jsonrpc::error::Web3Error;
+use crate::types::{BlockId, TransactionRequest};
+use clarity::abi::encode_call;
+use clarity::{Address, Uint256};
+
+/// EIP-1967 implementation slot, `keccak256("eip1967.proxy.implementation") - 1`
+pub const EIP1967_IMPLEMENTATION_SLOT: &str =
+    "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+/// EIP-1967 beacon slot, `keccak256("eip1967.proxy.beacon") - 1`
+pub const EIP1967_BEACON_SLOT: &str =
+    "0xa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+
+/// Runtime bytecode of an EIP-1167 minimal proxy before the implementation address
+const MINIMAL_PROXY_PREFIX: [u8; 10] = [0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d, 0x3d, 0x36, 0x3d, 0x73];
+/// Runtime bytecode of an EIP-1167 minimal proxy after the implementation address
+const MINIMAL_PROXY_SUFFIX: [u8; 15] = [
+    0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3,
+];
+
+/// The kind of proxy a contract is and the contract it delegates to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    /// an EIP-1167 minimal proxy, which can not be upgraded
+    MinimalProxy {
+        implementation: Address,
+    },
+    /// an EIP-1967 proxy with the implementation in its own storage, such as a
+    /// transparent or UUPS proxy
+    Eip1967 {
+        implementation: Address,
+    },
+    /// an EIP-1967 proxy that gets its implementation from a beacon contract
+    Eip1967Beacon {
+        beacon: Address,
+        implementation: Address,
+    },
+    NotAProxy,
+}
+
+impl ProxyKind {
+    /// The contract whose ABI should be used with the proxy, if it is a proxy
+    pub fn implementation(&self) -> Option<Address> {
+        match self {
+            ProxyKind::MinimalProxy { implementation }
+            | ProxyKind::Eip1967 { implementation }
+            | ProxyKind::Eip1967Beacon { implementation, .. } => Some(*implementation),
+            ProxyKind::NotAProxy => None,
+        }
+    }
+}
+
+/// Returns the implementation address of EIP-1167 minimal proxy runtime bytecode
+fn minimal_proxy_target(code: &[u8]) -> Option<Address> {
+    if code.len() != 45 || code[..10] != MINIMAL_PROXY_PREFIX || code[30..] != MINIMAL_PROXY_SUFFIX
+    {
+        return None;
+    }
+    bytes_to_address(&code[10..30])
+}
+
+/// Returns the address in the low 20 bytes of a storage word or return value, or
+/// `None` if it is zero which is what an unset slot reads as
+fn word_to_address(word: Uint256) -> Option<Address> {
+    if word == Uint256::default() || word.sig_bits() > 160 {
+        return None;
+    }
+    format!("0x{:040x}", word).parse().ok()
+}
+
+fn bytes_to_address(bytes: &[u8]) -> Option<Address> {
+    data_to_hex(bytes).parse().ok()
+}
+
+impl Web3 {
+    /// Detects whether `address` is an EIP-1167 minimal proxy or an EIP-1967 proxy, with
+    /// its implementation stored directly or behind a beacon, and returns the address of
+    /// the implementation. Contracts that are not proxies, or whose implementation has
+    /// not been set, are `ProxyKind::NotAProxy`.
+    pub async fn get_proxy_implementation(&self, address: Address) -> Result<ProxyKind, Web3Error> {
+        let code = self.eth_get_code(address, BlockId::Latest).await?;
+        if let Some(implementation) = minimal_proxy_target(&code) {
+            return Ok(ProxyKind::MinimalProxy { implementation });
+        }
+        if code.is_empty() {
+            return Ok(ProxyKind::NotAProxy);
+        }
+
+        let slot = self
+            .eth_get_storage_at(
+                address,
+                EIP1967_IMPLEMENTATION_SLOT.parse().unwrap(),
+                BlockId::Latest,
+            )
+            .await?;
+        if let Some(implementation) = word_to_address(slot) {
+            return Ok(ProxyKind::Eip1967 { implementation });
+        }
+
+        let slot = self
+            .eth_get_storage_at(
+                address,
+                EIP1967_BEACON_SLOT.parse().unwrap(),
+                BlockId::Latest,
+            )
+            .await?;
+        let beacon = match word_to_address(slot) {
+            Some(beacon) => beacon,
+            None => return Ok(ProxyKind::NotAProxy),
+        };
+        let res = self
+            .eth_call(TransactionRequest::call(
+                beacon,
+                encode_call("implementation()", &[])?,
+            ))
+            .await?;
+        match res
+            .get(0..32)
+            .and_then(Uint256::from_bytes_be)
+            .and_then(word_to_address)
+        {
+            Some(implementation) => Ok(ProxyKind::Eip1967Beacon {
+                beacon,
+                implementation,
+            }),
+            None => Err(Web3Error::ContractCallError(format!(
+                "Bad response from implementation() on beacon {}",
+                beacon
+            ))),
+        }
+    }
+}
+
+#[test]
+fn test_minimal_proxy_target() {
+    use clarity::utils::hex_str_to_bytes;
+
+    let code = hex_str_to_bytes(
+        "0x363d3d373d3d3d363d73bebebebebebebebebebebebebebebebebebebebe5af43d82803e903d91602b57fd5bf3",
+    )
+    .unwrap();
+    assert_eq!(
+        minimal_proxy_target(&code),
+        Some(
+            "0xbebebebebebebebebebebebebebebebebebebebe"
+                .parse()
+                .unwrap()
+        )
+    );
+    assert_eq!(minimal_proxy_target(&code[..44]), None);
+    assert_eq!(word_to_address(Uint256::default()), None);
+}
+
+#[tokio::test]
+async fn test_get_proxy_implementation() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let zero = format!("0x{:064x}", 0);
+    let word = |address: &str| format!("0x{:0>64}", address.trim_start_matches("0x"));
+    let node = MockNode::start(move |method, params| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_getCode" => match params[0].as_str().unwrap() {
+            "0x0000000000000000000000000000000000000000" => Ok(json!("0x")),
+            _ => Ok(json!("0x6080604052")),
+        },
+        // 0x1111.. is a transparent proxy and 0x2222.. is a beacon proxy
+        "eth_getStorageAt" => {
+            let address = params[0].as_str().unwrap();
+            let slot = params[1].as_str().unwrap();
+            if address.starts_with("0x1111") && slot == EIP1967_IMPLEMENTATION_SLOT {
+                Ok(json!(word("0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")))
+            } else if address.starts_with("0x2222") && slot == EIP1967_BEACON_SLOT {
+                Ok(json!(word("0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb")))
+            } else {
+                Ok(json!(zero))
+            }
+        }
+        "eth_call" => Ok(json!(word("0xcccccccccccccccccccccccccccccccccccccccc"))),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let parse = |address: &str| -> Address { address.parse().unwrap() };
+
+    assert_eq!(
+        web3.get_proxy_implementation(parse("0x1111111111111111111111111111111111111111"))
+            .await
+            .unwrap(),
+        ProxyKind::Eip1967 {
+            implementation: parse("0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")
+        }
+    );
+    let beacon = web3
+        .get_proxy_implementation(parse("0x2222222222222222222222222222222222222222"))
+        .await
+        .unwrap();
+    assert_eq!(
+        beacon,
+        ProxyKind::Eip1967Beacon {
+            beacon: parse("0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"),
+            implementation: parse("0xcccccccccccccccccccccccccccccccccccccccc"),
+        }
+    );
+    assert_eq!(
+        beacon.implementation(),
+        Some(parse("0xcccccccccccccccccccccccccccccccccccccccc"))
+    );
+    assert_eq!(
+        web3.get_proxy_implementation(parse("0x3333333333333333333333333333333333333333"))
+            .await
+            .unwrap(),
+        ProxyKind::NotAProxy
+    );
+    assert_eq!(
+        web3.get_proxy_implementation(parse("0x0000000000000000000000000000000000000000"))
+            .await
+            .unwrap(),
+        ProxyKind::NotAProxy
+    );
+    assert_eq!(node.calls("eth_call"), 1);
+}
@@ -0,0 +1,215 @@
+//! Serde helpers for JSON-RPC quantities. The spec requires minimal 0x prefixed hex, but
+//! providers variously send leading zeros ("0x01", "0x00"), decimal strings or plain JSON
+//! numbers, especially for fields like `chainId` from older clients. All of these are
+//! accepted when deserializing and quantities are always serialized as minimal hex.
+//!
+//! Use `#[serde(with = "quantity")]` on `Uint256` fields, `quantity::option` on
+//! `Option<Uint256>` fields (together with `default`) and `quantity::vec` on `Vec<Uint256>`.
+use clarity::Uint256;
+use serde::de::{Error, Unexpected, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Formatter, Result as FmtResult};
+
+/// Parses a quantity from 0x prefixed hex, with or without leading zeros, or from a decimal
+/// string. Empty hex ("0x") is rejected as it is not a number.
+pub fn parse_quantity(s: &str) -> Result<Uint256, String> {
+    let (digits, radix) = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None => (s, 10),
+    };
+    if digits.is_empty() {
+        return Err(format!("{:?} is not a quantity", s));
+    }
+    let mut n = Uint256::default();
+    for c in digits.chars() {
+        let digit = match c.to_digit(radix) {
+            Some(digit) => digit,
+            None => return Err(format!("{:?} is not a hex or decimal quantity", s)),
+        };
+        n = match n
+            .checked_mul(Uint256::from_u64(radix as u64))
+            .and_then(|n| n.checked_add(Uint256::from_u64(digit as u64)))
+        {
+            Some(n) => n,
+            None => return Err(format!("{:?} does not fit in 256 bits", s)),
+        };
+    }
+    Ok(n)
+}
+
+/// A `Uint256` that deserializes leniently and serializes as minimal hex, for use inside of
+/// containers the `with` modules here do not cover
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quantity(pub Uint256);
+
+impl From<Quantity> for Uint256 {
+    fn from(q: Quantity) -> Self {
+        q.0
+    }
+}
+
+impl Serialize for Quantity {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0.to_hex_string())
+    }
+}
+
+struct QuantityVisitor;
+
+impl<'de> Visitor<'de> for QuantityVisitor {
+    type Value = Quantity;
+
+    fn expecting(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "a hex or decimal quantity")
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<Quantity, E> {
+        parse_quantity(v).map(Quantity).map_err(E::custom)
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<Quantity, E> {
+        Ok(Quantity(Uint256::from_u64(v)))
+    }
+
+    fn visit_u128<E: Error>(self, v: u128) -> Result<Quantity, E> {
+        Ok(Quantity(Uint256::from_u128(v)))
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<Quantity, E> {
+        match u64::try_from(v) {
+            Ok(v) => self.visit_u64(v),
+            Err(_) => Err(E::invalid_value(Unexpected::Signed(v), &self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(QuantityVisitor)
+    }
+}
+
+pub fn serialize<S>(n: &Uint256, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    Quantity(*n).serialize(serializer)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Uint256, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Quantity::deserialize(deserializer).map(Uint256::from)
+}
+
+/// For `Option<Uint256>` fields, null is `None`
+pub mod option {
+    use super::Quantity;
+    use clarity::Uint256;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(n: &Option<Uint256>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        n.map(Quantity).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Uint256>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Option::<Quantity>::deserialize(deserializer)?.map(Uint256::from))
+    }
+}
+
+/// For `Vec<Uint256>` fields
+pub mod vec {
+    use super::Quantity;
+    use clarity::Uint256;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(n: &[Uint256], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(n.iter().copied().map(Quantity))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Uint256>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let n: Vec<Quantity> = Vec::deserialize(deserializer)?;
+        Ok(n.into_iter().map(Uint256::from).collect())
+    }
+}
+
+#[test]
+fn test_provider_quantity_encodings() {
+    use serde_json::json;
+
+    let n = |v: u64| Uint256::from_u64(v);
+    // (observed encoding, value)
+    let cases = [
+        // canonical
+        (json!("0x0"), n(0)),
+        (json!("0x1"), n(1)),
+        (json!("0x5208"), n(21000)),
+        // leading zeros, seen from Ankr and BSC nodes
+        (json!("0x00"), n(0)),
+        (json!("0x01"), n(1)),
+        (
+            json!("0x0000000000000000000000000000000000000000000000000000000000005208"),
+            n(21000),
+        ),
+        // uppercase digits
+        (json!("0x52AB"), n(0x52ab)),
+        // decimal strings, such as the chainId of older clients behind Alchemy
+        (json!("56"), n(56)),
+        (json!("0"), n(0)),
+        // plain JSON numbers
+        (json!(56), n(56)),
+        (json!(0), n(0)),
+        (json!(u64::MAX), n(u64::MAX)),
+    ];
+    for (encoded, value) in cases {
+        let decoded: Quantity = serde_json::from_value(encoded.clone()).unwrap();
+        assert_eq!(decoded.0, value, "{}", encoded);
+        // whatever came in, what goes out is minimal hex
+        assert_eq!(
+            serde_json::to_value(decoded).unwrap(),
+            json!(value.to_hex_string())
+        );
+    }
+    assert_eq!(serde_json::to_value(Quantity(n(0))).unwrap(), json!("0x0"));
+    assert_eq!(serde_json::to_value(Quantity(n(1))).unwrap(), json!("0x1"));
+
+    for bad in [
+        json!("0x"),
+        json!(""),
+        json!("0xzz"),
+        json!("1.5"),
+        json!(-1),
+        json!(1.5),
+        json!(null),
+        json!(format!("0x1{}", "0".repeat(64))),
+    ] {
+        assert!(
+            serde_json::from_value::<Quantity>(bad.clone()).is_err(),
+            "{}",
+            bad
+        );
+    }
+    assert_eq!(
+        serde_json::from_value::<Option<Quantity>>(json!(null)).unwrap(),
+        None
+    );
+}
@@ -0,0 +1,517 @@
+//! Decoding of raw signed transactions, as seen in the mempool or passed to
+//! eth_sendRawTransaction, in the legacy format and the EIP-2718 typed envelopes
+//!
+//! See more: https://eips.ethereum.org/EIPS/eip-2718 and https://ethereum.org/en/developers/docs/data-structures-and-encoding/rlp/
+use crate::hash::{TxHash, H256};
+use crate::hex::data_to_hex;
+use crate::jsonrpc::error::Web3Error;
+use crate::keccak::keccak256;
+use crate::types::AccessListItem;
+use clarity::{Address, Signature, Uint256};
+use sha3::{Digest, Keccak256};
+
+/// A decoded RLP item, keeping the encoded bytes so that it can be re-encoded unchanged
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RlpItem<'a> {
+    raw: &'a [u8],
+    kind: RlpKind<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RlpKind<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<RlpItem<'a>>),
+}
+
+/// How deeply RLP lists may nest, transactions need 4 levels for the storage keys of their
+/// access lists and anything deeper is refused rather than recursed into
+const MAX_RLP_DEPTH: usize = 16;
+
+fn bad(msg: String) -> Web3Error {
+    Web3Error::BadInput(format!("Invalid raw transaction: {}", msg))
+}
+
+/// Reads a big endian length of `len_of_len` bytes following the prefix byte
+fn read_length(data: &[u8], len_of_len: usize) -> Result<usize, Web3Error> {
+    let bytes = match data.get(1..1 + len_of_len) {
+        Some(bytes) => bytes,
+        None => return Err(bad("RLP length is truncated".to_string())),
+    };
+    if bytes[0] == 0 {
+        return Err(bad("RLP length has leading zeros".to_string()));
+    }
+    if len_of_len > 8 {
+        return Err(bad("RLP length is too large".to_string()));
+    }
+    let len = bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+    if len < 56 {
+        return Err(bad("RLP long form used for a short length".to_string()));
+    }
+    Ok(len as usize)
+}
+
+/// Decodes the first RLP item of `data`, returning it and the remaining bytes
+fn decode_rlp(data: &[u8]) -> Result<(RlpItem, &[u8]), Web3Error> {
+    decode_rlp_nested(data, 0)
+}
+
+/// Decodes an RLP item inside `depth` lists
+fn decode_rlp_nested(data: &[u8], depth: usize) -> Result<(RlpItem, &[u8]), Web3Error> {
+    let prefix = match data.first() {
+        Some(prefix) => *prefix as usize,
+        None => return Err(bad("unexpected end of RLP".to_string())),
+    };
+    let (header, len, is_list) = match prefix {
+        0x00..=0x7f => (0, 1, false),
+        0x80..=0xb7 => (1, prefix - 0x80, false),
+        0xb8..=0xbf => {
+            let len_of_len = prefix - 0xb7;
+            (1 + len_of_len, read_length(data, len_of_len)?, false)
+        }
+        0xc0..=0xf7 => (1, prefix - 0xc0, true),
+        _ => {
+            let len_of_len = prefix - 0xf7;
+            (1 + len_of_len, read_length(data, len_of_len)?, true)
+        }
+    };
+    let end = match header.checked_add(len) {
+        Some(end) if end <= data.len() => end,
+        _ => {
+            return Err(bad(format!(
+                "RLP item of {} bytes overruns the {} bytes available",
+                len,
+                data.len()
+            )))
+        }
+    };
+    let payload = &data[header..end];
+    let kind = if is_list {
+        if depth >= MAX_RLP_DEPTH {
+            return Err(bad(format!(
+                "RLP lists nested more than {} deep",
+                MAX_RLP_DEPTH
+            )));
+        }
+        let mut items = Vec::new();
+        let mut rest = payload;
+        while !rest.is_empty() {
+            let (item, remaining) = decode_rlp_nested(rest, depth + 1)?;
+            items.push(item);
+            rest = remaining;
+        }
+        RlpKind::List(items)
+    } else {
+        if header == 1 && len == 1 && payload[0] < 0x80 {
+            return Err(bad("RLP single byte is not encoded as itself".to_string()));
+        }
+        RlpKind::Bytes(payload)
+    };
+    Ok((
+        RlpItem {
+            raw: &data[..end],
+            kind,
+        },
+        &data[end..],
+    ))
+}
+
+/// Encodes the RLP header for a payload of `len` bytes, `offset` is 0x80 for bytes and
+/// 0xc0 for lists
+fn rlp_header(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let len_bytes: Vec<u8> = (len as u64)
+        .to_be_bytes()
+        .iter()
+        .skip_while(|b| **b == 0)
+        .copied()
+        .collect();
+    let mut res = vec![offset + 55 + len_bytes.len() as u8];
+    res.extend(len_bytes);
+    res
+}
+
+pub(crate) fn rlp_encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut res = rlp_header(bytes.len(), 0x80);
+    res.extend_from_slice(bytes);
+    res
+}
+
+/// Encodes a list from already encoded items
+pub(crate) fn rlp_encode_list(items: &[&[u8]]) -> Vec<u8> {
+    let payload: Vec<u8> = items.concat();
+    let mut res = rlp_header(payload.len(), 0xc0);
+    res.extend(payload);
+    res
+}
+
+fn list<'a, 'b>(item: &'b RlpItem<'a>, what: &str) -> Result<&'b [RlpItem<'a>], Web3Error> {
+    match &item.kind {
+        RlpKind::List(items) => Ok(items),
+        RlpKind::Bytes(_) => Err(bad(format!("{} is not a list", what))),
+    }
+}
+
+fn bytes<'a>(item: &RlpItem<'a>, what: &str) -> Result<&'a [u8], Web3Error> {
+    match item.kind {
+        RlpKind::Bytes(bytes) => Ok(bytes),
+        RlpKind::List(_) => Err(bad(format!("{} is a list", what))),
+    }
+}
+
+fn uint(item: &RlpItem, what: &str) -> Result<Uint256, Web3Error> {
+    let bytes = bytes(item, what)?;
+    if bytes.len() > 32 {
+        return Err(bad(format!("{} is larger than 32 bytes", what)));
+    }
+    if bytes.first() == Some(&0) {
+        return Err(bad(format!("{} has leading zeros", what)));
+    }
+    let mut word = [0u8; 32];
+    word[32 - bytes.len()..].copy_from_slice(bytes);
+    Ok(Uint256::from_bytes_be(&word).unwrap())
+}
+
+fn address(bytes: &[u8], what: &str) -> Result<Address, Web3Error> {
+    if bytes.len() != 20 {
+        return Err(bad(format!("{} is {} bytes, not 20", what, bytes.len())));
+    }
+    Ok(data_to_hex(bytes).parse().unwrap())
+}
+
+/// An empty `to` creates a contract
+fn to_address(item: &RlpItem) -> Result<Option<Address>, Web3Error> {
+    match bytes(item, "to")? {
+        [] => Ok(None),
+        to => Ok(Some(address(to, "to")?)),
+    }
+}
+
+fn access_list(item: &RlpItem) -> Result<Vec<AccessListItem>, Web3Error> {
+    let mut res = Vec::new();
+    for entry in list(item, "access list")? {
+        match list(entry, "access list entry")? {
+            [entry_address, keys] => {
+                let mut storage_keys = Vec::new();
+                for key in list(keys, "storage keys")? {
+                    let key = bytes(key, "storage key")?;
+                    if key.len() != 32 {
+                        return Err(bad("storage key is not 32 bytes".to_string()));
+                    }
+                    storage_keys.push(Uint256::from_bytes_be(key).unwrap());
+                }
+                res.push(AccessListItem {
+                    address: address(
+                        bytes(entry_address, "access list address")?,
+                        "access list address",
+                    )?,
+                    storage_keys,
+                });
+            }
+            _ => return Err(bad("access list entry is not [address, keys]".to_string())),
+        }
+    }
+    Ok(res)
+}
+
+/// A transaction decoded from its raw signed bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedTransaction {
+    /// 0 for legacy transactions, 1 for EIP-2930 and 2 for EIP-1559
+    pub tx_type: u8,
+    /// None only for legacy transactions without EIP-155 replay protection
+    pub chain_id: Option<Uint256>,
+    pub nonce: Uint256,
+    /// the gas price of legacy and EIP-2930 transactions
+    pub gas_price: Option<Uint256>,
+    pub max_fee_per_gas: Option<Uint256>,
+    pub max_priority_fee_per_gas: Option<Uint256>,
+    pub gas_limit: Uint256,
+    /// None for contract creation
+    pub to: Option<Address>,
+    pub value: Uint256,
+    pub data: Vec<u8>,
+    /// empty for legacy transactions
+    pub access_list: Vec<AccessListItem>,
+    /// the v value as it appears in the transaction, the y parity for typed transactions
+    pub v: Uint256,
+    pub r: Uint256,
+    pub s: Uint256,
+    /// the sender, recovered from the signature
+    pub from: Address,
+    /// the transaction hash
+    pub hash: TxHash,
+}
+
+/// Decodes a raw signed legacy or EIP-2718 typed (1 or 2) transaction and recovers its sender
+pub fn decode_raw_transaction(raw: &[u8]) -> Result<DecodedTransaction, Web3Error> {
+    let (tx_type, payload) = match raw.first() {
+        None => return Err(bad("empty input".to_string())),
+        Some(t @ (1 | 2)) => (*t, &raw[1..]),
+        Some(t) if *t < 0x7f => return Err(bad(format!("unsupported type {}", t))),
+        Some(_) => (0, raw),
+    };
+    let (item, rest) = decode_rlp(payload)?;
+    if !rest.is_empty() {
+        return Err(bad(format!("{} trailing bytes", rest.len())));
+    }
+    let fields = list(&item, "transaction")?;
+    let expected = match tx_type {
+        0 => 9,
+        1 => 11,
+        _ => 12,
+    };
+    if fields.len() != expected {
+        return Err(bad(format!(
+            "type {} transaction has {} fields, expected {}",
+            tx_type,
+            fields.len(),
+            expected
+        )));
+    }
+    let (unsigned, signature) = fields.split_at(expected - 3);
+    let v = uint(&signature[0], "v")?;
+    let r = uint(&signature[1], "r")?;
+    let s = uint(&signature[2], "s")?;
+    let raw_fields: Vec<&[u8]> = unsigned.iter().map(|item| item.raw).collect();
+
+    let mut decoded = DecodedTransaction {
+        tx_type,
+        chain_id: None,
+        nonce: Uint256::default(),
+        gas_price: None,
+        max_fee_per_gas: None,
+        max_priority_fee_per_gas: None,
+        gas_limit: Uint256::default(),
+        to: None,
+        value: Uint256::default(),
+        data: Vec::new(),
+        access_list: Vec::new(),
+        v,
+        r,
+        s,
+        from: Address::default(),
+        hash: H256(keccak256(raw)),
+    };
+    let (signing_payload, parity) = match tx_type {
+        0 => {
+            decoded.nonce = uint(&unsigned[0], "nonce")?;
+            decoded.gas_price = Some(uint(&unsigned[1], "gas price")?);
+            decoded.gas_limit = uint(&unsigned[2], "gas limit")?;
+            decoded.to = to_address(&unsigned[3])?;
+            decoded.value = uint(&unsigned[4], "value")?;
+            decoded.data = bytes(&unsigned[5], "data")?.to_vec();
+            if v >= Uint256::from_u64(35) {
+                // EIP-155, v is chain_id * 2 + 35 + parity
+                let (chain_id, parity) = v
+                    .checked_sub(Uint256::from_u64(35))
+                    .and_then(|v| v.divide(Uint256::from_u64(2)))
+                    .unwrap();
+                decoded.chain_id = Some(chain_id);
+                let chain_id = rlp_encode_bytes(&strip_zeros(&chain_id));
+                let mut items = raw_fields.clone();
+                items.extend_from_slice(&[&chain_id, &[0x80], &[0x80]]);
+                (rlp_encode_list(&items), parity)
+            } else if v == Uint256::from_u64(27) || v == Uint256::from_u64(28) {
+                (
+                    rlp_encode_list(&raw_fields),
+                    v.checked_sub(Uint256::from_u64(27)).unwrap(),
+                )
+            } else {
+                return Err(bad(format!("invalid legacy v {}", v)));
+            }
+        }
+        _ => {
+            decoded.chain_id = Some(uint(&unsigned[0], "chain id")?);
+            decoded.nonce = uint(&unsigned[1], "nonce")?;
+            let rest = if tx_type == 1 {
+                decoded.gas_price = Some(uint(&unsigned[2], "gas price")?);
+                &unsigned[3..]
+            } else {
+                decoded.max_priority_fee_per_gas =
+                    Some(uint(&unsigned[2], "max priority fee per gas")?);
+                decoded.max_fee_per_gas = Some(uint(&unsigned[3], "max fee per gas")?);
+                &unsigned[4..]
+            };
+            decoded.gas_limit = uint(&rest[0], "gas limit")?;
+            decoded.to = to_address(&rest[1])?;
+            decoded.value = uint(&rest[2], "value")?;
+            decoded.data = bytes(&rest[3], "data")?.to_vec();
+            decoded.access_list = access_list(&rest[4])?;
+            if v > Uint256::from_u64(1) {
+                return Err(bad(format!("invalid y parity {}", v)));
+            }
+            let mut payload = vec![tx_type];
+            payload.extend(rlp_encode_list(&raw_fields));
+            (payload, v)
+        }
+    };
+
+    let hash = Keccak256::digest(&signing_payload);
+    let v = parity.checked_add(Uint256::from_u64(27)).unwrap();
+    let signature = Signature::new(v, r, s);
+    decoded.from = match signature.recover(&hash) {
+        Ok(from) => from,
+        Err(e) => return Err(bad(format!("can not recover sender: {:?}", e))),
+    };
+    Ok(decoded)
+}
+
+/// Returns the big endian bytes of `value` without leading zeros
+pub(crate) fn strip_zeros(value: &Uint256) -> Vec<u8> {
+    let word = match clarity::abi::Token::Uint(*value).serialize() {
+        clarity::abi::SerializedToken::Static(word) => word,
+        clarity::abi::SerializedToken::Dynamic(_) => unreachable!(),
+    };
+    word.iter().skip_while(|b| **b == 0).copied().collect()
+}
+
+#[cfg(test)]
+const TEST_KEY: &str = "0x0123456789012345678901234567890123456789012345678901234567890123";
+
+#[test]
+fn test_decode_clarity_legacy_transaction() {
+    use clarity::{PrivateKey, Transaction};
+
+    let key: PrivateKey = TEST_KEY.parse().unwrap();
+    let to: Address = "0x3535353535353535353535353535353535353535"
+        .parse()
+        .unwrap();
+    let transaction = Transaction {
+        to,
+        nonce: Uint256::from_u64(9),
+        gas_price: Uint256::from_u64(20_000_000_000),
+        gas_limit: Uint256::from_u64(21000),
+        value: Uint256::from_u128(1_000_000_000_000_000_000),
+        data: vec![0xa9, 0x05, 0x9c, 0xbb],
+        signature: None,
+    };
+    let raw = transaction.sign(&key, Some(1)).to_bytes().unwrap();
+
+    let decoded = decode_raw_transaction(&raw).unwrap();
+    assert_eq!(decoded.tx_type, 0);
+    assert_eq!(decoded.chain_id, Some(Uint256::from_u64(1)));
+    assert_eq!(decoded.nonce, Uint256::from_u64(9));
+    assert_eq!(decoded.gas_price, Some(Uint256::from_u64(20_000_000_000)));
+    assert_eq!(decoded.gas_limit, Uint256::from_u64(21000));
+    assert_eq!(decoded.to, Some(to));
+    assert_eq!(decoded.value, Uint256::from_u128(1_000_000_000_000_000_000));
+    assert_eq!(decoded.data, vec![0xa9, 0x05, 0x9c, 0xbb]);
+    assert_eq!(decoded.from, key.to_address());
+    assert_eq!(decoded.hash, H256(keccak256(&raw)));
+}
+
+#[test]
+fn test_decode_typed_transactions() {
+    use clarity::utils::hex_str_to_bytes;
+
+    let sender: Address = "0x14791697260e4c9a71f18484c9f997b308e59325"
+        .parse()
+        .unwrap();
+
+    let raw = hex_str_to_bytes(
+        "0x02f8b00109847735940085174876e80082c35094353535353535353535353535353535353535353588\
+         0de0b6b3a764000084a9059cbbf838f7941111111111111111111111111111111111111111e1a00000\
+         00000000000000000000000000000000000000000000000000000000000501a06ca57aa1ffe2da8da6\
+         b2300b34b5f9466afdc03ed1f0836a08ac963308aa0442a00cf035e62bf014b7a2e53746972158225f\
+         d85ba83fde83a7c17b2549a469ed76",
+    )
+    .unwrap();
+    let decoded = decode_raw_transaction(&raw).unwrap();
+    assert_eq!(decoded.tx_type, 2);
+    assert_eq!(decoded.chain_id, Some(Uint256::from_u64(1)));
+    assert_eq!(decoded.nonce, Uint256::from_u64(9));
+    assert_eq!(decoded.gas_price, None);
+    assert_eq!(
+        decoded.max_priority_fee_per_gas,
+        Some(Uint256::from_u64(2_000_000_000))
+    );
+    assert_eq!(
+        decoded.max_fee_per_gas,
+        Some(Uint256::from_u64(100_000_000_000))
+    );
+    assert_eq!(decoded.gas_limit, Uint256::from_u64(50000));
+    assert_eq!(decoded.data, vec![0xa9, 0x05, 0x9c, 0xbb]);
+    assert_eq!(decoded.access_list.len(), 1);
+    assert_eq!(
+        decoded.access_list[0].storage_keys,
+        vec![Uint256::from_u64(5)]
+    );
+    assert_eq!(decoded.from, sender);
+    assert_eq!(
+        decoded.hash.to_string(),
+        "0x3668287d53fd67faf2111772a859b6d6f2b24d80033649a39eefa9a2d38d264c"
+    );
+
+    let raw = hex_str_to_bytes(
+        "0x01f86605808504a817c8008252089435353535353535353535353535353535353535350180c001a0\
+         a30480685314814204cad5fed31ae5dafd892ff8f319639d71315415bb4c88b3a02d7a62c9c665bd90\
+         abcf21fb612e6632fa844343011349342664e73aa7879106",
+    )
+    .unwrap();
+    let decoded = decode_raw_transaction(&raw).unwrap();
+    assert_eq!(decoded.tx_type, 1);
+    assert_eq!(decoded.chain_id, Some(Uint256::from_u64(5)));
+    assert_eq!(decoded.nonce, Uint256::from_u64(0));
+    assert_eq!(decoded.gas_price, Some(Uint256::from_u64(20_000_000_000)));
+    assert!(decoded.access_list.is_empty());
+    assert_eq!(decoded.from, sender);
+
+    // a contract creation without replay protection
+    let raw = hex_str_to_bytes(
+        "0xf85303843b9aca0082520880808560806040521ba054ef56346904ec17ba8ad38d07e591f2379\
+         0e0625045c6b1c61417daddd1c5d99fdb7eace2903b82362f11959e0168c715e197776729e0454e\
+         789818571065bc",
+    )
+    .unwrap();
+    let decoded = decode_raw_transaction(&raw).unwrap();
+    assert_eq!(decoded.chain_id, None);
+    assert_eq!(decoded.to, None);
+    assert_eq!(decoded.data, vec![0x60, 0x80, 0x60, 0x40, 0x52]);
+    assert_eq!(decoded.from, sender);
+}
+
+#[test]
+fn test_decode_malformed_transactions() {
+    let errors = [
+        vec![],
+        vec![0x03, 0xc0],
+        vec![0x02],
+        vec![0xc0],
+        // a list claiming more bytes than there are
+        vec![0xf8, 0xff, 0x01],
+        // a single byte that should have been encoded as itself
+        vec![0xc1, 0x81, 0x01],
+        // trailing bytes
+        vec![0xc0, 0x00],
+    ];
+    for raw in errors.iter() {
+        assert!(matches!(
+            decode_raw_transaction(raw),
+            Err(Web3Error::BadInput(_))
+        ));
+    }
+
+    // deeply nested lists are refused instead of overflowing the stack
+    let mut nested = vec![0xc0];
+    for _ in 0..1000 {
+        let mut outer = rlp_header(nested.len(), 0xc0);
+        outer.extend_from_slice(&nested);
+        nested = outer;
+    }
+    assert!(matches!(
+        decode_raw_transaction(&nested),
+        Err(Web3Error::BadInput(e)) if e.contains("nested")
+    ));
+    let mut nested = vec![0xc0];
+    for _ in 1..MAX_RLP_DEPTH {
+        let mut outer = rlp_header(nested.len(), 0xc0);
+        outer.extend_from_slice(&nested);
+        nested = outer;
+    }
+    assert!(decode_rlp(&nested).is_ok());
+}
@@ -0,0 +1,232 @@
+//! This module contains functions for decoding revert data and finding out why an
+//! already mined transaction failed
+use crate::hash::TxHash;
+use crate::jsonrpc::error::Web3Error;
+use crate::{
+    client::Web3,
+    types::{BlockId, Data, TransactionRequest},
+};
+use clarity::abi::derive_method_id;
+use clarity::utils::hex_str_to_bytes;
+use clarity::Uint256;
+use serde_json::Value;
+
+/// The selector of `Error(string)`, used by `require` and `revert` with a message
+pub const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// The selector of `Panic(uint256)`, used for failed asserts, overflows and similar
+pub const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// The decoded reason a call reverted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevertReason {
+    /// `Error(string)` with its message
+    Error(String),
+    /// `Panic(uint256)` with its panic code, for example 0x11 for an arithmetic overflow
+    Panic(Uint256),
+    /// one of the custom errors passed in by the caller, with the abi encoded arguments
+    CustomError { signature: String, args: Vec<u8> },
+    /// revert data that did not match any known error
+    Unknown(Vec<u8>),
+}
+
+/// The result of re-executing a failed transaction with `Web3::get_revert_reason`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevertOutcome {
+    /// the simulation reverted with data that was decoded
+    Reverted(RevertReason),
+    /// the simulation reverted without any revert data
+    RevertedWithoutReason,
+    /// the simulation did not revert, so the failure depended on state that changed within
+    /// the block, such as an earlier transaction in the same block
+    SimulationSucceeded,
+}
+
+/// Decodes the abi encoded string argument of `Error(string)` from the data after the selector
+fn decode_error_string(args: &[u8]) -> Option<String> {
+    let offset = Uint256::from_bytes_be(args.get(0..32)?)?;
+    let offset: usize = offset.to_string().parse().ok()?;
+    let len = Uint256::from_bytes_be(args.get(offset..offset.checked_add(32)?)?)?;
+    let len: usize = len.to_string().parse().ok()?;
+    let start = offset + 32;
+    let bytes = args.get(start..start.checked_add(len)?)?;
+    String::from_utf8(bytes.to_vec()).ok()
+}
+
+/// Decodes revert data into a `RevertReason`, `custom_errors` are signatures such as
+/// "InsufficientBalance(uint256,uint256)" that are matched against the selector
+pub fn decode_revert_data(data: &[u8], custom_errors: &[&str]) -> Result<RevertReason, Web3Error> {
+    let (selector, args) = match data.get(0..4) {
+        Some(selector) => (selector, &data[4..]),
+        None => return Ok(RevertReason::Unknown(data.to_vec())),
+    };
+    if selector == ERROR_SELECTOR {
+        if let Some(message) = decode_error_string(args) {
+            return Ok(RevertReason::Error(message));
+        }
+    } else if selector == PANIC_SELECTOR {
+        if let Some(code) = args.get(0..32).and_then(Uint256::from_bytes_be) {
+            return Ok(RevertReason::Panic(code));
+        }
+    }
+    for signature in custom_errors {
+        if derive_method_id(signature)? == selector {
+            return Ok(RevertReason::CustomError {
+                signature: signature.to_string(),
+                args: args.to_vec(),
+            });
+        }
+    }
+    Ok(RevertReason::Unknown(data.to_vec()))
+}
+
+/// Extracts the revert data from the error data of a JSON-RPC error, nodes either return
+/// it as a hex string or nest it in an object under "data"
+pub(crate) fn revert_data_from_error(data: &Value) -> Option<Vec<u8>> {
+    match data {
+        Value::String(hex) => hex_str_to_bytes(hex).ok(),
+        Value::Object(map) => revert_data_from_error(map.get("data")?),
+        _ => None,
+    }
+}
+
+impl Web3 {
+    /// Finds out why an already mined transaction failed by executing it again with eth_call
+    /// on top of the block before the one it was mined in and decoding the revert data.
+    ///
+    /// This is best effort, the transaction originally ran after the transactions before it in
+    /// its block, which this simulation does not include, so the result may differ. This is
+    /// reported as `SimulationSucceeded` if the simulation does not revert at all. Requires a
+    /// node with the state of the parent block, for old transactions that means an archive node.
+    pub async fn get_revert_reason(
+        &self,
+        tx_hash: impl Into<TxHash>,
+        custom_errors: &[&str],
+    ) -> Result<RevertOutcome, Web3Error> {
+        let tx_hash = tx_hash.into();
+        let tx = match self.eth_get_transaction_by_hash(tx_hash).await? {
+            Some(tx) => tx,
+            None => {
+                return Err(Web3Error::BadInput(format!(
+                    "Transaction {} not found",
+                    tx_hash
+                )))
+            }
+        };
+        let (block, to) = match (tx.block_number, tx.to) {
+            (Some(block), Some(to)) => (block, to),
+            (None, _) => {
+                return Err(Web3Error::BadInput(format!(
+                    "Transaction {} has not been mined",
+                    tx_hash
+                )))
+            }
+            (_, None) => {
+                return Err(Web3Error::BadInput(
+                    "Contract creations can not be simulated with eth_call".to_string(),
+                ))
+            }
+        };
+        let parent = match block.checked_sub(Uint256::from_u64(1)) {
+            Some(parent) => parent,
+            None => return Err(Web3Error::BadInput("Can not simulate genesis".to_string())),
+        };
+
+        let request = TransactionRequest::builder()
+            .from(tx.from)
+            .to(to)
+            .gas(tx.gas)
+            .value(tx.value)
+            .data(tx.input.0)
+            .build()?;
+        let res: Result<Data, Web3Error> = self
+            .jsonrpc_client
+            .request_method("eth_call", (request, BlockId::Number(parent)), self.timeout)
+            .await;
+        match res {
+            Ok(_) => Ok(RevertOutcome::SimulationSucceeded),
+            Err(Web3Error::JsonRpcError {
+                code,
+                message,
+                data,
+                request_id,
+            }) => match data.as_ref().and_then(revert_data_from_error) {
+                Some(data) if !data.is_empty() => Ok(RevertOutcome::Reverted(decode_revert_data(
+                    &data,
+                    custom_errors,
+                )?)),
+                _ => match message.strip_prefix("execution reverted: ") {
+                    Some(reason) => Ok(RevertOutcome::Reverted(RevertReason::Error(
+                        reason.to_string(),
+                    ))),
+                    None if message.contains("revert") => Ok(RevertOutcome::RevertedWithoutReason),
+                    // not a revert, for example the node is missing the state of the block
+                    None => Err(Web3Error::JsonRpcError {
+                        code,
+                        message,
+                        data,
+                        request_id,
+                    }),
+                },
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[test]
+fn test_decode_revert_data() {
+    // Error("Not enough Ether provided.")
+    let data = hex_str_to_bytes(
+        "0x08c379a0\
+         0000000000000000000000000000000000000000000000000000000000000020\
+         000000000000000000000000000000000000000000000000000000000000001a\
+         4e6f7420656e6f7567682045746865722070726f76696465642e000000000000",
+    )
+    .unwrap();
+    assert_eq!(
+        decode_revert_data(&data, &[]).unwrap(),
+        RevertReason::Error("Not enough Ether provided.".to_string())
+    );
+
+    // Panic(0x11), arithmetic overflow
+    let data = hex_str_to_bytes(
+        "0x4e487b710000000000000000000000000000000000000000000000000000000000000011",
+    )
+    .unwrap();
+    assert_eq!(
+        decode_revert_data(&data, &[]).unwrap(),
+        RevertReason::Panic(Uint256::from_u64(0x11))
+    );
+
+    let mut data = derive_method_id("Unauthorized()").unwrap().to_vec();
+    assert_eq!(
+        decode_revert_data(&data, &["Paused()", "Unauthorized()"]).unwrap(),
+        RevertReason::CustomError {
+            signature: "Unauthorized()".to_string(),
+            args: vec![]
+        }
+    );
+    data.push(1);
+    assert!(matches!(
+        decode_revert_data(&data, &[]).unwrap(),
+        RevertReason::Unknown(_)
+    ));
+    // truncated Error(string)
+    assert!(matches!(
+        decode_revert_data(&ERROR_SELECTOR, &[]).unwrap(),
+        RevertReason::Unknown(_)
+    ));
+}
+
+#[test]
+fn test_revert_data_from_error() {
+    assert_eq!(
+        revert_data_from_error(&serde_json::json!("0x4e487b71")),
+        Some(vec![0x4e, 0x48, 0x7b, 0x71])
+    );
+    assert_eq!(
+        revert_data_from_error(&serde_json::json!({"data": "0x4e487b71"})),
+        Some(vec![0x4e, 0x48, 0x7b, 0x71])
+    );
+    assert_eq!(revert_data_from_error(&serde_json::json!(3)), None);
+}
@@ -0,0 +1,185 @@
+//! Signing of transactions sent with `Web3::send_transaction_with_signer`, implement
+//! `TransactionSigner` to keep keys outside of this process, such as in an HSM or a
+//! remote signing service
+use crate::client::Web3;
+use crate::jsonrpc::error::Web3Error;
+use crate::offline_tx::{build_transaction, TransactionParams};
+use crate::types::{Data, TransactionRequest};
+use clarity::{Address, PrivateKey, Transaction};
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+
+/// The future returned by `TransactionSigner::sign_transaction`
+pub type SignedBytesFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<u8>, Web3Error>> + Send + 'a>>;
+
+/// Something that can sign transactions for a single address
+pub trait TransactionSigner: Send + Sync {
+    /// The address transactions are signed for, used for the nonce and balance checks
+    fn address(&self) -> Address;
+
+    /// Signs `transaction` with EIP-155 replay protection for `network_id`, returning the
+    /// raw bytes to be broadcast with `eth_sendRawTransaction`
+    fn sign_transaction<'a>(
+        &'a self,
+        transaction: Transaction,
+        network_id: u64,
+    ) -> SignedBytesFuture<'a>;
+}
+
+impl TransactionSigner for PrivateKey {
+    fn address(&self) -> Address {
+        self.to_address()
+    }
+
+    fn sign_transaction<'a>(
+        &'a self,
+        transaction: Transaction,
+        network_id: u64,
+    ) -> SignedBytesFuture<'a> {
+        Box::pin(async move {
+            let unsigned =
+                build_transaction(TransactionParams::from_legacy(transaction, network_id))?;
+            Ok(unsigned.sign(self)?.raw)
+        })
+    }
+}
+
+/// Signs with an account that is unlocked on the node using `eth_signTransaction`, as with
+/// development nodes or a node backed by clef. The node signs for its own chain id, so the
+/// `network_id` passed to `sign_transaction` is not used.
+#[derive(Clone)]
+pub struct NodeSigner {
+    pub web3: Web3,
+    pub address: Address,
+}
+
+impl NodeSigner {
+    pub fn new(web3: Web3, address: Address) -> Self {
+        NodeSigner { web3, address }
+    }
+}
+
+impl TransactionSigner for NodeSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn sign_transaction<'a>(
+        &'a self,
+        transaction: Transaction,
+        _network_id: u64,
+    ) -> SignedBytesFuture<'a> {
+        Box::pin(async move {
+            let request = TransactionRequest::builder()
+                .from(self.address)
+                .to(transaction.to)
+                .gas(transaction.gas_limit)
+                .gas_price(transaction.gas_price)
+                .value(transaction.value)
+                .data(transaction.data)
+                .nonce(transaction.nonce)
+                .build_send()?;
+            let res: Value = self
+                .web3
+                .jsonrpc_client
+                .request_method("eth_signTransaction", vec![request], self.web3.timeout)
+                .await
+                .map_err(|e| e.map_unsupported("eth_signTransaction"))?;
+            raw_from_response(res)
+        })
+    }
+}
+
+/// Geth returns `{"raw": .., "tx": ..}` while other nodes return only the raw bytes
+fn raw_from_response(res: Value) -> Result<Vec<u8>, Web3Error> {
+    let raw = match res {
+        Value::Object(mut object) => object.remove("raw").unwrap_or_default(),
+        raw => raw,
+    };
+    match serde_json::from_value::<Data>(raw) {
+        Ok(raw) if !raw.is_empty() => Ok(raw.0),
+        _ => Err(Web3Error::BadResponse(
+            "eth_signTransaction returned no raw transaction".to_string(),
+        )),
+    }
+}
+
+#[tokio::test]
+async fn test_private_key_signer() {
+    use clarity::u256;
+
+    let secret: PrivateKey = "0xb1bab011e03a9862664706fc3bbaa1b16651528e5f0e7fbfcbfdd8be302a13e7"
+        .parse()
+        .unwrap();
+    let transaction = Transaction {
+        to: secret.to_address(),
+        nonce: u256!(3),
+        gas_price: u256!(1000000000),
+        gas_limit: u256!(21000),
+        value: u256!(1),
+        data: Vec::new(),
+        signature: None,
+    };
+    let expected = transaction
+        .clone()
+        .sign(&secret, Some(1))
+        .to_bytes()
+        .unwrap();
+    let signer: &dyn TransactionSigner = &secret;
+    assert_eq!(signer.address(), secret.to_address());
+    let raw = signer.sign_transaction(transaction, 1).await.unwrap();
+    assert_eq!(raw, expected);
+}
+
+#[tokio::test]
+async fn test_node_signer() {
+    use crate::jsonrpc::mock::MockNode;
+    use crate::types::SendTxOption;
+    use clarity::u256;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let txid = crate::types::SignedTx::new(vec![0xf8, 0x6c, 0x05], u256!(5))
+        .hash
+        .to_string();
+    let node = MockNode::start(move |method, _| match method {
+        "eth_syncing" => Ok(json!(false)),
+        "eth_getBalance" => Ok(json!("0xde0b6b3a7640000")),
+        "eth_getTransactionCount" => Ok(json!("0x5")),
+        "net_version" => Ok(json!("1")),
+        "eth_signTransaction" => Ok(json!({"raw": "0xf86c05", "tx": {}})),
+        "eth_sendRawTransaction" => Ok(json!(txid)),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let address: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let signer = NodeSigner::new(web3.clone(), address);
+
+    let sent = web3
+        .send_transaction_with_signer(
+            address,
+            Vec::new(),
+            u256!(1),
+            &signer,
+            vec![
+                SendTxOption::GasPrice(u256!(1)),
+                SendTxOption::GasLimit(u256!(21000)),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(sent.to_string(), txid);
+    let request = &node.params("eth_signTransaction")[0][0];
+    assert_eq!(request["nonce"], "0x5");
+    assert_eq!(
+        node.params("eth_sendRawTransaction")[0],
+        json!(["0xf86c05"])
+    );
+
+    assert_eq!(raw_from_response(json!("0x01")).unwrap(), vec![1]);
+    assert!(raw_from_response(json!({"tx": {}})).is_err());
+}
@@ -0,0 +1,382 @@
+//! This module contains methods for controlling development chains such as ganache, hardhat
+//! and anvil. None of these methods are available on production nodes.
+use crate::{
+    client::Web3,
+    hash::TxHash,
+    hex::{data_to_hex, quantity_to_hex, word_to_hex},
+    jsonrpc::error::Web3Error,
+    types::{ClientKind, TransactionRequest},
+};
+use clarity::{Address, Uint256};
+use serde_json::Value;
+
+/// Dev chains disagree on whether numeric results are JSON numbers, decimal strings or hex
+/// strings, this accepts all of them
+fn value_to_u64(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => match s.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => s.parse().ok(),
+        },
+        _ => None,
+    }
+}
+
+impl Web3 {
+    /// Sends a dev chain control request, mapping "method not found" into an
+    /// error that names the dev chain feature that is missing
+    async fn dev_chain_request<T: serde::Serialize>(
+        &self,
+        method: &str,
+        params: T,
+        feature: &str,
+    ) -> Result<Value, Web3Error> {
+        self.jsonrpc_client
+            .request_method(method, params, self.timeout)
+            .await
+            .map_err(|e| e.map_unsupported(&format!("{} ({})", method, feature)))
+    }
+
+    /// Mines a single block immediately
+    pub async fn evm_mine(&self) -> Result<(), Web3Error> {
+        self.dev_chain_request("evm_mine", Vec::<String>::new(), "manual block mining")
+            .await?;
+        Ok(())
+    }
+
+    /// Moves the chain's clock forward by `seconds`, the change takes effect on the next
+    /// mined block. Returns the total time adjustment in seconds reported by the node.
+    pub async fn evm_increase_time(&self, seconds: u64) -> Result<u64, Web3Error> {
+        let res = self
+            .dev_chain_request("evm_increaseTime", vec![seconds], "time manipulation")
+            .await?;
+        value_to_u64(&res).ok_or_else(|| {
+            Web3Error::BadResponse(format!("Unexpected evm_increaseTime result {}", res))
+        })
+    }
+
+    /// Sets the timestamp of the next mined block, which must be greater than the current one
+    pub async fn evm_set_next_block_timestamp(&self, timestamp: u64) -> Result<(), Web3Error> {
+        self.dev_chain_request(
+            "evm_setNextBlockTimestamp",
+            vec![timestamp],
+            "time manipulation",
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Enables or disables mining a block for every transaction sent
+    pub async fn evm_set_automine(&self, enabled: bool) -> Result<(), Web3Error> {
+        self.dev_chain_request("evm_setAutomine", vec![enabled], "automine control")
+            .await?;
+        Ok(())
+    }
+
+    /// Starts block production on a node with mining control
+    pub async fn miner_start(&self) -> Result<(), Web3Error> {
+        self.dev_chain_request("miner_start", Vec::<String>::new(), "mining control")
+            .await?;
+        Ok(())
+    }
+
+    /// Stops block production on a node with mining control, transactions will
+    /// accumulate in the mempool until `miner_start` or `evm_mine` is called
+    pub async fn miner_stop(&self) -> Result<(), Web3Error> {
+        self.dev_chain_request("miner_stop", Vec::<String>::new(), "mining control")
+            .await?;
+        Ok(())
+    }
+
+    /// Returns an unlocked account with a nonzero balance that the node will sign for, the
+    /// coinbase if it is funded and otherwise the first such account of eth_accounts. None
+    /// if the node has no funded accounts, as on any production node.
+    pub async fn get_funded_dev_account(&self) -> Result<Option<Address>, Web3Error> {
+        let accounts = self.eth_accounts().await?;
+        let mut candidates = Vec::new();
+        // the coinbase of geth --dev is funded but only listed if it is also unlocked
+        if let Some(coinbase) = self.eth_coinbase().await? {
+            if accounts.contains(&coinbase) {
+                candidates.push(coinbase);
+            }
+        }
+        candidates.extend(accounts);
+        for account in candidates {
+            if self.eth_get_balance(account).await? > Uint256::from_u64(0) {
+                return Ok(Some(account));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Detects the flavor of the connected node from its web3_clientVersion
+    pub async fn get_client_kind(&self) -> Result<ClientKind, Web3Error> {
+        Ok(ClientKind::from_client_version(
+            &self.web3_client_version().await?,
+        ))
+    }
+
+    /// Sends a hardhat_ or anvil_ cheat code, picking the namespace based on the detected node
+    /// flavor so that the same function works against either of them
+    async fn cheat_code_request<T: serde::Serialize>(
+        &self,
+        method: &str,
+        params: T,
+    ) -> Result<Value, Web3Error> {
+        let namespace = match self.get_client_kind().await? {
+            ClientKind::Hardhat => "hardhat",
+            ClientKind::Anvil => "anvil",
+            kind => {
+                return Err(Web3Error::UnsupportedMethod(format!(
+                    "{} (cheat codes require hardhat or anvil, found {:?})",
+                    method, kind
+                )))
+            }
+        };
+        let method = format!("{}_{}", namespace, method);
+        self.dev_chain_request(&method, params, "cheat codes").await
+    }
+
+    /// Allows transactions to be sent from `address` without its private key using
+    /// `send_transaction_impersonated`, useful for acting as a whale on a mainnet fork
+    pub async fn impersonate_account(&self, address: Address) -> Result<(), Web3Error> {
+        self.cheat_code_request("impersonateAccount", vec![address.to_string()])
+            .await?;
+        Ok(())
+    }
+
+    /// Undoes `impersonate_account`
+    pub async fn stop_impersonating_account(&self, address: Address) -> Result<(), Web3Error> {
+        self.cheat_code_request("stopImpersonatingAccount", vec![address.to_string()])
+            .await?;
+        Ok(())
+    }
+
+    /// Sets the ETH balance of an address
+    pub async fn set_balance(&self, address: Address, balance: Uint256) -> Result<(), Web3Error> {
+        self.cheat_code_request(
+            "setBalance",
+            vec![address.to_string(), quantity_to_hex(balance)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Replaces the code deployed at an address
+    pub async fn set_code(&self, address: Address, code: Vec<u8>) -> Result<(), Web3Error> {
+        self.cheat_code_request("setCode", vec![address.to_string(), data_to_hex(&code)])
+            .await?;
+        Ok(())
+    }
+
+    /// Writes a single storage slot of an address
+    pub async fn set_storage_at(
+        &self,
+        address: Address,
+        slot: Uint256,
+        value: Uint256,
+    ) -> Result<(), Web3Error> {
+        // hardhat rejects slots with leading zeros but requires the value to be a full word
+        self.cheat_code_request(
+            "setStorageAt",
+            vec![
+                address.to_string(),
+                quantity_to_hex(slot),
+                word_to_hex(value),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Sets the nonce of an address
+    pub async fn set_nonce(&self, address: Address, nonce: Uint256) -> Result<(), Web3Error> {
+        self.cheat_code_request(
+            "setNonce",
+            vec![address.to_string(), quantity_to_hex(nonce)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Sends a transaction from an address that has been impersonated with
+    /// `impersonate_account`, the node signs it since no private key is available
+    pub async fn send_transaction_impersonated(
+        &self,
+        from: Address,
+        to: Address,
+        data: Vec<u8>,
+        value: Uint256,
+    ) -> Result<TxHash, Web3Error> {
+        let request = TransactionRequest::builder()
+            .from(from)
+            .to(to)
+            .value(value)
+            .data(data)
+            .build_send()?;
+        self.eth_send_transaction(vec![request]).await
+    }
+}
+
+/// Reverts the dev chain to a snapshot when finished with, so that a test can not leave
+/// the chain dirty for the next one. Since `Drop` can not await, prefer calling `revert()`
+/// explicitly, dropping an armed guard spawns a best effort revert on the current runtime.
+///
+/// ```rust,ignore
+/// let guard = web3.snapshot_guard().await?;
+/// // mutate state here
+/// guard.revert().await?;
+/// ```
+pub struct SnapshotGuard {
+    web3: Web3,
+    id: Uint256,
+    armed: bool,
+}
+
+impl SnapshotGuard {
+    /// The id of the underlying evm_snapshot
+    pub fn id(&self) -> Uint256 {
+        self.id
+    }
+
+    /// Reverts the chain to the snapshot, erroring if the node reports
+    /// that the snapshot could not be reverted to
+    pub async fn revert(mut self) -> Result<(), Web3Error> {
+        self.armed = false;
+        if self.web3.evm_revert(self.id).await? {
+            Ok(())
+        } else {
+            Err(Web3Error::BadResponse(format!(
+                "evm_revert to snapshot {:#x} returned false",
+                self.id
+            )))
+        }
+    }
+
+    /// Disarms the guard, keeping all changes made since the snapshot and returning
+    /// the snapshot id for manual handling
+    pub fn finish(mut self) -> Uint256 {
+        self.armed = false;
+        self.id
+    }
+}
+
+impl Drop for SnapshotGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let web3 = self.web3.clone();
+                let id = self.id;
+                handle.spawn(async move {
+                    if let Err(e) = web3.evm_revert(id).await {
+                        warn!("Failed to revert to snapshot {:#x} on drop {:?}", id, e);
+                    }
+                });
+            }
+            Err(_) => warn!(
+                "SnapshotGuard for snapshot {:#x} dropped outside of a runtime, not reverting",
+                self.id
+            ),
+        }
+    }
+}
+
+impl Web3 {
+    /// Takes an evm_snapshot and returns a guard that reverts to it, see `SnapshotGuard`
+    pub async fn snapshot_guard(&self) -> Result<SnapshotGuard, Web3Error> {
+        let id = self.evm_snapshot().await?;
+        Ok(SnapshotGuard {
+            web3: self.clone(),
+            id,
+            armed: true,
+        })
+    }
+}
+
+#[test]
+fn test_value_to_u64() {
+    assert_eq!(value_to_u64(&serde_json::json!(60)), Some(60));
+    assert_eq!(value_to_u64(&serde_json::json!("60")), Some(60));
+    assert_eq!(value_to_u64(&serde_json::json!("0x3c")), Some(60));
+    assert_eq!(value_to_u64(&serde_json::json!(null)), None);
+    assert_eq!(value_to_u64(&serde_json::json!("sixty")), None);
+}
+
+#[tokio::test]
+async fn test_get_funded_dev_account() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let empty = "0x1111111111111111111111111111111111111111";
+    let funded = "0x2222222222222222222222222222222222222222";
+    let node = MockNode::start(move |method, params| match method {
+        "eth_coinbase" => Err((-32000, "etherbase must be explicitly specified".into())),
+        "eth_accounts" => Ok(json!([empty, funded])),
+        "eth_syncing" => Ok(json!(false)),
+        "eth_getBalance" if params[0] == json!(funded) => Ok(json!("0xde0b6b3a7640000")),
+        "eth_getBalance" => Ok(json!("0x0")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    assert_eq!(web3.eth_coinbase().await.unwrap(), None);
+    assert_eq!(
+        web3.get_funded_dev_account().await.unwrap(),
+        Some(funded.parse().unwrap())
+    );
+
+    // the coinbase is preferred and nodes without accounts have nothing to offer
+    let node = MockNode::start(move |method, _| match method {
+        "eth_coinbase" => Ok(json!(funded)),
+        "eth_accounts" => Ok(json!([empty, funded])),
+        "eth_syncing" => Ok(json!(false)),
+        "eth_getBalance" => Ok(json!("0x1")),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    assert_eq!(
+        web3.get_funded_dev_account().await.unwrap(),
+        Some(funded.parse().unwrap())
+    );
+    let node = MockNode::start(|method, _| match method {
+        "eth_coinbase" => Ok(json!("0x0000000000000000000000000000000000000000")),
+        "eth_accounts" => Ok(json!([])),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    assert_eq!(web3.eth_coinbase().await.unwrap(), None);
+    assert_eq!(web3.get_funded_dev_account().await.unwrap(), None);
+
+    // other server errors are not mistaken for a missing coinbase
+    let node = MockNode::start(|method, _| match method {
+        "eth_coinbase" => Err((-32000, "header not found".into())),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    assert!(web3.eth_coinbase().await.is_err());
+}
+
+#[ignore]
+#[tokio::test]
+async fn test_snapshot_guard_restores_balance() {
+    use clarity::u256;
+    use std::time::Duration;
+
+    // run with a local hardhat or anvil node
+    let web3 = Web3::new("http://localhost:8545", Duration::from_secs(30));
+    let address: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let original = web3.eth_get_balance(address).await.unwrap();
+
+    let guard = web3.snapshot_guard().await.unwrap();
+    web3.set_balance(address, u256!(1234567)).await.unwrap();
+    assert_eq!(web3.eth_get_balance(address).await.unwrap(), u256!(1234567));
+    guard.revert().await.unwrap();
+
+    assert_eq!(web3.eth_get_balance(address).await.unwrap(), original);
+}
@@ -0,0 +1,52 @@
+//! Timers that work both on native targets, where they run on tokio, and on wasm32 in the
+//! browser, where tokio has no timer driver and `std::time::Instant` panics. Everything in
+//! this crate that sleeps or measures time goes through here.
+use crate::jsonrpc::error::Web3Error;
+use std::future::Future;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::Instant;
+
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+compile_error!("building web30 for wasm32 requires the wasm feature");
+
+/// Waits for `duration`
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+    // the browser timer is not Send, but wasm32 in the browser has only the one thread
+    #[cfg(target_arch = "wasm32")]
+    send_wrapper::SendWrapper::new(gloo_timers::future::sleep(duration)).await;
+}
+
+/// Runs `future` for at most `duration`, failing with `TransactionTimeout` as a tokio
+/// timeout converted into a `Web3Error` does
+pub(crate) async fn timeout<F: Future>(
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Web3Error> {
+    tokio::select! {
+        biased;
+
+        output = future => Ok(output),
+        _ = sleep(duration) => Err(Web3Error::TransactionTimeout(None)),
+    }
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    let start = Instant::now();
+    assert_eq!(
+        timeout(Duration::from_secs(5), async { 7 }).await.unwrap(),
+        7
+    );
+    assert!(matches!(
+        timeout(Duration::from_millis(20), sleep(Duration::from_secs(5))).await,
+        Err(Web3Error::TransactionTimeout(None))
+    ));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(20) && elapsed < Duration::from_secs(1));
+}
@@ -0,0 +1,149 @@
+//! This module contains wrappers for the parity style trace_ namespace, which erigon, nethermind
+//! and reth serve and which is much cheaper than the debug_ namespace for finding internal calls
+use crate::hash::TxHash;
+use crate::hex::quantity_to_hex;
+use crate::jsonrpc::error::Web3Error;
+use crate::{client::Web3, types::Trace};
+use clarity::{Address, Uint256};
+
+/// The params of trace_filter
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFilter {
+    from_block: String,
+    to_block: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    from_address: Vec<Address>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    to_address: Vec<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<u64>,
+}
+
+impl Web3 {
+    /// Gets the traces from `from_block` to `to_block` inclusive with an action sent from one of
+    /// `from_addresses` or to one of `to_addresses`, empty lists match any address. Clients differ
+    /// in whether both lists must match when both are given, query them separately to be sure.
+    /// `after` skips that many matching traces and `count` limits the number returned, for
+    /// paging through busy ranges. Returns `UnsupportedMethod` if the node does not have the
+    /// trace namespace enabled.
+    pub async fn trace_filter(
+        &self,
+        from_block: Uint256,
+        to_block: Uint256,
+        from_addresses: Vec<Address>,
+        to_addresses: Vec<Address>,
+        after: Option<u64>,
+        count: Option<u64>,
+    ) -> Result<Vec<Trace>, Web3Error> {
+        let filter = TraceFilter {
+            from_block: quantity_to_hex(from_block),
+            to_block: quantity_to_hex(to_block),
+            from_address: from_addresses,
+            to_address: to_addresses,
+            after,
+            count,
+        };
+        self.jsonrpc_client
+            .request_method("trace_filter", vec![filter], self.timeout)
+            .await
+            .map_err(|e| e.map_unsupported("trace_filter"))
+    }
+
+    /// Gets every trace of a mined transaction, the top level call followed by its internal
+    /// calls in execution order. Returns `UnsupportedMethod` if the node does not have the trace
+    /// namespace enabled.
+    pub async fn trace_transaction(
+        &self,
+        tx_hash: impl Into<TxHash>,
+    ) -> Result<Vec<Trace>, Web3Error> {
+        self.jsonrpc_client
+            .request_method("trace_transaction", vec![tx_hash.into()], self.timeout)
+            .await
+            .map_err(|e| e.map_unsupported("trace_transaction"))
+    }
+}
+
+#[tokio::test]
+async fn test_trace_transaction() {
+    use crate::jsonrpc::mock::MockNode;
+    use crate::types::TraceKind;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let node = MockNode::start(|method, _| match method {
+        "trace_transaction" => Ok(json!([
+            {
+                "action": {
+                    "callType": "call",
+                    "from": "0x1111111111111111111111111111111111111111",
+                    "gas": "0x5208",
+                    "input": "0x",
+                    "to": "0x2222222222222222222222222222222222222222",
+                    "value": "0x1"
+                },
+                "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+                "blockNumber": 15725407,
+                "result": {"gasUsed": "0x0", "output": "0x"},
+                "subtraces": 1,
+                "traceAddress": [],
+                "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+                "transactionPosition": 102,
+                "type": "call"
+            },
+            {
+                "action": {
+                    "from": "0x2222222222222222222222222222222222222222",
+                    "gas": "0x0",
+                    "init": "0x6080",
+                    "value": "0x0"
+                },
+                "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+                "blockNumber": 15725407,
+                "error": "out of gas",
+                "subtraces": 0,
+                "traceAddress": [0],
+                "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+                "transactionPosition": 102,
+                "type": "create"
+            }
+        ])),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+
+    let traces = web3.trace_transaction(Uint256::from_u64(1)).await.unwrap();
+    assert_eq!(traces.len(), 2);
+    assert!(matches!(traces[0].kind, TraceKind::Call { ref result, .. } if result.is_some()));
+    assert!(matches!(
+        traces[1].kind,
+        TraceKind::Create { result: None, .. }
+    ));
+    assert_eq!(traces[1].error.as_deref(), Some("out of gas"));
+    assert_eq!(traces[1].to(), None);
+    assert_eq!(
+        node.params("trace_transaction")[0],
+        json!([format!("{:#066x}", 1)])
+    );
+
+    let node = MockNode::start(|_, _| Err((-32601, "Method not found".to_string())));
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    assert!(matches!(
+        web3.trace_filter(
+            Uint256::from_u64(1),
+            Uint256::from_u64(2),
+            Vec::new(),
+            Vec::new(),
+            None,
+            Some(10)
+        )
+        .await,
+        Err(Web3Error::UnsupportedMethod(_))
+    ));
+    assert_eq!(
+        node.params("trace_filter")[0],
+        json!([{"fromBlock": "0x1", "toBlock": "0x2", "count": 10}])
+    );
+}
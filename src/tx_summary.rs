@@ -0,0 +1,448 @@
+//! Block explorer style summaries of a single transaction, for support tooling that has to
+//! show someone what happened to their transaction without making them read raw JSON
+use crate::client::Web3;
+use crate::event_utils::{decode_events, ParamKind};
+use crate::hash::TxHash;
+use crate::jsonrpc::error::Web3Error;
+use crate::keccak::{canonical_signature, keccak256, split_args};
+use crate::types::{TransactionReceipt, TransactionResponse};
+use crate::units::{format_units, ETH_DECIMALS};
+use clarity::abi::Token;
+use clarity::{Address, Uint256};
+use std::slice;
+
+/// Function signatures `describe_transaction` always recognizes, the ERC-20 and ERC-721
+/// methods that move or approve tokens. `approve` and `transferFrom` have the same selectors
+/// in both standards.
+pub const KNOWN_FUNCTIONS: &[&str] = &[
+    "transfer(address,uint256)",
+    "approve(address,uint256)",
+    "transferFrom(address,address,uint256)",
+    "safeTransferFrom(address,address,uint256)",
+    "safeTransferFrom(address,address,uint256,bytes)",
+    "setApprovalForAll(address,bool)",
+];
+
+/// Event signatures `describe_transaction` always decodes. ERC-721 indexes the token id of
+/// Transfer and Approval as well, which the same signatures decode since the leading
+/// arguments are taken from however many topics the log has.
+pub const KNOWN_EVENTS: &[&str] = &[
+    "Transfer(address,address,uint256)",
+    "Approval(address,address,uint256)",
+    "ApprovalForAll(address,address,bool)",
+];
+
+/// Whether a transaction succeeded, part of a `TxSummary`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TxStatus {
+    /// not mined yet
+    Pending,
+    Succeeded,
+    /// reverted, `Web3::get_revert_reason` can find out why
+    Failed,
+    /// mined but the node could not return the receipt, or the receipt predates Byzantium
+    /// and has no status
+    Unknown,
+}
+
+/// The function a transaction called, part of a `TxSummary`
+#[derive(Debug, Clone)]
+pub struct DecodedCall {
+    /// the first four bytes of the calldata
+    pub selector: [u8; 4],
+    /// the canonical signature, None if the selector matched none of the known signatures
+    pub signature: Option<String>,
+    /// the arguments in declaration order, None if the signature is unknown, has argument
+    /// types that can not be decoded or the calldata does not fit it
+    pub args: Option<Vec<Token>>,
+}
+
+/// An event emitted by a transaction, part of a `TxSummary`
+#[derive(Debug, Clone)]
+pub struct DecodedEvent {
+    /// the contract that emitted the event
+    pub address: Address,
+    /// the canonical signature
+    pub signature: String,
+    /// the arguments in declaration order, see `decode_events`
+    pub args: Vec<Token>,
+}
+
+/// Everything about a transaction a block explorer would show, returned by
+/// `Web3::describe_transaction`. Fields that depend on the receipt are None while the
+/// transaction is pending or if the node could not return it.
+#[derive(Debug, Clone)]
+pub struct TxSummary {
+    pub transaction: TransactionResponse,
+    pub receipt: Option<TransactionReceipt>,
+    pub status: TxStatus,
+    /// the value of the transaction formatted in ETH
+    pub value_eth: String,
+    /// None for plain ETH transfers and contract creations
+    pub call: Option<DecodedCall>,
+    /// the events of the receipt that matched a known signature, in log order
+    pub events: Vec<DecodedEvent>,
+    /// how many logs of the receipt matched no known signature
+    pub unknown_logs: usize,
+    /// the total fee in wei, including the L1 data fee on OP stack chains
+    pub fee: Option<Uint256>,
+    /// `fee` formatted in ETH
+    pub fee_eth: Option<String>,
+    /// the number of blocks since the transaction was mined, counting its own block, None
+    /// while pending or if the latest block could not be fetched
+    pub confirmations: Option<Uint256>,
+}
+
+/// Decodes the arguments of calldata after the selector according to a canonical signature
+fn decode_args(signature: &str, data: &[u8]) -> Option<Vec<Token>> {
+    let (_, args) = signature.split_once('(')?;
+    let args = args.strip_suffix(')')?;
+    if args.is_empty() {
+        return Some(Vec::new());
+    }
+    split_args(args)
+        .into_iter()
+        .enumerate()
+        .map(|(i, ty)| ParamKind::parse(ty)?.decode_data(data, i * 32))
+        .collect()
+}
+
+/// Matches the selector of `input` against `signatures` and then `KNOWN_FUNCTIONS`, decoding
+/// the arguments of the first match. Arguments can be any type that `decode_events` can
+/// decode. Returns None if `input` is too short to have a selector.
+pub fn decode_function_call(
+    input: &[u8],
+    signatures: &[&str],
+) -> Result<Option<DecodedCall>, Web3Error> {
+    let selector: [u8; 4] = match input.get(0..4) {
+        Some(selector) => selector.try_into().expect("four bytes"),
+        None => return Ok(None),
+    };
+    for signature in signatures.iter().chain(KNOWN_FUNCTIONS) {
+        let signature = canonical_signature(signature)?;
+        if keccak256(signature.as_bytes())[0..4] == selector {
+            return Ok(Some(DecodedCall {
+                selector,
+                args: decode_args(&signature, &input[4..]),
+                signature: Some(signature),
+            }));
+        }
+    }
+    Ok(Some(DecodedCall {
+        selector,
+        signature: None,
+        args: None,
+    }))
+}
+
+impl Web3 {
+    /// Gathers a summary of a transaction as a block explorer would show it, or None if the
+    /// node does not know the transaction. The called function is decoded against
+    /// `functions` and `KNOWN_FUNCTIONS`, the logs of the receipt against `events` and
+    /// `KNOWN_EVENTS`, the caller's signatures taking precedence.
+    ///
+    /// A missing receipt or latest block does not fail the summary, the fields that depend
+    /// on them are left as None and the reason is logged.
+    pub async fn describe_transaction(
+        &self,
+        hash: impl Into<TxHash>,
+        functions: &[&str],
+        events: &[&str],
+    ) -> Result<Option<TxSummary>, Web3Error> {
+        let hash = hash.into();
+        let transaction = match self.eth_get_transaction_by_hash(hash).await? {
+            Some(transaction) => transaction,
+            None => return Ok(None),
+        };
+        let call = match transaction.to {
+            Some(_) => decode_function_call(&transaction.input.0, functions)?,
+            None => None,
+        };
+        let mut summary = TxSummary {
+            status: TxStatus::Pending,
+            value_eth: format_units(transaction.value, ETH_DECIMALS),
+            call,
+            receipt: None,
+            events: Vec::new(),
+            unknown_logs: 0,
+            fee: None,
+            fee_eth: None,
+            confirmations: None,
+            transaction,
+        };
+        let block_number = match summary.transaction.block_number {
+            Some(block_number) => block_number,
+            None => return Ok(Some(summary)),
+        };
+
+        let (receipt, latest) = tokio::join!(
+            self.eth_get_transaction_receipt(hash),
+            self.eth_block_number()
+        );
+        match latest {
+            Ok(latest) => {
+                summary.confirmations = Some(
+                    latest
+                        .checked_sub(block_number)
+                        .and_then(|depth| depth.checked_add(Uint256::from_u64(1)))
+                        .unwrap_or_default(),
+                )
+            }
+            Err(e) => warn!("Could not fetch the latest block for {}: {:?}", hash, e),
+        }
+        let receipt = match receipt {
+            Ok(Some(receipt)) => receipt,
+            Ok(None) => {
+                warn!("No receipt for mined transaction {}", hash);
+                summary.status = TxStatus::Unknown;
+                return Ok(Some(summary));
+            }
+            Err(e) => {
+                warn!("Could not fetch the receipt of {}: {:?}", hash, e);
+                summary.status = TxStatus::Unknown;
+                return Ok(Some(summary));
+            }
+        };
+
+        summary.status = match receipt.status {
+            Some(status) if status == Uint256::from_u64(1) => TxStatus::Succeeded,
+            Some(_) => TxStatus::Failed,
+            None => TxStatus::Unknown,
+        };
+        let effective_gas_price = receipt
+            .effective_gas_price
+            .unwrap_or(summary.transaction.gas_price);
+        let fee = receipt
+            .gas_used
+            .checked_mul(effective_gas_price)
+            .and_then(|fee| fee.checked_add(receipt.l1_fee.unwrap_or_default()))
+            .ok_or_else(|| Web3Error::BadResponse("Transaction fee overflows".to_string()))?;
+        summary.fee = Some(fee);
+        summary.fee_eth = Some(format_units(fee, ETH_DECIMALS));
+
+        let signatures: Vec<&str> = events.iter().chain(KNOWN_EVENTS).copied().collect();
+        for log in &receipt.logs {
+            match decode_events(slice::from_ref(log), None, &signatures)?.pop() {
+                Some((signature, args)) => summary.events.push(DecodedEvent {
+                    address: log.address,
+                    signature,
+                    args,
+                }),
+                None => summary.unknown_logs += 1,
+            }
+        }
+        summary.receipt = Some(receipt);
+        Ok(Some(summary))
+    }
+}
+
+#[test]
+fn test_decode_function_call() {
+    use clarity::abi::encode_call;
+    use clarity::u256;
+
+    let to: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let transfer = encode_call(
+        "transfer(address,uint256)",
+        &[Token::Address(to), Token::Uint(u256!(5))],
+    )
+    .unwrap();
+    let call = decode_function_call(&transfer, &[]).unwrap().unwrap();
+    assert_eq!(call.selector, [0xa9, 0x05, 0x9c, 0xbb]);
+    assert_eq!(call.signature.as_deref(), Some("transfer(address,uint256)"));
+    let args = call.args.unwrap();
+    assert!(matches!(&args[0], Token::Address(a) if *a == to));
+    assert!(matches!(&args[1], Token::Uint(v) if *v == u256!(5)));
+
+    // caller signatures are canonicalized, and decoded where the types allow
+    let deposit = encode_call(
+        "deposit(uint256,string)",
+        &[Token::Uint(u256!(7)), Token::String("memo".to_string())],
+    )
+    .unwrap();
+    let call = decode_function_call(&deposit, &["deposit(uint amount, string memo)"])
+        .unwrap()
+        .unwrap();
+    assert_eq!(call.signature.as_deref(), Some("deposit(uint256,string)"));
+    let args = call.args.unwrap();
+    assert!(matches!(&args[1], Token::String(s) if s == "memo"));
+    let batch = encode_call(
+        "batch(uint256[])",
+        &[Token::Dynamic(vec![Token::Uint(u256!(1))])],
+    )
+    .unwrap();
+    let call = decode_function_call(&batch, &["batch(uint256[])"])
+        .unwrap()
+        .unwrap();
+    assert_eq!(call.signature.as_deref(), Some("batch(uint256[])"));
+    assert!(call.args.is_none());
+
+    // unknown selectors and truncated calldata
+    let call = decode_function_call(&deposit, &[]).unwrap().unwrap();
+    assert_eq!(call.signature, None);
+    assert!(call.args.is_none());
+    let call = decode_function_call(&transfer[..20], &[]).unwrap().unwrap();
+    assert_eq!(call.signature.as_deref(), Some("transfer(address,uint256)"));
+    assert!(call.args.is_none());
+    assert!(decode_function_call(&[0xa9, 0x05], &[]).unwrap().is_none());
+    assert!(decode_function_call(&transfer, &["transfer("]).is_err());
+}
+
+#[tokio::test]
+async fn test_describe_transaction() {
+    use crate::event_utils::address_to_event;
+    use crate::hex::{data_to_hex, word_to_hex};
+    use crate::jsonrpc::mock::MockNode;
+    use clarity::abi::encode_call;
+    use clarity::u256;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let token: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let from: Address = "0x2222222222222222222222222222222222222222"
+        .parse()
+        .unwrap();
+    let to: Address = "0x3333333333333333333333333333333333333333"
+        .parse()
+        .unwrap();
+    let input = encode_call(
+        "transfer(address,uint256)",
+        &[Token::Address(to), Token::Uint(u256!(5))],
+    )
+    .unwrap();
+    let tx = |hash: &str, block: Option<&str>| {
+        json!({
+            "blockHash": block.map(|_| format!("0x{}", "ab".repeat(32))),
+            "blockNumber": block,
+            "from": from.to_string(),
+            "gas": "0x10000",
+            "gasPrice": "0x3b9aca00",
+            "hash": hash,
+            "input": data_to_hex(&input),
+            "nonce": "0x1",
+            "to": token.to_string(),
+            "transactionIndex": block.map(|_| "0x0"),
+            "value": "0x0"
+        })
+    };
+    let mined = format!("0x{}", "01".repeat(32));
+    let pending = format!("0x{}", "02".repeat(32));
+    let lost = format!("0x{}", "03".repeat(32));
+    let transfer_log = json!({
+        "address": token.to_string(),
+        "data": word_to_hex(u256!(5)),
+        "topics": [
+            data_to_hex(&keccak256(b"Transfer(address,address,uint256)")),
+            data_to_hex(&address_to_event(from)),
+            data_to_hex(&address_to_event(to)),
+        ]
+    });
+    let other_log = json!({
+        "address": token.to_string(),
+        "data": "0x",
+        "topics": [data_to_hex(&keccak256(b"Paused()"))]
+    });
+    let receipt = json!({
+        "blockHash": format!("0x{}", "ab".repeat(32)),
+        "blockNumber": "0x64",
+        "contractAddress": null,
+        "cumulativeGasUsed": "0x9000",
+        "effectiveGasPrice": "0x77359400",
+        "from": from.to_string(),
+        "gasUsed": "0x9000",
+        "logs": [transfer_log, other_log],
+        "logsBloom": "0x00",
+        "status": "0x1",
+        "to": token.to_string(),
+        "transactionHash": mined.clone(),
+        "transactionIndex": "0x0"
+    });
+    let (mined_tx, pending_tx, lost_tx) = (
+        tx(&mined, Some("0x64")),
+        tx(&pending, None),
+        tx(&lost, Some("0x64")),
+    );
+    let (mined_hash, pending_hash, lost_hash) = (mined.clone(), pending.clone(), lost.clone());
+    let node = MockNode::start(move |method, params| {
+        let hash = params[0].as_str().unwrap_or_default();
+        match method {
+            "eth_syncing" => Ok(json!(false)),
+            "eth_blockNumber" => Ok(json!("0x6d")),
+            "eth_getTransactionByHash" if hash == mined_hash => Ok(mined_tx.clone()),
+            "eth_getTransactionByHash" if hash == pending_hash => Ok(pending_tx.clone()),
+            "eth_getTransactionByHash" if hash == lost_hash => Ok(lost_tx.clone()),
+            "eth_getTransactionByHash" => Ok(json!(null)),
+            "eth_getTransactionReceipt" if hash == mined_hash => Ok(receipt.clone()),
+            "eth_getTransactionReceipt" => Ok(json!(null)),
+            _ => Err((-32601, "Method not found".to_string())),
+        }
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let hash = |s: &str| s.parse::<TxHash>().unwrap();
+
+    let summary = web3
+        .describe_transaction(hash(&mined), &[], &[])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.status, TxStatus::Succeeded);
+    assert_eq!(summary.value_eth, "0");
+    let call = summary.call.unwrap();
+    assert_eq!(call.signature.as_deref(), Some("transfer(address,uint256)"));
+    assert_eq!(call.args.unwrap().len(), 2);
+    assert_eq!(summary.events.len(), 1);
+    assert_eq!(summary.events[0].address, token);
+    assert_eq!(
+        summary.events[0].signature,
+        "Transfer(address,address,uint256)"
+    );
+    assert!(matches!(&summary.events[0].args[1], Token::Address(a) if *a == to));
+    assert_eq!(summary.unknown_logs, 1);
+    // 0x9000 gas at 2 gwei
+    assert_eq!(summary.fee, Some(u256!(73728000000000)));
+    assert_eq!(summary.fee_eth.as_deref(), Some("0.000073728"));
+    // blocks 0x64 through 0x6d
+    assert_eq!(summary.confirmations, Some(u256!(10)));
+    assert!(summary.receipt.is_some());
+
+    // caller event signatures are tried first
+    let summary = web3
+        .describe_transaction(hash(&mined), &[], &["Paused()"])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.events.len(), 2);
+    assert_eq!(summary.events[1].signature, "Paused()");
+    assert_eq!(summary.unknown_logs, 0);
+
+    let summary = web3
+        .describe_transaction(hash(&pending), &[], &[])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.status, TxStatus::Pending);
+    assert!(summary.call.is_some());
+    assert!(summary.receipt.is_none() && summary.fee.is_none());
+    assert_eq!(summary.confirmations, None);
+
+    // mined but the receipt is gone, what is known is still returned
+    let summary = web3
+        .describe_transaction(hash(&lost), &[], &[])
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.status, TxStatus::Unknown);
+    assert!(summary.receipt.is_none() && summary.fee_eth.is_none());
+    assert_eq!(summary.confirmations, Some(u256!(10)));
+
+    let unknown = format!("0x{}", "04".repeat(32));
+    assert!(web3
+        .describe_transaction(hash(&unknown), &[], &[])
+        .await
+        .unwrap()
+        .is_none());
+}
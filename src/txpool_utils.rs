@@ -0,0 +1,55 @@
+//! This module contains wrappers for the txpool_ namespace, which exposes the contents of
+//! a node's mempool. These methods are supported by geth and most geth derived clients.
+use crate::jsonrpc::error::{Web3Error, METHOD_NOT_FOUND};
+use crate::{
+    client::Web3,
+    types::{TxpoolContent, TxpoolContentFrom, TxpoolStatus},
+};
+use clarity::Address;
+
+impl Web3 {
+    /// Returns the number of pending and queued transactions in the node's mempool
+    pub async fn txpool_status(&self) -> Result<TxpoolStatus, Web3Error> {
+        self.jsonrpc_client
+            .request_method("txpool_status", Vec::<String>::new(), self.timeout)
+            .await
+            .map_err(|e| e.map_unsupported("txpool_status"))
+    }
+
+    /// Returns every pending and queued transaction in the node's mempool grouped by sender and
+    /// nonce. On busy nodes this response can be very large, prefer `txpool_content_from`.
+    pub async fn txpool_content(&self) -> Result<TxpoolContent, Web3Error> {
+        self.jsonrpc_client
+            .request_method("txpool_content", Vec::<String>::new(), self.timeout)
+            .await
+            .map_err(|e| e.map_unsupported("txpool_content"))
+    }
+
+    /// Returns the pending and queued transactions of a single sender keyed by nonce, a gap
+    /// between the sender's nonce and the lowest queued nonce means the queued transactions
+    /// are stuck. Uses txpool_contentFrom where available and otherwise falls back to
+    /// filtering the full txpool_content locally.
+    pub async fn txpool_content_from(
+        &self,
+        address: Address,
+    ) -> Result<TxpoolContentFrom, Web3Error> {
+        let res = self
+            .jsonrpc_client
+            .request_method(
+                "txpool_contentFrom",
+                vec![address.to_string()],
+                self.timeout,
+            )
+            .await;
+        match res {
+            Err(Web3Error::JsonRpcError { code, .. }) if code == METHOD_NOT_FOUND => {
+                let mut content = self.txpool_content().await?;
+                Ok(TxpoolContentFrom {
+                    pending: content.pending.remove(&address).unwrap_or_default(),
+                    queued: content.queued.remove(&address).unwrap_or_default(),
+                })
+            }
+            res => res,
+        }
+    }
+}
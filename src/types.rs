@@ -1,7 +1,21 @@
-use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
+use crate::bloom::Bloom;
+use crate::event_utils::decode_events;
+use crate::gas_price::{blob_base_fee, GasFees, GasStrategy};
+use crate::hash::{BlockHash, TxHash, H256};
+use crate::hex::{data_to_hex, hex_to_data, quantity_to_hex, HexMode};
+use crate::jsonrpc::error::Web3Error;
+use crate::keccak::keccak256;
+use crate::quantity::{self, parse_quantity};
+use clarity::abi::Token;
+use clarity::utils::bytes_to_hex_str;
 use clarity::Uint256;
 use clarity::{u256, Address};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 use std::{cmp::Ordering, ops::Deref};
 
 /// Serializes slice of data as "UNFORMATTED DATA" format required
@@ -12,11 +26,11 @@ pub fn data_serialize<S>(x: &[u8], s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    s.serialize_str(&format!("0x{}", bytes_to_hex_str(x)))
+    s.serialize_str(&data_to_hex(x))
 }
 
 /// Deserializes slice of data as "UNFORMATTED DATA" format required
-/// by Ethereum JSONRPC API.
+/// by Ethereum JSONRPC API, see `Data::from_str` for what is accepted.
 ///
 /// See more https://github.com/ethereum/wiki/wiki/JSON-RPC#hex-value-encoding
 pub fn data_deserialize<'de, D>(d: D) -> Result<Vec<u8>, D::Error>
@@ -24,7 +38,9 @@ where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(d)?;
-    hex_str_to_bytes(&s).map_err(serde::de::Error::custom)
+    s.parse::<Data>()
+        .map(|data| data.0)
+        .map_err(serde::de::Error::custom)
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq)]
@@ -32,19 +48,19 @@ pub struct Log {
     /// true when the log was removed, due to a chain reorganization. false if its a valid log.
     pub removed: Option<bool>,
     /// integer of the log index position in the block. null when its pending log.
-    #[serde(rename = "logIndex")]
+    #[serde(rename = "logIndex", with = "quantity::option", default)]
     pub log_index: Option<Uint256>,
     /// integer of the transactions index position log was created from. null when its pending log.
-    #[serde(rename = "transactionIndex")]
+    #[serde(rename = "transactionIndex", with = "quantity::option", default)]
     pub transaction_index: Option<Uint256>,
     /// hash of the transactions this log was created from. null when its pending log.
-    #[serde(rename = "transactionHash")]
-    pub transaction_hash: Option<Data>,
+    #[serde(rename = "transactionHash", default)]
+    pub transaction_hash: Option<TxHash>,
     /// hash of the block where this log was in. null when its pending. null when its pending log.
-    #[serde(rename = "blockHash")]
-    pub block_hash: Option<Data>,
+    #[serde(rename = "blockHash", default)]
+    pub block_hash: Option<BlockHash>,
     /// the block number where this log was in. null when its pending. null when its pending log.
-    #[serde(rename = "blockNumber")]
+    #[serde(rename = "blockNumber", with = "quantity::option", default)]
     pub block_number: Option<Uint256>,
     /// 20 Bytes - address from which this log originated.
     pub address: Address,
@@ -57,6 +73,35 @@ pub struct Log {
     pub topics: Vec<Data>,
     #[serde(rename = "type")]
     pub type_: Option<String>,
+    /// fields not known to this struct, such as the chain specific additions of L2s
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The position of a log in the chain, ordering these orders logs in the order they were
+/// emitted. Pending logs have no position and sort before mined logs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LogPosition {
+    pub block_number: Option<Uint256>,
+    pub transaction_index: Option<Uint256>,
+    pub log_index: Option<Uint256>,
+}
+
+impl Log {
+    /// The position of this log, for sorting logs into the order they were emitted
+    pub fn position(&self) -> LogPosition {
+        LogPosition {
+            block_number: self.block_number,
+            transaction_index: self.transaction_index,
+            log_index: self.log_index,
+        }
+    }
+
+    /// Returns true if the log was removed from the chain by a reorg, these are delivered by
+    /// filters so that consumers can undo the effects of the log
+    pub fn is_removed(&self) -> bool {
+        self.removed == Some(true)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq, Eq, Hash)]
@@ -69,8 +114,14 @@ pub struct Data(
 );
 
 impl Deref for Data {
-    type Target = Vec<u8>;
-    fn deref(&self) -> &Vec<u8> {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Data {
+    fn as_ref(&self) -> &[u8] {
         &self.0
     }
 }
@@ -81,43 +132,155 @@ impl From<Vec<u8>> for Data {
     }
 }
 
+impl From<Data> for Vec<u8> {
+    fn from(data: Data) -> Self {
+        data.0
+    }
+}
+
+/// Lowercase hex with a 0x prefix, the same as the JSON-RPC form
+impl Display for Data {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "0x{}", bytes_to_hex_str(&self.0))
+    }
+}
+
+/// Parses 0x prefixed hex with an even number of digits, either case. "0x" and the empty
+/// string are both empty data, which nodes use interchangeably for calls that return nothing.
+impl FromStr for Data {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(Data(Vec::new()));
+        }
+        hex_to_data(s, HexMode::Strict).map(Data)
+    }
+}
+
 /// As received by getTransactionByHash
 ///
 /// See more: https://github.com/ethereum/wiki/wiki/JSON-RPC#eth_gettransactionbyhash
-#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
 pub struct TransactionResponse {
     /// hash of the block where this transaction was in. null when its pending.
     #[serde(rename = "blockHash")]
-    pub block_hash: Option<Data>,
+    pub block_hash: Option<BlockHash>,
     /// block number where this transaction was in. null when its pending.
-    #[serde(rename = "blockNumber")]
+    #[serde(rename = "blockNumber", with = "quantity::option", default)]
     pub block_number: Option<Uint256>,
     /// address of the sender.
     pub from: Address,
     /// gas provided by the sender.
+    #[serde(with = "quantity")]
     pub gas: Uint256,
     /// gas price provided by the sender in Wei.
-    #[serde(rename = "gasPrice")]
+    #[serde(rename = "gasPrice", with = "quantity")]
     pub gas_price: Uint256,
     /// hash of the transaction
-    pub hash: Data,
+    pub hash: TxHash,
     /// the data send along with the transaction.
     pub input: Data,
     /// the number of transactions made by the sender prior to this one.
+    #[serde(with = "quantity")]
     pub nonce: Uint256,
     /// address of the receiver. null when its a contract creation transaction.
     pub to: Option<Address>,
     /// integer of the transaction's index position in the block. null when its pending.
-    #[serde(rename = "transactionIndex")]
+    #[serde(rename = "transactionIndex", with = "quantity::option", default)]
     pub transaction_index: Option<Uint256>,
     /// value transferred in Wei.
+    #[serde(with = "quantity")]
     pub value: Uint256,
-    /// ECDSA recovery id
-    pub v: Uint256,
+    /// ECDSA recovery id, absent for unsigned system transactions on some L2s
+    #[serde(default, with = "quantity::option")]
+    pub v: Option<Uint256>,
     /// ECDSA signature r
-    pub r: Uint256,
+    #[serde(default, with = "quantity::option")]
+    pub r: Option<Uint256>,
     /// ECDSA signature s
-    pub s: Uint256,
+    #[serde(default, with = "quantity::option")]
+    pub s: Option<Uint256>,
+    /// the EIP-2718 transaction type, absent for legacy transactions on older nodes
+    #[serde(
+        rename = "type",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "quantity::option"
+    )]
+    pub transaction_type: Option<Uint256>,
+    /// chain id the transaction is signed for, absent for pre EIP-155 transactions
+    #[serde(
+        rename = "chainId",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "quantity::option"
+    )]
+    pub chain_id: Option<Uint256>,
+    /// EIP-2930 access list, present for type 1 and later transactions
+    #[serde(
+        rename = "accessList",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub access_list: Option<Vec<AccessListItem>>,
+    /// EIP-1559 maximum total fee per gas, present for type 2 transactions
+    #[serde(
+        rename = "maxFeePerGas",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "quantity::option"
+    )]
+    pub max_fee_per_gas: Option<Uint256>,
+    /// EIP-1559 maximum priority fee per gas, present for type 2 transactions
+    #[serde(
+        rename = "maxPriorityFeePerGas",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "quantity::option"
+    )]
+    pub max_priority_fee_per_gas: Option<Uint256>,
+    /// EIP-4844 maximum fee per blob gas, present for type 3 blob transactions
+    #[serde(
+        rename = "maxFeePerBlobGas",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "quantity::option"
+    )]
+    pub max_fee_per_blob_gas: Option<Uint256>,
+    /// EIP-4844 versioned hashes of the blobs carried by a type 3 blob transaction
+    #[serde(
+        rename = "blobVersionedHashes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub blob_versioned_hashes: Option<Vec<Uint256>>,
+    /// fields not known to this struct, such as the chain specific additions of L2s
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl TransactionResponse {
+    /// Returns the EIP-2718 type of this transaction, 0 for legacy transactions
+    pub fn tx_type(&self) -> u8 {
+        match self.transaction_type {
+            Some(t) => t.to_string().parse().unwrap_or(u8::MAX),
+            None => 0,
+        }
+    }
+
+    /// Returns true if the transaction has not been included in a block yet
+    pub fn is_pending(&self) -> bool {
+        self.block_number.is_none()
+    }
+}
+
+/// An entry of an EIP-2930 access list
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct AccessListItem {
+    pub address: Address,
+    #[serde(rename = "storageKeys")]
+    pub storage_keys: Vec<Uint256>,
 }
 
 impl Ord for TransactionResponse {
@@ -141,12 +304,109 @@ impl Ord for TransactionResponse {
     }
 }
 
+impl Hash for TransactionResponse {
+    /// transactions that are equal have the same hash, the extra fields are not hashable
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
 impl PartialOrd for TransactionResponse {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
+/// As received by getTransactionReceipt
+///
+/// See more: https://ethereum.org/en/developers/docs/apis/json-rpc/#eth_gettransactionreceipt
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransactionReceipt {
+    /// hash of the transaction
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: TxHash,
+    /// integer of the transaction's index position in the block.
+    #[serde(rename = "transactionIndex", with = "quantity")]
+    pub transaction_index: Uint256,
+    /// hash of the block where this transaction was in.
+    #[serde(rename = "blockHash")]
+    pub block_hash: BlockHash,
+    /// block number where this transaction was in.
+    #[serde(rename = "blockNumber", with = "quantity")]
+    pub block_number: Uint256,
+    /// address of the sender.
+    pub from: Address,
+    /// address of the receiver. null when its a contract creation transaction.
+    pub to: Option<Address>,
+    /// the total amount of gas used in the block up to and including this transaction.
+    #[serde(rename = "cumulativeGasUsed", with = "quantity")]
+    pub cumulative_gas_used: Uint256,
+    /// the amount of gas used by this transaction alone.
+    #[serde(rename = "gasUsed", with = "quantity")]
+    pub gas_used: Uint256,
+    /// the price per gas actually paid, not returned by some older nodes.
+    #[serde(rename = "effectiveGasPrice", default, with = "quantity::option")]
+    pub effective_gas_price: Option<Uint256>,
+    /// the contract address created, if the transaction was a contract creation.
+    #[serde(rename = "contractAddress")]
+    pub contract_address: Option<Address>,
+    /// logs generated by this transaction.
+    pub logs: Vec<Log>,
+    /// 1 for success and 0 for failure, null for pre Byzantium transactions.
+    #[serde(default, with = "quantity::option")]
+    pub status: Option<Uint256>,
+    /// the L1 data fee paid on OP stack chains, on top of the execution fee.
+    #[serde(rename = "l1Fee", default, with = "quantity::option")]
+    pub l1_fee: Option<Uint256>,
+    /// the amount of L1 gas the data of this transaction is charged for on OP stack chains.
+    #[serde(rename = "l1GasUsed", default, with = "quantity::option")]
+    pub l1_gas_used: Option<Uint256>,
+    /// the L1 base fee used for the L1 data fee on OP stack chains.
+    #[serde(rename = "l1GasPrice", default, with = "quantity::option")]
+    pub l1_gas_price: Option<Uint256>,
+    /// the decimal scalar applied to the L1 data fee before the OP stack Ecotone upgrade.
+    #[serde(rename = "l1FeeScalar", default)]
+    pub l1_fee_scalar: Option<String>,
+    /// the blob gas used by a type 3 blob transaction, added by the dencun hardfork
+    #[serde(rename = "blobGasUsed", default, with = "quantity::option")]
+    pub blob_gas_used: Option<Uint256>,
+    /// the price per blob gas paid by a type 3 blob transaction
+    #[serde(rename = "blobGasPrice", default, with = "quantity::option")]
+    pub blob_gas_price: Option<Uint256>,
+    /// fields not known to this struct, such as the chain specific additions of L2s
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// The result of waiting for a transaction with `Web3::wait_for_transaction_outcome`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionOutcome {
+    /// the transaction was included in a block
+    Mined(TransactionReceipt),
+    /// a different transaction using the same nonce was mined instead, so this
+    /// transaction will never be included
+    Replaced { mined_nonce: Uint256 },
+    /// the transaction is still pending, or unknown to the node, after the timeout
+    TimedOut,
+}
+
+impl TransactionReceipt {
+    /// Returns true unless the receipt reports that the transaction failed
+    pub fn is_success(&self) -> bool {
+        self.status != Some(u256!(0))
+    }
+
+    /// Decodes the logs in this receipt that match any of the given event signatures,
+    /// see `decode_events` for the signature format
+    pub fn decode_events(
+        &self,
+        contract: Option<Address>,
+        signatures: &[&str],
+    ) -> Result<Vec<(String, Vec<Token>)>, Web3Error> {
+        decode_events(&self.logs, contract, signatures)
+    }
+}
+
 #[derive(Serialize, Default, Debug, Clone)]
 pub struct NewFilter {
     #[serde(rename = "fromBlock", skip_serializing_if = "Option::is_none")]
@@ -156,6 +416,22 @@ pub struct NewFilter {
     pub address: Vec<Address>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub topics: Option<Vec<Option<Vec<Option<String>>>>>,
+    /// Restricts the filter to the logs of a single block, this can not be combined with
+    /// `from_block` or `to_block` but unlike a range it is not affected by reorgs
+    #[serde(rename = "blockHash", skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<String>,
+}
+
+impl NewFilter {
+    /// Checks that the filter is one nodes will accept
+    pub fn validate(&self) -> Result<(), Web3Error> {
+        if self.block_hash.is_some() && (self.from_block.is_some() || self.to_block.is_some()) {
+            return Err(Web3Error::BadInput(
+                "A filter with a block hash can not also have a block range".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Clone, Eq, PartialEq)]
@@ -181,17 +457,208 @@ pub struct TransactionRequest {
     //  This allows to overwrite your own pending transactions that use the same nonce.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nonce: Option<UnpaddedHex>,
+    // The EIP-1559 fee cap, can not be combined with gasPrice
+    #[serde(rename = "maxFeePerGas", skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<UnpaddedHex>,
+    // The EIP-1559 priority fee paid to the block producer within the fee cap
+    #[serde(
+        rename = "maxPriorityFeePerGas",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_priority_fee_per_gas: Option<UnpaddedHex>,
+}
+
+impl TransactionRequest {
+    /// Starts building a request, see `TransactionRequestBuilder`
+    pub fn builder() -> TransactionRequestBuilder {
+        TransactionRequestBuilder::default()
+    }
+
+    /// A read only call of `to` with `data`, for eth_call
+    pub fn call(to: Address, data: impl Into<Data>) -> TransactionRequest {
+        TransactionRequest {
+            from: None,
+            to,
+            gas: None,
+            gas_price: None,
+            value: None,
+            data: Some(data.into()),
+            nonce: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+        }
+    }
+
+    /// A plain ETH transfer of `value` from `from` to `to`
+    pub fn transfer(from: Address, to: Address, value: Uint256) -> TransactionRequest {
+        TransactionRequest {
+            from: Some(from),
+            value: Some(value.into()),
+            data: None,
+            ..TransactionRequest::call(to, Data::default())
+        }
+    }
+
+    /// Checks that the request is one nodes will accept, that it does not mix legacy and
+    /// EIP-1559 fees. Calls and gas estimates may leave out the sender, see `validate_send`
+    /// for requests that are sent as transactions.
+    pub fn validate(&self) -> Result<(), Web3Error> {
+        let eip1559 = self.max_fee_per_gas.is_some() || self.max_priority_fee_per_gas.is_some();
+        if self.gas_price.is_some() && eip1559 {
+            return Err(Web3Error::BadInput(
+                "A transaction can not have both a gas price and EIP-1559 fees".to_string(),
+            ));
+        }
+        if let (Some(max_fee), Some(priority_fee)) =
+            (self.max_fee_per_gas, self.max_priority_fee_per_gas)
+        {
+            if priority_fee.0 > max_fee.0 {
+                return Err(Web3Error::BadInput(
+                    "The priority fee of a transaction can not exceed its max fee".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// `validate` for a request the node signs and sends, which also needs a sender if it
+    /// moves value or uses a nonce
+    pub fn validate_send(&self) -> Result<(), Web3Error> {
+        self.validate()?;
+        let moves_value = matches!(self.value, Some(value) if value.0 != Uint256::from_u64(0));
+        if self.from.is_none() && (moves_value || self.nonce.is_some()) {
+            return Err(Web3Error::BadInput(
+                "A transaction with a value or nonce needs a from address".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `TransactionRequest` with typed setters, `build` validates the result
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionRequestBuilder {
+    from: Option<Address>,
+    to: Option<Address>,
+    gas: Option<Uint256>,
+    gas_price: Option<Uint256>,
+    max_fee_per_gas: Option<Uint256>,
+    max_priority_fee_per_gas: Option<Uint256>,
+    value: Option<Uint256>,
+    data: Option<Data>,
+    nonce: Option<Uint256>,
+}
+
+impl TransactionRequestBuilder {
+    pub fn to(mut self, to: Address) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    pub fn from(mut self, from: Address) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn value(mut self, value: Uint256) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    pub fn data(mut self, data: impl Into<Data>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// The gas limit
+    pub fn gas(mut self, gas: Uint256) -> Self {
+        self.gas = Some(gas);
+        self
+    }
+
+    /// A legacy gas price, for chains without EIP-1559
+    pub fn gas_price(mut self, gas_price: Uint256) -> Self {
+        self.gas_price = Some(gas_price);
+        self
+    }
+
+    pub fn max_fee_per_gas(mut self, max_fee: Uint256) -> Self {
+        self.max_fee_per_gas = Some(max_fee);
+        self
+    }
+
+    pub fn max_priority_fee_per_gas(mut self, priority_fee: Uint256) -> Self {
+        self.max_priority_fee_per_gas = Some(priority_fee);
+        self
+    }
+
+    /// Sets the fee fields matching `fees`, as returned by a `GasPriceStrategy`
+    pub fn fees(self, fees: GasFees) -> Self {
+        match fees {
+            GasFees::Legacy { price } => self.gas_price(price),
+            GasFees::Eip1559 {
+                max_fee,
+                priority_fee,
+            } => self
+                .max_fee_per_gas(max_fee)
+                .max_priority_fee_per_gas(priority_fee),
+        }
+    }
+
+    pub fn nonce(mut self, nonce: Uint256) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    /// Returns the request, or `BadInput` if `to` is missing or it fails
+    /// `TransactionRequest::validate`
+    pub fn build(self) -> Result<TransactionRequest, Web3Error> {
+        let request = self.into_request()?;
+        request.validate()?;
+        Ok(request)
+    }
+
+    /// Returns a request to be signed and sent by the node, or `BadInput` if `to` is missing
+    /// or it fails `TransactionRequest::validate_send`
+    pub fn build_send(self) -> Result<TransactionRequest, Web3Error> {
+        let request = self.into_request()?;
+        request.validate_send()?;
+        Ok(request)
+    }
+
+    fn into_request(self) -> Result<TransactionRequest, Web3Error> {
+        let to = match self.to {
+            Some(to) => to,
+            None => {
+                return Err(Web3Error::BadInput(
+                    "A transaction request needs a to address".to_string(),
+                ))
+            }
+        };
+        let request = TransactionRequest {
+            from: self.from,
+            to,
+            gas: self.gas.map(UnpaddedHex),
+            gas_price: self.gas_price.map(UnpaddedHex),
+            value: self.value.map(UnpaddedHex),
+            data: self.data,
+            nonce: self.nonce.map(UnpaddedHex),
+            max_fee_per_gas: self.max_fee_per_gas.map(UnpaddedHex),
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas.map(UnpaddedHex),
+        };
+        Ok(request)
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
-pub struct UnpaddedHex(pub Uint256);
+pub struct UnpaddedHex(#[serde(with = "quantity")] pub Uint256);
 
 impl Serialize for UnpaddedHex {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_str(&self.0.to_hex_string())
+        serializer.serialize_str(&quantity_to_hex(self.0))
     }
 }
 
@@ -212,92 +679,191 @@ impl From<u64> for UnpaddedHex {
 pub struct Block {
     // geth does not include the author in it's RPC response.
     pub author: Option<Address>,
+    #[serde(with = "quantity")]
     pub difficulty: Uint256,
     #[serde(
         rename = "extraData",
         deserialize_with = "parse_possibly_empty_hex_val"
     )]
     pub extra_data: Uint256,
-    #[serde(rename = "gasLimit")]
+    #[serde(rename = "gasLimit", with = "quantity")]
     pub gas_limit: Uint256,
-    #[serde(rename = "gasUsed")]
+    #[serde(rename = "gasUsed", with = "quantity")]
     pub gas_used: Uint256,
     /// this field will not exist until after
     /// the london hardfork
-    #[serde(rename = "baseFeePerGas")]
+    #[serde(rename = "baseFeePerGas", with = "quantity::option", default)]
     pub base_fee_per_gas: Option<Uint256>,
-    pub hash: Uint256,
+    pub hash: BlockHash,
     #[serde(rename = "logsBloom")]
     pub logs_bloom: Data,
     pub miner: Address,
-    #[serde(rename = "mixHash")]
+    #[serde(rename = "mixHash", with = "quantity::option", default)]
     pub mix_hash: Option<Uint256>,
+    #[serde(with = "quantity")]
     pub nonce: Uint256,
+    #[serde(with = "quantity")]
     pub number: Uint256,
     #[serde(rename = "parentHash")]
-    pub parent_hash: Uint256,
-    #[serde(rename = "receiptsRoot")]
+    pub parent_hash: BlockHash,
+    #[serde(rename = "receiptsRoot", with = "quantity")]
     pub receipts_root: Uint256,
     // Geth also does not include this field.
     #[serde(rename = "sealFields")]
     pub seal_fields: Option<Vec<String>>,
-    #[serde(rename = "sha3Uncles")]
+    #[serde(rename = "sha3Uncles", with = "quantity")]
     pub sha3_uncles: Uint256,
+    #[serde(with = "quantity")]
     pub size: Uint256,
-    #[serde(rename = "stateRoot")]
+    #[serde(rename = "stateRoot", with = "quantity")]
     pub state_root: Uint256,
+    #[serde(with = "quantity")]
     pub timestamp: Uint256,
-    #[serde(rename = "totalDifficulty")]
-    pub total_difficulty: Uint256,
+    /// removed from the responses of some clients after the merge
+    #[serde(rename = "totalDifficulty", with = "quantity::option", default)]
+    pub total_difficulty: Option<Uint256>,
     pub transactions: Vec<TransactionResponse>,
-    #[serde(rename = "transactionsRoot")]
+    #[serde(rename = "transactionsRoot", with = "quantity")]
     pub transactions_root: Uint256,
-    pub uncles: Vec<Uint256>,
+    pub uncles: Vec<BlockHash>,
+    /// this field will not exist until after
+    /// the shanghai hardfork
+    #[serde(rename = "withdrawalsRoot", with = "quantity::option", default)]
+    pub withdrawals_root: Option<Uint256>,
+    /// validator withdrawals processed in this block, added by
+    /// the shanghai hardfork
+    pub withdrawals: Option<Vec<Withdrawal>>,
+    /// the total blob gas used by the transactions of this block, added by
+    /// the dencun hardfork
+    #[serde(rename = "blobGasUsed", default, with = "quantity::option")]
+    pub blob_gas_used: Option<Uint256>,
+    /// the blob gas above the target accumulated over previous blocks, which
+    /// determines the blob base fee, added by the dencun hardfork
+    #[serde(rename = "excessBlobGas", default, with = "quantity::option")]
+    pub excess_blob_gas: Option<Uint256>,
+    /// the beacon block root of the parent slot, added by the dencun hardfork
+    #[serde(rename = "parentBeaconBlockRoot", default, with = "quantity::option")]
+    pub parent_beacon_block_root: Option<Uint256>,
+    /// fields not known to this struct, such as the chain specific additions of L2s
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl Block {
+    /// The base fee per gas of this block, None for blocks before the london hardfork
+    pub fn base_fee(&self) -> Option<Uint256> {
+        self.base_fee_per_gas
+    }
+
+    /// The timestamp of this block in seconds since the unix epoch
+    pub fn timestamp_secs(&self) -> u64 {
+        saturating_u64(self.timestamp)
+    }
+
+    /// The timestamp of this block as a UTC date and time
+    #[cfg(feature = "chrono")]
+    pub fn timestamp_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        timestamp_datetime(self.timestamp_secs())
+    }
+
+    /// The logs bloom of this block, None if the node returned a malformed bloom
+    pub fn bloom(&self) -> Option<Bloom> {
+        Bloom::from_bytes(&self.logs_bloom)
+    }
+
+    /// The blob base fee of this block given the blob base fee update fraction of the fork
+    /// it was produced in, see `blob_base_fee`. None before the dencun hardfork.
+    pub fn blob_gas_price(&self, update_fraction: u64) -> Option<Uint256> {
+        blob_base_fee(self.excess_blob_gas?, update_fraction)
+    }
+
+    /// The address credited with the fees of this block, the miner before the merge
+    pub fn miner(&self) -> Address {
+        self.miner
+    }
+
+    /// The address credited with the fees of this block as chosen by the validator, the
+    /// post merge name for `miner`
+    pub fn fee_recipient(&self) -> Address {
+        self.miner
+    }
+}
+
+/// Converts a quantity that fits in a u64 in practice, such as a timestamp or block number
+pub(crate) fn saturating_u64(n: Uint256) -> u64 {
+    if n > Uint256::from_u64(u64::MAX) {
+        u64::MAX
+    } else {
+        n.resize_to_u128() as u64
+    }
+}
+
+#[cfg(feature = "chrono")]
+fn timestamp_datetime(secs: u64) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::from_timestamp(i64::try_from(secs).ok()?, 0)
+}
+
+/// A validator withdrawal included in a block after the shanghai hardfork
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct Withdrawal {
+    #[serde(with = "quantity")]
+    pub index: Uint256,
+    #[serde(rename = "validatorIndex", with = "quantity")]
+    pub validator_index: Uint256,
+    pub address: Address,
+    /// the withdrawn amount in gwei
+    #[serde(with = "quantity")]
+    pub amount: Uint256,
 }
 
 /// Xdai block
 #[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Eq)]
 pub struct XdaiBlock {
     pub author: Address,
+    #[serde(with = "quantity")]
     pub difficulty: Uint256,
     #[serde(
         rename = "extraData",
         deserialize_with = "parse_possibly_empty_hex_val"
     )]
     pub extra_data: Uint256,
-    #[serde(rename = "gasLimit")]
+    #[serde(rename = "gasLimit", with = "quantity")]
     pub gas_limit: Uint256,
-    #[serde(rename = "gasUsed")]
+    #[serde(rename = "gasUsed", with = "quantity")]
     pub gas_used: Uint256,
     /// this field will not exist until after
     /// the london hardfork
-    #[serde(rename = "baseFeePerGas")]
+    #[serde(rename = "baseFeePerGas", with = "quantity::option", default)]
     pub base_fee_per_gas: Option<Uint256>,
-    pub hash: Uint256,
+    pub hash: BlockHash,
     #[serde(rename = "logsBloom")]
     pub logs_bloom: Data,
     pub miner: Address,
+    #[serde(with = "quantity")]
     pub number: Uint256,
     #[serde(rename = "parentHash")]
-    pub parent_hash: Uint256,
-    #[serde(rename = "receiptsRoot")]
+    pub parent_hash: BlockHash,
+    #[serde(rename = "receiptsRoot", with = "quantity")]
     pub receipts_root: Uint256,
     #[serde(rename = "sealFields")]
     pub seal_fields: Vec<String>,
-    #[serde(rename = "sha3Uncles")]
+    #[serde(rename = "sha3Uncles", with = "quantity")]
     pub sha3_uncles: Uint256,
     pub signature: String,
+    #[serde(with = "quantity")]
     pub size: Uint256,
-    #[serde(rename = "stateRoot")]
+    #[serde(rename = "stateRoot", with = "quantity")]
     pub state_root: Uint256,
+    #[serde(with = "quantity")]
     pub step: Uint256,
+    #[serde(with = "quantity")]
     pub timestamp: Uint256,
-    #[serde(rename = "totalDifficulty")]
+    #[serde(rename = "totalDifficulty", with = "quantity")]
     pub total_difficulty: Uint256,
     pub transactions: Vec<TransactionResponse>,
-    #[serde(rename = "transactionsRoot")]
+    #[serde(rename = "transactionsRoot", with = "quantity")]
     pub transactions_root: Uint256,
-    pub uncles: Vec<Uint256>,
+    pub uncles: Vec<BlockHash>,
 }
 
 /// block with more concise tx hashes instead of full transactions
@@ -305,92 +871,221 @@ pub struct XdaiBlock {
 pub struct ConciseBlock {
     // geth does not include the author in it's RPC response.
     pub author: Option<Address>,
+    #[serde(with = "quantity")]
     pub difficulty: Uint256,
     #[serde(
         rename = "extraData",
         deserialize_with = "parse_possibly_empty_hex_val"
     )]
     pub extra_data: Uint256,
-    #[serde(rename = "gasLimit")]
+    #[serde(rename = "gasLimit", with = "quantity")]
     pub gas_limit: Uint256,
-    #[serde(rename = "gasUsed")]
+    #[serde(rename = "gasUsed", with = "quantity")]
     pub gas_used: Uint256,
     /// this field will not exist until after
     /// the london hardfork
-    #[serde(rename = "baseFeePerGas")]
+    #[serde(rename = "baseFeePerGas", with = "quantity::option", default)]
     pub base_fee_per_gas: Option<Uint256>,
-    pub hash: Uint256,
+    pub hash: BlockHash,
     #[serde(rename = "logsBloom")]
     pub logs_bloom: Data,
     pub miner: Address,
-    #[serde(rename = "mixHash")]
+    #[serde(rename = "mixHash", with = "quantity::option", default)]
     pub mix_hash: Option<Uint256>,
+    #[serde(with = "quantity")]
     pub nonce: Uint256,
+    #[serde(with = "quantity")]
     pub number: Uint256,
     #[serde(rename = "parentHash")]
-    pub parent_hash: Uint256,
-    #[serde(rename = "receiptsRoot")]
+    pub parent_hash: BlockHash,
+    #[serde(rename = "receiptsRoot", with = "quantity")]
     pub receipts_root: Uint256,
     // Geth also does not include this field.
     #[serde(rename = "sealFields")]
     pub seal_fields: Option<Vec<String>>,
-    #[serde(rename = "sha3Uncles")]
+    #[serde(rename = "sha3Uncles", with = "quantity")]
     pub sha3_uncles: Uint256,
+    #[serde(with = "quantity")]
     pub size: Uint256,
-    #[serde(rename = "stateRoot")]
+    #[serde(rename = "stateRoot", with = "quantity")]
     pub state_root: Uint256,
+    #[serde(with = "quantity")]
     pub timestamp: Uint256,
-    #[serde(rename = "totalDifficulty")]
-    pub total_difficulty: Uint256,
-    pub transactions: Vec<Uint256>,
-    #[serde(rename = "transactionsRoot")]
+    /// removed from the responses of some clients after the merge
+    #[serde(rename = "totalDifficulty", with = "quantity::option", default)]
+    pub total_difficulty: Option<Uint256>,
+    pub transactions: Vec<TxHash>,
+    #[serde(rename = "transactionsRoot", with = "quantity")]
+    pub transactions_root: Uint256,
+    pub uncles: Vec<BlockHash>,
+    /// this field will not exist until after
+    /// the shanghai hardfork
+    #[serde(rename = "withdrawalsRoot", with = "quantity::option", default)]
+    pub withdrawals_root: Option<Uint256>,
+    /// validator withdrawals processed in this block, added by
+    /// the shanghai hardfork
+    pub withdrawals: Option<Vec<Withdrawal>>,
+    /// the total blob gas used by the transactions of this block, added by
+    /// the dencun hardfork
+    #[serde(rename = "blobGasUsed", default, with = "quantity::option")]
+    pub blob_gas_used: Option<Uint256>,
+    /// the blob gas above the target accumulated over previous blocks, which
+    /// determines the blob base fee, added by the dencun hardfork
+    #[serde(rename = "excessBlobGas", default, with = "quantity::option")]
+    pub excess_blob_gas: Option<Uint256>,
+    /// the beacon block root of the parent slot, added by the dencun hardfork
+    #[serde(rename = "parentBeaconBlockRoot", default, with = "quantity::option")]
+    pub parent_beacon_block_root: Option<Uint256>,
+    /// fields not known to this struct, such as the chain specific additions of L2s
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl ConciseBlock {
+    /// The base fee per gas of this block, None for blocks before the london hardfork
+    pub fn base_fee(&self) -> Option<Uint256> {
+        self.base_fee_per_gas
+    }
+
+    /// The timestamp of this block in seconds since the unix epoch
+    pub fn timestamp_secs(&self) -> u64 {
+        saturating_u64(self.timestamp)
+    }
+
+    /// The timestamp of this block as a UTC date and time
+    #[cfg(feature = "chrono")]
+    pub fn timestamp_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        timestamp_datetime(self.timestamp_secs())
+    }
+
+    /// The logs bloom of this block, None if the node returned a malformed bloom
+    pub fn bloom(&self) -> Option<Bloom> {
+        Bloom::from_bytes(&self.logs_bloom)
+    }
+
+    /// The blob base fee of this block given the blob base fee update fraction of the fork
+    /// it was produced in, see `blob_base_fee`. None before the dencun hardfork.
+    pub fn blob_gas_price(&self, update_fraction: u64) -> Option<Uint256> {
+        blob_base_fee(self.excess_blob_gas?, update_fraction)
+    }
+
+    /// The address credited with the fees of this block, the miner before the merge
+    pub fn miner(&self) -> Address {
+        self.miner
+    }
+
+    /// The address credited with the fees of this block as chosen by the validator, the
+    /// post merge name for `miner`
+    pub fn fee_recipient(&self) -> Address {
+        self.miner
+    }
+}
+
+/// An uncle block header as returned by eth_getUncleByBlockNumberAndIndex. Uncles are only
+/// known by their header, so clients return a block without transactions and differ in
+/// which of the remaining fields they include.
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct UncleBlock {
+    #[serde(with = "quantity")]
+    pub difficulty: Uint256,
+    #[serde(
+        rename = "extraData",
+        deserialize_with = "parse_possibly_empty_hex_val"
+    )]
+    pub extra_data: Uint256,
+    #[serde(rename = "gasLimit", with = "quantity")]
+    pub gas_limit: Uint256,
+    #[serde(rename = "gasUsed", with = "quantity")]
+    pub gas_used: Uint256,
+    #[serde(rename = "baseFeePerGas", with = "quantity::option", default)]
+    pub base_fee_per_gas: Option<Uint256>,
+    pub hash: BlockHash,
+    #[serde(rename = "logsBloom")]
+    pub logs_bloom: Data,
+    pub miner: Address,
+    #[serde(rename = "mixHash", with = "quantity::option", default)]
+    pub mix_hash: Option<Uint256>,
+    #[serde(with = "quantity::option", default)]
+    pub nonce: Option<Uint256>,
+    #[serde(with = "quantity")]
+    pub number: Uint256,
+    #[serde(rename = "parentHash")]
+    pub parent_hash: BlockHash,
+    #[serde(rename = "receiptsRoot", with = "quantity")]
+    pub receipts_root: Uint256,
+    #[serde(rename = "sha3Uncles", with = "quantity")]
+    pub sha3_uncles: Uint256,
+    #[serde(with = "quantity::option", default)]
+    pub size: Option<Uint256>,
+    #[serde(rename = "stateRoot", with = "quantity")]
+    pub state_root: Uint256,
+    #[serde(with = "quantity")]
+    pub timestamp: Uint256,
+    #[serde(rename = "totalDifficulty", with = "quantity::option", default)]
+    pub total_difficulty: Option<Uint256>,
+    #[serde(rename = "transactionsRoot", with = "quantity")]
     pub transactions_root: Uint256,
-    pub uncles: Vec<Uint256>,
+    /// fields not known to this struct, such as the empty transactions and uncles lists
+    /// some clients include
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl UncleBlock {
+    /// The timestamp of this uncle in seconds since the unix epoch
+    pub fn timestamp_secs(&self) -> u64 {
+        saturating_u64(self.timestamp)
+    }
 }
 
 /// Xdai block with more concise tx hashes instead of full transactions
 #[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Eq)]
 pub struct ConciseXdaiBlock {
     pub author: Address,
+    #[serde(with = "quantity")]
     pub difficulty: Uint256,
     #[serde(
         rename = "extraData",
         deserialize_with = "parse_possibly_empty_hex_val"
     )]
     pub extra_data: Uint256,
-    #[serde(rename = "gasLimit")]
+    #[serde(rename = "gasLimit", with = "quantity")]
     pub gas_limit: Uint256,
-    #[serde(rename = "gasUsed")]
+    #[serde(rename = "gasUsed", with = "quantity")]
     pub gas_used: Uint256,
     /// this field will not exist until after
     /// the london hardfork
-    #[serde(rename = "baseFeePerGas")]
+    #[serde(rename = "baseFeePerGas", with = "quantity::option", default)]
     pub base_fee_per_gas: Option<Uint256>,
-    pub hash: Uint256,
+    pub hash: BlockHash,
     #[serde(rename = "logsBloom")]
     pub logs_bloom: Data,
     pub miner: Address,
+    #[serde(with = "quantity")]
     pub number: Uint256,
     #[serde(rename = "parentHash")]
-    pub parent_hash: Uint256,
-    #[serde(rename = "receiptsRoot")]
+    pub parent_hash: BlockHash,
+    #[serde(rename = "receiptsRoot", with = "quantity")]
     pub receipts_root: Uint256,
     #[serde(rename = "sealFields")]
     pub seal_fields: Vec<String>,
-    #[serde(rename = "sha3Uncles")]
+    #[serde(rename = "sha3Uncles", with = "quantity")]
     pub sha3_uncles: Uint256,
     pub signature: String,
+    #[serde(with = "quantity")]
     pub size: Uint256,
-    #[serde(rename = "stateRoot")]
+    #[serde(rename = "stateRoot", with = "quantity")]
     pub state_root: Uint256,
+    #[serde(with = "quantity")]
     pub step: Uint256,
+    #[serde(with = "quantity")]
     pub timestamp: Uint256,
-    #[serde(rename = "totalDifficulty")]
+    #[serde(rename = "totalDifficulty", with = "quantity")]
     pub total_difficulty: Uint256,
-    pub transactions: Vec<Uint256>,
-    #[serde(rename = "transactionsRoot")]
+    pub transactions: Vec<TxHash>,
+    #[serde(rename = "transactionsRoot", with = "quantity")]
     pub transactions_root: Uint256,
-    pub uncles: Vec<Uint256>,
+    pub uncles: Vec<BlockHash>,
 }
 
 /// Used to configure send_transaction
@@ -402,6 +1097,99 @@ pub enum SendTxOption {
     GasLimit(Uint256),
     NetworkId(u64),
     Nonce(Uint256),
+    /// chooses the gas price with a strategy instead of the node suggested price,
+    /// `GasPrice` takes precedence and `GasPriceMultiplier` does not apply
+    GasStrategy(GasStrategy),
+    /// refuses to send with `Web3Error::GasPriceTooHigh` if the fee per gas would be above
+    /// this, overriding `Web3::set_max_gas_price`
+    MaxGasPrice(Uint256),
+    /// checks that the pending balance of the sender covers the value and the maximum gas
+    /// cost, returning `Web3Error::InsufficientBalance` instead of sending if it does not
+    CheckBalance,
+}
+
+/// A signed transaction that has not necessarily been broadcast, see `Web3::sign_transaction`.
+/// Keeping this around allows a transaction to be tracked even if the response to broadcasting
+/// it is lost, and to be rebroadcast through another node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedTx {
+    /// the keccak256 of `raw`, the txid the node should return when it is broadcast
+    pub hash: TxHash,
+    /// the signed transaction as sent with eth_sendRawTransaction
+    pub raw: Vec<u8>,
+    pub nonce: Uint256,
+}
+
+impl SignedTx {
+    pub fn new(raw: Vec<u8>, nonce: Uint256) -> Self {
+        let hash = H256(keccak256(&raw));
+        SignedTx { hash, raw, nonce }
+    }
+
+    /// The bytes to broadcast with `Web3::eth_send_raw_transaction`
+    pub fn raw_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn hash(&self) -> TxHash {
+        self.hash
+    }
+}
+
+/// A transaction built and signed by `Web3::prepare_transaction` but not yet sent, pass it to
+/// `Web3::broadcast` to send it
+pub type PreparedTx = SignedTx;
+
+/// As received by eth_feeHistory
+///
+/// See more: https://ethereum.org/en/developers/docs/apis/json-rpc/#eth_feehistory
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeeHistory {
+    /// the first block of the returned range
+    #[serde(rename = "oldestBlock", with = "quantity")]
+    pub oldest_block: Uint256,
+    /// the base fee of each block in the range, followed by the base fee of the next block
+    #[serde(rename = "baseFeePerGas", with = "quantity::vec")]
+    pub base_fee_per_gas: Vec<Uint256>,
+    /// the fraction of the gas limit used by each block in the range
+    #[serde(rename = "gasUsedRatio")]
+    pub gas_used_ratio: Vec<f64>,
+    /// for each block the priority fee paid at each of the requested percentiles
+    #[serde(default, with = "fee_history_reward")]
+    pub reward: Option<Vec<Vec<Uint256>>>,
+}
+
+mod fee_history_reward {
+    use crate::quantity::Quantity;
+    use clarity::Uint256;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(n: &Option<Vec<Vec<Uint256>>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        n.as_ref()
+            .map(|blocks| {
+                blocks
+                    .iter()
+                    .map(|block| block.iter().copied().map(Quantity).collect())
+                    .collect::<Vec<Vec<Quantity>>>()
+            })
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Vec<Vec<Uint256>>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let n: Option<Vec<Vec<Quantity>>> = Option::deserialize(deserializer)?;
+        Ok(n.map(|blocks| {
+            blocks
+                .into_iter()
+                .map(|block| block.into_iter().map(Uint256::from).collect())
+                .collect()
+        }))
+    }
 }
 
 fn parse_possibly_empty_hex_val<'de, D>(deserializer: D) -> Result<Uint256, D::Error>
@@ -409,43 +1197,534 @@ where
     D: Deserializer<'de>,
 {
     let s: String = String::deserialize(deserializer)?;
-    match Uint256::from_dec_or_hex_str_restricted(&s) {
-        Ok(val) => Ok(val),
-        Err(_) => Ok(u256!(0)),
-    }
+    Ok(parse_quantity(&s).unwrap_or_default())
 }
 
-/// This enum encapsulates the syncing status returned by a call to eth_syncing
-/// This will either return a bool 'false' if not syncing, or an object with details
-/// about which blocks are syncing
-#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Eq)]
-#[serde(untagged)]
-pub enum SyncingStatus {
-    NotSyncing(bool),
-    #[serde(rename_all = "camelCase")]
-    Syncing {
-        starting_block: Uint256,
-        current_block: Uint256,
-        highest_block: Uint256,
-    },
+/// Deserializes a list of "UNFORMATTED DATA" strings, such as the RLP encoded
+/// trie nodes of a Merkle proof, into byte vectors
+fn data_vec_deserialize<'de, D>(d: D) -> Result<Vec<Vec<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let data: Vec<Data> = Vec::deserialize(d)?;
+    Ok(data.into_iter().map(|v| v.0).collect())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::client::Web3;
-    use std::fs::read_to_string;
-    use std::time::Duration;
+fn data_vec_serialize<S>(x: &[Vec<u8>], s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.collect_seq(x.iter().map(|v| data_to_hex(v)))
+}
 
-    /// This test is used to get new blocks for testing easily
-    #[tokio::test]
-    #[ignore]
-    async fn test_arbitrary_block() {
-        env_logger::init();
+/// Identifies a block for requests that take a block parameter, either by
+/// number or by one of the tags supported by the JSONRPC API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockId {
+    Number(Uint256),
+    Earliest,
+    Latest,
+    Pending,
+    /// post merge only, the latest block that is unlikely to be reorged
+    Safe,
+    /// post merge only, the latest block finalized by 2/3rds of validators
+    Finalized,
+}
+
+impl Serialize for BlockId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            BlockId::Number(n) => serializer.serialize_str(&quantity_to_hex(*n)),
+            BlockId::Earliest => serializer.serialize_str("earliest"),
+            BlockId::Latest => serializer.serialize_str("latest"),
+            BlockId::Pending => serializer.serialize_str("pending"),
+            BlockId::Safe => serializer.serialize_str("safe"),
+            BlockId::Finalized => serializer.serialize_str("finalized"),
+        }
+    }
+}
+
+impl From<Uint256> for BlockId {
+    fn from(n: Uint256) -> Self {
+        BlockId::Number(n)
+    }
+}
+
+/// Account and storage Merkle proofs as returned by eth_getProof
+///
+/// See more: https://eips.ethereum.org/EIPS/eip-1186
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub address: Address,
+    pub balance: Uint256,
+    /// hash of the account code
+    #[serde(rename = "codeHash")]
+    pub code_hash: Uint256,
+    pub nonce: Uint256,
+    /// root of the account storage trie
+    #[serde(rename = "storageHash")]
+    pub storage_hash: Uint256,
+    /// RLP encoded trie nodes from the state root to the account
+    #[serde(
+        rename = "accountProof",
+        serialize_with = "data_vec_serialize",
+        deserialize_with = "data_vec_deserialize"
+    )]
+    pub account_proof: Vec<Vec<u8>>,
+    /// one entry per requested storage key
+    #[serde(rename = "storageProof")]
+    pub storage_proof: Vec<StorageProof>,
+}
+
+/// Merkle proof of a single storage slot of an account
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StorageProof {
+    pub key: Uint256,
+    pub value: Uint256,
+    /// RLP encoded trie nodes from the storage root to the slot
+    #[serde(
+        serialize_with = "data_vec_serialize",
+        deserialize_with = "data_vec_deserialize"
+    )]
+    pub proof: Vec<Vec<u8>>,
+}
+
+/// This enum encapsulates the syncing status returned by a call to eth_syncing
+/// This will either return a bool 'false' if not syncing, or an object with details
+/// about which blocks are syncing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncingStatus {
+    Synced,
+    Syncing {
+        starting_block: Uint256,
+        current_block: Uint256,
+        highest_block: Uint256,
+    },
+}
+
+/// The wire format of eth_syncing, which is either `false` or an object
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawSyncingStatus {
+    Synced(bool),
+    #[serde(rename_all = "camelCase")]
+    Syncing {
+        starting_block: Uint256,
+        current_block: Uint256,
+        highest_block: Uint256,
+    },
+}
+
+impl<'de> Deserialize<'de> for SyncingStatus {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match RawSyncingStatus::deserialize(deserializer)? {
+            RawSyncingStatus::Synced(false) => Ok(SyncingStatus::Synced),
+            RawSyncingStatus::Synced(true) => Err(serde::de::Error::custom(
+                "eth_syncing returned true instead of a sync status object",
+            )),
+            RawSyncingStatus::Syncing {
+                starting_block,
+                current_block,
+                highest_block,
+            } => Ok(SyncingStatus::Syncing {
+                starting_block,
+                current_block,
+                highest_block,
+            }),
+        }
+    }
+}
+
+impl Serialize for SyncingStatus {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.clone() {
+            SyncingStatus::Synced => RawSyncingStatus::Synced(false),
+            SyncingStatus::Syncing {
+                starting_block,
+                current_block,
+                highest_block,
+            } => RawSyncingStatus::Syncing {
+                starting_block,
+                current_block,
+                highest_block,
+            },
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Configuration for debug_traceTransaction and debug_traceCall. With no tracer
+/// set the node uses the default struct logger, which produces very large responses
+/// unless memory, stack and storage capture are disabled.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceOptions {
+    /// name of a built in tracer such as "callTracer", or None for the struct logger
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracer: Option<String>,
+    /// a duration string such as "10s", overriding the node default of 5 seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_storage: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_stack: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable_memory: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable_return_data: Option<bool>,
+    /// tracer specific options, such as `{"onlyTopCall": true}` for the callTracer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracer_config: Option<Value>,
+}
+
+impl TraceOptions {
+    /// The default struct logger with memory, stack and storage capture
+    /// disabled to keep the response size manageable
+    pub fn struct_logger() -> Self {
+        TraceOptions {
+            disable_storage: Some(true),
+            disable_stack: Some(true),
+            enable_memory: Some(false),
+            ..Default::default()
+        }
+    }
+
+    /// The built in callTracer, which returns a tree of calls
+    pub fn call_tracer() -> Self {
+        TraceOptions {
+            tracer: Some("callTracer".to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// The node implementation as identified by its web3_clientVersion string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientKind {
+    Geth,
+    Hardhat,
+    Anvil,
+    Ganache,
+    Unknown,
+}
+
+impl ClientKind {
+    pub fn from_client_version(version: &str) -> ClientKind {
+        let version = version.to_lowercase();
+        if version.starts_with("hardhat") {
+            ClientKind::Hardhat
+        } else if version.starts_with("anvil") {
+            ClientKind::Anvil
+        } else if version.starts_with("ganache") || version.starts_with("ethereumjs testrpc") {
+            ClientKind::Ganache
+        } else if version.starts_with("geth") {
+            ClientKind::Geth
+        } else {
+            ClientKind::Unknown
+        }
+    }
+
+    /// Returns true for clients that only run development chains
+    pub fn is_dev_chain(&self) -> bool {
+        matches!(
+            self,
+            ClientKind::Hardhat | ClientKind::Anvil | ClientKind::Ganache
+        )
+    }
+}
+
+/// Basic information about a node as returned by `Web3::node_info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+    /// the network id from net_version
+    pub network_id: u64,
+    pub peer_count: u64,
+    pub listening: bool,
+    /// the raw web3_clientVersion string
+    pub client_version: String,
+    pub client_kind: ClientKind,
+}
+
+/// Number of transactions in the node's mempool as returned by txpool_status
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxpoolStatus {
+    /// transactions that are ready to be included in the next block
+    pub pending: Uint256,
+    /// transactions that can not be included yet, usually due to a nonce gap
+    pub queued: Uint256,
+}
+
+/// Transactions of a single sender in the mempool, keyed by nonce
+pub type TxpoolTransactions = BTreeMap<u64, TransactionResponse>;
+
+/// Contents of the node's mempool as returned by txpool_content, keyed by sender
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxpoolContent {
+    pub pending: HashMap<Address, TxpoolTransactions>,
+    pub queued: HashMap<Address, TxpoolTransactions>,
+}
+
+/// Contents of the node's mempool for a single sender, as returned by txpool_contentFrom
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxpoolContentFrom {
+    pub pending: TxpoolTransactions,
+    pub queued: TxpoolTransactions,
+}
+
+/// Overrides applied to an account's state before executing eth_call or
+/// debug_traceCall, all fields are optional
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountOverride {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<UnpaddedHex>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<UnpaddedHex>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<Data>,
+    /// replaces the entire storage of the account, keys and values
+    /// are 32 byte 0x prefixed hex strings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<HashMap<String, String>>,
+    /// replaces only the given storage slots, keys and values
+    /// are 32 byte 0x prefixed hex strings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_diff: Option<HashMap<String, String>>,
+}
+
+/// The state override set accepted by eth_call and debug_traceCall
+pub type StateOverride = HashMap<Address, AccountOverride>;
+
+/// The result of a trace, the shape depends on the tracer used
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum TraceResult {
+    CallTracer(CallFrame),
+    StructLogger(StructLoggerTrace),
+    /// output of any other tracer
+    Other(Value),
+}
+
+/// Output of the default struct logger
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLoggerTrace {
+    pub gas: u64,
+    pub failed: bool,
+    /// hex encoded return value, geth does not include a 0x prefix here
+    pub return_value: String,
+    pub struct_logs: Vec<StructLog>,
+}
+
+/// A single execution step from the struct logger
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLog {
+    pub pc: u64,
+    pub op: String,
+    pub gas: u64,
+    pub gas_cost: u64,
+    pub depth: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage: Option<HashMap<String, String>>,
+}
+
+/// A call and its subcalls as produced by the callTracer
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    /// CALL, STATICCALL, DELEGATECALL, CREATE, etc
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub from: Address,
+    /// may be absent for a failed contract creation
+    #[serde(default)]
+    pub to: Option<Address>,
+    #[serde(default)]
+    pub value: Option<Uint256>,
+    pub gas: Uint256,
+    pub gas_used: Uint256,
+    pub input: Data,
+    #[serde(default)]
+    pub output: Option<Data>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub revert_reason: Option<String>,
+    #[serde(default)]
+    pub calls: Vec<CallFrame>,
+}
+
+/// A single action of a transaction or block reward as produced by the parity style trace
+/// namespace served by erigon, nethermind and reth
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Trace {
+    /// the action and its result, tagged by the type of the trace
+    #[serde(flatten)]
+    pub kind: TraceKind,
+    #[serde(default)]
+    pub block_hash: Option<BlockHash>,
+    pub block_number: u64,
+    /// absent for block rewards
+    #[serde(default)]
+    pub transaction_hash: Option<TxHash>,
+    #[serde(default)]
+    pub transaction_position: Option<u64>,
+    /// the path of the call within the transaction, empty for the top level call
+    pub trace_address: Vec<u64>,
+    /// the number of direct subcalls
+    pub subtraces: u64,
+    /// set if the action failed, the result is absent then
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TraceKind {
+    Call {
+        action: CallAction,
+        #[serde(default)]
+        result: Option<CallResult>,
+    },
+    Create {
+        action: CreateAction,
+        #[serde(default)]
+        result: Option<CreateResult>,
+    },
+    /// a selfdestruct
+    Suicide { action: SuicideAction },
+    /// a block or uncle reward, these have no transaction
+    Reward { action: RewardAction },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallAction {
+    pub from: Address,
+    pub to: Address,
+    pub value: Uint256,
+    pub gas: Uint256,
+    pub input: Data,
+    /// call, staticcall, delegatecall or callcode
+    pub call_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallResult {
+    pub gas_used: Uint256,
+    pub output: Data,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAction {
+    pub from: Address,
+    pub value: Uint256,
+    pub gas: Uint256,
+    pub init: Data,
+    /// create or create2, only returned by some clients
+    #[serde(default)]
+    pub creation_method: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateResult {
+    pub gas_used: Uint256,
+    pub code: Data,
+    pub address: Address,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SuicideAction {
+    /// the contract that destroyed itself
+    pub address: Address,
+    pub refund_address: Address,
+    pub balance: Uint256,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RewardAction {
+    pub author: Address,
+    pub value: Uint256,
+    /// block or uncle
+    pub reward_type: String,
+}
+
+impl Trace {
+    /// The address ETH or a call was sent from, the contract for a selfdestruct and None
+    /// for rewards
+    pub fn from(&self) -> Option<Address> {
+        match &self.kind {
+            TraceKind::Call { action, .. } => Some(action.from),
+            TraceKind::Create { action, .. } => Some(action.from),
+            TraceKind::Suicide { action } => Some(action.address),
+            TraceKind::Reward { .. } => None,
+        }
+    }
+
+    /// The address ETH or a call was sent to, None for a contract creation that failed
+    pub fn to(&self) -> Option<Address> {
+        match &self.kind {
+            TraceKind::Call { action, .. } => Some(action.to),
+            TraceKind::Create { result, .. } => result.as_ref().map(|r| r.address),
+            TraceKind::Suicide { action } => Some(action.refund_address),
+            TraceKind::Reward { action } => Some(action.author),
+        }
+    }
+
+    /// The amount of ETH moved by this trace
+    pub fn value(&self) -> Uint256 {
+        match &self.kind {
+            TraceKind::Call { action, .. } => action.value,
+            TraceKind::Create { action, .. } => action.value,
+            TraceKind::Suicide { action } => action.balance,
+            TraceKind::Reward { action } => action.value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Web3;
+    use std::fs::read_to_string;
+    use std::time::Duration;
+
+    /// This test is used to get new blocks for testing easily
+    #[tokio::test]
+    #[ignore]
+    async fn test_arbitrary_block() {
+        env_logger::init();
 
         let web3 = Web3::new("https://eth.althea.net", Duration::from_secs(5));
         let block_number = u256!(10750715);
-        let res = web3.eth_get_block_by_number(block_number).await.unwrap();
+        let res = web3
+            .eth_get_block_by_number(block_number)
+            .await
+            .unwrap()
+            .unwrap();
 
         assert_eq!(block_number, res.number);
     }
@@ -474,6 +1753,270 @@ mod tests {
         println!("{:#?}", res);
     }
 
+    #[test]
+    fn decode_proof() {
+        let res: Proof = serde_json::from_str(
+            r#"{
+      "address": "0x7f0d15c7faae65896648c8273b6d7e43f58fa842",
+      "accountProof": [
+        "0xf90211a090dcaf88c40c7bbc95a912cbdde67c175767b31173df9ee4b0d733bfdd511c43",
+        "0xf8518080808080a00c5f1ea2b0e3e8a1e0e6f3a09c6a7f0a"
+      ],
+      "balance": "0x0",
+      "codeHash": "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470",
+      "nonce": "0x0",
+      "storageHash": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+      "storageProof": [
+        {
+          "key": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+          "proof": [
+            "0xe2a0310000000000000000000000000000000000000000000000000000000000000001"
+          ],
+          "value": "0x1"
+        }
+      ]
+    }"#,
+        )
+        .unwrap();
+
+        assert_eq!(res.account_proof.len(), 2);
+        assert_eq!(res.account_proof[1][0], 0xf8);
+        assert_eq!(res.storage_proof.len(), 1);
+        assert_eq!(res.storage_proof[0].value, u256!(1));
+        assert_eq!(res.storage_proof[0].proof[0].len(), 35);
+        // the byte vectors must round trip back into the same hex strings
+        let reencoded: Proof = serde_json::from_str(&serde_json::to_string(&res).unwrap()).unwrap();
+        assert_eq!(res, reencoded);
+    }
+
+    #[test]
+    fn decode_traces() {
+        let call: TraceResult = serde_json::from_str(
+            r#"{
+      "type": "CALL",
+      "from": "0x5a0b54d5dc17e0aadc383d2db43b0a0d3e029c4c",
+      "to": "0x6b175474e89094c44da98b954eedeac495271d0f",
+      "value": "0x0",
+      "gas": "0x7148",
+      "gasUsed": "0x5a2b",
+      "input": "0xa9059cbb",
+      "output": "0x",
+      "error": "execution reverted",
+      "calls": [
+        {
+          "type": "STATICCALL",
+          "from": "0x6b175474e89094c44da98b954eedeac495271d0f",
+          "to": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+          "gas": "0x1000",
+          "gasUsed": "0x100",
+          "input": "0x70a08231"
+        }
+      ]
+    }"#,
+        )
+        .unwrap();
+        match call {
+            TraceResult::CallTracer(frame) => {
+                assert_eq!(frame.calls.len(), 1);
+                assert_eq!(frame.calls[0].call_type, "STATICCALL");
+                assert!(frame.calls[0].value.is_none());
+            }
+            _ => panic!("Expected a call frame"),
+        }
+
+        let logger: TraceResult = serde_json::from_str(
+            r#"{
+      "gas": 21000,
+      "failed": false,
+      "returnValue": "",
+      "structLogs": [
+        {"pc": 0, "op": "PUSH1", "gas": 79000, "gasCost": 3, "depth": 1},
+        {"pc": 2, "op": "MSTORE", "gas": 78997, "gasCost": 12, "depth": 1, "stack": ["0x80", "0x40"]}
+      ]
+    }"#,
+        )
+        .unwrap();
+        match logger {
+            TraceResult::StructLogger(trace) => {
+                assert_eq!(trace.struct_logs.len(), 2);
+                assert_eq!(trace.struct_logs[1].op, "MSTORE");
+            }
+            _ => panic!("Expected a struct logger trace"),
+        }
+    }
+
+    #[test]
+    fn encode_trace_options() {
+        assert_eq!(
+            serde_json::to_string(&TraceOptions::call_tracer()).unwrap(),
+            r#"{"tracer":"callTracer"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&TraceOptions::struct_logger()).unwrap(),
+            r#"{"disableStorage":true,"disableStack":true,"enableMemory":false}"#
+        );
+    }
+
+    #[test]
+    fn decode_txpool_content() {
+        let res: TxpoolContent = serde_json::from_str(
+            r#"{
+      "pending": {
+        "0x0216d5032f356960cd3749c31ab34eeff21b3395": {
+          "806": {
+            "blockHash": null,
+            "blockNumber": null,
+            "from": "0x0216d5032f356960cd3749c31ab34eeff21b3395",
+            "gas": "0x5208",
+            "gasPrice": "0xba43b7400",
+            "hash": "0xaf953a2d01f55cfe080c0c94150a60105e8ac3d51153058a1f03dd239dd08586",
+            "input": "0x",
+            "nonce": "0x326",
+            "to": "0x7f69a91a3cf4be60020fb58b893b7cbb65376db8",
+            "transactionIndex": null,
+            "value": "0x19a99f0cf456000",
+            "v": "0x1c",
+            "r": "0x2a7fa5d5c0a8b4f0cd8fdc0aeba2fa5f1dd6a5eae3be9daed8e8a2c3f5f7d1e2",
+            "s": "0x1c2bb1e9d9e4b1a0a3a9be22a6c4e1a6d9e1d8c0f0c8d8b7a6e5f4e3d2c1b0a9"
+          }
+        }
+      },
+      "queued": {}
+    }"#,
+        )
+        .unwrap();
+        let sender: Address = "0x0216d5032f356960cd3749c31ab34eeff21b3395"
+            .parse()
+            .unwrap();
+        let txs = &res.pending[&sender];
+        assert_eq!(txs[&806u64].nonce, u256!(806));
+        assert!(res.queued.is_empty());
+
+        let status: TxpoolStatus =
+            serde_json::from_str(r#"{"pending": "0x10", "queued": "0x7"}"#).unwrap();
+        assert_eq!(status.pending, u256!(16));
+        assert_eq!(status.queued, u256!(7));
+    }
+
+    #[test]
+    fn client_kind_detection() {
+        assert_eq!(
+            ClientKind::from_client_version("HardhatNetwork/2.14.0/@ethereumjs/vm/5.9.3"),
+            ClientKind::Hardhat
+        );
+        assert_eq!(
+            ClientKind::from_client_version("anvil/v0.1.0"),
+            ClientKind::Anvil
+        );
+        assert_eq!(
+            ClientKind::from_client_version("Ganache/v7.7.3/EthereumJS TestRPC/v7.7.3/ethereum-js"),
+            ClientKind::Ganache
+        );
+        assert_eq!(
+            ClientKind::from_client_version("Geth/v1.11.6-stable-ea9e62ca/linux-amd64/go1.20.3"),
+            ClientKind::Geth
+        );
+        assert_eq!(
+            ClientKind::from_client_version("erigon/2.43.0/linux-amd64/go1.19.4"),
+            ClientKind::Unknown
+        );
+    }
+
+    #[test]
+    fn build_transaction_request() {
+        let from: Address = "0x1111111111111111111111111111111111111111"
+            .parse()
+            .unwrap();
+        let to: Address = "0x2222222222222222222222222222222222222222"
+            .parse()
+            .unwrap();
+
+        let request = TransactionRequest::builder()
+            .from(from)
+            .to(to)
+            .value(u256!(1))
+            .fees(GasFees::Eip1559 {
+                max_fee: u256!(0x3b9aca00),
+                priority_fee: u256!(0x5f5e100),
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "from": from,
+                "to": to,
+                "value": "0x1",
+                "maxFeePerGas": "0x3b9aca00",
+                "maxPriorityFeePerGas": "0x5f5e100"
+            })
+        );
+        assert!(TransactionRequest::transfer(from, to, u256!(1))
+            .validate_send()
+            .is_ok());
+        assert_eq!(
+            TransactionRequest::call(to, vec![1, 2]).data,
+            Some(vec![1, 2].into())
+        );
+
+        // legacy and EIP-1559 fees can not be mixed
+        let res = TransactionRequest::builder()
+            .from(from)
+            .to(to)
+            .gas_price(u256!(1))
+            .max_fee_per_gas(u256!(1))
+            .build();
+        assert!(matches!(res, Err(Web3Error::BadInput(_))));
+        let res = TransactionRequest::builder()
+            .from(from)
+            .to(to)
+            .max_fee_per_gas(u256!(1))
+            .max_priority_fee_per_gas(u256!(2))
+            .build();
+        assert!(matches!(res, Err(Web3Error::BadInput(_))));
+        // sending value needs a sender, a zero value or a call does not
+        let res = TransactionRequest::builder()
+            .to(to)
+            .value(u256!(1))
+            .build_send();
+        assert!(matches!(res, Err(Web3Error::BadInput(_))));
+        assert!(TransactionRequest::builder()
+            .to(to)
+            .value(u256!(0))
+            .build_send()
+            .is_ok());
+        assert!(TransactionRequest::builder()
+            .to(to)
+            .value(u256!(1))
+            .build()
+            .is_ok());
+        let res = TransactionRequest::builder()
+            .to(to)
+            .nonce(u256!(0))
+            .build_send();
+        assert!(matches!(res, Err(Web3Error::BadInput(_))));
+        assert!(matches!(
+            TransactionRequest::builder().from(from).build(),
+            Err(Web3Error::BadInput(_))
+        ));
+    }
+
+    #[test]
+    fn encode_block_id() {
+        assert_eq!(
+            serde_json::to_string(&BlockId::Number(u256!(255))).unwrap(),
+            r#""0xff""#
+        );
+        assert_eq!(
+            serde_json::to_string(&BlockId::Finalized).unwrap(),
+            r#""finalized""#
+        );
+        assert_eq!(
+            serde_json::to_string(&BlockId::Latest).unwrap(),
+            r#""latest""#
+        );
+    }
+
     #[test]
     fn decode_block_concise() {
         let original = r#"
@@ -579,6 +2122,33 @@ mod tests {
         let _decoded: ConciseBlock = serde_json::from_str(&file).unwrap();
     }
 
+    #[test]
+    fn decode_block_hardfork_fields() {
+        let file = read_to_string("test_files/concise_geth_eth_block.json")
+            .expect("Failed to read test files!");
+        let pre_london: ConciseBlock = serde_json::from_str(&file).unwrap();
+        assert_eq!(pre_london.base_fee(), None);
+        assert!(pre_london.total_difficulty.is_some());
+        assert!(pre_london.withdrawals.is_none());
+
+        let file = read_to_string("test_files/concise_geth_post_merge_block.json")
+            .expect("Failed to read test files!");
+        let post_merge: ConciseBlock = serde_json::from_str(&file).unwrap();
+        assert_eq!(post_merge.base_fee(), Some(u256!(0x2d8f3c1a9)));
+        assert_eq!(post_merge.difficulty, u256!(0));
+        assert!(post_merge.withdrawals_root.is_none());
+
+        let file = read_to_string("test_files/concise_geth_shanghai_block.json")
+            .expect("Failed to read test files!");
+        let shanghai: ConciseBlock = serde_json::from_str(&file).unwrap();
+        assert!(shanghai.total_difficulty.is_none());
+        assert!(shanghai.withdrawals_root.is_some());
+        let withdrawals = shanghai.withdrawals.unwrap();
+        assert_eq!(withdrawals.len(), 2);
+        assert_eq!(withdrawals[1].validator_index, u256!(0x2e6b7));
+        assert_eq!(withdrawals[1].amount, u256!(0x2cf0f1));
+    }
+
     #[test]
     fn decode_xdai_block() {
         let file = read_to_string("test_files/complete_xdai_block.json")
@@ -586,4 +2156,572 @@ mod tests {
 
         let _decoded: XdaiBlock = serde_json::from_str(&file).unwrap();
     }
+
+    #[test]
+    fn decode_syncing_status() {
+        let synced: SyncingStatus = serde_json::from_str("false").unwrap();
+        assert_eq!(synced, SyncingStatus::Synced);
+
+        let syncing: SyncingStatus = serde_json::from_str(
+            r#"{"startingBlock":"0x384","currentBlock":"0x386","highestBlock":"0x454"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            syncing,
+            SyncingStatus::Syncing {
+                starting_block: u256!(900),
+                current_block: u256!(902),
+                highest_block: u256!(1108),
+            }
+        );
+
+        assert!(serde_json::from_str::<SyncingStatus>("true").is_err());
+        assert_eq!(serde_json::to_string(&synced).unwrap(), "false");
+    }
+
+    #[test]
+    fn decode_transaction_receipt() {
+        let receipt: TransactionReceipt = serde_json::from_str(
+            r#"{
+            "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+            "blockNumber": "0xeff35f",
+            "contractAddress": null,
+            "cumulativeGasUsed": "0xa12515",
+            "effectiveGasPrice": "0x5a9c688d4",
+            "from": "0x6221a9c005f6e47eb398fd867784cacfdcfff4e7",
+            "gasUsed": "0xb4c8",
+            "logs": [],
+            "logsBloom": "0x00",
+            "status": "0x1",
+            "to": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "transactionIndex": "0x66",
+            "type": "0x2"
+        }"#,
+        )
+        .unwrap();
+        assert_eq!(receipt.gas_used, u256!(0xb4c8));
+        assert_eq!(receipt.block_number, u256!(0xeff35f));
+        assert!(receipt.is_success());
+        assert!(receipt.contract_address.is_none());
+    }
+
+    #[test]
+    fn decode_pending_legacy_transaction() {
+        let tx: TransactionResponse = serde_json::from_str(
+            r#"{
+            "blockHash": null,
+            "blockNumber": null,
+            "from": "0x6221a9c005f6e47eb398fd867784cacfdcfff4e7",
+            "gas": "0x5208",
+            "gasPrice": "0x4a817c800",
+            "hash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "input": "0x",
+            "nonce": "0x9",
+            "to": "0x3535353535353535353535353535353535353535",
+            "transactionIndex": null,
+            "value": "0xde0b6b3a7640000",
+            "v": "0x25",
+            "r": "0x28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276",
+            "s": "0x67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        }"#,
+        )
+        .unwrap();
+        assert!(tx.is_pending());
+        assert_eq!(tx.tx_type(), 0);
+        assert!(tx.max_fee_per_gas.is_none());
+        assert!(tx.access_list.is_none());
+    }
+
+    #[test]
+    fn decode_mined_dynamic_fee_transaction() {
+        let tx: TransactionResponse = serde_json::from_str(
+            r#"{
+            "accessList": [
+                {
+                    "address": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+                    "storageKeys": [
+                        "0x0000000000000000000000000000000000000000000000000000000000000003"
+                    ]
+                }
+            ],
+            "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+            "blockNumber": "0xeff35f",
+            "chainId": "0x1",
+            "from": "0x6221a9c005f6e47eb398fd867784cacfdcfff4e7",
+            "gas": "0xd6d8",
+            "gasPrice": "0x5a9c688d4",
+            "hash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "input": "0xa9059cbb0000000000000000000000006221a9c005f6e47eb398fd867784cacfdcfff4e7",
+            "maxFeePerGas": "0x7d7d3e8e0",
+            "maxPriorityFeePerGas": "0x59682f00",
+            "nonce": "0x4e",
+            "r": "0x28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276",
+            "s": "0x67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+            "to": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            "transactionIndex": "0x66",
+            "type": "0x2",
+            "v": "0x1",
+            "value": "0x0",
+            "yParity": "0x1"
+        }"#,
+        )
+        .unwrap();
+        assert!(!tx.is_pending());
+        assert_eq!(tx.tx_type(), 2);
+        assert_eq!(tx.chain_id, Some(u256!(1)));
+        assert_eq!(tx.max_priority_fee_per_gas, Some(u256!(0x59682f00)));
+        assert_eq!(tx.access_list.unwrap()[0].storage_keys, vec![u256!(3)]);
+    }
+
+    #[test]
+    fn decode_pre_dencun_block() {
+        use crate::gas_price::BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN;
+
+        // blocks before dencun have no blob fields, see decode_dencun_block for ones that do
+        let block: ConciseBlock = serde_json::from_str(
+            &read_to_string("test_files/concise_geth_shanghai_block.json").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(block.blob_gas_used, None);
+        assert_eq!(block.parent_beacon_block_root, None);
+        assert!(block
+            .blob_gas_price(BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN)
+            .is_none());
+    }
+
+    #[test]
+    fn decode_dencun_block() {
+        use crate::gas_price::BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN;
+
+        let block: Block = serde_json::from_str(
+            &read_to_string("test_files/complete_geth_dencun_block.json").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(block.blob_gas_used, Some(u256!(0x60000)));
+        assert_eq!(block.excess_blob_gas, Some(u256!(0x4b60000)));
+        assert!(block.parent_beacon_block_root.is_some());
+        assert!(block.extra.is_empty(), "{:?}", block.extra);
+
+        let tx = &block.transactions[1];
+        assert_eq!(tx.tx_type(), 3);
+        assert_eq!(tx.max_fee_per_blob_gas, Some(u256!(0x4a817c800)));
+        let hashes = tx.blob_versioned_hashes.as_ref().unwrap();
+        assert_eq!(hashes.len(), 3);
+        // versioned hashes of KZG commitments start with version 1
+        assert!(hashes.iter().all(|h| H256::from(*h).0[0] == 1));
+        // geth adds yParity to typed transactions, nothing blob related is left over
+        assert_eq!(tx.extra.keys().collect::<Vec<_>>(), vec!["yParity"]);
+        assert_eq!(block.transactions[0].tx_type(), 2);
+        assert!(block.transactions[0].blob_versioned_hashes.is_none());
+
+        let receipt: TransactionReceipt = serde_json::from_str(
+            &read_to_string("test_files/geth_blob_transaction_receipt.json").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(receipt.transaction_hash, tx.hash);
+        // every blob is 2^17 blob gas
+        assert_eq!(
+            receipt.blob_gas_used,
+            Some(Uint256::from_u64((hashes.len() as u64) << 17))
+        );
+        assert_eq!(receipt.blob_gas_used, block.blob_gas_used);
+        assert_eq!(
+            receipt.blob_gas_price,
+            block.blob_gas_price(BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN)
+        );
+        assert!(receipt.blob_gas_price <= tx.max_fee_per_blob_gas);
+    }
+
+    #[test]
+    fn decode_l2_transaction() {
+        // Arbitrum adds fields of its own, these are ignored
+        let tx: TransactionResponse = serde_json::from_str(
+            r#"{
+            "blockHash": "0x5c9b2b3b8a1d4b3f6f0e4fd2a7ba2cb6e8d2d0cb0e8b7e4d7ee1e0f4f3c1b2a9",
+            "blockNumber": "0x9a3b2c1",
+            "chainId": "0xa4b1",
+            "from": "0x6221a9c005f6e47eb398fd867784cacfdcfff4e7",
+            "gas": "0x2dc6c0",
+            "gasPrice": "0x5f5e100",
+            "hash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "input": "0x",
+            "l1BlockNumber": "0x1139c6e",
+            "nonce": "0x0",
+            "r": "0x28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276",
+            "requestId": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "s": "0x67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+            "to": "0x3535353535353535353535353535353535353535",
+            "transactionIndex": "0x1",
+            "type": "0x64",
+            "v": "0x0",
+            "value": "0x0"
+        }"#,
+        )
+        .unwrap();
+        assert_eq!(tx.tx_type(), 0x64);
+        assert_eq!(tx.chain_id, Some(u256!(0xa4b1)));
+        assert_eq!(tx.extra["l1BlockNumber"], "0x1139c6e");
+    }
+
+    #[test]
+    fn decode_optimism_deposit_transaction() {
+        // deposit transactions are not signed and carry the L1 deposit details
+        let tx: TransactionResponse = serde_json::from_str(
+            r#"{
+            "blockHash": "0x5c9b2b3b8a1d4b3f6f0e4fd2a7ba2cb6e8d2d0cb0e8b7e4d7ee1e0f4f3c1b2a9",
+            "blockNumber": "0x6f1e2a3",
+            "from": "0xdeaddeaddeaddeaddeaddeaddeaddeaddead0001",
+            "gas": "0xf4240",
+            "gasPrice": "0x0",
+            "hash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "input": "0x440a5e20",
+            "isSystemTx": false,
+            "mint": "0x0",
+            "nonce": "0x6f1e2a2",
+            "sourceHash": "0x9d2b3f1e0a8c7d6e5f4a3b2c1d0e9f8a7b6c5d4e3f2a1b0c9d8e7f6a5b4c3d2e",
+            "to": "0x4200000000000000000000000000000000000015",
+            "transactionIndex": "0x0",
+            "type": "0x7e",
+            "value": "0x0"
+        }"#,
+        )
+        .unwrap();
+        assert_eq!(tx.tx_type(), 0x7e);
+        assert!(tx.v.is_none() && tx.r.is_none() && tx.s.is_none());
+        assert_eq!(tx.extra["isSystemTx"], false);
+    }
+
+    #[test]
+    fn decode_l2_receipts() {
+        let receipt: TransactionReceipt = serde_json::from_str(
+            r#"{
+            "blockHash": "0x5c9b2b3b8a1d4b3f6f0e4fd2a7ba2cb6e8d2d0cb0e8b7e4d7ee1e0f4f3c1b2a9",
+            "blockNumber": "0x6f1e2a3",
+            "contractAddress": null,
+            "cumulativeGasUsed": "0x2dc6c",
+            "effectiveGasPrice": "0xf4310",
+            "from": "0x6221a9c005f6e47eb398fd867784cacfdcfff4e7",
+            "gasUsed": "0x5208",
+            "l1Fee": "0x1c6bf52634000",
+            "l1FeeScalar": "0.684",
+            "l1GasPrice": "0x3b9aca00",
+            "l1GasUsed": "0x640",
+            "logs": [],
+            "logsBloom": "0x00",
+            "status": "0x1",
+            "to": "0x3535353535353535353535353535353535353535",
+            "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "transactionIndex": "0x3",
+            "type": "0x2"
+        }"#,
+        )
+        .unwrap();
+        assert!(receipt.is_success());
+        assert_eq!(receipt.l1_fee, Some(u256!(0x1c6bf52634000)));
+        assert_eq!(receipt.l1_gas_used, Some(u256!(0x640)));
+        assert_eq!(receipt.l1_fee_scalar, Some("0.684".to_string()));
+        assert!(!receipt.extra.contains_key("l1Fee"));
+
+        let receipt: TransactionReceipt = serde_json::from_str(
+            r#"{
+            "blockHash": "0x7b3e1f0d9a2c8b4e6f5a3d2c1b0e9f8a7d6c5b4a3e2d1c0b9a8f7e6d5c4b3a29",
+            "blockNumber": "0xb6c4f2a",
+            "contractAddress": null,
+            "cumulativeGasUsed": "0x0",
+            "effectiveGasPrice": "0x989680",
+            "from": "0x6221a9c005f6e47eb398fd867784cacfdcfff4e7",
+            "gasUsed": "0x1a4b2c",
+            "gasUsedForL1": "0x15f90",
+            "l1BlockNumber": "0x12a05f2",
+            "logs": [
+                {
+                    "address": "0xaf88d065e77c8cc2239327c5edb3a432268e5831",
+                    "topics": [],
+                    "data": "0x",
+                    "blockNumber": "0xb6c4f2a",
+                    "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+                    "transactionIndex": "0x1",
+                    "blockHash": "0x7b3e1f0d9a2c8b4e6f5a3d2c1b0e9f8a7d6c5b4a3e2d1c0b9a8f7e6d5c4b3a29",
+                    "logIndex": "0x0",
+                    "removed": false
+                }
+            ],
+            "logsBloom": "0x00",
+            "status": "0x1",
+            "to": "0xaf88d065e77c8cc2239327c5edb3a432268e5831",
+            "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "transactionIndex": "0x1",
+            "type": "0x2"
+        }"#,
+        )
+        .unwrap();
+        assert_eq!(receipt.logs.len(), 1);
+        assert_eq!(receipt.extra["gasUsedForL1"], "0x15f90");
+    }
+
+    #[test]
+    fn decode_arbitrum_block() {
+        let file = read_to_string("test_files/concise_arbitrum_block.json")
+            .expect("Failed to read test files!");
+        let block: ConciseBlock = serde_json::from_str(&file).unwrap();
+        assert_eq!(block.extra["l1BlockNumber"], "0x12a05f2");
+        assert!(block.extra.contains_key("sendRoot"));
+        assert_eq!(block.transactions.len(), 2);
+    }
+
+    #[test]
+    fn decode_removed_log() {
+        // as delivered by geth through eth_getFilterChanges after a reorg
+        let log: Log = serde_json::from_str(
+            r#"{
+            "address": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+            "topics": [
+                "0xe1fffcc4923d04b559f4d29a8bfc6cda04eb5b0d3c460751c2402c5c5cc9109c",
+                "0x0000000000000000000000007a250d5630b4cf539739df2c5dacb4c659f2488d"
+            ],
+            "data": "0x00000000000000000000000000000000000000000000000002c68af0bb140000",
+            "blockNumber": "0xeff35f",
+            "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "transactionIndex": "0x66",
+            "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+            "logIndex": "0x1b",
+            "removed": true
+        }"#,
+        )
+        .unwrap();
+        assert!(log.is_removed());
+        assert_eq!(log.block_number, Some(u256!(0xeff35f)));
+        assert_eq!(log.log_index, Some(u256!(0x1b)));
+        assert_eq!(
+            log.block_hash.map(|h| h.to_string()),
+            Some("0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3".to_string())
+        );
+
+        // pending logs have no position in the chain
+        let log: Log = serde_json::from_str(
+            r#"{
+            "address": "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+            "topics": [],
+            "data": "0x",
+            "blockNumber": null,
+            "transactionHash": null,
+            "transactionIndex": null,
+            "blockHash": null,
+            "logIndex": null,
+            "removed": false
+        }"#,
+        )
+        .unwrap();
+        assert!(!log.is_removed());
+        assert!(log.block_hash.is_none() && log.log_index.is_none());
+    }
+
+    #[test]
+    fn decode_uncle_block() {
+        // an uncle with only the header fields, without size, totalDifficulty or transactions
+        let mut value: Value = serde_json::from_str(
+            r#"{
+            "difficulty": "0xbf93da424b943",
+            "extraData": "0x",
+            "gasLimit": "0x7a1200",
+            "gasUsed": "0x79ef1e",
+            "hash": "0x1b7ad1b8a4e0f76f15e4d4e2b0c0d5e7d6c0b2f8f0a4e0ad2b1a9b4e8c3d7f1a",
+            "logsBloom": "0x00",
+            "miner": "0xea674fdde714fd979de3edf0f56aa9716b898ec8",
+            "mixHash": "0x2c0b4ee5c5f2a1e4e0f3a9d1c3b8a7e6d5c4b3a291807f6e5d4c3b2a19080706",
+            "nonce": "0x4bb2f8d0ce5a8f4c",
+            "number": "0x6a4d2f",
+            "parentHash": "0x5f7e8d9c0b1a29384756afbecd0123456789abcdef0123456789abcdef012345",
+            "receiptsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+            "sha3Uncles": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+            "stateRoot": "0x9d6b3e2f4a5c7b8e1d0f2a3c4b5e6d7f8091a2b3c4d5e6f708192a3b4c5d6e7f",
+            "timestamp": "0x5bad55d2",
+            "transactionsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+        }"#,
+        )
+        .unwrap();
+        let uncle: UncleBlock = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(uncle.number, u256!(0x6a4d2f));
+        assert_eq!(uncle.timestamp_secs(), 0x5bad55d2);
+        assert!(uncle.size.is_none() && uncle.total_difficulty.is_none());
+
+        // geth includes the size and empty transaction and uncle lists
+        value["size"] = "0x21a".into();
+        value["transactions"] = Value::Array(Vec::new());
+        value["uncles"] = Value::Array(Vec::new());
+        let uncle: UncleBlock = serde_json::from_value(value).unwrap();
+        assert_eq!(uncle.size, Some(u256!(0x21a)));
+        assert!(uncle.extra.contains_key("transactions"));
+    }
+
+    #[test]
+    fn decode_traces() {
+        let traces: Vec<Trace> = serde_json::from_str(
+            r#"[{
+            "action": {
+                "address": "0x1111111111111111111111111111111111111111",
+                "balance": "0x2a",
+                "refundAddress": "0x2222222222222222222222222222222222222222"
+            },
+            "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+            "blockNumber": 15725407,
+            "result": null,
+            "subtraces": 0,
+            "traceAddress": [0, 1],
+            "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "transactionPosition": 3,
+            "type": "suicide"
+        }, {
+            "action": {
+                "author": "0xea674fdde714fd979de3edf0f56aa9716b898ec8",
+                "rewardType": "block",
+                "value": "0x1bc16d674ec80000"
+            },
+            "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+            "blockNumber": 15725407,
+            "subtraces": 0,
+            "traceAddress": [],
+            "type": "reward"
+        }]"#,
+        )
+        .unwrap();
+        assert!(matches!(traces[0].kind, TraceKind::Suicide { .. }));
+        assert_eq!(traces[0].value(), u256!(42));
+        assert_eq!(traces[0].trace_address, vec![0, 1]);
+        assert!(
+            matches!(&traces[1].kind, TraceKind::Reward { action } if action.reward_type == "block")
+        );
+        assert_eq!(traces[1].from(), None);
+        assert!(traces[1].transaction_hash.is_none());
+        assert_eq!(traces[1].value(), u256!(2000000000000000000));
+    }
+
+    #[test]
+    fn data_hex_round_trip() {
+        // xorshift, so that failures are reproducible
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..1000 {
+            let len = (next() % 100) as usize;
+            let data = Data((0..len).map(|_| next() as u8).collect());
+            let json = serde_json::to_value(&data).unwrap();
+            let hex = json.as_str().unwrap().to_string();
+            assert_eq!(hex, data.to_string());
+            assert_eq!(hex.len(), 2 + 2 * len);
+            assert!(hex.starts_with("0x"));
+            assert_eq!(hex, hex.to_lowercase());
+            assert_eq!(serde_json::from_value::<Data>(json).unwrap(), data);
+            assert_eq!(hex.to_uppercase().replacen("0X", "0x", 1).parse(), Ok(data));
+        }
+
+        assert_eq!(
+            serde_json::from_str::<Data>("\"0x\"").unwrap(),
+            Data::default()
+        );
+        assert_eq!(
+            serde_json::from_str::<Data>("\"\"").unwrap(),
+            Data::default()
+        );
+        assert_eq!(Data::default().to_string(), "0x");
+        assert!("0x123".parse::<Data>().is_err());
+        assert!("0x12zz".parse::<Data>().is_err());
+        assert!("1234".parse::<Data>().is_err());
+        assert!(serde_json::from_str::<Data>("\"0xabc\"").is_err());
+
+        let data = Data(vec![1, 2, 3]);
+        assert_eq!(&data[1..], &[2, 3]);
+        assert_eq!(data.as_ref(), &[1, 2, 3]);
+        assert_eq!(Vec::from(data), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn decode_nonstandard_quantities() {
+        use serde_json::json;
+
+        // a legacy transaction as served by a BSC node, with padded quantities, a decimal
+        // chainId and a numeric type
+        let tx: TransactionResponse = serde_json::from_value(json!({
+            "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+            "blockNumber": "0x0012884e1",
+            "chainId": "56",
+            "from": "0x6887246668a3b87f54deb3b94ba47a6f63f32985",
+            "gas": "0x05208",
+            "gasPrice": 5000000000u64,
+            "hash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+            "input": "0x",
+            "nonce": "0x00",
+            "r": "0x28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276",
+            "s": "0x67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+            "to": "0xff00000000000000000000000000000000000010",
+            "transactionIndex": "0x03",
+            "type": 0,
+            "v": "0x93",
+            "value": "0x0"
+        }))
+        .unwrap();
+        assert_eq!(tx.block_number, Some(u256!(0x12884e1)));
+        assert_eq!(tx.chain_id, Some(u256!(56)));
+        assert_eq!(tx.gas, u256!(21000));
+        assert_eq!(tx.gas_price, u256!(5000000000));
+        assert_eq!(tx.nonce, u256!(0));
+        assert_eq!(tx.transaction_index, Some(u256!(3)));
+        assert_eq!(tx.tx_type(), 0);
+
+        // requests and re-serialized responses always use minimal hex
+        let tx = serde_json::to_value(&tx).unwrap();
+        assert_eq!(tx["blockNumber"], json!("0x12884e1"));
+        assert_eq!(tx["chainId"], json!("0x38"));
+        assert_eq!(tx["gasPrice"], json!("0x12a05f200"));
+        assert_eq!(tx["nonce"], json!("0x0"));
+        assert_eq!(
+            serde_json::to_value(UnpaddedHex(u256!(1))).unwrap(),
+            json!("0x1")
+        );
+        assert_eq!(
+            serde_json::from_value::<UnpaddedHex>(json!("0x0001")).unwrap(),
+            UnpaddedHex(u256!(1))
+        );
+
+        // an Alchemy style log with a padded log index and a pending log with null positions
+        let log: Log = serde_json::from_value(json!({
+            "address": "0xff00000000000000000000000000000000000010",
+            "blockNumber": "0x012884e1",
+            "data": "0x",
+            "logIndex": "0x0000",
+            "removed": false,
+            "topics": [],
+            "transactionIndex": "0x3"
+        }))
+        .unwrap();
+        assert_eq!(log.block_number, Some(u256!(0x12884e1)));
+        assert_eq!(log.log_index, Some(u256!(0)));
+        assert_eq!(log.transaction_hash, None);
+        let log: Log = serde_json::from_value(json!({
+            "address": "0xff00000000000000000000000000000000000010",
+            "blockNumber": null,
+            "data": "0x",
+            "logIndex": null,
+            "topics": []
+        }))
+        .unwrap();
+        assert_eq!(log.position(), LogPosition::default());
+
+        // an Ankr style fee history with padded fees
+        let history: FeeHistory = serde_json::from_value(json!({
+            "oldestBlock": "0x0fd0b4c",
+            "baseFeePerGas": ["0x03b9aca00", "0x3b9aca00"],
+            "gasUsedRatio": [0.5],
+            "reward": [["0x00", "0x05f5e100"]]
+        }))
+        .unwrap();
+        assert_eq!(history.oldest_block, u256!(0xfd0b4c));
+        assert_eq!(history.base_fee_per_gas, vec![u256!(1000000000); 2]);
+        assert_eq!(history.reward, Some(vec![vec![u256!(0), u256!(100000000)]]));
+    }
 }
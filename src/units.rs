@@ -0,0 +1,191 @@
+//! Conversions between human readable decimal amounts and integer base units (wei for ETH).
+//!
+//! All conversions are performed on decimal strings so that no floating point rounding is
+//! ever involved, the decimals argument can be any token's `decimals()` value.
+use crate::jsonrpc::error::Web3Error;
+use clarity::{u256, Uint256};
+
+/// Decimals of ETH, 1 ETH is 10^18 wei
+pub const ETH_DECIMALS: u32 = 18;
+/// Decimals of gwei, 1 gwei is 10^9 wei
+pub const GWEI_DECIMALS: u32 = 9;
+
+/// Parses a string of decimal digits into a Uint256, erroring on overflow
+fn parse_digits(digits: &str) -> Result<Uint256, Web3Error> {
+    let ten = u256!(10);
+    let mut value = u256!(0);
+    for c in digits.chars() {
+        let digit = match c.to_digit(10) {
+            Some(d) => d,
+            None => return Err(Web3Error::BadInput(format!("{} is not a decimal digit", c))),
+        };
+        value = match value
+            .checked_mul(ten)
+            .and_then(|v| v.checked_add(Uint256::from_u64(digit as u64)))
+        {
+            Some(v) => v,
+            None => {
+                return Err(Web3Error::BadInput(
+                    "Value is too large for a Uint256".to_string(),
+                ))
+            }
+        };
+    }
+    Ok(value)
+}
+
+/// Parses a decimal string such as "1.5" into an integer amount of base units with
+/// `decimals` decimal places, so `parse_units("1.5", 18)` is 1.5 * 10^18. Values with
+/// more fractional digits than `decimals` allows are rejected rather than truncated.
+pub fn parse_units(value: &str, decimals: u32) -> Result<Uint256, Web3Error> {
+    let value = value.trim();
+    let (integer, fraction) = match value.split_once('.') {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (value, ""),
+    };
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(Web3Error::BadInput(format!(
+            "{:?} is not a decimal number",
+            value
+        )));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(Web3Error::BadInput(format!(
+            "{} has more than {} fractional digits",
+            value, decimals
+        )));
+    }
+
+    let mut digits = String::with_capacity(integer.len() + decimals as usize);
+    digits.push_str(integer);
+    digits.push_str(fraction);
+    for _ in fraction.len()..decimals as usize {
+        digits.push('0');
+    }
+    parse_digits(&digits)
+}
+
+/// Formats an integer amount of base units as a decimal string with `decimals`
+/// decimal places, trailing zeros in the fraction are removed so
+/// `format_units(1.5 * 10^18, 18)` is "1.5" and `format_units(10^18, 18)` is "1"
+pub fn format_units(value: Uint256, decimals: u32) -> String {
+    format_digits(&value.to_string(), decimals)
+}
+
+/// `format_units` for a string of decimal digits, for values that may not fit in a Uint256
+pub(crate) fn format_digits(digits: &str, decimals: u32) -> String {
+    let decimals = decimals as usize;
+    let (integer, fraction) = if digits.len() > decimals {
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+        (integer.to_string(), fraction.to_string())
+    } else {
+        (
+            "0".to_string(),
+            format!("{}{}", "0".repeat(decimals - digits.len()), digits),
+        )
+    };
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        integer
+    } else {
+        format!("{}.{}", integer, fraction)
+    }
+}
+
+/// Converts a decimal ETH amount such as "0.25" into wei
+pub fn eth_to_wei(eth: &str) -> Result<Uint256, Web3Error> {
+    parse_units(eth, ETH_DECIMALS)
+}
+
+/// Converts a floating point ETH amount into wei, the float is converted using its
+/// shortest exact decimal representation so 0.1 becomes exactly 10^17 wei
+pub fn eth_to_wei_f64(eth: f64) -> Result<Uint256, Web3Error> {
+    if !eth.is_finite() || eth < 0.0 {
+        return Err(Web3Error::BadInput(format!(
+            "{} is not a valid ETH amount",
+            eth
+        )));
+    }
+    eth_to_wei(&format!("{}", eth))
+}
+
+/// Converts a decimal gwei amount such as "1.5" into wei
+pub fn gwei_to_wei(gwei: &str) -> Result<Uint256, Web3Error> {
+    parse_units(gwei, GWEI_DECIMALS)
+}
+
+/// Formats a wei amount as ETH with exactly `precision` fractional digits,
+/// any digits beyond the requested precision are truncated
+pub fn wei_to_eth_string(wei: Uint256, precision: usize) -> String {
+    let formatted = format_units(wei, ETH_DECIMALS);
+    let (integer, fraction) = match formatted.split_once('.') {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (formatted.as_str(), ""),
+    };
+    if precision == 0 {
+        return integer.to_string();
+    }
+    let mut fraction: String = fraction.chars().take(precision).collect();
+    while fraction.len() < precision {
+        fraction.push('0');
+    }
+    format!("{}.{}", integer, fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(parse_units("1", 18).unwrap(), u256!(1000000000000000000));
+        assert_eq!(parse_units("1.5", 18).unwrap(), u256!(1500000000000000000));
+        assert_eq!(parse_units(".5", 6).unwrap(), u256!(500000));
+        assert_eq!(parse_units("12.", 6).unwrap(), u256!(12000000));
+        assert_eq!(parse_units("0.000001", 6).unwrap(), u256!(1));
+        assert_eq!(parse_units("42", 0).unwrap(), u256!(42));
+        // too many fractional digits must not be silently truncated
+        assert!(parse_units("0.0000001", 6).is_err());
+        assert!(parse_units("1.5", 0).is_err());
+        assert!(parse_units("", 18).is_err());
+        assert!(parse_units(".", 18).is_err());
+        assert!(parse_units("-1", 18).is_err());
+        assert!(parse_units("1.2.3", 18).is_err());
+        assert!(parse_units("1e18", 18).is_err());
+        // overflow
+        assert!(parse_units(
+            "1000000000000000000000000000000000000000000000000000000000000",
+            18
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_format_units() {
+        assert_eq!(format_units(u256!(1000000000000000000), 18), "1");
+        assert_eq!(format_units(u256!(1500000000000000000), 18), "1.5");
+        assert_eq!(format_units(u256!(1), 18), "0.000000000000000001");
+        assert_eq!(format_units(u256!(0), 18), "0");
+        assert_eq!(format_units(u256!(123456), 0), "123456");
+        assert_eq!(format_units(u256!(123456), 3), "123.456");
+        for (value, decimals) in [("1.5", 18), ("0.000001", 6), ("123456789.123", 9)] {
+            assert_eq!(
+                format_units(parse_units(value, decimals).unwrap(), decimals),
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_eth_conversions() {
+        assert_eq!(eth_to_wei("0.1").unwrap(), u256!(100000000000000000));
+        assert_eq!(eth_to_wei_f64(0.1).unwrap(), u256!(100000000000000000));
+        assert_eq!(eth_to_wei_f64(2.0).unwrap(), u256!(2000000000000000000));
+        assert!(eth_to_wei_f64(-1.0).is_err());
+        assert!(eth_to_wei_f64(f64::NAN).is_err());
+        assert_eq!(gwei_to_wei("1.5").unwrap(), u256!(1500000000));
+        assert_eq!(wei_to_eth_string(u256!(1234567890000000000), 4), "1.2345");
+        assert_eq!(wei_to_eth_string(u256!(1000000000000000000), 2), "1.00");
+        assert_eq!(wei_to_eth_string(u256!(1999999999999999999), 0), "1");
+    }
+}
@@ -0,0 +1,531 @@
+//! ERC-4337 account abstraction, the user operations that smart contract wallets submit
+//! through a bundler instead of sending transactions. Bundlers serve their own eth_ methods
+//! at their own endpoint, create a `Web3` with the bundler url to use them.
+//!
+//! See more: https://eips.ethereum.org/EIPS/eip-4337
+use crate::client::Web3;
+use crate::event_utils::address_to_event;
+use crate::hash::{BlockHash, TxHash};
+use crate::hex::word_to_hex;
+use crate::jsonrpc::error::Web3Error;
+use crate::keccak::keccak256;
+use crate::personal_sign::personal_sign_local;
+use crate::types::{Data, Log, TransactionReceipt, UnpaddedHex};
+use clarity::abi::{SerializedToken, Token};
+use clarity::{Address, PrivateKey, Uint256};
+
+/// The address of the v0.6 EntryPoint contract, the same on every chain
+pub const ENTRY_POINT_V06: &str = "0x5ff137d4b0fdcd49dca30c7cf57e578a026d2789";
+/// The address of the v0.7 EntryPoint contract, the same on every chain
+pub const ENTRY_POINT_V07: &str = "0x0000000071727de22e5e9d8baf0edac6f37da032";
+
+/// A user operation for the v0.6 EntryPoint
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationV06 {
+    pub sender: Address,
+    pub nonce: UnpaddedHex,
+    /// the factory address followed by its calldata when the account is not deployed yet
+    pub init_code: Data,
+    pub call_data: Data,
+    pub call_gas_limit: UnpaddedHex,
+    pub verification_gas_limit: UnpaddedHex,
+    pub pre_verification_gas: UnpaddedHex,
+    pub max_fee_per_gas: UnpaddedHex,
+    pub max_priority_fee_per_gas: UnpaddedHex,
+    /// the paymaster address followed by its data, empty if the sender pays
+    pub paymaster_and_data: Data,
+    pub signature: Data,
+}
+
+/// A user operation for the v0.7 EntryPoint. The contract packs the factory, gas and
+/// paymaster fields into fewer words but bundlers take them unpacked as they are here.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationV07 {
+    pub sender: Address,
+    pub nonce: UnpaddedHex,
+    /// the factory that deploys the account, only for the first operation of an account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factory: Option<Address>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factory_data: Option<Data>,
+    pub call_data: Data,
+    pub call_gas_limit: UnpaddedHex,
+    pub verification_gas_limit: UnpaddedHex,
+    pub pre_verification_gas: UnpaddedHex,
+    pub max_fee_per_gas: UnpaddedHex,
+    pub max_priority_fee_per_gas: UnpaddedHex,
+    /// the paymaster that pays for the operation, None if the sender pays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster: Option<Address>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster_verification_gas_limit: Option<UnpaddedHex>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster_post_op_gas_limit: Option<UnpaddedHex>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster_data: Option<Data>,
+    pub signature: Data,
+}
+
+/// A user operation in the format of either EntryPoint version, which must match the
+/// entry point it is sent to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum UserOperation {
+    V06(UserOperationV06),
+    V07(UserOperationV07),
+}
+
+/// The single word encoding of a uint256
+fn uint_word(n: Uint256) -> [u8; 32] {
+    match Token::Uint(n).serialize() {
+        SerializedToken::Static(v) => v,
+        SerializedToken::Dynamic(_) => unreachable!(),
+    }
+}
+
+/// Packs two uint128s into a bytes32 as the v0.7 EntryPoint does for gas fields
+fn pack_u128s(high: Uint256, low: Uint256) -> Result<[u8; 32], Web3Error> {
+    if high.sig_bits() > 128 || low.sig_bits() > 128 {
+        return Err(Web3Error::BadInput(
+            "v0.7 user operation gas fields must fit in a uint128".to_string(),
+        ));
+    }
+    let mut word = [0u8; 32];
+    word[..16].copy_from_slice(&uint_word(high)[16..]);
+    word[16..].copy_from_slice(&uint_word(low)[16..]);
+    Ok(word)
+}
+
+impl UserOperation {
+    pub fn sender(&self) -> Address {
+        match self {
+            UserOperation::V06(op) => op.sender,
+            UserOperation::V07(op) => op.sender,
+        }
+    }
+
+    pub fn nonce(&self) -> Uint256 {
+        match self {
+            UserOperation::V06(op) => op.nonce.0,
+            UserOperation::V07(op) => op.nonce.0,
+        }
+    }
+
+    /// Replaces the signature, for signing schemes other than `sign`
+    pub fn set_signature(&mut self, signature: Vec<u8>) {
+        match self {
+            UserOperation::V06(op) => op.signature = signature.into(),
+            UserOperation::V07(op) => op.signature = signature.into(),
+        }
+    }
+
+    /// The hash of the fields of the operation other than the signature, as computed by
+    /// `EntryPoint.getUserOpHash` before the entry point and chain are mixed in
+    fn packed_hash(&self) -> Result<[u8; 32], Web3Error> {
+        let words = match self {
+            UserOperation::V06(op) => vec![
+                address_to_event(op.sender),
+                uint_word(op.nonce.0),
+                keccak256(&op.init_code),
+                keccak256(&op.call_data),
+                uint_word(op.call_gas_limit.0),
+                uint_word(op.verification_gas_limit.0),
+                uint_word(op.pre_verification_gas.0),
+                uint_word(op.max_fee_per_gas.0),
+                uint_word(op.max_priority_fee_per_gas.0),
+                keccak256(&op.paymaster_and_data),
+            ],
+            UserOperation::V07(op) => {
+                let mut init_code = Vec::new();
+                if let Some(factory) = op.factory {
+                    init_code.extend_from_slice(&address_to_event(factory)[12..]);
+                    if let Some(data) = &op.factory_data {
+                        init_code.extend_from_slice(data);
+                    }
+                }
+                let mut paymaster_and_data = Vec::new();
+                if let Some(paymaster) = op.paymaster {
+                    let gas = pack_u128s(
+                        op.paymaster_verification_gas_limit
+                            .map(|g| g.0)
+                            .unwrap_or_default(),
+                        op.paymaster_post_op_gas_limit
+                            .map(|g| g.0)
+                            .unwrap_or_default(),
+                    )?;
+                    paymaster_and_data.extend_from_slice(&address_to_event(paymaster)[12..]);
+                    paymaster_and_data.extend_from_slice(&gas);
+                    if let Some(data) = &op.paymaster_data {
+                        paymaster_and_data.extend_from_slice(data);
+                    }
+                }
+                vec![
+                    address_to_event(op.sender),
+                    uint_word(op.nonce.0),
+                    keccak256(&init_code),
+                    keccak256(&op.call_data),
+                    pack_u128s(op.verification_gas_limit.0, op.call_gas_limit.0)?,
+                    uint_word(op.pre_verification_gas.0),
+                    pack_u128s(op.max_priority_fee_per_gas.0, op.max_fee_per_gas.0)?,
+                    keccak256(&paymaster_and_data),
+                ]
+            }
+        };
+        Ok(keccak256(&words.concat()))
+    }
+
+    /// Computes the userOpHash that the account signs and bundlers return, the same as
+    /// `EntryPoint.getUserOpHash` on chain `chain_id`
+    pub fn hash(&self, entry_point: Address, chain_id: u64) -> Result<[u8; 32], Web3Error> {
+        let words = [
+            self.packed_hash()?,
+            address_to_event(entry_point),
+            uint_word(Uint256::from_u64(chain_id)),
+        ];
+        Ok(keccak256(&words.concat()))
+    }
+
+    /// Signs the userOpHash with `personal_sign` as the reference SimpleAccount expects,
+    /// accounts with other validation logic need `set_signature` instead
+    pub fn sign(
+        &mut self,
+        key: &PrivateKey,
+        entry_point: Address,
+        chain_id: u64,
+    ) -> Result<(), Web3Error> {
+        let hash = self.hash(entry_point, chain_id)?;
+        self.set_signature(personal_sign_local(&hash, key).to_vec());
+        Ok(())
+    }
+}
+
+/// The gas limits a bundler suggests for a user operation
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationGasEstimate {
+    pub pre_verification_gas: Uint256,
+    pub verification_gas_limit: Uint256,
+    pub call_gas_limit: Uint256,
+    /// only returned for v0.7 operations with a paymaster
+    #[serde(default)]
+    pub paymaster_verification_gas_limit: Option<Uint256>,
+    #[serde(default)]
+    pub paymaster_post_op_gas_limit: Option<Uint256>,
+}
+
+/// A user operation as returned by eth_getUserOperationByHash
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationResponse {
+    pub user_operation: UserOperation,
+    pub entry_point: Address,
+    /// null while the operation is waiting in the bundler's mempool
+    #[serde(default)]
+    pub block_number: Option<Uint256>,
+    #[serde(default)]
+    pub block_hash: Option<BlockHash>,
+    #[serde(default)]
+    pub transaction_hash: Option<TxHash>,
+}
+
+/// The outcome of an included user operation, as returned by eth_getUserOperationReceipt
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationReceipt {
+    pub user_op_hash: Uint256,
+    pub entry_point: Address,
+    pub sender: Address,
+    pub nonce: Uint256,
+    #[serde(default)]
+    pub paymaster: Option<Address>,
+    pub actual_gas_cost: Uint256,
+    pub actual_gas_used: Uint256,
+    /// false if the call reverted, the fees are paid either way
+    pub success: bool,
+    /// the revert data of a failed call
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// the logs emitted during this user operation only
+    pub logs: Vec<Log>,
+    /// the receipt of the bundle transaction that included the operation
+    pub receipt: TransactionReceipt,
+}
+
+impl Web3 {
+    /// Submits a signed user operation to the bundler's mempool for `entry_point`, returning
+    /// the userOpHash. Returns `UnsupportedMethod` if the endpoint is not a bundler.
+    pub async fn eth_send_user_operation(
+        &self,
+        user_op: &UserOperation,
+        entry_point: Address,
+    ) -> Result<Uint256, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_sendUserOperation",
+                (user_op, entry_point),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("eth_sendUserOperation"))
+    }
+
+    /// Simulates a user operation and returns the gas limits it needs. The signature does
+    /// not have to be valid but must have the length and shape the account expects, a dummy
+    /// signature that reverts validation causes the estimate to fail.
+    pub async fn eth_estimate_user_operation_gas(
+        &self,
+        user_op: &UserOperation,
+        entry_point: Address,
+    ) -> Result<UserOperationGasEstimate, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_estimateUserOperationGas",
+                (user_op, entry_point),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("eth_estimateUserOperationGas"))
+    }
+
+    /// Gets a user operation known to the bundler, None if it has never seen it
+    pub async fn eth_get_user_operation_by_hash(
+        &self,
+        user_op_hash: Uint256,
+    ) -> Result<Option<UserOperationResponse>, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_getUserOperationByHash",
+                vec![word_to_hex(user_op_hash)],
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("eth_getUserOperationByHash"))
+    }
+
+    /// Gets the receipt of a user operation, None until it has been included in a block
+    pub async fn eth_get_user_operation_receipt(
+        &self,
+        user_op_hash: Uint256,
+    ) -> Result<Option<UserOperationReceipt>, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_getUserOperationReceipt",
+                vec![word_to_hex(user_op_hash)],
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("eth_getUserOperationReceipt"))
+    }
+
+    /// Returns the entry points the bundler accepts operations for, in order of preference
+    pub async fn eth_supported_entry_points(&self) -> Result<Vec<Address>, Web3Error> {
+        self.jsonrpc_client
+            .request_method(
+                "eth_supportedEntryPoints",
+                Vec::<String>::new(),
+                self.timeout,
+            )
+            .await
+            .map_err(|e| e.map_unsupported("eth_supportedEntryPoints"))
+    }
+}
+
+#[cfg(test)]
+fn test_user_operations() -> (UserOperation, UserOperation) {
+    let sender: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let call_data: Data = vec![0xb6, 0x1d, 0x27, 0xf6].into();
+    let v06 = UserOperation::V06(UserOperationV06 {
+        sender,
+        nonce: 1u64.into(),
+        init_code: Data::default(),
+        call_data: call_data.clone(),
+        call_gas_limit: 100_000u64.into(),
+        verification_gas_limit: 200_000u64.into(),
+        pre_verification_gas: 50_000u64.into(),
+        max_fee_per_gas: 30_000_000_000u64.into(),
+        max_priority_fee_per_gas: 1_000_000_000u64.into(),
+        paymaster_and_data: Data::default(),
+        signature: Data::default(),
+    });
+    let v07 = UserOperation::V07(UserOperationV07 {
+        sender,
+        nonce: 1u64.into(),
+        factory: Some(
+            "0x2222222222222222222222222222222222222222"
+                .parse()
+                .unwrap(),
+        ),
+        factory_data: Some(vec![0xab, 0xcd].into()),
+        call_data,
+        call_gas_limit: 100_000u64.into(),
+        verification_gas_limit: 200_000u64.into(),
+        pre_verification_gas: 50_000u64.into(),
+        max_fee_per_gas: 30_000_000_000u64.into(),
+        max_priority_fee_per_gas: 1_000_000_000u64.into(),
+        paymaster: Some(
+            "0x3333333333333333333333333333333333333333"
+                .parse()
+                .unwrap(),
+        ),
+        paymaster_verification_gas_limit: Some(60_000u64.into()),
+        paymaster_post_op_gas_limit: Some(10_000u64.into()),
+        paymaster_data: Some(vec![0x01].into()),
+        signature: Data::default(),
+    });
+    (v06, v07)
+}
+
+#[test]
+fn test_user_operation_hash() {
+    use crate::personal_sign::recover_personal_signature;
+    use clarity::utils::bytes_to_hex_str;
+
+    let (mut v06, mut v07) = test_user_operations();
+    let entry_point_v06: Address = ENTRY_POINT_V06.parse().unwrap();
+    let entry_point_v07: Address = ENTRY_POINT_V07.parse().unwrap();
+    assert_eq!(
+        bytes_to_hex_str(&v06.hash(entry_point_v06, 1).unwrap()),
+        "7fa5bccabd175ceb1db79c22f61c7a0bd42a4469e3c9987d2a390f0ebde55b45"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&v07.hash(entry_point_v07, 11155111).unwrap()),
+        "0e4b63dbb83f9bd6ccf2d600355fbb017c12562228b148220b605a91d22192a5"
+    );
+
+    // the signature is not part of the hash
+    let key: PrivateKey = "0x8888888888888888888888888888888888888888888888888888888888888888"
+        .parse()
+        .unwrap();
+    let hash = v06.hash(entry_point_v06, 1).unwrap();
+    v06.sign(&key, entry_point_v06, 1).unwrap();
+    assert_eq!(v06.hash(entry_point_v06, 1).unwrap(), hash);
+    let signature = match &v06 {
+        UserOperation::V06(op) => op.signature.clone(),
+        UserOperation::V07(_) => unreachable!(),
+    };
+    assert_eq!(
+        recover_personal_signature(&hash, &signature).unwrap(),
+        key.to_address()
+    );
+
+    if let UserOperation::V07(op) = &mut v07 {
+        op.call_gas_limit = Uint256::from_u128(u128::MAX)
+            .checked_add(Uint256::from_u64(1))
+            .unwrap()
+            .into();
+    }
+    assert!(matches!(
+        v07.hash(entry_point_v07, 1),
+        Err(Web3Error::BadInput(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_bundler_methods() {
+    use crate::jsonrpc::mock::MockNode;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let (v06, v07) = test_user_operations();
+    let node = MockNode::start(|method, _| match method {
+        "eth_supportedEntryPoints" => Ok(json!([ENTRY_POINT_V07, ENTRY_POINT_V06])),
+        "eth_sendUserOperation" => Ok(json!(format!("{:#066x}", 0xabc))),
+        "eth_estimateUserOperationGas" => Ok(json!({
+            "preVerificationGas": "0xc350",
+            "verificationGasLimit": "0x30d40",
+            "callGasLimit": "0x186a0"
+        })),
+        "eth_getUserOperationReceipt" => Ok(json!({
+            "userOpHash": format!("{:#066x}", 0xabc),
+            "entryPoint": ENTRY_POINT_V07,
+            "sender": "0x1111111111111111111111111111111111111111",
+            "nonce": "0x1",
+            "paymaster": "0x3333333333333333333333333333333333333333",
+            "actualGasCost": "0x2386f26fc10000",
+            "actualGasUsed": "0x3d090",
+            "success": false,
+            "reason": "0x08c379a0",
+            "logs": [],
+            "receipt": {
+                "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+                "blockNumber": "0xeff35f",
+                "contractAddress": null,
+                "cumulativeGasUsed": "0xa12515",
+                "effectiveGasPrice": "0x5a9c688d4",
+                "from": "0x6221a9c005f6e47eb398fd867784cacfdcfff4e7",
+                "gasUsed": "0xb4c8",
+                "logs": [],
+                "logsBloom": "0x00",
+                "status": "0x1",
+                "to": "0x0000000071727de22e5e9d8baf0edac6f37da032",
+                "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5",
+                "transactionIndex": "0x0",
+                "type": "0x2"
+            }
+        })),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let web3 = Web3::new(&node.url, Duration::from_secs(5));
+    let entry_point: Address = ENTRY_POINT_V07.parse().unwrap();
+
+    assert_eq!(
+        web3.eth_supported_entry_points().await.unwrap(),
+        vec![entry_point, ENTRY_POINT_V06.parse().unwrap()]
+    );
+    assert_eq!(
+        web3.eth_send_user_operation(&v07, entry_point)
+            .await
+            .unwrap(),
+        Uint256::from_u64(0xabc)
+    );
+    // bundlers reject zero padded quantities and want empty bytes as "0x"
+    let sent = &node.params("eth_sendUserOperation")[0];
+    assert_eq!(sent[0]["callGasLimit"], json!("0x186a0"));
+    assert_eq!(sent[0]["factoryData"], json!("0xabcd"));
+    assert_eq!(sent[0]["paymasterPostOpGasLimit"], json!("0x2710"));
+    assert_eq!(sent[0]["signature"], json!("0x"));
+    assert!(sent[0].get("initCode").is_none());
+
+    let estimate = web3
+        .eth_estimate_user_operation_gas(&v06, ENTRY_POINT_V06.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(estimate.call_gas_limit, Uint256::from_u64(100_000));
+    assert_eq!(estimate.paymaster_verification_gas_limit, None);
+    let sent = &node.params("eth_estimateUserOperationGas")[0];
+    assert_eq!(sent[0]["initCode"], json!("0x"));
+    assert_eq!(sent[0]["maxFeePerGas"], json!("0x6fc23ac00"));
+
+    // v0.7 operations have no initCode so they are not mistaken for v0.6 ones
+    let node_v07 = MockNode::start(move |method, _| match method {
+        "eth_getUserOperationByHash" => Ok(json!({
+            "userOperation": v07.clone(),
+            "entryPoint": ENTRY_POINT_V07,
+            "blockNumber": "0xeff35f",
+            "blockHash": "0xa957d47df264a31badc3ae823e10ac1d444b098d9b73d204c40426e57f47e8c3",
+            "transactionHash": "0x85d995eba9763907fdf35cd2034144dd9d53ce32cbec21349d4b12823c6860c5"
+        })),
+        _ => Err((-32601, "Method not found".to_string())),
+    });
+    let response = Web3::new(&node_v07.url, Duration::from_secs(5))
+        .eth_get_user_operation_by_hash(Uint256::from_u64(0xabc))
+        .await
+        .unwrap()
+        .unwrap();
+    let (_, v07) = test_user_operations();
+    assert_eq!(response.user_operation, v07);
+    assert_eq!(response.block_number, Some(Uint256::from_u64(0xeff35f)));
+
+    let receipt = web3
+        .eth_get_user_operation_receipt(Uint256::from_u64(0xabc))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!receipt.success);
+    assert_eq!(receipt.actual_gas_used, Uint256::from_u64(250_000));
+    assert_eq!(receipt.receipt.status, Some(Uint256::from_u64(1)));
+}